@@ -0,0 +1,32 @@
+fn main() {
+    #[cfg(feature = "grpc")]
+    {
+        use tonic_build::manual::{Builder, Method, Service};
+
+        let method = |name: &str, route: &str, input: &str, output: &str| {
+            Method::builder()
+                .name(name)
+                .route_name(route)
+                .input_type(format!("crate::grpc::{}", input))
+                .output_type(format!("crate::grpc::{}", output))
+                .codec_path("tonic_prost::ProstCodec")
+        };
+        let service = Service::builder()
+            .name("Ip2x")
+            .package("ip2x.v1")
+            .method(method("lookup", "Lookup", "LookupRequest", "Record").build())
+            .method(
+                method(
+                    "lookup_batch",
+                    "LookupBatch",
+                    "LookupRequest",
+                    "LookupBatchResponse",
+                )
+                .client_streaming()
+                .build(),
+            )
+            .build();
+        Builder::new().build_client(false).compile(&[service]);
+    }
+    println!("cargo:rerun-if-changed=build.rs");
+}
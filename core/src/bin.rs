@@ -0,0 +1,668 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::varint::{read_signed_varint, read_varint};
+use crate::{Error, Result};
+
+pub type GeoRange = (u128, u128, f32, f32, Option<u16>);
+pub type ProxyRange = (u128, u128, u8, u16);
+pub type AsnRange = (u128, u128, usize, usize, usize, Option<usize>);
+pub type IspRange = (u128, u128, usize, usize, usize);
+pub type MobileRange = (u128, u128, usize, usize, usize);
+pub type UsageRange = (u128, u128, u16);
+pub type ElevationRange = (u128, u128, i16);
+pub type ThreatRange = (u128, u128, usize, usize);
+pub type RdnsRange = (u128, u128, usize, u32, u32);
+pub type NetworkTypeRange = (u128, u128, u8);
+pub type DomainRange = (u128, u128, u32);
+pub type Alternate = Option<(AlternateCountry, [u8; 2])>;
+pub type CountryRange = (
+    u128,
+    u128,
+    [u8; 2],
+    [u8; 2],
+    bool,
+    Alternate,
+    Option<String>,
+);
+
+pub const HEADER_MAGIC: &[u8; 4] = b"IP2X";
+pub const FORMAT_VERSION: u8 = 5;
+pub const ORDER_FORMAT_VERSION: u8 = 4;
+pub const PAYLOAD_FORMAT_VERSION: u8 = 3;
+pub const OLDEST_FORMAT_VERSION: u8 = 2;
+pub const LEGACY_FORMAT_VERSION: u8 = 1;
+
+pub const SHARED_STRINGS: u32 = 1;
+pub const PACKED_STRINGS: u32 = 1 << 1;
+pub const SPLIT_FAMILIES: u32 = 1 << 2;
+pub const DELTA_COORDS: u32 = 1 << 3;
+pub const GEO_ACCURACY: u32 = 1 << 4;
+pub const GEO_CELLS: u32 = 1 << 5;
+pub const PROXY_CONFIDENCE: u32 = 1 << 6;
+pub const INDEXED: u32 = 1 << 7;
+pub const TRIE: u32 = 1 << 8;
+pub const GEO_PAYLOAD: u32 = 1 << 9;
+pub const PROXY_LAST_SEEN: u32 = 1 << 10;
+pub const LARGEST_FIRST: u32 = 1 << 11;
+pub const ORDER_MASK: u32 = 3 << 11;
+pub const IPV4_ONLY: u32 = 1 << 13;
+pub const ASN_ORGANIZATION: u32 = 1 << 14;
+pub const KNOWN_FLAGS: u32 = SHARED_STRINGS
+    | PACKED_STRINGS
+    | SPLIT_FAMILIES
+    | DELTA_COORDS
+    | GEO_ACCURACY
+    | GEO_CELLS
+    | PROXY_CONFIDENCE
+    | INDEXED
+    | TRIE
+    | GEO_PAYLOAD
+    | PROXY_LAST_SEEN
+    | LARGEST_FIRST
+    | IPV4_ONLY
+    | ASN_ORGANIZATION;
+
+pub const UNKNOWN_LAST_SEEN: u16 = u16::MAX;
+
+pub const HAS_ACCURACY: u8 = 1;
+pub const UNLOCATED: u8 = 1 << 1;
+pub const IN_EUROPEAN_UNION: u8 = 0x80;
+pub const REPRESENTED_COUNTRY: u8 = 0x40;
+pub const REGISTERED_COUNTRY: u8 = 0x20;
+pub const HAS_SUBDIVISION: u8 = 0x10;
+pub const CONTINENT_MASK: u8 = 0x07;
+pub const CONTINENTS: [&[u8; 2]; 7] = [b"AF", b"AN", b"AS", b"EU", b"NA", b"OC", b"SA"];
+
+pub const IPV4_BASE: u128 = 0xffff << 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AlternateCountry {
+    Registered,
+    Represented,
+}
+
+impl AlternateCountry {
+    pub fn name(self) -> &'static str {
+        match self {
+            AlternateCountry::Registered => "registered",
+            AlternateCountry::Represented => "represented",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Order {
+    #[default]
+    SmallestFirst,
+    LargestFirst,
+}
+
+impl Order {
+    pub fn parse(name: &str) -> Option<Order> {
+        match name {
+            "smallest-first" => Some(Order::SmallestFirst),
+            "largest-first" => Some(Order::LargestFirst),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Order::SmallestFirst => "smallest-first",
+            Order::LargestFirst => "largest-first",
+        }
+    }
+
+    pub fn flag(self) -> u32 {
+        match self {
+            Order::SmallestFirst => 0,
+            Order::LargestFirst => LARGEST_FIRST,
+        }
+    }
+
+    pub fn size_key(self, size: u128) -> u128 {
+        match self {
+            Order::SmallestFirst => size,
+            Order::LargestFirst => u128::MAX - size,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Header {
+    pub flags: u32,
+    pub geo_precision: u8,
+    pub geo_cells: Option<[u8; 2]>,
+}
+
+impl Header {
+    pub fn has(self, flag: u32) -> bool {
+        self.flags & flag != 0
+    }
+
+    pub fn coordinate_scale(self) -> f64 {
+        match self.has(DELTA_COORDS) {
+            true => (0..self.geo_precision).fold(1.0, |scale, _| scale * 10.0),
+            false => 1000.0,
+        }
+    }
+
+    pub fn order(self) -> Order {
+        match self.has(LARGEST_FIRST) {
+            true => Order::LargestFirst,
+            false => Order::SmallestFirst,
+        }
+    }
+
+    pub fn version(self) -> u8 {
+        if self.has(ASN_ORGANIZATION | PROXY_LAST_SEEN) {
+            FORMAT_VERSION
+        } else if self.has(ORDER_MASK | IPV4_ONLY) {
+            ORDER_FORMAT_VERSION
+        } else if self.has(GEO_PAYLOAD) {
+            PAYLOAD_FORMAT_VERSION
+        } else {
+            OLDEST_FORMAT_VERSION
+        }
+    }
+
+    pub fn section_bases(self) -> &'static [u128] {
+        if self.has(IPV4_ONLY) {
+            &[IPV4_BASE]
+        } else if self.has(SPLIT_FAMILIES) {
+            &[IPV4_BASE, 0]
+        } else {
+            &[0]
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SyncState {
+    pub prev_from: u128,
+    pub regs: [i64; 3],
+}
+
+pub fn format_version(input: &[u8]) -> Result<u8> {
+    match input.strip_prefix(HEADER_MAGIC) {
+        Some(mut rest) => read_u8(&mut rest),
+        None => Ok(LEGACY_FORMAT_VERSION),
+    }
+}
+
+pub fn read_header(input: &mut &[u8]) -> Result<Header> {
+    let Some(rest) = input.strip_prefix(HEADER_MAGIC) else {
+        return Ok(Header::default());
+    };
+    *input = rest;
+
+    match read_u8(input)? {
+        version @ OLDEST_FORMAT_VERSION..=FORMAT_VERSION => read_header_fields(input, version),
+        version if version > FORMAT_VERSION => Err(Error::Unsupported(
+            "Bin format version is newer than this reader supports",
+        )),
+        _ => Err(Error::InvalidData("Bad bin format version")),
+    }
+}
+
+fn read_header_fields(input: &mut &[u8], version: u8) -> Result<Header> {
+    let mut header = Header {
+        flags: read_u32(input)?,
+        ..Header::default()
+    };
+    if version < header.version() {
+        return Err(Error::InvalidData("Bad bin header flags"));
+    }
+    if header.flags & ORDER_MASK & !LARGEST_FIRST != 0 {
+        return Err(Error::Unsupported(
+            "Bin record order is not supported by this reader",
+        ));
+    }
+    if header.flags & !KNOWN_FLAGS != 0 {
+        return Err(Error::Unsupported(
+            "Bin header flags are not supported by this reader",
+        ));
+    }
+    if header.has(DELTA_COORDS) {
+        header.geo_precision = read_u8(input)?;
+        if header.geo_precision > 9 {
+            return Err(Error::InvalidData("Bad geo precision"));
+        }
+    }
+    if header.has(GEO_CELLS) {
+        header.geo_cells = Some([read_u8(input)?, read_u8(input)?]);
+    }
+    Ok(header)
+}
+
+fn take<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    if input.len() < len {
+        return Err(Error::UnexpectedEnd);
+    }
+    let (head, rest) = input.split_at(len);
+    *input = rest;
+    Ok(head)
+}
+
+pub fn read_u8(input: &mut &[u8]) -> Result<u8> {
+    Ok(take(input, 1)?[0])
+}
+
+pub fn read_u16(input: &mut &[u8]) -> Result<u16> {
+    Ok(u16::from_le_bytes(take(input, 2)?.try_into().unwrap()))
+}
+
+pub fn read_u32(input: &mut &[u8]) -> Result<u32> {
+    Ok(u32::from_le_bytes(take(input, 4)?.try_into().unwrap()))
+}
+
+fn read_i32(input: &mut &[u8]) -> Result<i32> {
+    read_u32(input).map(|v| v as i32)
+}
+
+fn read_string(input: &mut &[u8], len: usize) -> Result<String> {
+    core::str::from_utf8(take(input, len)?)
+        .map(String::from)
+        .map_err(|_| Error::InvalidData("Bad UTF-8 string"))
+}
+
+pub fn read_range(input: &mut &[u8], header: Header, prev_from: u128) -> Result<(u128, u128)> {
+    if header.has(IPV4_ONLY) {
+        let from = IPV4_BASE + read_u32(input)? as u128;
+        let to = IPV4_BASE + read_u32(input)? as u128;
+        if to < from {
+            return Err(Error::InvalidData("Range end before start"));
+        }
+        return Ok((from, to));
+    }
+    let from = prev_from
+        .checked_add(read_varint(input)?)
+        .ok_or(Error::InvalidData("Range start overflow"))?;
+    let to = from
+        .checked_add(read_varint(input)?)
+        .ok_or(Error::InvalidData("Range end overflow"))?;
+    Ok((from, to))
+}
+
+fn apply_delta(prev: usize, delta: i64) -> Result<usize> {
+    let value = prev as i64 + delta;
+    if value < 0 {
+        return Err(Error::InvalidData("Negative string index"));
+    }
+    Ok(value as usize)
+}
+
+pub fn sort_ranges<T>(items: &mut [T], range: impl Fn(&T) -> (u128, u128)) {
+    sort_ranges_by(items, Order::SmallestFirst, range);
+}
+
+pub fn sort_ranges_by<T>(items: &mut [T], order: Order, range: impl Fn(&T) -> (u128, u128)) {
+    items.sort_by(|a, b| {
+        let (a_from, a_to) = range(a);
+        let (b_from, b_to) = range(b);
+        a_from.cmp(&b_from).then_with(|| {
+            order
+                .size_key(a_to - a_from)
+                .cmp(&order.size_key(b_to - b_from))
+        })
+    });
+}
+
+pub fn read_entries(input: &mut &[u8], count: usize, packed: bool) -> Result<Vec<String>> {
+    let mut strings = Vec::with_capacity(count.min(1 << 20));
+    let mut prev: Vec<u8> = Vec::new();
+    for _ in 0..count {
+        if !packed {
+            let len = read_u16(input)? as usize;
+            strings.push(read_string(input, len)?);
+            continue;
+        }
+
+        let shared = read_varint(input)? as usize;
+        let suffix = read_varint(input)? as usize;
+        if shared > prev.len() || suffix > u16::MAX as usize {
+            return Err(Error::InvalidData("Bad packed string entry"));
+        }
+        prev.truncate(shared);
+        prev.extend_from_slice(take(input, suffix)?);
+        let s = core::str::from_utf8(&prev).map_err(|_| Error::InvalidData("Bad UTF-8 string"))?;
+        strings.push(String::from(s));
+    }
+    Ok(strings)
+}
+
+pub fn read_string_table(input: &mut &[u8], packed: bool) -> Result<Vec<String>> {
+    let table_len = read_u32(input)? as usize;
+    if table_len == 0 {
+        return Err(Error::InvalidData("Empty string table"));
+    }
+
+    let mut strings = read_entries(input, table_len, packed)?;
+    strings.remove(0);
+    Ok(strings)
+}
+
+pub fn read_strings(input: &mut &[u8]) -> Result<Vec<String>> {
+    let header = read_header(input)?;
+    let count = read_u32(input)? as usize;
+    read_entries(input, count, header.has(PACKED_STRINGS))
+}
+
+pub fn read_sections<T, E: From<Error>>(
+    input: &mut &[u8],
+    header: Header,
+    range: impl Fn(&T) -> (u128, u128),
+    read: impl Fn(&mut &[u8], &mut SyncState) -> core::result::Result<T, E>,
+) -> core::result::Result<Vec<T>, E> {
+    let mut items = Vec::new();
+    for &base in header.section_bases() {
+        let count = read_u32(input)? as usize;
+        items.reserve(count.min(1 << 20));
+        let mut state = SyncState {
+            prev_from: base,
+            ..SyncState::default()
+        };
+        for _ in 0..count {
+            items.push(read(input, &mut state)?);
+        }
+    }
+    if header.has(SPLIT_FAMILIES) {
+        sort_ranges_by(&mut items, header.order(), range);
+    }
+    Ok(items)
+}
+
+pub fn no_cells(_cell: u64) -> Result<(f32, f32)> {
+    Err(Error::Unsupported(
+        "Geo cell bins need a cell center function",
+    ))
+}
+
+pub fn read_geo<E: From<Error>>(
+    input: &mut &[u8],
+    header: Header,
+    center: impl Fn(u64) -> core::result::Result<(f32, f32), E>,
+) -> core::result::Result<Vec<GeoRange>, E> {
+    read_sections(
+        input,
+        header,
+        |r: &GeoRange| (r.0, r.1),
+        |input, state| read_geo_record(input, header, state, &center),
+    )
+}
+
+pub fn read_geo_record<E: From<Error>>(
+    input: &mut &[u8],
+    header: Header,
+    state: &mut SyncState,
+    center: impl Fn(u64) -> core::result::Result<(f32, f32), E>,
+) -> core::result::Result<GeoRange, E> {
+    let scale = header.coordinate_scale();
+    let (from, to) = read_range(input, header, state.prev_from)?;
+    let payload = match header.has(GEO_PAYLOAD) {
+        true => read_u8(input)?,
+        false => 0,
+    };
+    let [lat, lon, cell] = &mut state.regs;
+    let mut position = None;
+    if header.geo_cells.is_some() && header.has(GEO_CELLS) {
+        *cell = cell.wrapping_add(read_signed_varint(input)?);
+        if payload & UNLOCATED == 0 {
+            position = Some(center(*cell as u64)?);
+        }
+    } else if header.has(DELTA_COORDS) || header.has(GEO_PAYLOAD) {
+        *lat = lat.wrapping_add(read_signed_varint(input)?);
+        *lon = lon.wrapping_add(read_signed_varint(input)?);
+    } else {
+        *lat = read_i32(input)? as i64;
+        *lon = read_i32(input)? as i64;
+    }
+    let mut accuracy = None;
+    if header.has(GEO_PAYLOAD) {
+        for bit in 0..8 {
+            if payload & 1 << bit == 0 {
+                continue;
+            }
+            let value = read_varint(input)?;
+            if 1 << bit == HAS_ACCURACY {
+                accuracy = Some(value as u16);
+            }
+        }
+    } else if header.has(GEO_ACCURACY) && read_u8(input)? & HAS_ACCURACY != 0 {
+        accuracy = Some(read_varint(input)? as u16);
+    }
+    let (latitude, longitude) = match payload & UNLOCATED {
+        0 => position.unwrap_or(((*lat as f64 / scale) as f32, (*lon as f64 / scale) as f32)),
+        _ => (f32::NAN, f32::NAN),
+    };
+    state.prev_from = from;
+    Ok((from, to, latitude, longitude, accuracy))
+}
+
+pub fn read_usage(input: &mut &[u8], header: Header) -> Result<Vec<UsageRange>> {
+    read_sections(
+        input,
+        header,
+        |r: &UsageRange| (r.0, r.1),
+        |input, state| read_usage_record(input, header, state),
+    )
+}
+
+pub fn read_usage_record(
+    input: &mut &[u8],
+    header: Header,
+    state: &mut SyncState,
+) -> Result<UsageRange> {
+    let (from, to) = read_range(input, header, state.prev_from)?;
+    let mask = u16::try_from(read_varint(input)?)
+        .map_err(|_| Error::InvalidData("Usage mask overflow"))?;
+    state.prev_from = from;
+    Ok((from, to, mask))
+}
+
+pub fn read_elevation(input: &mut &[u8], header: Header) -> Result<Vec<ElevationRange>> {
+    read_sections(
+        input,
+        header,
+        |r: &ElevationRange| (r.0, r.1),
+        |input, state| read_elevation_record(input, header, state),
+    )
+}
+
+pub fn read_elevation_record(
+    input: &mut &[u8],
+    header: Header,
+    state: &mut SyncState,
+) -> Result<ElevationRange> {
+    let (from, to) = read_range(input, header, state.prev_from)?;
+    let meters = i16::try_from(read_signed_varint(input)?)
+        .map_err(|_| Error::InvalidData("Elevation overflow"))?;
+    state.prev_from = from;
+    Ok((from, to, meters))
+}
+
+pub fn read_country(input: &mut &[u8], header: Header) -> Result<Vec<CountryRange>> {
+    read_sections(
+        input,
+        header,
+        |r: &CountryRange| (r.0, r.1),
+        |input, state| read_country_record(input, header, state),
+    )
+}
+
+pub fn read_country_record(
+    input: &mut &[u8],
+    header: Header,
+    state: &mut SyncState,
+) -> Result<CountryRange> {
+    let (from, to) = read_range(input, header, state.prev_from)?;
+    let bytes = take(input, 3)?;
+    let continent = match (bytes[2] & CONTINENT_MASK) as usize {
+        0 => *b"--",
+        i => **CONTINENTS
+            .get(i - 1)
+            .ok_or(Error::InvalidData("Unknown continent index"))?,
+    };
+    let eu = bytes[2] & IN_EUROPEAN_UNION != 0;
+    let source = match bytes[2] & (REGISTERED_COUNTRY | REPRESENTED_COUNTRY) {
+        0 => None,
+        REGISTERED_COUNTRY => Some(AlternateCountry::Registered),
+        REPRESENTED_COUNTRY => Some(AlternateCountry::Represented),
+        _ => {
+            return Err(Error::InvalidData(
+                "Country record is both registered and represented",
+            ))
+        }
+    };
+    let alternate = match source {
+        Some(source) => Some((source, take(input, 2)?.try_into().unwrap())),
+        None => None,
+    };
+    let subdivision = match bytes[2] & HAS_SUBDIVISION {
+        0 => None,
+        _ => {
+            let len = read_u8(input)? as usize;
+            Some(read_string(input, len)?)
+        }
+    };
+    state.prev_from = from;
+    Ok((
+        from,
+        to,
+        [bytes[0], bytes[1]],
+        continent,
+        eu,
+        alternate,
+        subdivision,
+    ))
+}
+
+pub fn read_proxy_types(
+    input: &mut &[u8],
+    header: Header,
+) -> Result<Vec<(String, Vec<ProxyRange>)>> {
+    let type_count = read_u16(input)? as usize;
+    let mut types = Vec::with_capacity(type_count.min(1 << 10));
+
+    for _ in 0..type_count {
+        let name_len = read_u8(input)? as usize;
+        let proxy_type = read_string(input, name_len)?;
+
+        let mut ranges = Vec::new();
+        for &base in header.section_bases() {
+            let count = read_u32(input)? as usize;
+            ranges.reserve(count.min(1 << 20));
+
+            let mut prev_from = base;
+            for _ in 0..count {
+                let range = read_proxy_record(input, header, prev_from)?;
+                ranges.push(range);
+                prev_from = range.0;
+            }
+        }
+        if header.has(SPLIT_FAMILIES) {
+            ranges.sort_by_key(|r| r.0);
+        }
+        types.push((proxy_type, ranges));
+    }
+    Ok(types)
+}
+
+pub fn read_proxy_record(input: &mut &[u8], header: Header, prev_from: u128) -> Result<ProxyRange> {
+    let (from, to) = read_range(input, header, prev_from)?;
+    let confidence = if header.has(PROXY_CONFIDENCE) {
+        read_u8(input)?
+    } else {
+        1
+    };
+    let last_seen = match header.has(PROXY_LAST_SEEN) {
+        true => match read_varint(input)? {
+            0 => UNKNOWN_LAST_SEEN,
+            days => u16::try_from(days - 1)
+                .ok()
+                .filter(|&days| days != UNKNOWN_LAST_SEEN)
+                .ok_or(Error::InvalidData("Bad proxy last seen"))?,
+        },
+        false => UNKNOWN_LAST_SEEN,
+    };
+    Ok((from, to, confidence, last_seen))
+}
+
+pub fn read_asn(
+    input: &mut &[u8],
+    header: Header,
+    shared: Option<Vec<String>>,
+) -> Result<(Vec<String>, Vec<AsnRange>)> {
+    let strings = match shared {
+        Some(strings) => strings,
+        None => {
+            let count = read_u32(input)? as usize;
+            read_entries(input, count, header.has(PACKED_STRINGS))?
+        }
+    };
+    let data = read_sections(
+        input,
+        header,
+        |r: &AsnRange| (r.0, r.1),
+        |input, state| read_asn_record(input, header, state),
+    )?;
+    Ok((strings, data))
+}
+
+pub fn read_asn_record(
+    input: &mut &[u8],
+    header: Header,
+    state: &mut SyncState,
+) -> Result<AsnRange> {
+    let (from, to) = read_range(input, header, state.prev_from)?;
+    let mut idx = [0usize; 3];
+    for (slot, prev) in idx.iter_mut().zip(&mut state.regs) {
+        *slot = apply_delta(*prev as usize, read_signed_varint(input)?)?;
+        *prev = *slot as i64;
+    }
+    let organization = match header.has(ASN_ORGANIZATION) {
+        true => (read_varint(input)? as usize).checked_sub(1),
+        false => None,
+    };
+    state.prev_from = from;
+    Ok((from, to, idx[0], idx[1], idx[2], organization))
+}
+
+pub fn read_isp(
+    input: &mut &[u8],
+    header: Header,
+    shared: Option<Vec<String>>,
+) -> Result<(Vec<String>, Vec<IspRange>)> {
+    let strings = match shared {
+        Some(strings) => strings,
+        None => read_string_table(input, header.has(PACKED_STRINGS))?,
+    };
+    let wide = strings.len() >= 65536;
+    let data = read_sections(
+        input,
+        header,
+        |r: &IspRange| (r.0, r.1),
+        |input, state| read_isp_record(input, header, wide, state),
+    )?;
+    Ok((strings, data))
+}
+
+pub fn read_isp_record(
+    input: &mut &[u8],
+    header: Header,
+    wide: bool,
+    state: &mut SyncState,
+) -> Result<IspRange> {
+    let (from, to) = read_range(input, header, state.prev_from)?;
+    let mut idx = [0usize; 3];
+    for slot in &mut idx {
+        *slot = if wide {
+            read_u32(input)? as usize
+        } else {
+            read_u16(input)? as usize
+        };
+    }
+    state.prev_from = from;
+    Ok((from, to, idx[0], idx[1], idx[2]))
+}
@@ -0,0 +1,35 @@
+use core::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    UnexpectedEnd,
+    InvalidData(&'static str),
+    Unsupported(&'static str),
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnexpectedEnd => f.write_str("failed to fill whole buffer"),
+            Error::InvalidData(message) | Error::Unsupported(message) => f.write_str(message),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+#[cfg(feature = "std")]
+impl From<Error> for std::io::Error {
+    fn from(err: Error) -> Self {
+        use std::io::ErrorKind;
+        let kind = match err {
+            Error::UnexpectedEnd => ErrorKind::UnexpectedEof,
+            Error::InvalidData(_) => ErrorKind::InvalidData,
+            Error::Unsupported(_) => ErrorKind::Unsupported,
+        };
+        std::io::Error::new(kind, err)
+    }
+}
@@ -0,0 +1,14 @@
+#![no_std]
+
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+pub mod bin;
+mod error;
+pub mod lookup;
+pub mod reader;
+pub mod usage;
+pub mod varint;
+
+pub use error::{Error, Result};
@@ -0,0 +1,177 @@
+use alloc::vec::Vec;
+
+use crate::bin::{
+    AsnRange, CountryRange, DomainRange, ElevationRange, GeoRange, IspRange, NetworkTypeRange,
+    ProxyRange, RdnsRange, ThreatRange, UsageRange,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LookupMode {
+    #[default]
+    MostSpecific,
+    FirstMatch,
+}
+
+impl LookupMode {
+    pub fn parse(name: &str) -> Option<LookupMode> {
+        match name {
+            "most-specific" => Some(LookupMode::MostSpecific),
+            "first-match" => Some(LookupMode::FirstMatch),
+            _ => None,
+        }
+    }
+
+    pub fn prefers(self, size: u128, best_size: u128) -> bool {
+        match self {
+            LookupMode::MostSpecific => size < best_size,
+            LookupMode::FirstMatch => true,
+        }
+    }
+}
+
+pub trait Ranged {
+    fn range(&self) -> (u128, u128);
+}
+
+impl Ranged for ProxyRange {
+    fn range(&self) -> (u128, u128) {
+        (self.0, self.1)
+    }
+}
+
+impl Ranged for GeoRange {
+    fn range(&self) -> (u128, u128) {
+        (self.0, self.1)
+    }
+}
+
+impl Ranged for AsnRange {
+    fn range(&self) -> (u128, u128) {
+        (self.0, self.1)
+    }
+}
+
+impl Ranged for IspRange {
+    fn range(&self) -> (u128, u128) {
+        (self.0, self.1)
+    }
+}
+
+impl Ranged for UsageRange {
+    fn range(&self) -> (u128, u128) {
+        (self.0, self.1)
+    }
+}
+
+impl Ranged for ElevationRange {
+    fn range(&self) -> (u128, u128) {
+        (self.0, self.1)
+    }
+}
+
+impl Ranged for ThreatRange {
+    fn range(&self) -> (u128, u128) {
+        (self.0, self.1)
+    }
+}
+
+impl Ranged for NetworkTypeRange {
+    fn range(&self) -> (u128, u128) {
+        (self.0, self.1)
+    }
+}
+
+impl Ranged for DomainRange {
+    fn range(&self) -> (u128, u128) {
+        (self.0, self.1)
+    }
+}
+
+impl Ranged for RdnsRange {
+    fn range(&self) -> (u128, u128) {
+        (self.0, self.1)
+    }
+}
+
+impl Ranged for CountryRange {
+    fn range(&self) -> (u128, u128) {
+        (self.0, self.1)
+    }
+}
+
+pub struct RangeIndex<T> {
+    items: Vec<T>,
+    max_end: Vec<u128>,
+}
+
+impl<T: Ranged> RangeIndex<T> {
+    pub fn new(items: Vec<T>) -> Self {
+        let mut max_end = Vec::with_capacity(items.len());
+        let mut running = 0u128;
+        for item in &items {
+            running = running.max(item.range().1);
+            max_end.push(running);
+        }
+        Self { items, max_end }
+    }
+
+    pub fn items(&self) -> &[T] {
+        &self.items
+    }
+
+    pub fn find(&self, ip: u128, mode: LookupMode) -> Option<&T> {
+        let upper = self.items.partition_point(|item| item.range().0 <= ip);
+        self.best_below(upper, ip, mode)
+    }
+
+    pub fn find_sorted(&self, ips: &[u128], mode: LookupMode) -> Vec<Option<&T>> {
+        let mut upper = 0;
+        ips.iter()
+            .map(|&ip| {
+                upper = advance(&self.items, upper, |item| item.range().0 <= ip);
+                self.best_below(upper, ip, mode)
+            })
+            .collect()
+    }
+
+    fn best_below(&self, upper: usize, ip: u128, mode: LookupMode) -> Option<&T> {
+        let mut best: Option<&T> = None;
+
+        for i in (0..upper).rev() {
+            if self.max_end[i] < ip {
+                break;
+            }
+            let (from, to) = self.items[i].range();
+            if to < ip {
+                continue;
+            }
+            let preferred = best.is_none_or(|b| {
+                let (b_from, b_to) = b.range();
+                mode.prefers(to - from, b_to - b_from)
+            });
+            if preferred {
+                best = Some(&self.items[i]);
+            }
+        }
+
+        best
+    }
+}
+
+fn advance<T>(items: &[T], start: usize, before: impl Fn(&T) -> bool) -> usize {
+    let mut bound = 1;
+    while start + bound < items.len() && before(&items[start + bound]) {
+        bound *= 2;
+    }
+    let end = (start + bound + 1).min(items.len());
+    start + items[start..end].partition_point(before)
+}
+
+impl<T> Default for RangeIndex<T> {
+    fn default() -> Self {
+        Self {
+            items: Vec::new(),
+            max_end: Vec::new(),
+        }
+    }
+}
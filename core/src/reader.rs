@@ -0,0 +1,145 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+use core::net::IpAddr;
+
+use crate::bin::{
+    self, AsnRange, CountryRange, ElevationRange, GeoRange, IspRange, MobileRange, ProxyRange,
+    UsageRange, SHARED_STRINGS,
+};
+use crate::lookup::{LookupMode, RangeIndex};
+use crate::{Error, Result};
+
+pub const KINDS: [&str; 8] = [
+    "geo",
+    "proxy_types",
+    "asn",
+    "isp",
+    "mobile",
+    "usage_type",
+    "elevation",
+    "country",
+];
+
+#[derive(Default)]
+pub struct Reader {
+    pub mode: LookupMode,
+    shared: Option<Vec<String>>,
+    pub geo: RangeIndex<GeoRange>,
+    pub proxy_types: Vec<(String, RangeIndex<ProxyRange>)>,
+    pub asn_strings: Vec<String>,
+    pub asn: RangeIndex<AsnRange>,
+    pub isp_strings: Vec<String>,
+    pub isp: RangeIndex<IspRange>,
+    pub mobile_strings: Vec<String>,
+    pub mobile: RangeIndex<MobileRange>,
+    pub usage: RangeIndex<UsageRange>,
+    pub elevation: RangeIndex<ElevationRange>,
+    pub country: RangeIndex<CountryRange>,
+}
+
+pub fn ip_to_u128(ip: IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(v4) => u128::from(v4.to_ipv6_mapped()),
+        IpAddr::V6(v6) => u128::from(v6),
+    }
+}
+
+impl Reader {
+    pub fn load_strings(&mut self, bytes: &[u8]) -> Result<()> {
+        self.shared = Some(bin::read_strings(&mut &bytes[..])?);
+        Ok(())
+    }
+
+    pub fn load(&mut self, name: &str, bytes: &[u8]) -> Result<()> {
+        let input = &mut &bytes[..];
+        let header = bin::read_header(input)?;
+        let shared = match header.has(SHARED_STRINGS) {
+            true => Some(self.shared.clone().ok_or(Error::InvalidData(
+                "Bin uses shared strings but strings.bin is not loaded",
+            ))?),
+            false => None,
+        };
+        match name.strip_suffix(".bin").unwrap_or(name) {
+            "geo" => self.geo = RangeIndex::new(bin::read_geo(input, header, bin::no_cells)?),
+            "proxy_types" => {
+                self.proxy_types = bin::read_proxy_types(input, header)?
+                    .into_iter()
+                    .map(|(name, ranges)| (name, RangeIndex::new(ranges)))
+                    .collect();
+            }
+            "asn" => {
+                let (strings, data) = bin::read_asn(input, header, shared)?;
+                self.asn_strings = strings;
+                self.asn = RangeIndex::new(data);
+            }
+            "isp" => {
+                let (strings, data) = bin::read_isp(input, header, shared)?;
+                self.isp_strings = strings;
+                self.isp = RangeIndex::new(data);
+            }
+            "mobile" => {
+                let (strings, data) = bin::read_isp(input, header, shared)?;
+                self.mobile_strings = strings;
+                self.mobile = RangeIndex::new(data);
+            }
+            "usage_type" => self.usage = RangeIndex::new(bin::read_usage(input, header)?),
+            "elevation" => self.elevation = RangeIndex::new(bin::read_elevation(input, header)?),
+            "country" => self.country = RangeIndex::new(bin::read_country(input, header)?),
+            _ => return Err(Error::Unsupported("Unknown bin name")),
+        }
+        Ok(())
+    }
+
+    pub fn geo(&self, ip: IpAddr) -> Option<&GeoRange> {
+        self.geo
+            .find(ip_to_u128(ip), self.mode)
+            .filter(|range| !range.2.is_nan())
+    }
+
+    pub fn country(&self, ip: IpAddr) -> Option<&CountryRange> {
+        self.country.find(ip_to_u128(ip), self.mode)
+    }
+
+    pub fn asn(&self, ip: IpAddr) -> Option<[&str; 3]> {
+        let (_, _, cidr, asn, name, _) = *self.asn.find(ip_to_u128(ip), self.mode)?;
+        let string = |idx: usize| self.asn_strings.get(idx).map_or("", String::as_str);
+        Some([string(cidr), string(asn), string(name)])
+    }
+
+    pub fn proxy(&self, ip: IpAddr) -> Option<(&str, u8)> {
+        let target = ip_to_u128(ip);
+        self.proxy_types
+            .iter()
+            .filter_map(|(name, index)| index.find(target, self.mode).map(|r| (name, r)))
+            .min_by_key(|(_, r)| (r.1 - r.0, Reverse(r.2)))
+            .map(|(name, r)| (name.as_str(), r.2))
+    }
+
+    pub fn isp(&self, ip: IpAddr) -> Option<[&str; 3]> {
+        let (_, _, isp, domain, provider) = *self.isp.find(ip_to_u128(ip), self.mode)?;
+        let strings = &self.isp_strings;
+        Some([isp, domain, provider].map(|idx| string_or_dash(strings, idx)))
+    }
+
+    pub fn mobile(&self, ip: IpAddr) -> Option<[&str; 3]> {
+        let (_, _, mcc, mnc, brand) = *self.mobile.find(ip_to_u128(ip), self.mode)?;
+        let strings = &self.mobile_strings;
+        Some([mcc, mnc, brand].map(|idx| string_or_dash(strings, idx)))
+    }
+
+    pub fn usage(&self, ip: IpAddr) -> Option<u16> {
+        self.usage.find(ip_to_u128(ip), self.mode).map(|r| r.2)
+    }
+
+    pub fn elevation(&self, ip: IpAddr) -> Option<i16> {
+        self.elevation.find(ip_to_u128(ip), self.mode).map(|r| r.2)
+    }
+}
+
+fn string_or_dash(strings: &[String], idx: usize) -> &str {
+    match idx.checked_sub(1).and_then(|i| strings.get(i)) {
+        Some(s) => s,
+        None => "-",
+    }
+}
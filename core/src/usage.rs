@@ -0,0 +1,25 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+pub const USAGE_TYPES: [&str; 12] = [
+    "COM", "ORG", "GOV", "MIL", "EDU", "LIB", "CDN", "ISP", "MOB", "DCH", "SES", "RSV",
+];
+
+pub const KNOWN_MASK: u16 = (1 << USAGE_TYPES.len()) - 1;
+
+pub fn parse(value: &str) -> u16 {
+    value
+        .split('/')
+        .filter_map(|code| USAGE_TYPES.iter().position(|t| *t == code.trim()))
+        .fold(0, |mask, bit| mask | 1 << bit)
+}
+
+pub fn names(mask: u16) -> String {
+    USAGE_TYPES
+        .iter()
+        .enumerate()
+        .filter(|(bit, _)| mask & (1 << bit) != 0)
+        .map(|(_, name)| *name)
+        .collect::<Vec<_>>()
+        .join("/")
+}
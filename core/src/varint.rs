@@ -0,0 +1,48 @@
+use crate::{Error, Result};
+
+pub const MAX_LEN: usize = 19;
+
+pub fn decode<E: From<Error>>(
+    mut next: impl FnMut() -> core::result::Result<u8, E>,
+) -> core::result::Result<u128, E> {
+    let mut result = 0u128;
+    for i in 0..MAX_LEN {
+        let byte = next()?;
+        let bits = (byte & 0x7F) as u128;
+        let shift = i * 7;
+        if shift + 7 > 128 && bits >> (128 - shift) != 0 {
+            return Err(Error::InvalidData("Varint overflow").into());
+        }
+        result |= bits << shift;
+        if byte & 0x80 == 0 {
+            return Ok(result);
+        }
+    }
+    Err(Error::InvalidData("Varint overflow").into())
+}
+
+pub fn signed(encoded: u128) -> Result<i64> {
+    let encoded =
+        u64::try_from(encoded).map_err(|_| Error::InvalidData("Signed varint overflow"))?;
+    Ok(unzigzag(encoded))
+}
+
+pub fn read_varint(input: &mut &[u8]) -> Result<u128> {
+    decode(|| {
+        let (&byte, rest) = input.split_first().ok_or(Error::UnexpectedEnd)?;
+        *input = rest;
+        Ok(byte)
+    })
+}
+
+pub fn read_signed_varint(input: &mut &[u8]) -> Result<i64> {
+    signed(read_varint(input)?)
+}
+
+pub fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+pub fn unzigzag(encoded: u64) -> i64 {
+    (encoded >> 1) as i64 ^ -((encoded & 1) as i64)
+}
@@ -0,0 +1,38 @@
+#ifndef IP2X_H
+#define IP2X_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct Ip2xReader ip2x_reader;
+
+typedef struct {
+    float latitude;
+    float longitude;
+    int32_t accuracy_radius; /* -1 when the bin has no accuracy */
+} ip2x_geo;
+
+/* Strings are owned by the reader and stay valid until ip2x_free. */
+typedef struct {
+    const char *cidr;
+    const char *asn;
+    const char *as_name;
+} ip2x_asn;
+
+/* Loads every *.bin (and strings.bin) found in dir. Returns NULL on error. */
+ip2x_reader *ip2x_open(const char *dir);
+
+/* Return 1 on a hit, 0 when no range contains ip, -1 on a bad argument or IP. */
+int ip2x_lookup_geo(const ip2x_reader *reader, const char *ip, ip2x_geo *out);
+int ip2x_lookup_asn(const ip2x_reader *reader, const char *ip, ip2x_asn *out);
+
+void ip2x_free(ip2x_reader *reader);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
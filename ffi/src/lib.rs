@@ -0,0 +1,135 @@
+#![allow(clippy::missing_safety_doc)]
+
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::fs;
+use std::io::{ErrorKind, Result};
+use std::net::IpAddr;
+use std::path::Path;
+use std::ptr;
+
+use ip2x_core::reader::{self, ip_to_u128, KINDS};
+
+const STRINGS_FILE: &str = "strings.bin";
+
+pub struct Ip2xReader {
+    reader: reader::Reader,
+    asn_strings: Vec<CString>,
+}
+
+#[repr(C)]
+pub struct Ip2xGeo {
+    pub latitude: f32,
+    pub longitude: f32,
+    pub accuracy_radius: i32,
+}
+
+#[repr(C)]
+pub struct Ip2xAsn {
+    pub cidr: *const c_char,
+    pub asn: *const c_char,
+    pub as_name: *const c_char,
+}
+
+fn open(dir: &Path) -> Result<Ip2xReader> {
+    let mut reader = reader::Reader::default();
+    match fs::read(dir.join(STRINGS_FILE)) {
+        Ok(bytes) => reader.load_strings(&bytes)?,
+        Err(err) if err.kind() == ErrorKind::NotFound => {}
+        Err(err) => return Err(err),
+    }
+    for name in KINDS {
+        match fs::read(dir.join(name).with_extension("bin")) {
+            Ok(bytes) => reader.load(name, &bytes)?,
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+    }
+    let asn_strings = reader
+        .asn_strings
+        .iter()
+        .map(|s| CString::new(s.replace('\0', "")).unwrap())
+        .collect();
+    Ok(Ip2xReader {
+        reader,
+        asn_strings,
+    })
+}
+
+unsafe fn parse_ip(ip: *const c_char) -> Option<IpAddr> {
+    if ip.is_null() {
+        return None;
+    }
+    CStr::from_ptr(ip).to_str().ok()?.parse().ok()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ip2x_open(dir: *const c_char) -> *mut Ip2xReader {
+    if dir.is_null() {
+        return ptr::null_mut();
+    }
+    let Ok(dir) = CStr::from_ptr(dir).to_str() else {
+        return ptr::null_mut();
+    };
+    match open(Path::new(dir)) {
+        Ok(reader) => Box::into_raw(Box::new(reader)),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ip2x_lookup_geo(
+    reader: *const Ip2xReader,
+    ip: *const c_char,
+    out: *mut Ip2xGeo,
+) -> c_int {
+    let (Some(reader), Some(ip), false) = (reader.as_ref(), parse_ip(ip), out.is_null()) else {
+        return -1;
+    };
+    match reader.reader.geo(ip) {
+        Some(&(_, _, latitude, longitude, accuracy)) => {
+            *out = Ip2xGeo {
+                latitude,
+                longitude,
+                accuracy_radius: accuracy.map_or(-1, i32::from),
+            };
+            1
+        }
+        None => 0,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ip2x_lookup_asn(
+    reader: *const Ip2xReader,
+    ip: *const c_char,
+    out: *mut Ip2xAsn,
+) -> c_int {
+    let (Some(reader), Some(ip), false) = (reader.as_ref(), parse_ip(ip), out.is_null()) else {
+        return -1;
+    };
+    let inner = &reader.reader;
+    match inner.asn.find(ip_to_u128(ip), inner.mode) {
+        Some(&(_, _, cidr, asn, name, _)) => {
+            let string = |idx: usize| {
+                reader
+                    .asn_strings
+                    .get(idx)
+                    .map_or(c"".as_ptr(), |s| s.as_ptr())
+            };
+            *out = Ip2xAsn {
+                cidr: string(cidr),
+                asn: string(asn),
+                as_name: string(name),
+            };
+            1
+        }
+        None => 0,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ip2x_free(reader: *mut Ip2xReader) {
+    if !reader.is_null() {
+        drop(Box::from_raw(reader));
+    }
+}
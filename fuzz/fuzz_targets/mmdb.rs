@@ -0,0 +1,15 @@
+#![no_main]
+
+use ip2x::maxmind::MaxMindReader;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(reader) = MaxMindReader::from_untrusted_bytes(data.to_vec()) else {
+        return;
+    };
+    for ip in ["1.1.1.1", "81.2.69.160", "::1", "2001:db8::1", "ffff::"] {
+        let _ = reader.lookup(ip);
+        let _ = reader.lookup_network(ip);
+    }
+    let _ = reader.load_all();
+});
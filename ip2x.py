@@ -1,6 +1,8 @@
+import io
 import struct
 import ipaddress
 import json
+import math
 import time
 from pathlib import Path
 from typing import Dict, Any, Optional, List, Tuple
@@ -13,6 +15,153 @@ def ip_to_int(ip: str) -> int:
     return int(ip_obj)
 
 
+ZSTD_MAGIC = b"IP2Z"
+
+
+def zstd_decompress(data: bytes) -> bytes:
+    try:
+        from compression import zstd
+        return zstd.decompress(data)
+    except ImportError:
+        import zstandard
+        return zstandard.ZstdDecompressor().decompress(data)
+
+
+def open_bin(path: str):
+    f = open(path, "rb")
+    if f.read(4) != ZSTD_MAGIC:
+        f.seek(0)
+        return f
+
+    with f:
+        header_len = struct.unpack("<I", f.read(4))[0]
+        raw = bytearray(f.read(header_len))
+        f.read(4)
+        while prefix := f.read(4):
+            block_len = struct.unpack("<I", prefix)[0]
+            raw += zstd_decompress(f.read(block_len))
+    return io.BytesIO(bytes(raw))
+
+
+HEADER_MAGIC = b"IP2X"
+OLDEST_FORMAT_VERSION = 2
+FORMAT_VERSION = 5
+SHARED_STRINGS = 1
+PACKED_STRINGS = 2
+SPLIT_FAMILIES = 4
+DELTA_COORDS = 8
+GEO_ACCURACY = 16
+GEO_CELLS = 32
+PROXY_CONFIDENCE = 64
+INDEXED = 128
+TRIE = 256
+GEO_PAYLOAD = 512
+PROXY_LAST_SEEN = 1024
+LARGEST_FIRST = 1 << 11
+IPV4_ONLY = 1 << 13
+ASN_ORGANIZATION = 1 << 14
+KNOWN_FLAGS = (
+    SHARED_STRINGS
+    | PACKED_STRINGS
+    | SPLIT_FAMILIES
+    | DELTA_COORDS
+    | GEO_ACCURACY
+    | GEO_CELLS
+    | PROXY_CONFIDENCE
+    | INDEXED
+    | TRIE
+    | GEO_PAYLOAD
+    | PROXY_LAST_SEEN
+    | LARGEST_FIRST
+    | IPV4_ONLY
+    | ASN_ORGANIZATION
+)
+REORDERED = SPLIT_FAMILIES | LARGEST_FIRST
+HAS_ACCURACY = 1
+UNLOCATED = 2
+CELL_SYSTEMS = ["s2", "h3"]
+USAGE_TYPES = ["COM", "ORG", "GOV", "MIL", "EDU", "LIB", "CDN", "ISP", "MOB", "DCH", "SES", "RSV"]
+CONTINENTS = ["AF", "AN", "AS", "EU", "NA", "OC", "SA"]
+IN_EUROPEAN_UNION = 0x80
+REPRESENTED_COUNTRY = 0x40
+REGISTERED_COUNTRY = 0x20
+HAS_SUBDIVISION = 0x10
+CONTINENT_MASK = 0x07
+IPV4_BASE = 0xFFFF << 32
+
+
+def read_header(f) -> Tuple[int, int, Optional[Tuple[str, int]]]:
+    start = f.tell()
+    if f.read(4) != HEADER_MAGIC:
+        f.seek(start)
+        return 0, 0, None
+    version = f.read(1)[0]
+    if not OLDEST_FORMAT_VERSION <= version <= FORMAT_VERSION:
+        raise ValueError(f"Unsupported bin format version {version}")
+    flags = struct.unpack("<I", f.read(4))[0]
+    if flags & ~KNOWN_FLAGS:
+        raise ValueError(f"Unsupported bin flags {flags & ~KNOWN_FLAGS:#x}")
+    geo_precision = f.read(1)[0] if flags & DELTA_COORDS else 0
+    grid = None
+    if flags & GEO_CELLS:
+        system, level = f.read(2)
+        grid = (CELL_SYSTEMS[system], level)
+    return flags, geo_precision, grid
+
+
+def expand_cell(grid: Tuple[str, int], compact: int) -> int:
+    system, level = grid
+    if system == "s2":
+        return ((compact << 1) | 1) << (2 * (30 - level))
+    unused = 3 * (15 - level)
+    return (compact << unused) | ((1 << unused) - 1)
+
+
+def cell_token(grid: Tuple[str, int], cell: int) -> str:
+    if grid[0] == "s2":
+        return format(cell, "016x").rstrip("0")
+    return format(cell, "x")
+
+
+def section_bases(flags: int) -> List[int]:
+    if flags & IPV4_ONLY:
+        return [IPV4_BASE]
+    return [IPV4_BASE, 0] if flags & SPLIT_FAMILIES else [0]
+
+
+def read_range(f, flags: int, prev: int) -> Tuple[int, int]:
+    if flags & IPV4_ONLY:
+        start, end = struct.unpack("<II", f.read(8))
+        return IPV4_BASE + start, IPV4_BASE + end
+    start = prev + read_varint(f)
+    return start, start + read_varint(f)
+
+
+def sort_ranges(ranges: list):
+    ranges.sort(key=lambda r: (r[0], r[1] - r[0]))
+
+
+def read_entries(f, count: int, packed: bool) -> List[str]:
+    strings = []
+    prev = b""
+    for _ in range(count):
+        if packed:
+            shared = read_varint(f)
+            prev = prev[:shared] + f.read(read_varint(f))
+        else:
+            prev = f.read(struct.unpack("<H", f.read(2))[0])
+        strings.append(prev.decode("utf-8"))
+    return strings
+
+
+def read_strings(path: str) -> List[str]:
+    with open(path, "rb") as f:
+        flags, _, _ = read_header(f)
+        packed = bool(flags & PACKED_STRINGS)
+        count = struct.unpack("<I", f.read(4))[0]
+        return read_entries(f, count, packed)
+
+
 def read_varint(f) -> int:
     result = shift = 0
     while True:
@@ -60,19 +209,36 @@ def binary_search(ranges: list, target: int) -> Optional[int]:
 class DatabaseLoader:
     def __init__(self):
         self.geo_ranges: List[Tuple] = []
+        self.geo_grid: Optional[Tuple[str, int]] = None
         self.proxy_types: Dict[str, List[Tuple]] = {}
         self.asn_strings: List[str] = []
         self.asn_ranges: List[Tuple] = []
         self.isp_strings: List[str] = []
         self.isp_ranges: List[Tuple] = []
         self.isp_use_u16: bool = True
+        self.mobile_strings: List[str] = []
+        self.mobile_ranges: List[Tuple] = []
+        self.usage_ranges: List[Tuple] = []
+        self.elevation_ranges: List[Tuple] = []
+        self.country_ranges: List[Tuple] = []
+        self.city_strings: List[str] = []
+        self.cities: List[Tuple] = []
+        self.city_locales: List[str] = []
+        self.country_names: Dict[int, List[int]] = {}
+        self.city_names: Dict[Tuple[int, int], List[int]] = {}
+        self.city_subdivisions: Dict[Tuple[int, int], int] = {}
 
     def load_all(self):
         start = time.time()
         self._load_geo()
+        self._load_cities()
         self._load_proxy_types()
         self._load_asn()
         self._load_isp()
+        self._load_mobile()
+        self._load_usage_type()
+        self._load_elevation()
+        self._load_country()
         elapsed = time.time() - start
         print(f"Databases loaded in {elapsed:.3f}s")
 
@@ -80,87 +246,257 @@ class DatabaseLoader:
         if not Path("geo.bin").exists():
             return
 
-        with open("geo.bin", "rb") as f:
-            count = struct.unpack("<I", f.read(4))[0]
-            current = 0
+        with open_bin("geo.bin") as f:
+            flags, geo_precision, self.geo_grid = read_header(f)
+            payload = flags & GEO_PAYLOAD
+            delta = flags & DELTA_COORDS
+            scale = 10.0 ** geo_precision if delta else 1000.0
+            for current in section_bases(flags):
+                count = struct.unpack("<I", f.read(4))[0]
+                lat = lon = 0
+
+                cell = 0
+
+                for _ in range(count):
+                    current, end = read_range(f, flags, current)
+                    descriptor = f.read(1)[0] if payload else 0
+                    if self.geo_grid:
+                        cell += read_signed_varint(f)
+                    elif delta or payload:
+                        lat += read_signed_varint(f)
+                        lon += read_signed_varint(f)
+                    else:
+                        lat, lon = struct.unpack("<ii", f.read(8))
+                    accuracy = None
+                    if payload:
+                        for bit in range(8):
+                            if descriptor >> bit & 1:
+                                value = read_varint(f)
+                                if 1 << bit == HAS_ACCURACY:
+                                    accuracy = value
+                    elif flags & GEO_ACCURACY and f.read(1)[0] & HAS_ACCURACY:
+                        accuracy = read_varint(f)
+                    if descriptor & UNLOCATED:
+                        point = (None, None)
+                    elif self.geo_grid:
+                        point = (expand_cell(self.geo_grid, cell), None)
+                    else:
+                        point = (lat / scale, lon / scale)
+                    self.geo_ranges.append((current, end, *point, accuracy))
+
+            if flags & REORDERED:
+                sort_ranges(self.geo_ranges)
+
+    def _load_cities(self):
+        if not Path("cities.bin").exists():
+            return
 
-            for _ in range(count):
-                current += read_varint(f)
-                size = read_varint(f)
-                lat = struct.unpack("<i", f.read(4))[0] / 1000.0
-                lon = struct.unpack("<i", f.read(4))[0] / 1000.0
-                self.geo_ranges.append((current, current + size, lat, lon))
+        with open("cities.bin", "rb") as f:
+            str_count = struct.unpack("<I", f.read(4))[0]
+            self.city_strings = read_entries(f, str_count, True)
+            city_count = struct.unpack("<I", f.read(4))[0]
+            for _ in range(city_count):
+                point = struct.unpack("<fff", f.read(12))
+                self.cities.append(
+                    (point, read_varint(f), read_varint(f), read_varint(f))
+                )
+
+            locale_count = f.read(4)
+            if len(locale_count) < 4:
+                return
+            self.city_locales = read_entries(f, struct.unpack("<I", locale_count)[0], True)
+            for _ in range(struct.unpack("<I", f.read(4))[0]):
+                country = read_varint(f)
+                self.country_names[country] = [read_varint(f) for _ in self.city_locales]
+            for _ in range(struct.unpack("<I", f.read(4))[0]):
+                key = (read_varint(f), read_varint(f))
+                self.city_names[key] = [read_varint(f) for _ in self.city_locales]
+
+            subdivision_count = f.read(4)
+            if len(subdivision_count) < 4:
+                return
+            for _ in range(struct.unpack("<I", subdivision_count)[0]):
+                key = (read_varint(f), read_varint(f))
+                self.city_subdivisions[key] = read_varint(f)
 
     def _load_proxy_types(self):
         if not Path("proxy_types.bin").exists():
             return
 
-        with open("proxy_types.bin", "rb") as f:
+        with open_bin("proxy_types.bin") as f:
+            flags, _, _ = read_header(f)
             type_count = struct.unpack("<H", f.read(2))[0]
 
             for _ in range(type_count):
                 name_len = struct.unpack("<B", f.read(1))[0]
                 proxy_type = f.read(name_len).decode("utf-8")
-                range_count = struct.unpack("<I", f.read(4))[0]
 
                 ranges = []
-                current = 0
-                for _ in range(range_count):
-                    current += read_varint(f)
-                    size = read_varint(f)
-                    ranges.append((current, current + size))
-
+                for current in section_bases(flags):
+                    range_count = struct.unpack("<I", f.read(4))[0]
+                    for _ in range(range_count):
+                        current, end = read_range(f, flags, current)
+                        confidence = 1
+                        if flags & PROXY_CONFIDENCE:
+                            confidence = f.read(1)[0]
+                        last_seen = read_varint(f) - 1 if flags & PROXY_LAST_SEEN else -1
+                        ranges.append((current, end, confidence, last_seen))
+
+                if flags & REORDERED:
+                    ranges.sort()
                 self.proxy_types[proxy_type] = ranges
 
     def _load_asn(self):
         if not Path("asn.bin").exists():
             return
 
-        with open("asn.bin", "rb") as f:
-            str_count = struct.unpack("<I", f.read(4))[0]
-            for _ in range(str_count):
-                str_len = struct.unpack("<H", f.read(2))[0]
-                self.asn_strings.append(f.read(str_len).decode("utf-8"))
-
-            range_count = struct.unpack("<I", f.read(4))[0]
-            current = cidr = asn = name = 0
-
-            for _ in range(range_count):
-                current += read_varint(f)
-                size = read_varint(f)
-                cidr += read_signed_varint(f)
-                asn += read_signed_varint(f)
-                name += read_signed_varint(f)
-                self.asn_ranges.append(
-                    (current, current + size, cidr, asn, name)
+        with open_bin("asn.bin") as f:
+            flags, _, _ = read_header(f)
+            if flags & SHARED_STRINGS:
+                self.asn_strings = read_strings("strings.bin")
+            else:
+                str_count = struct.unpack("<I", f.read(4))[0]
+                self.asn_strings = read_entries(
+                    f, str_count, bool(flags & PACKED_STRINGS)
                 )
 
+            for current in section_bases(flags):
+                range_count = struct.unpack("<I", f.read(4))[0]
+                cidr = asn = name = 0
+
+                for _ in range(range_count):
+                    current, end = read_range(f, flags, current)
+                    cidr += read_signed_varint(f)
+                    asn += read_signed_varint(f)
+                    name += read_signed_varint(f)
+                    org = read_varint(f) - 1 if flags & ASN_ORGANIZATION else -1
+                    self.asn_ranges.append(
+                        (current, end, cidr, asn, name, org)
+                    )
+
+            if flags & REORDERED:
+                sort_ranges(self.asn_ranges)
+
     def _load_isp(self):
         if not Path("isp.bin").exists():
             return
 
-        with open("isp.bin", "rb") as f:
+        with open_bin("isp.bin") as f:
+            flags, _, _ = read_header(f)
+            if flags & SHARED_STRINGS:
+                self.isp_strings = ["-"] + read_strings("strings.bin")
+            else:
+                str_count = struct.unpack("<I", f.read(4))[0]
+                entries = read_entries(f, str_count, bool(flags & PACKED_STRINGS))
+                self.isp_strings = ["-"] + entries[1:]
+
+            self.isp_use_u16 = len(self.isp_strings) - 1 < 65536
+            fmt = "<HHH" if self.isp_use_u16 else "<III"
+            for current in section_bases(flags):
+                range_count = struct.unpack("<I", f.read(4))[0]
+
+                for _ in range(range_count):
+                    current, end = read_range(f, flags, current)
+                    isp_idx, domain_idx, provider_idx = struct.unpack(
+                        fmt, f.read(6 if self.isp_use_u16 else 12)
+                    )
+                    self.isp_ranges.append(
+                        (current, end, isp_idx, domain_idx, provider_idx)
+                    )
+
+            if flags & REORDERED:
+                sort_ranges(self.isp_ranges)
+
+    def _load_mobile(self):
+        if not Path("mobile.bin").exists():
+            return
+
+        with open_bin("mobile.bin") as f:
+            flags, _, _ = read_header(f)
             str_count = struct.unpack("<I", f.read(4))[0]
-            for _ in range(str_count):
-                str_len = struct.unpack("<H", f.read(2))[0]
-                self.isp_strings.append(
-                    "-" if str_len == 0 else f.read(str_len).decode("utf-8")
-                )
+            entries = read_entries(f, str_count, bool(flags & PACKED_STRINGS))
+            self.mobile_strings = ["-"] + entries[1:]
 
-            self.isp_use_u16 = str_count < 65536
-            range_count = struct.unpack("<I", f.read(4))[0]
-            current = 0
+            use_u16 = len(self.mobile_strings) - 1 < 65536
+            fmt = "<HHH" if use_u16 else "<III"
+            for current in section_bases(flags):
+                range_count = struct.unpack("<I", f.read(4))[0]
 
-            for _ in range(range_count):
-                current += read_varint(f)
-                size = read_varint(f)
-                fmt = "<HHH" if self.isp_use_u16 else "<III"
-                isp_idx, domain_idx, provider_idx = struct.unpack(
-                    fmt, f.read(6 if self.isp_use_u16 else 12)
-                )
-                self.isp_ranges.append(
-                    (current, current + size, isp_idx, domain_idx, provider_idx)
-                )
+                for _ in range(range_count):
+                    current, end = read_range(f, flags, current)
+                    mcc, mnc, brand = struct.unpack(fmt, f.read(6 if use_u16 else 12))
+                    self.mobile_ranges.append((current, end, mcc, mnc, brand))
+
+            if flags & REORDERED:
+                sort_ranges(self.mobile_ranges)
+
+    def _load_usage_type(self):
+        if not Path("usage_type.bin").exists():
+            return
+
+        with open_bin("usage_type.bin") as f:
+            flags, _, _ = read_header(f)
+            for current in section_bases(flags):
+                count = struct.unpack("<I", f.read(4))[0]
+
+                for _ in range(count):
+                    current, end = read_range(f, flags, current)
+                    mask = read_varint(f)
+                    self.usage_ranges.append((current, end, mask))
+
+            if flags & REORDERED:
+                sort_ranges(self.usage_ranges)
+
+    def _load_elevation(self):
+        if not Path("elevation.bin").exists():
+            return
+
+        with open_bin("elevation.bin") as f:
+            flags, _, _ = read_header(f)
+            for current in section_bases(flags):
+                count = struct.unpack("<I", f.read(4))[0]
+
+                for _ in range(count):
+                    current, end = read_range(f, flags, current)
+                    meters = read_signed_varint(f)
+                    self.elevation_ranges.append((current, end, meters))
+
+            if flags & REORDERED:
+                sort_ranges(self.elevation_ranges)
+
+    def _load_country(self):
+        if not Path("country.bin").exists():
+            return
+
+        with open_bin("country.bin") as f:
+            flags, _, _ = read_header(f)
+            for current in section_bases(flags):
+                count = struct.unpack("<I", f.read(4))[0]
+
+                for _ in range(count):
+                    current, end = read_range(f, flags, current)
+                    country = f.read(2).decode("ascii")
+                    packed = f.read(1)[0]
+                    continent = packed & CONTINENT_MASK
+                    alternate = None
+                    if packed & (REGISTERED_COUNTRY | REPRESENTED_COUNTRY):
+                        alternate = f.read(2).decode("ascii")
+                    subdivision = None
+                    if packed & HAS_SUBDIVISION:
+                        subdivision = f.read(f.read(1)[0]).decode("ascii")
+                    self.country_ranges.append((
+                        current,
+                        end,
+                        country,
+                        CONTINENTS[continent - 1] if continent else "-",
+                        bool(packed & IN_EUROPEAN_UNION),
+                        alternate if packed & REGISTERED_COUNTRY else None,
+                        alternate if packed & REPRESENTED_COUNTRY else None,
+                        f"{country}-{subdivision}" if subdivision else None,
+                    ))
+
+            if flags & REORDERED:
+                sort_ranges(self.country_ranges)
 
     def lookup_geo(self, ip: str) -> Dict[str, Any]:
         if not self.geo_ranges:
@@ -169,20 +505,69 @@ class DatabaseLoader:
         target = ip_to_int(ip)
         idx = binary_search(self.geo_ranges, target)
         if idx is not None:
-            return {
-                "latitude": self.geo_ranges[idx][2],
-                "longitude": self.geo_ranges[idx][3],
-            }
+            r = self.geo_ranges[idx]
+            if r[2] is None:
+                return {}
+            if self.geo_grid:
+                result = {f"{self.geo_grid[0]}_cell": cell_token(self.geo_grid, r[2])}
+            else:
+                result = {"latitude": r[2], "longitude": r[3]}
+            if r[4] is not None:
+                result["accuracy_radius"] = r[4]
+            return result
         return {}
 
+    def reverse_lookup(self, lat: float, lon: float, locale: Optional[str] = None) -> Dict[str, Any]:
+        if not self.cities:
+            return {}
+
+        lat, lon = math.radians(lat), math.radians(lon)
+        target = (
+            math.cos(lat) * math.cos(lon),
+            math.cos(lat) * math.sin(lon),
+            math.sin(lat),
+        )
+        point, city, region, country = min(
+            self.cities,
+            key=lambda c: sum((a - b) ** 2 for a, b in zip(c[0], target)),
+        )
+        chord = math.dist(point, target)
+        result = {
+            "city": self.city_strings[city],
+            "region": self.city_strings[region],
+            "country_code": self.city_strings[country],
+            "distance_km": 2 * 6371.0088 * math.asin(min(chord / 2, 1.0)),
+        }
+        subdivision = self.city_subdivisions.get((region, country))
+        if subdivision is not None:
+            result["subdivision_code"] = f"{self.city_strings[country]}-{self.city_strings[subdivision]}"
+        if locale in self.city_locales:
+            i = self.city_locales.index(locale)
+            city_name = self.city_names.get((city, country), [0] * len(self.city_locales))[i]
+            country_name = self.country_names.get(country, [0] * len(self.city_locales))[i]
+            if city_name:
+                result["city"] = self.city_strings[city_name]
+            if country_name:
+                result["country_name"] = self.city_strings[country_name]
+        return result
+
     def lookup_proxy_type(self, ip: str) -> Dict[str, Any]:
         if not self.proxy_types:
             return {}
 
         target = ip_to_int(ip)
+        matches = []
         for proxy_type, ranges in self.proxy_types.items():
-            if binary_search(ranges, target) is not None:
-                return {"proxy_type": proxy_type}
+            idx = binary_search(ranges, target)
+            if idx is not None:
+                start, end, confidence, last_seen = ranges[idx]
+                matches.append((end - start, -confidence, proxy_type, last_seen))
+        if matches:
+            _, confidence, proxy_type, last_seen = min(matches)
+            result = {"proxy_type": proxy_type, "confidence": -confidence}
+            if last_seen >= 0:
+                result["last_seen"] = last_seen
+            return result
         return {}
 
     def lookup_asn(self, ip: str) -> Dict[str, Any]:
@@ -197,6 +582,7 @@ class DatabaseLoader:
                 "cidr": self.asn_strings[r[2]],
                 "asn": self.asn_strings[r[3]],
                 "as_name": self.asn_strings[r[4]],
+                "organization": self.asn_strings[r[5]] if r[5] >= 0 else None,
             }
         return {}
 
@@ -215,22 +601,86 @@ class DatabaseLoader:
             }
         return {}
 
+    def lookup_mobile(self, ip: str) -> Dict[str, Any]:
+        if not self.mobile_ranges:
+            return {}
+
+        target = ip_to_int(ip)
+        idx = binary_search(self.mobile_ranges, target)
+        if idx is not None:
+            r = self.mobile_ranges[idx]
+            return {
+                "mcc": self.mobile_strings[r[2]],
+                "mnc": self.mobile_strings[r[3]],
+                "mobile_brand": self.mobile_strings[r[4]],
+            }
+        return {}
+
+    def lookup_usage_type(self, ip: str) -> Dict[str, Any]:
+        if not self.usage_ranges:
+            return {}
+
+        target = ip_to_int(ip)
+        idx = binary_search(self.usage_ranges, target)
+        if idx is not None:
+            mask = self.usage_ranges[idx][2]
+            names = [name for bit, name in enumerate(USAGE_TYPES) if mask >> bit & 1]
+            return {"usage_type": "/".join(names)}
+        return {}
+
+    def lookup_elevation(self, ip: str) -> Dict[str, Any]:
+        if not self.elevation_ranges:
+            return {}
+
+        target = ip_to_int(ip)
+        idx = binary_search(self.elevation_ranges, target)
+        if idx is not None:
+            return {"elevation": self.elevation_ranges[idx][2]}
+        return {}
+
+    def lookup_country(self, ip: str) -> Dict[str, Any]:
+        if not self.country_ranges:
+            return {}
+
+        target = ip_to_int(ip)
+        idx = binary_search(self.country_ranges, target)
+        if idx is not None:
+            _, _, country, continent, eu, registered, represented, subdivision = self.country_ranges[idx]
+            result = {
+                "country_code": country,
+                "continent_code": continent,
+                "is_in_european_union": eu,
+            }
+            if registered:
+                result["registered_country_code"] = registered
+            if represented:
+                result["represented_country_code"] = represented
+            if subdivision:
+                result["subdivision_code"] = subdivision
+            return result
+        return {}
+
     def lookup_all(self, ip: str) -> Dict[str, Any]:
         return {
             "ip": ip,
             **self.lookup_geo(ip),
+            **self.lookup_country(ip),
             **self.lookup_asn(ip),
             **self.lookup_proxy_type(ip),
             **self.lookup_isp(ip),
+            **self.lookup_mobile(ip),
+            **self.lookup_usage_type(ip),
+            **self.lookup_elevation(ip),
         }
 
 
-db = DatabaseLoader()
-db.load_all()
+if __name__ == "__main__":
+    db = DatabaseLoader()
+    db.load_all()
 
-start = time.time()
-result = db.lookup_all("8.8.8.8")
-elapsed = time.time() - start
+    start = time.time()
+    result = db.lookup_all("8.8.8.8")
+    elapsed = time.time() - start
 
-print(json.dumps(result, indent=2))
-print(f"Lookup completed in {elapsed*1000:.3f}ms")
+    print(json.dumps(result, indent=2))
+    print(f"Lookup completed in {elapsed*1000:.3f}ms")
@@ -0,0 +1,86 @@
+use std::net::IpAddr;
+
+use ip2x::cli::Args;
+use ip2x::reader::{self, Ip2xReader, Record};
+use napi::bindgen_prelude::*;
+use napi::{Env, JsUnknown};
+use napi_derive::napi;
+
+#[napi(object)]
+pub struct ReaderOptions {
+    pub mode: Option<String>,
+    pub public_key: Option<String>,
+    pub mmap: Option<bool>,
+    pub block_cache: Option<u32>,
+}
+
+#[napi]
+pub struct Reader(Ip2xReader);
+
+fn parse_ip(ip: &str) -> Result<IpAddr> {
+    ip.parse()
+        .map_err(|_| Error::new(Status::InvalidArg, format!("Bad IP address: {}", ip)))
+}
+
+fn parse_ips(ips: &[String]) -> Result<Vec<IpAddr>> {
+    ips.iter().map(|ip| parse_ip(ip)).collect()
+}
+
+fn json(records: &impl serde::Serialize) -> Result<Buffer> {
+    let bytes = serde_json::to_vec(records).map_err(|err| Error::from_reason(err.to_string()))?;
+    Ok(bytes.into())
+}
+
+#[napi]
+impl Reader {
+    #[napi(constructor)]
+    pub fn new(bins: Option<String>, options: Option<ReaderOptions>) -> Result<Reader> {
+        let mut raw = vec![
+            "--bins".to_string(),
+            bins.unwrap_or_else(|| ".".to_string()),
+        ];
+        if let Some(options) = options {
+            if let Some(mode) = options.mode {
+                raw.extend(["--mode".to_string(), mode]);
+            }
+            if let Some(public_key) = options.public_key {
+                raw.extend(["--public-key".to_string(), public_key]);
+            }
+            if options.mmap == Some(true) {
+                raw.push("--mmap".to_string());
+            }
+            if let Some(blocks) = options.block_cache {
+                raw.extend(["--block-cache".to_string(), blocks.to_string()]);
+            }
+        }
+        let reader = reader::open_from_args(&Args::parse(&raw, &["--mmap"]))
+            .map_err(|err| Error::from_reason(err.to_string()))?;
+        Ok(Reader(reader))
+    }
+
+    #[napi(ts_return_type = "Record")]
+    pub fn lookup(&self, env: Env, ip: String) -> Result<JsUnknown> {
+        let records: Vec<Record> = self.0.lookup_many(&[parse_ip(&ip)?]);
+        env.to_js_value(&records[0])
+    }
+
+    #[napi(ts_return_type = "Record[]")]
+    pub fn lookup_many(&self, env: Env, ips: Vec<String>) -> Result<JsUnknown> {
+        env.to_js_value(&self.0.lookup_many(&parse_ips(&ips)?))
+    }
+
+    #[napi]
+    pub fn lookup_json(&self, ip: String) -> Result<Buffer> {
+        json(&self.0.lookup_many(&[parse_ip(&ip)?])[0])
+    }
+
+    #[napi]
+    pub fn lookup_many_json(&self, ips: Vec<String>) -> Result<Buffer> {
+        json(&self.0.lookup_many(&parse_ips(&ips)?))
+    }
+
+    #[napi]
+    pub fn is_proxy(&self, ip: String, proxy_type: Option<String>) -> Result<bool> {
+        Ok(self.0.is_proxy(parse_ip(&ip)?, proxy_type.as_deref()))
+    }
+}
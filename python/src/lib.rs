@@ -0,0 +1,167 @@
+use std::net::IpAddr;
+use std::sync::Mutex;
+
+use ip2x::cli::Args;
+use ip2x::reader::{self, Ip2xReader};
+use pyo3::exceptions::{PyOSError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+use serde_json::Value;
+
+static BUILD: Mutex<()> = Mutex::new(());
+
+fn options(options: Option<&Bound<'_, PyDict>>) -> PyResult<Vec<String>> {
+    let mut raw = Vec::new();
+    for (key, value) in options.into_iter().flatten() {
+        let flag = format!("--{}", key.extract::<String>()?.replace('_', "-"));
+        if let Ok(switch) = value.extract::<bool>() {
+            if switch {
+                raw.push(flag);
+            }
+            continue;
+        }
+        raw.push(flag);
+        raw.push(value.str()?.to_string());
+    }
+    Ok(raw)
+}
+
+fn shortest(value: f64) -> f64 {
+    match (value as f32) as f64 == value {
+        true => (value as f32).to_string().parse().unwrap_or(value),
+        false => value,
+    }
+}
+
+fn to_python<'py>(py: Python<'py>, value: Value) -> PyResult<Bound<'py, PyAny>> {
+    Ok(match value {
+        Value::Null => py.None().into_bound(py),
+        Value::Bool(b) => b.into_pyobject(py)?.to_owned().into_any(),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => i.into_pyobject(py)?.into_any(),
+            None => shortest(n.as_f64().unwrap_or(f64::NAN))
+                .into_pyobject(py)?
+                .into_any(),
+        },
+        Value::String(s) => s.into_pyobject(py)?.into_any(),
+        Value::Array(items) => {
+            let items = items
+                .into_iter()
+                .map(|item| to_python(py, item))
+                .collect::<PyResult<Vec<_>>>()?;
+            PyList::new(py, items)?.into_any()
+        }
+        Value::Object(fields) => {
+            let dict = PyDict::new(py);
+            for (key, value) in fields {
+                dict.set_item(key, to_python(py, value)?)?;
+            }
+            dict.into_any()
+        }
+    })
+}
+
+fn dict<'py>(py: Python<'py>, value: impl serde::Serialize) -> PyResult<Bound<'py, PyAny>> {
+    let value =
+        serde_json::to_value(value).map_err(|err| PyValueError::new_err(err.to_string()))?;
+    to_python(py, value)
+}
+
+fn parse_ip(ip: &str) -> PyResult<IpAddr> {
+    ip.parse()
+        .map_err(|_| PyValueError::new_err(format!("Bad IP address: {}", ip)))
+}
+
+#[pyfunction]
+#[pyo3(signature = (data_dir, out_dir, **kwargs))]
+fn build(
+    py: Python<'_>,
+    data_dir: &str,
+    out_dir: &str,
+    kwargs: Option<&Bound<'_, PyDict>>,
+) -> PyResult<()> {
+    let mut raw = vec![
+        data_dir.to_string(),
+        "--out-dir".to_string(),
+        out_dir.to_string(),
+    ];
+    raw.extend(options(kwargs)?);
+    let args = Args::parse(&raw, ip2x::BUILD_SWITCHES);
+    py.detach(|| {
+        let _guard = BUILD.lock().unwrap_or_else(|err| err.into_inner());
+        ip2x::build(&args)
+    })
+    .map_err(PyOSError::new_err)
+}
+
+#[pyclass(frozen)]
+struct Reader(Ip2xReader);
+
+#[pymethods]
+impl Reader {
+    #[new]
+    #[pyo3(signature = (bins = ".", **kwargs))]
+    fn new(bins: &str, kwargs: Option<&Bound<'_, PyDict>>) -> PyResult<Reader> {
+        let mut raw = vec!["--bins".to_string(), bins.to_string()];
+        raw.extend(options(kwargs)?);
+        let reader = reader::open_from_args(&Args::parse(&raw, &["--mmap"]))?;
+        Ok(Reader(reader))
+    }
+
+    fn lookup<'py>(&self, py: Python<'py>, ip: &str) -> PyResult<Bound<'py, PyAny>> {
+        let records = self.0.lookup_many(&[parse_ip(ip)?]);
+        dict(py, &records[0])
+    }
+
+    fn lookup_many<'py>(&self, py: Python<'py>, ips: Vec<String>) -> PyResult<Bound<'py, PyAny>> {
+        let ips = ips
+            .iter()
+            .map(|ip| parse_ip(ip))
+            .collect::<PyResult<Vec<_>>>()?;
+        dict(py, py.detach(|| self.0.lookup_many(&ips)))
+    }
+
+    fn lookup_geo<'py>(&self, py: Python<'py>, ip: &str) -> PyResult<Bound<'py, PyAny>> {
+        dict(py, self.0.lookup_geo(parse_ip(ip)?))
+    }
+
+    fn lookup_country<'py>(&self, py: Python<'py>, ip: &str) -> PyResult<Bound<'py, PyAny>> {
+        dict(py, self.0.lookup_country(parse_ip(ip)?))
+    }
+
+    fn lookup_asn<'py>(&self, py: Python<'py>, ip: &str) -> PyResult<Bound<'py, PyAny>> {
+        dict(py, self.0.lookup_asn(parse_ip(ip)?))
+    }
+
+    fn lookup_proxy<'py>(&self, py: Python<'py>, ip: &str) -> PyResult<Bound<'py, PyAny>> {
+        dict(py, self.0.lookup_proxy(parse_ip(ip)?))
+    }
+
+    fn lookup_isp<'py>(&self, py: Python<'py>, ip: &str) -> PyResult<Bound<'py, PyAny>> {
+        dict(py, self.0.lookup_isp(parse_ip(ip)?))
+    }
+
+    fn lookup_mobile<'py>(&self, py: Python<'py>, ip: &str) -> PyResult<Bound<'py, PyAny>> {
+        dict(py, self.0.lookup_mobile(parse_ip(ip)?))
+    }
+
+    fn lookup_usage_type<'py>(&self, py: Python<'py>, ip: &str) -> PyResult<Bound<'py, PyAny>> {
+        dict(py, self.0.lookup_usage_type(parse_ip(ip)?))
+    }
+
+    fn lookup_elevation(&self, ip: &str) -> PyResult<Option<i16>> {
+        Ok(self.0.lookup_elevation(parse_ip(ip)?))
+    }
+
+    #[pyo3(signature = (ip, proxy_type = None))]
+    fn is_proxy(&self, ip: &str, proxy_type: Option<&str>) -> PyResult<bool> {
+        Ok(self.0.is_proxy(parse_ip(ip)?, proxy_type))
+    }
+}
+
+#[pymodule]
+fn ip2x_native(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(build, m)?)?;
+    m.add_class::<Reader>()?;
+    Ok(())
+}
@@ -0,0 +1,114 @@
+use std::io::Result;
+use std::net::IpAddr;
+use std::panic;
+use std::sync::Arc;
+
+use tokio::task;
+
+use crate::reader::{
+    AsnRecord, CountryRecord, GeoRecord, Ip2xReader, IspRecord, MobileRecord, ProxyRecord, Record,
+    UsageRecord,
+};
+
+#[derive(Clone)]
+pub struct AsyncIp2xReader {
+    reader: Arc<Ip2xReader>,
+    blocking: bool,
+}
+
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    task::spawn_blocking(f)
+        .await
+        .unwrap_or_else(|err| panic::resume_unwind(err.into_panic()))
+}
+
+impl From<Ip2xReader> for AsyncIp2xReader {
+    fn from(reader: Ip2xReader) -> AsyncIp2xReader {
+        AsyncIp2xReader {
+            blocking: reader.decodes_on_demand(),
+            reader: Arc::new(reader),
+        }
+    }
+}
+
+impl AsyncIp2xReader {
+    pub async fn open(dir: &str) -> Result<AsyncIp2xReader> {
+        let dir = dir.to_string();
+        blocking(move || Ip2xReader::open(&dir).map(AsyncIp2xReader::from)).await
+    }
+
+    pub async fn open_verified(dir: &str, public_key: &str) -> Result<AsyncIp2xReader> {
+        let (dir, public_key) = (dir.to_string(), public_key.to_string());
+        blocking(move || Ip2xReader::open_verified(&dir, &public_key).map(AsyncIp2xReader::from))
+            .await
+    }
+
+    pub async fn open_mapped(dir: &str, cache_blocks: usize) -> Result<AsyncIp2xReader> {
+        let dir = dir.to_string();
+        blocking(move || Ip2xReader::open_mapped(&dir, cache_blocks).map(AsyncIp2xReader::from))
+            .await
+    }
+
+    pub fn get_ref(&self) -> &Ip2xReader {
+        &self.reader
+    }
+
+    async fn run<T: Send + 'static>(
+        &self,
+        lookup: impl FnOnce(&Ip2xReader) -> T + Send + 'static,
+    ) -> T {
+        if !self.blocking {
+            return lookup(&self.reader);
+        }
+        let reader = self.reader.clone();
+        blocking(move || lookup(&reader)).await
+    }
+
+    pub async fn lookup(&self, ip: IpAddr) -> Record {
+        self.run(move |reader| reader.lookup_many(&[ip]).remove(0))
+            .await
+    }
+
+    pub async fn lookup_many(&self, ips: &[IpAddr]) -> Vec<Record> {
+        let ips = ips.to_vec();
+        self.run(move |reader| reader.lookup_many(&ips)).await
+    }
+
+    pub async fn lookup_geo(&self, ip: IpAddr) -> Option<GeoRecord> {
+        self.run(move |reader| reader.lookup_geo(ip)).await
+    }
+
+    pub async fn lookup_country(&self, ip: IpAddr) -> Option<CountryRecord> {
+        self.run(move |reader| reader.lookup_country(ip)).await
+    }
+
+    pub async fn lookup_asn(&self, ip: IpAddr) -> Option<AsnRecord> {
+        self.run(move |reader| reader.lookup_asn(ip)).await
+    }
+
+    pub async fn lookup_proxy(&self, ip: IpAddr) -> Option<ProxyRecord> {
+        self.run(move |reader| reader.lookup_proxy(ip)).await
+    }
+
+    pub async fn lookup_isp(&self, ip: IpAddr) -> Option<IspRecord> {
+        self.run(move |reader| reader.lookup_isp(ip)).await
+    }
+
+    pub async fn lookup_mobile(&self, ip: IpAddr) -> Option<MobileRecord> {
+        self.run(move |reader| reader.lookup_mobile(ip)).await
+    }
+
+    pub async fn lookup_usage_type(&self, ip: IpAddr) -> Option<UsageRecord> {
+        self.run(move |reader| reader.lookup_usage_type(ip)).await
+    }
+
+    pub async fn lookup_elevation(&self, ip: IpAddr) -> Option<i16> {
+        self.run(move |reader| reader.lookup_elevation(ip)).await
+    }
+
+    pub async fn is_proxy(&self, ip: IpAddr, proxy_type: Option<&str>) -> bool {
+        let proxy_type = proxy_type.map(str::to_string);
+        self.run(move |reader| reader.is_proxy(ip, proxy_type.as_deref()))
+            .await
+    }
+}
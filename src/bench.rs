@@ -0,0 +1,118 @@
+use std::io::{Error, ErrorKind, Result};
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
+
+use crate::cli::Args;
+use crate::ip::{ipv4_to_ipv6, u128_to_ip};
+use crate::reader::{open_from_args, Ip2xReader, Record};
+use crate::selftest::Rng;
+
+const DEFAULT_LOOKUPS: usize = 1_000_000;
+
+fn number(args: &Args, name: &str, default: usize) -> Result<usize> {
+    match args.value(&[name]) {
+        Some(value) => value.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("{} must be a positive number", name),
+            )
+        }),
+        None => Ok(default),
+    }
+}
+
+fn lookup_all(reader: &Ip2xReader, ip: u128) -> usize {
+    let ip = u128_to_ip(ip);
+    [
+        reader.lookup_geo(ip).is_some(),
+        reader.lookup_country(ip).is_some(),
+        reader.lookup_asn(ip).is_some(),
+        reader.lookup_proxy(ip).is_some(),
+        reader.lookup_isp(ip).is_some(),
+        reader.lookup_mobile(ip).is_some(),
+        reader.lookup_usage_type(ip).is_some(),
+        reader.lookup_elevation(ip).is_some(),
+    ]
+    .into_iter()
+    .filter(|&hit| hit)
+    .count()
+}
+
+fn hits(record: &Record) -> usize {
+    [
+        record.geo.is_some(),
+        record.country.is_some(),
+        record.asn.is_some(),
+        record.proxy.is_some(),
+        record.isp.is_some(),
+        record.mobile.is_some(),
+        record.usage_type.is_some(),
+        record.elevation.is_some(),
+    ]
+    .into_iter()
+    .filter(|&hit| hit)
+    .count()
+}
+
+pub fn run(args: &Args) -> Result<()> {
+    let threads = number(
+        args,
+        "--threads",
+        thread::available_parallelism().map_or(1, |n| n.get()),
+    )?;
+    let lookups = number(args, "--lookups", DEFAULT_LOOKUPS)?;
+    let seed = number(args, "--seed", 1)? as u64;
+    let batch = number(args, "--batch", 1)?;
+    let proxy_only = args.flag("--proxy-only");
+
+    let started = Instant::now();
+    let reader = Arc::new(open_from_args(args)?);
+    println!("Opened bins in {:.3}s", started.elapsed().as_secs_f64());
+
+    let started = Instant::now();
+    let workers: Vec<_> = (0..threads)
+        .map(|worker| {
+            let reader = Arc::clone(&reader);
+            let count = lookups / threads + usize::from(worker < lookups % threads);
+            thread::spawn(move || {
+                let mut rng = Rng((seed + worker as u64) | 1);
+                let mut ip = move || ipv4_to_ipv6(rng.next() as u32);
+                if proxy_only {
+                    return (0..count)
+                        .filter(|_| reader.is_proxy(u128_to_ip(ip()), None))
+                        .count();
+                }
+                if batch == 1 {
+                    return (0..count).map(|_| lookup_all(&reader, ip())).sum::<usize>();
+                }
+                let mut found = 0;
+                for start in (0..count).step_by(batch) {
+                    let ips: Vec<_> = (start..count.min(start + batch))
+                        .map(|_| u128_to_ip(ip()))
+                        .collect();
+                    found += reader.lookup_many(&ips).iter().map(hits).sum::<usize>();
+                }
+                found
+            })
+        })
+        .collect();
+    let mut hits = 0;
+    for worker in workers {
+        hits += worker
+            .join()
+            .map_err(|_| Error::other("Benchmark worker panicked"))?;
+    }
+
+    let elapsed = started.elapsed().as_secs_f64();
+    println!(
+        "{} lookups on {} threads in batches of {} in {:.3}s: {:.0} lookups/s, {} records found",
+        lookups,
+        threads,
+        batch,
+        elapsed,
+        lookups as f64 / elapsed,
+        hits
+    );
+    Ok(())
+}
@@ -0,0 +1,121 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Error, ErrorKind, Result, Write};
+
+use crate::format::ProxyRange;
+use crate::ip::range_to_cidrs;
+
+pub const BLOOM_FILE: &str = "proxy_types.bloom";
+pub const DEFAULT_FPR: f64 = 0.01;
+const MAGIC: &[u8; 4] = b"IP2F";
+const HEADER_LEN: usize = 20;
+const IPV4_PREFIX: u8 = 96 + 24;
+const IPV6_PREFIX: u8 = 48;
+const MAX_HASHES: u32 = 16;
+
+pub struct ProxyFilter {
+    hashes: u32,
+    levels: u32,
+    bits: Vec<u8>,
+}
+
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+fn mask(ip: u128, level: u8) -> u128 {
+    ip & !(u128::MAX >> level)
+}
+
+fn probes(network: u128, level: u8, hashes: u32, len: u64) -> impl Iterator<Item = u64> {
+    let h1 = mix(mix(network as u64 ^ level as u64) ^ (network >> 64) as u64);
+    let h2 = mix(h1) | 1;
+    (0..hashes as u64).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % len)
+}
+
+fn keys<'a>(ranges: impl Iterator<Item = &'a ProxyRange>) -> Vec<(u128, u8)> {
+    let mut keys = Vec::new();
+    for &(from, to, ..) in ranges {
+        for (network, prefix) in range_to_cidrs(from, to) {
+            let finest = match network >> 32 == 0xffff && prefix >= 96 {
+                true => IPV4_PREFIX,
+                false => IPV6_PREFIX,
+            };
+            let level = finest.min(prefix.div_ceil(8) * 8);
+            let count = 1u128 << (level - level.min(prefix));
+            let step = 1u128.checked_shl(128 - level as u32).unwrap_or(0);
+            for i in 0..count {
+                keys.push((mask(network, level) + i * step, level));
+            }
+        }
+    }
+    keys.sort_unstable();
+    keys.dedup();
+    keys
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+impl ProxyFilter {
+    pub fn build(types: &[(String, Vec<ProxyRange>)], fpr: f64) -> ProxyFilter {
+        let keys = keys(types.iter().flat_map(|(_, ranges)| ranges));
+        let ln2 = std::f64::consts::LN_2;
+        let bits = (-(keys.len().max(1) as f64) * fpr.ln() / (ln2 * ln2)).ceil() as u64;
+        let bits = bits.div_ceil(8).max(1) * 8;
+        let hashes = ((bits as f64 / keys.len().max(1) as f64) * ln2).round() as u32;
+        let mut filter = ProxyFilter {
+            hashes: hashes.clamp(1, MAX_HASHES),
+            levels: 0,
+            bits: vec![0; bits as usize / 8],
+        };
+        for &(network, level) in &keys {
+            filter.levels |= 1 << (level / 8);
+            for bit in probes(network, level, filter.hashes, bits) {
+                filter.bits[bit as usize / 8] |= 1 << (bit % 8);
+            }
+        }
+        filter
+    }
+
+    pub fn contains(&self, ip: u128) -> bool {
+        let len = self.bits.len() as u64 * 8;
+        (0..16u8)
+            .filter(|level| self.levels & 1 << level != 0)
+            .any(|level| {
+                probes(mask(ip, level * 8), level * 8, self.hashes, len)
+                    .all(|bit| self.bits[bit as usize / 8] & 1 << (bit % 8) != 0)
+            })
+    }
+
+    pub fn write(&self, path: &str) -> Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(MAGIC)?;
+        out.write_all(&self.hashes.to_le_bytes())?;
+        out.write_all(&self.levels.to_le_bytes())?;
+        out.write_all(&(self.bits.len() as u64 * 8).to_le_bytes())?;
+        out.write_all(&self.bits)?;
+        out.flush()
+    }
+
+    pub fn read(path: &str) -> Result<ProxyFilter> {
+        let bytes = fs::read(path)?;
+        if bytes.len() < HEADER_LEN || !bytes.starts_with(MAGIC) {
+            return Err(invalid("Not a proxy bloom filter"));
+        }
+        let hashes = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+        let levels = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+        let len = u64::from_le_bytes(bytes[12..20].try_into().unwrap());
+        let bits = bytes[HEADER_LEN..].to_vec();
+        if len == 0 || len != bits.len() as u64 * 8 || !(1..=MAX_HASHES).contains(&hashes) {
+            return Err(invalid("Bad proxy bloom filter header"));
+        }
+        Ok(ProxyFilter {
+            hashes,
+            levels,
+            bits,
+        })
+    }
+}
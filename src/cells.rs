@@ -0,0 +1,248 @@
+use std::io::{Error, ErrorKind, Result};
+
+const S2_MAX_LEVEL: u8 = 30;
+const H3_MAX_RESOLUTION: u8 = 15;
+const S2_FINEST_LEVEL: u8 = 20;
+const H3_FINEST_RESOLUTION: u8 = 12;
+
+const S2_IJ_TO_POS: [[u64; 4]; 4] = [[0, 1, 3, 2], [0, 3, 1, 2], [2, 3, 1, 0], [2, 1, 3, 0]];
+const S2_POS_TO_IJ: [[u64; 4]; 4] = [[0, 1, 3, 2], [0, 2, 3, 1], [3, 2, 0, 1], [3, 1, 0, 2]];
+const S2_POS_TO_ORIENTATION: [usize; 4] = [1, 0, 0, 3];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellSystem {
+    S2,
+    H3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Grid {
+    pub system: CellSystem,
+    pub level: u8,
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidInput, message)
+}
+
+impl Grid {
+    pub fn parse(spec: &str) -> Result<Grid> {
+        let (system, level) = spec.split_once(':').ok_or_else(|| {
+            invalid(format!(
+                "Expected s2:<level> or h3:<resolution>, got {}",
+                spec
+            ))
+        })?;
+        let system = match system {
+            "s2" => CellSystem::S2,
+            "h3" => CellSystem::H3,
+            other => return Err(invalid(format!("Unknown cell system: {}", other))),
+        };
+        let level = level
+            .parse()
+            .map_err(|_| invalid(format!("Bad cell level: {}", level)))?;
+        Grid::new(system, level)
+    }
+
+    pub fn new(system: CellSystem, level: u8) -> Result<Grid> {
+        let max = match system {
+            CellSystem::S2 => S2_FINEST_LEVEL,
+            CellSystem::H3 => H3_FINEST_RESOLUTION,
+        };
+        if level > max {
+            return Err(invalid(format!(
+                "{} level must be between 0 and {}",
+                system.name(),
+                max
+            )));
+        }
+        Ok(Grid { system, level })
+    }
+
+    pub fn from_bytes(system: u8, level: u8) -> Result<Grid> {
+        let system = match system {
+            0 => CellSystem::S2,
+            1 => CellSystem::H3,
+            _ => return Err(Error::new(ErrorKind::InvalidData, "Bad cell system")),
+        };
+        Grid::new(system, level).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+
+    pub fn to_bytes(self) -> [u8; 2] {
+        let system = match self.system {
+            CellSystem::S2 => 0,
+            CellSystem::H3 => 1,
+        };
+        [system, self.level]
+    }
+
+    pub fn cell(self, lat: f32, lon: f32) -> Result<u64> {
+        match self.system {
+            CellSystem::S2 => Ok(s2_cell(lat as f64, lon as f64, self.level)),
+            CellSystem::H3 => h3_cell(lat as f64, lon as f64, self.level),
+        }
+    }
+
+    pub fn center(self, cell: u64) -> Result<(f32, f32)> {
+        let (lat, lon) = match self.system {
+            CellSystem::S2 => s2_center(cell),
+            CellSystem::H3 => h3_center(cell)?,
+        };
+        Ok((lat as f32, lon as f32))
+    }
+
+    pub fn compact(self, cell: u64) -> u64 {
+        cell >> self.unused_bits()
+    }
+
+    pub fn expand(self, compact: u64) -> u64 {
+        let unused = self.unused_bits();
+        match self.system {
+            CellSystem::S2 => ((compact << 1) | 1) << (unused - 1),
+            CellSystem::H3 => (compact << unused) | ((1 << unused) - 1),
+        }
+    }
+
+    fn unused_bits(self) -> u32 {
+        match self.system {
+            CellSystem::S2 => 2 * (S2_MAX_LEVEL - self.level) as u32 + 1,
+            CellSystem::H3 => 3 * (H3_MAX_RESOLUTION - self.level) as u32,
+        }
+    }
+}
+
+impl CellSystem {
+    pub fn name(self) -> &'static str {
+        match self {
+            CellSystem::S2 => "s2",
+            CellSystem::H3 => "h3",
+        }
+    }
+}
+
+fn s2_st(uv: f64) -> f64 {
+    if uv >= 0.0 {
+        0.5 * (1.0 + 3.0 * uv).sqrt()
+    } else {
+        1.0 - 0.5 * (1.0 - 3.0 * uv).sqrt()
+    }
+}
+
+fn s2_uv(st: f64) -> f64 {
+    if st >= 0.5 {
+        (4.0 * st * st - 1.0) / 3.0
+    } else {
+        (1.0 - 4.0 * (1.0 - st) * (1.0 - st)) / 3.0
+    }
+}
+
+fn s2_cell(lat: f64, lon: f64, level: u8) -> u64 {
+    let (lat, lon) = (lat.to_radians(), lon.to_radians());
+    let (x, y, z) = (lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin());
+
+    let face = if x.abs() >= y.abs() && x.abs() >= z.abs() {
+        if x > 0.0 {
+            0
+        } else {
+            3
+        }
+    } else if y.abs() >= z.abs() {
+        if y > 0.0 {
+            1
+        } else {
+            4
+        }
+    } else if z > 0.0 {
+        2
+    } else {
+        5
+    };
+    let (u, v) = match face {
+        0 => (y / x, z / x),
+        1 => (-x / y, z / y),
+        2 => (-x / z, -y / z),
+        3 => (z / x, y / x),
+        4 => (z / y, -x / y),
+        _ => (-y / z, -x / z),
+    };
+
+    let max = (1u64 << S2_MAX_LEVEL) - 1;
+    let scale = (1u64 << S2_MAX_LEVEL) as f64;
+    let i = ((s2_st(u) * scale).floor().max(0.0) as u64).min(max);
+    let j = ((s2_st(v) * scale).floor().max(0.0) as u64).min(max);
+
+    let mut orientation = face as usize & 1;
+    let mut pos = 0u64;
+    for k in (0..S2_MAX_LEVEL).rev() {
+        let ij = (((i >> k) & 1) << 1 | ((j >> k) & 1)) as usize;
+        let bits = S2_IJ_TO_POS[orientation][ij];
+        pos = (pos << 2) | bits;
+        orientation ^= S2_POS_TO_ORIENTATION[bits as usize];
+    }
+
+    let leaf = ((face as u64) << 61) | (pos << 1) | 1;
+    let lsb = 1u64 << (2 * (S2_MAX_LEVEL - level));
+    (leaf & lsb.wrapping_neg()) | lsb
+}
+
+fn s2_center(cell: u64) -> (f64, f64) {
+    let face = cell >> 61;
+    let mut orientation = face as usize & 1;
+    let (mut i, mut j) = (0u64, 0u64);
+    for k in (0..S2_MAX_LEVEL).rev() {
+        let bits = (cell >> (2 * k + 1)) & 3;
+        let ij = S2_POS_TO_IJ[orientation][bits as usize];
+        i |= (ij >> 1) << k;
+        j |= (ij & 1) << k;
+        orientation ^= S2_POS_TO_ORIENTATION[bits as usize];
+    }
+
+    let size = 1u64 << (cell.trailing_zeros() / 2);
+    let scale = (1u64 << S2_MAX_LEVEL) as f64;
+    let s = ((i & !(size - 1)) as f64 + size as f64 / 2.0) / scale;
+    let t = ((j & !(size - 1)) as f64 + size as f64 / 2.0) / scale;
+    let (u, v) = (s2_uv(s), s2_uv(t));
+
+    let (x, y, z) = match face {
+        0 => (1.0, u, v),
+        1 => (-u, 1.0, v),
+        2 => (-u, -v, 1.0),
+        3 => (-1.0, -v, -u),
+        4 => (v, -1.0, -u),
+        _ => (v, u, -1.0),
+    };
+    let lat = z.atan2((x * x + y * y).sqrt());
+    (lat.to_degrees(), y.atan2(x).to_degrees())
+}
+
+#[cfg(feature = "h3")]
+fn h3_cell(lat: f64, lon: f64, resolution: u8) -> Result<u64> {
+    let resolution = h3o::Resolution::try_from(resolution).map_err(Error::other)?;
+    let point = h3o::LatLng::new(lat, lon).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    Ok(point.to_cell(resolution).into())
+}
+
+#[cfg(feature = "h3")]
+fn h3_center(cell: u64) -> Result<(f64, f64)> {
+    let cell = h3o::CellIndex::try_from(cell).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    let center = h3o::LatLng::from(cell);
+    Ok((center.lat(), center.lng()))
+}
+
+#[cfg(not(feature = "h3"))]
+fn h3_cell(_lat: f64, _lon: f64, _resolution: u8) -> Result<u64> {
+    Err(h3_unsupported())
+}
+
+#[cfg(not(feature = "h3"))]
+fn h3_center(_cell: u64) -> Result<(f64, f64)> {
+    Err(h3_unsupported())
+}
+
+#[cfg(not(feature = "h3"))]
+fn h3_unsupported() -> Error {
+    Error::new(
+        ErrorKind::Unsupported,
+        "H3 cells require building with --features h3",
+    )
+}
@@ -0,0 +1,260 @@
+pub const CENTROID_ACCURACY_KM: u16 = 1000;
+
+const CENTROIDS: &[(&[u8; 2], f32, f32)] = &[
+    (b"AD", 42.546, 1.602),
+    (b"AE", 23.424, 53.848),
+    (b"AF", 33.939, 67.710),
+    (b"AG", 17.061, -61.796),
+    (b"AI", 18.221, -63.069),
+    (b"AL", 41.153, 20.168),
+    (b"AM", 40.069, 45.038),
+    (b"AO", -11.203, 17.874),
+    (b"AQ", -75.251, -0.071),
+    (b"AR", -38.416, -63.617),
+    (b"AS", -14.271, -170.132),
+    (b"AT", 47.516, 14.550),
+    (b"AU", -25.274, 133.775),
+    (b"AW", 12.521, -69.968),
+    (b"AX", 60.179, 19.916),
+    (b"AZ", 40.143, 47.577),
+    (b"BA", 43.916, 17.679),
+    (b"BB", 13.194, -59.543),
+    (b"BD", 23.685, 90.356),
+    (b"BE", 50.504, 4.470),
+    (b"BF", 12.238, -1.562),
+    (b"BG", 42.734, 25.486),
+    (b"BH", 25.930, 50.638),
+    (b"BI", -3.373, 29.919),
+    (b"BJ", 9.308, 2.316),
+    (b"BL", 17.900, -62.830),
+    (b"BM", 32.321, -64.757),
+    (b"BN", 4.535, 114.728),
+    (b"BO", -16.290, -63.589),
+    (b"BQ", 12.180, -68.250),
+    (b"BR", -14.235, -51.925),
+    (b"BS", 25.034, -77.396),
+    (b"BT", 27.514, 90.434),
+    (b"BV", -54.423, 3.413),
+    (b"BW", -22.328, 24.685),
+    (b"BY", 53.710, 27.953),
+    (b"BZ", 17.190, -88.498),
+    (b"CA", 56.130, -106.347),
+    (b"CC", -12.164, 96.871),
+    (b"CD", -4.038, 21.759),
+    (b"CF", 6.611, 20.939),
+    (b"CG", -0.228, 15.828),
+    (b"CH", 46.818, 8.228),
+    (b"CI", 7.540, -5.547),
+    (b"CK", -21.237, -159.778),
+    (b"CL", -35.675, -71.543),
+    (b"CM", 7.370, 12.355),
+    (b"CN", 35.862, 104.195),
+    (b"CO", 4.571, -74.297),
+    (b"CR", 9.749, -83.753),
+    (b"CU", 21.522, -77.781),
+    (b"CV", 16.002, -24.013),
+    (b"CW", 12.170, -68.990),
+    (b"CX", -10.448, 105.690),
+    (b"CY", 35.126, 33.430),
+    (b"CZ", 49.817, 15.473),
+    (b"DE", 51.166, 10.452),
+    (b"DJ", 11.825, 42.590),
+    (b"DK", 56.264, 9.502),
+    (b"DM", 15.415, -61.371),
+    (b"DO", 18.736, -70.163),
+    (b"DZ", 28.034, 1.660),
+    (b"EC", -1.831, -78.183),
+    (b"EE", 58.595, 25.014),
+    (b"EG", 26.821, 30.802),
+    (b"EH", 24.216, -12.886),
+    (b"ER", 15.179, 39.782),
+    (b"ES", 40.464, -3.749),
+    (b"ET", 9.145, 40.490),
+    (b"FI", 61.924, 25.748),
+    (b"FJ", -16.578, 179.414),
+    (b"FK", -51.796, -59.524),
+    (b"FM", 7.426, 150.551),
+    (b"FO", 61.893, -6.912),
+    (b"FR", 46.228, 2.214),
+    (b"GA", -0.804, 11.609),
+    (b"GB", 55.378, -3.436),
+    (b"GD", 12.263, -61.604),
+    (b"GE", 42.315, 43.357),
+    (b"GF", 3.934, -53.126),
+    (b"GG", 49.466, -2.585),
+    (b"GH", 7.947, -1.023),
+    (b"GI", 36.138, -5.345),
+    (b"GL", 71.707, -42.604),
+    (b"GM", 13.443, -15.310),
+    (b"GN", 9.946, -9.697),
+    (b"GP", 16.996, -62.068),
+    (b"GQ", 1.651, 10.268),
+    (b"GR", 39.074, 21.824),
+    (b"GS", -54.430, -36.588),
+    (b"GT", 15.783, -90.231),
+    (b"GU", 13.444, 144.794),
+    (b"GW", 11.804, -15.180),
+    (b"GY", 4.860, -58.930),
+    (b"HK", 22.396, 114.109),
+    (b"HM", -53.082, 73.504),
+    (b"HN", 15.200, -86.242),
+    (b"HR", 45.100, 15.200),
+    (b"HT", 18.971, -72.285),
+    (b"HU", 47.162, 19.503),
+    (b"ID", -0.789, 113.921),
+    (b"IE", 53.413, -8.244),
+    (b"IL", 31.046, 34.852),
+    (b"IM", 54.236, -4.548),
+    (b"IN", 20.594, 78.963),
+    (b"IO", -6.343, 71.877),
+    (b"IQ", 33.223, 43.679),
+    (b"IR", 32.428, 53.688),
+    (b"IS", 64.963, -19.021),
+    (b"IT", 41.872, 12.567),
+    (b"JE", 49.214, -2.131),
+    (b"JM", 18.110, -77.298),
+    (b"JO", 30.585, 36.238),
+    (b"JP", 36.205, 138.253),
+    (b"KE", -0.024, 37.906),
+    (b"KG", 41.204, 74.766),
+    (b"KH", 12.566, 104.991),
+    (b"KI", -3.370, -168.734),
+    (b"KM", -11.875, 43.872),
+    (b"KN", 17.358, -62.783),
+    (b"KP", 40.340, 127.510),
+    (b"KR", 35.908, 127.767),
+    (b"KW", 29.312, 47.482),
+    (b"KY", 19.513, -80.567),
+    (b"KZ", 48.020, 66.924),
+    (b"LA", 19.856, 102.495),
+    (b"LB", 33.855, 35.862),
+    (b"LC", 13.909, -60.979),
+    (b"LI", 47.166, 9.555),
+    (b"LK", 7.873, 80.772),
+    (b"LR", 6.428, -9.429),
+    (b"LS", -29.610, 28.234),
+    (b"LT", 55.169, 23.881),
+    (b"LU", 49.815, 6.130),
+    (b"LV", 56.880, 24.603),
+    (b"LY", 26.335, 17.228),
+    (b"MA", 31.792, -7.093),
+    (b"MC", 43.750, 7.413),
+    (b"MD", 47.412, 28.370),
+    (b"ME", 42.709, 19.374),
+    (b"MF", 18.080, -63.050),
+    (b"MG", -18.767, 46.869),
+    (b"MH", 7.131, 171.184),
+    (b"MK", 41.609, 21.745),
+    (b"ML", 17.571, -3.996),
+    (b"MM", 21.914, 95.956),
+    (b"MN", 46.862, 103.847),
+    (b"MO", 22.199, 113.544),
+    (b"MP", 17.331, 145.385),
+    (b"MQ", 14.642, -61.024),
+    (b"MR", 21.008, -10.941),
+    (b"MS", 16.742, -62.187),
+    (b"MT", 35.937, 14.375),
+    (b"MU", -20.348, 57.552),
+    (b"MV", 3.203, 73.221),
+    (b"MW", -13.254, 34.302),
+    (b"MX", 23.635, -102.553),
+    (b"MY", 4.210, 101.976),
+    (b"MZ", -18.666, 35.530),
+    (b"NA", -22.958, 18.490),
+    (b"NC", -20.904, 165.618),
+    (b"NE", 17.608, 8.082),
+    (b"NF", -29.041, 167.955),
+    (b"NG", 9.082, 8.675),
+    (b"NI", 12.865, -85.207),
+    (b"NL", 52.133, 5.291),
+    (b"NO", 60.472, 8.469),
+    (b"NP", 28.395, 84.124),
+    (b"NR", -0.523, 166.932),
+    (b"NU", -19.054, -169.867),
+    (b"NZ", -40.901, 174.886),
+    (b"OM", 21.513, 55.923),
+    (b"PA", 8.538, -80.782),
+    (b"PE", -9.190, -75.015),
+    (b"PF", -17.680, -149.407),
+    (b"PG", -6.315, 143.956),
+    (b"PH", 12.880, 121.774),
+    (b"PK", 30.375, 69.345),
+    (b"PL", 51.919, 19.145),
+    (b"PM", 46.942, -56.271),
+    (b"PN", -24.704, -127.439),
+    (b"PR", 18.221, -66.590),
+    (b"PS", 31.952, 35.233),
+    (b"PT", 39.400, -8.224),
+    (b"PW", 7.515, 134.583),
+    (b"PY", -23.443, -58.444),
+    (b"QA", 25.355, 51.184),
+    (b"RE", -21.115, 55.536),
+    (b"RO", 45.943, 24.967),
+    (b"RS", 44.017, 21.006),
+    (b"RU", 61.524, 105.319),
+    (b"RW", -1.940, 29.874),
+    (b"SA", 23.886, 45.079),
+    (b"SB", -9.646, 160.156),
+    (b"SC", -4.680, 55.492),
+    (b"SD", 12.863, 30.218),
+    (b"SE", 60.128, 18.644),
+    (b"SG", 1.352, 103.820),
+    (b"SH", -24.143, -10.031),
+    (b"SI", 46.151, 14.995),
+    (b"SJ", 77.554, 23.670),
+    (b"SK", 48.669, 19.699),
+    (b"SL", 8.461, -11.780),
+    (b"SM", 43.942, 12.458),
+    (b"SN", 14.497, -14.452),
+    (b"SO", 5.152, 46.200),
+    (b"SR", 3.919, -56.028),
+    (b"SS", 6.877, 31.307),
+    (b"ST", 0.186, 6.613),
+    (b"SV", 13.794, -88.897),
+    (b"SX", 18.040, -63.070),
+    (b"SY", 34.802, 38.997),
+    (b"SZ", -26.523, 31.466),
+    (b"TC", 21.694, -71.798),
+    (b"TD", 15.454, 18.732),
+    (b"TF", -49.280, 69.349),
+    (b"TG", 8.620, 0.825),
+    (b"TH", 15.870, 100.993),
+    (b"TJ", 38.861, 71.276),
+    (b"TK", -8.967, -171.856),
+    (b"TL", -8.874, 125.728),
+    (b"TM", 38.970, 59.556),
+    (b"TN", 33.887, 9.537),
+    (b"TO", -21.179, -175.198),
+    (b"TR", 38.964, 35.243),
+    (b"TT", 10.692, -61.223),
+    (b"TV", -7.110, 177.649),
+    (b"TW", 23.698, 120.961),
+    (b"TZ", -6.369, 34.889),
+    (b"UA", 48.379, 31.166),
+    (b"UG", 1.373, 32.290),
+    (b"UM", 19.280, 166.650),
+    (b"US", 37.090, -95.713),
+    (b"UY", -32.523, -55.766),
+    (b"UZ", 41.377, 64.585),
+    (b"VA", 41.903, 12.453),
+    (b"VC", 12.984, -61.287),
+    (b"VE", 6.424, -66.590),
+    (b"VG", 18.421, -64.640),
+    (b"VI", 18.336, -64.896),
+    (b"VN", 14.058, 108.277),
+    (b"VU", -15.377, 166.959),
+    (b"WF", -13.769, -177.156),
+    (b"WS", -13.759, -172.105),
+    (b"XK", 42.603, 20.903),
+    (b"YE", 15.553, 48.516),
+    (b"YT", -12.828, 45.166),
+    (b"ZA", -30.559, 22.938),
+    (b"ZM", -13.134, 27.849),
+    (b"ZW", -19.015, 29.155),
+];
+
+pub fn centroid(country: &[u8]) -> Option<(f32, f32)> {
+    let code: &[u8; 2] = country.try_into().ok()?;
+    let i = CENTROIDS.binary_search_by_key(&code, |c| c.0).ok()?;
+    Some((CENTROIDS[i].1, CENTROIDS[i].2))
+}
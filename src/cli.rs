@@ -0,0 +1,72 @@
+use std::io::{Error, ErrorKind, Result};
+
+fn is_option(arg: &str) -> bool {
+    arg.starts_with('-') && arg.len() > 1 && arg.parse::<f64>().is_err()
+}
+
+#[derive(Clone)]
+pub struct Args {
+    positional: Vec<String>,
+    options: Vec<(String, Option<String>)>,
+}
+
+impl Args {
+    pub fn parse(raw: &[String], switches: &[&str]) -> Args {
+        let mut positional = Vec::new();
+        let mut options = Vec::new();
+        let mut iter = raw.iter().peekable();
+
+        while let Some(arg) = iter.next() {
+            if let Some((name, value)) = arg.split_once('=').filter(|_| arg.starts_with("--")) {
+                options.push((name.to_string(), Some(value.to_string())));
+            } else if switches.contains(&arg.as_str()) {
+                options.push((arg.clone(), None));
+            } else if is_option(arg) {
+                let value = iter.next_if(|next| !is_option(next)).cloned();
+                options.push((arg.clone(), value));
+            } else {
+                positional.push(arg.clone());
+            }
+        }
+
+        Args {
+            positional,
+            options,
+        }
+    }
+
+    pub fn positional(&self) -> &[String] {
+        &self.positional
+    }
+
+    pub fn flag(&self, name: &str) -> bool {
+        self.options.iter().any(|(option, _)| option == name)
+    }
+
+    pub fn value(&self, names: &[&str]) -> Option<&str> {
+        self.options
+            .iter()
+            .rev()
+            .find(|(name, _)| names.contains(&name.as_str()))
+            .and_then(|(_, value)| value.as_deref())
+    }
+
+    pub fn with_switches(&self, switches: &[&str]) -> Args {
+        let mut args = self.clone();
+        for switch in switches {
+            if !args.flag(switch) {
+                args.options.push((switch.to_string(), None));
+            }
+        }
+        args
+    }
+
+    pub fn require(&self, names: &[&str]) -> Result<&str> {
+        self.value(names).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("Missing required option {}", names.join("/")),
+            )
+        })
+    }
+}
@@ -0,0 +1,106 @@
+use std::io::{BufRead, Error, ErrorKind, Result};
+#[cfg(feature = "zstd")]
+use std::io::{Read, Write};
+
+#[cfg(feature = "zstd")]
+use crate::format::read_u32;
+use crate::format::{Dataset, Layout};
+
+pub const MAGIC: &[u8; 4] = b"IP2Z";
+pub const DEFAULT_BLOCK_SIZE: usize = 1 << 20;
+#[cfg(feature = "zstd")]
+const LEVEL: i32 = 19;
+
+#[cfg(feature = "zstd")]
+pub fn write_zstd(dataset: &Dataset, path: &str, layout: Layout, block_size: usize) -> Result<()> {
+    let mut raw = Vec::new();
+    dataset.write_to(&mut raw, layout)?;
+    let compressed = compress(&raw, header_len(dataset, layout)?, block_size)?;
+    std::fs::File::create(path)?.write_all(&compressed)
+}
+
+#[cfg(not(feature = "zstd"))]
+pub fn write_zstd(
+    _dataset: &Dataset,
+    _path: &str,
+    _layout: Layout,
+    _block_size: usize,
+) -> Result<()> {
+    Err(Error::new(
+        ErrorKind::Unsupported,
+        "zstd compression requires building with --features zstd",
+    ))
+}
+
+#[cfg(feature = "zstd")]
+fn header_len(dataset: &Dataset, layout: Layout) -> Result<usize> {
+    let empty = match dataset {
+        Dataset::Geo(_) => Dataset::Geo(Vec::new()),
+        Dataset::Proxy(_) => Dataset::Proxy(Vec::new()),
+        Dataset::Asn(strings, _) => Dataset::Asn(strings.clone(), Vec::new()),
+        Dataset::Isp(strings, _) => Dataset::Isp(strings.clone(), Vec::new()),
+        Dataset::Mobile(strings, _) => Dataset::Mobile(strings.clone(), Vec::new()),
+        Dataset::Usage(_) => Dataset::Usage(Vec::new()),
+        Dataset::Elevation(_) => Dataset::Elevation(Vec::new()),
+        Dataset::Country(_) => Dataset::Country(Vec::new()),
+    };
+    let mut header = Vec::new();
+    empty.write_to(&mut header, layout)?;
+    Ok(header.len())
+}
+
+#[cfg(feature = "zstd")]
+fn compress(raw: &[u8], header_len: usize, block_size: usize) -> Result<Vec<u8>> {
+    let (header, records) = raw.split_at(header_len);
+    let mut out = Vec::with_capacity(raw.len() / 4);
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&(header.len() as u32).to_le_bytes());
+    out.extend_from_slice(header);
+    out.extend_from_slice(&(block_size as u32).to_le_bytes());
+
+    let mut compressor = zstd::bulk::Compressor::new(LEVEL)?;
+    for block in records.chunks(block_size.max(1)) {
+        let compressed = compressor.compress(block)?;
+        out.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+        out.extend_from_slice(&compressed);
+    }
+    Ok(out)
+}
+
+#[cfg(feature = "zstd")]
+pub fn decompress(input: &mut impl BufRead) -> Result<Vec<u8>> {
+    let header_len = read_u32(input)? as usize;
+    let mut raw = Vec::new();
+    input
+        .by_ref()
+        .take(header_len as u64)
+        .read_to_end(&mut raw)?;
+    if raw.len() != header_len {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "truncated header"));
+    }
+
+    let block_size = read_u32(input)? as usize;
+    let mut decompressor = zstd::bulk::Decompressor::new()?;
+    let mut compressed = Vec::new();
+    while !input.fill_buf()?.is_empty() {
+        let len = read_u32(input)? as usize;
+        compressed.clear();
+        input
+            .by_ref()
+            .take(len as u64)
+            .read_to_end(&mut compressed)?;
+        if compressed.len() != len {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "truncated block"));
+        }
+        raw.extend_from_slice(&decompressor.decompress(&compressed, block_size)?);
+    }
+    Ok(raw)
+}
+
+#[cfg(not(feature = "zstd"))]
+pub fn decompress(_input: &mut impl BufRead) -> Result<Vec<u8>> {
+    Err(Error::new(
+        ErrorKind::Unsupported,
+        "zstd-compressed bins require building with --features zstd",
+    ))
+}
@@ -0,0 +1,97 @@
+use std::io::Result;
+
+use ip2x_core::usage::USAGE_TYPES;
+
+use crate::format::{Dataset, NetworkTypeRange};
+use crate::interval::IntervalMap;
+use crate::maxmind::{MaxMindReader, Value};
+use crate::network_type::{join_adjacent, read_labeled, write_labeled};
+
+pub const CONNECTION_TYPE_FILE: &str = "connection_type.bin";
+const MAGIC: &[u8; 4] = b"IP2C";
+pub const CONNECTION_TYPES: [&str; 5] =
+    ["Dialup", "Cable/DSL", "Corporate", "Cellular", "Satellite"];
+const DIALUP: u8 = 0;
+const CABLE_DSL: u8 = 1;
+const CORPORATE: u8 = 2;
+const CELLULAR: u8 = 3;
+
+const NET_SPEED_RULES: [(&str, u8); 4] = [
+    ("DIAL", DIALUP),
+    ("DSL", CABLE_DSL),
+    ("COMP", CORPORATE),
+    ("T1", CORPORATE),
+];
+const USAGE_RULES: [(&[&str], u8); 3] = [
+    (&["MOB"], CELLULAR),
+    (&["ISP"], CABLE_DSL),
+    (
+        &[
+            "COM", "ORG", "GOV", "MIL", "EDU", "LIB", "DCH", "CDN", "SES",
+        ],
+        CORPORATE,
+    ),
+];
+
+pub fn from_net_speed(speed: &str) -> Option<u8> {
+    NET_SPEED_RULES
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(speed.trim()))
+        .map(|&(_, connection_type)| connection_type)
+}
+
+pub fn from_usage(mask: u16) -> Option<u8> {
+    USAGE_RULES.iter().find_map(|&(codes, connection_type)| {
+        codes
+            .iter()
+            .filter_map(|code| USAGE_TYPES.iter().position(|t| t == code))
+            .any(|bit| mask & (1 << bit) != 0)
+            .then_some(connection_type)
+    })
+}
+
+pub fn load_maxmind(path: &str) -> Result<Vec<NetworkTypeRange>> {
+    let reader = MaxMindReader::open(path)?;
+    Ok(reader
+        .load_paths(&["connection_type"])
+        .into_iter()
+        .filter_map(|(from, to, values)| {
+            let name = values[0].as_ref().and_then(Value::as_str)?;
+            let connection_type = CONNECTION_TYPES.iter().position(|&t| t == name)?;
+            Some((from, to, connection_type as u8))
+        })
+        .collect())
+}
+
+pub fn derive(
+    maxmind: Vec<NetworkTypeRange>,
+    net_speed: Vec<NetworkTypeRange>,
+    usage: &Dataset,
+) -> Vec<NetworkTypeRange> {
+    let mut map = IntervalMap::new(
+        |r: &NetworkTypeRange| (r.0, r.1),
+        |r, from, to| (from, to, r.2),
+    );
+    map.extend(0, maxmind);
+    map.extend(2, net_speed);
+    if let Dataset::Usage(ranges) = usage {
+        for r in ranges {
+            if let Some(connection_type) = from_usage(r.2) {
+                let priority = match connection_type {
+                    CELLULAR => 1,
+                    _ => 3,
+                };
+                map.extend(priority, [(r.0, r.1, connection_type)]);
+            }
+        }
+    }
+    join_adjacent(map.flatten())
+}
+
+pub fn write(path: &str, ranges: &[NetworkTypeRange]) -> Result<()> {
+    write_labeled(path, MAGIC, &CONNECTION_TYPES, ranges)
+}
+
+pub fn read(path: &str) -> Result<(Vec<String>, Vec<NetworkTypeRange>)> {
+    read_labeled(path, MAGIC, "connection type")
+}
@@ -0,0 +1,100 @@
+use std::io::{BufRead, Result};
+use std::ops::Index;
+
+#[derive(Default)]
+pub struct Fields {
+    text: String,
+    ends: Vec<usize>,
+}
+
+impl Fields {
+    fn split(&mut self, line: &str) {
+        self.text.clear();
+        self.ends.clear();
+        let bytes = line.as_bytes();
+        let mut in_quotes = false;
+        let mut start = 0;
+        let mut i = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'"' => {
+                    self.text.push_str(&line[start..i]);
+                    if in_quotes && bytes.get(i + 1) == Some(&b'"') {
+                        self.text.push('"');
+                        i += 1;
+                    } else {
+                        in_quotes = !in_quotes;
+                    }
+                    start = i + 1;
+                }
+                b',' if !in_quotes => {
+                    self.text.push_str(&line[start..i]);
+                    self.ends.push(self.text.len());
+                    start = i + 1;
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+        self.text.push_str(&line[start..]);
+        self.ends.push(self.text.len());
+    }
+
+    pub fn len(&self) -> usize {
+        self.ends.len()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&str> {
+        let end = *self.ends.get(index)?;
+        let start = match index {
+            0 => 0,
+            _ => self.ends[index - 1],
+        };
+        Some(&self.text[start..end])
+    }
+}
+
+impl Index<usize> for Fields {
+    type Output = str;
+
+    fn index(&self, index: usize) -> &str {
+        self.get(index).expect("CSV field index out of range")
+    }
+}
+
+pub struct CsvReader<R> {
+    input: R,
+    line: String,
+    fields: Fields,
+}
+
+impl<R: BufRead> CsvReader<R> {
+    pub fn new(input: R) -> CsvReader<R> {
+        CsvReader {
+            input,
+            line: String::new(),
+            fields: Fields::default(),
+        }
+    }
+
+    pub fn next_record(&mut self) -> Option<Result<&Fields>> {
+        self.line.clear();
+        match self.input.read_line(&mut self.line) {
+            Ok(0) => return None,
+            Ok(_) => {}
+            Err(err) => return Some(Err(err)),
+        }
+        let line = match self.line.strip_suffix('\n') {
+            Some(line) => line.strip_suffix('\r').unwrap_or(line),
+            None => &self.line,
+        };
+        self.fields.split(line);
+        Some(Ok(&self.fields))
+    }
+}
+
+pub fn parse_line(line: &str) -> Vec<String> {
+    let mut fields = Fields::default();
+    fields.split(line);
+    (0..fields.len()).map(|i| fields[i].to_string()).collect()
+}
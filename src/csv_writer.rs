@@ -0,0 +1,133 @@
+use std::fs::File;
+use std::io::{BufWriter, Result, Write};
+
+use ip2x_core::usage;
+
+use crate::format::{sort_ranges, subdivision_code, Dataset, Kind};
+use crate::ip::format_cidr;
+
+pub fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "\"\""))
+}
+
+fn write_row(
+    out: &mut impl Write,
+    aligned: bool,
+    from: u128,
+    to: u128,
+    fields: &[&str],
+) -> Result<()> {
+    write!(out, "\"{}\",\"{}\"", from, to)?;
+    if aligned {
+        let prefix = 128 - (to - from).count_ones() as u8;
+        write!(out, ",\"{}\"", format_cidr(from, prefix))?;
+    }
+    for field in fields {
+        write!(out, ",{}", quote(field))?;
+    }
+    writeln!(out)
+}
+
+pub fn file_name(kind: Kind) -> String {
+    format!("{}.csv", kind.name())
+}
+
+pub fn write(path: &str, dataset: &Dataset, aligned: bool) -> Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+
+    match dataset {
+        Dataset::Geo(ranges) => {
+            for (from, to, lat, lon, accuracy) in ranges {
+                let accuracy = accuracy.map_or(String::new(), |radius| radius.to_string());
+                write_row(
+                    &mut out,
+                    aligned,
+                    *from,
+                    *to,
+                    &[&lat.to_string(), &lon.to_string(), &accuracy],
+                )?;
+            }
+        }
+        Dataset::Proxy(types) => {
+            let mut rows: Vec<(u128, u128, &str, u8)> = types
+                .iter()
+                .flat_map(|(name, ranges)| {
+                    ranges.iter().map(move |r| (r.0, r.1, name.as_str(), r.2))
+                })
+                .collect();
+            sort_ranges(&mut rows, |r| (r.0, r.1));
+            for (from, to, proxy_type, confidence) in rows {
+                write_row(
+                    &mut out,
+                    aligned,
+                    from,
+                    to,
+                    &[proxy_type, &confidence.to_string()],
+                )?;
+            }
+        }
+        Dataset::Asn(strings, data) => {
+            let string = |idx: usize| strings.get(idx).map_or("-", String::as_str);
+            for (from, to, cidr, asn, name, _) in data {
+                write_row(
+                    &mut out,
+                    aligned,
+                    *from,
+                    *to,
+                    &[string(*cidr), string(*asn), string(*name)],
+                )?;
+            }
+        }
+        Dataset::Isp(strings, data) | Dataset::Mobile(strings, data) => {
+            let string = |idx: usize| {
+                idx.checked_sub(1)
+                    .and_then(|i| strings.get(i))
+                    .map_or("-", String::as_str)
+            };
+            for (from, to, isp, domain, provider) in data {
+                write_row(
+                    &mut out,
+                    aligned,
+                    *from,
+                    *to,
+                    &[string(*isp), string(*domain), string(*provider)],
+                )?;
+            }
+        }
+        Dataset::Usage(ranges) => {
+            for (from, to, mask) in ranges {
+                write_row(&mut out, aligned, *from, *to, &[&usage::names(*mask)])?;
+            }
+        }
+        Dataset::Elevation(ranges) => {
+            for (from, to, meters) in ranges {
+                write_row(&mut out, aligned, *from, *to, &[&meters.to_string()])?;
+            }
+        }
+        Dataset::Country(ranges) => {
+            for range in ranges {
+                let (from, to, country, continent, eu, alternate, _) = range;
+                let (source, alternate) = match alternate {
+                    Some((source, code)) => (source.name(), String::from_utf8_lossy(code)),
+                    None => ("-", "-".into()),
+                };
+                write_row(
+                    &mut out,
+                    aligned,
+                    *from,
+                    *to,
+                    &[
+                        &String::from_utf8_lossy(country),
+                        &String::from_utf8_lossy(continent),
+                        if *eu { "1" } else { "0" },
+                        &alternate,
+                        source,
+                        &subdivision_code(range).unwrap_or_else(|| "-".to_string()),
+                    ],
+                )?;
+            }
+        }
+    }
+
+    out.flush()
+}
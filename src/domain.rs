@@ -0,0 +1,193 @@
+use std::collections::{HashMap, HashSet};
+use std::io::Result;
+use std::path::Path;
+use std::sync::LazyLock;
+
+use crate::format::{intern_with_offset, order_by_frequency, Dataset, DomainRange};
+use crate::interval::IntervalMap;
+use crate::logging;
+use crate::network_type::{join_adjacent, read_labeled, write_labeled};
+use crate::storage;
+
+pub const DOMAIN_FILE: &str = "domain.bin";
+pub const PUBLIC_SUFFIX_LIST: &str = "public_suffix_list.dat";
+const MAGIC: &[u8; 4] = b"IP2M";
+const BUILTIN_SUFFIXES: &str = "
+ac.uk co.uk gov.uk ltd.uk me.uk net.uk nhs.uk org.uk plc.uk sch.uk
+com.au edu.au gov.au id.au net.au org.au asn.au
+ac.nz co.nz geek.nz gen.nz govt.nz net.nz org.nz school.nz
+ac.jp ad.jp co.jp ed.jp go.jp gr.jp lg.jp ne.jp or.jp
+ac.kr co.kr go.kr ne.kr or.kr re.kr
+com.cn edu.cn gov.cn net.cn org.cn ac.cn
+com.hk edu.hk gov.hk net.hk org.hk idv.hk
+com.tw edu.tw gov.tw net.tw org.tw idv.tw
+com.sg edu.sg gov.sg net.sg org.sg
+com.my edu.my gov.my net.my org.my
+ac.in co.in edu.in gov.in net.in org.in firm.in gen.in ind.in
+ac.id co.id go.id net.id or.id web.id my.id
+ac.th co.th go.th in.th net.th or.th
+com.vn edu.vn gov.vn net.vn org.vn
+com.ph edu.ph gov.ph net.ph org.ph
+com.pk edu.pk gov.pk net.pk org.pk
+ac.il co.il gov.il net.il org.il muni.il
+com.tr edu.tr gov.tr net.tr org.tr
+com.sa edu.sa gov.sa net.sa org.sa
+ac.ae co.ae gov.ae net.ae org.ae
+com.eg edu.eg gov.eg net.eg org.eg
+ac.za co.za gov.za net.za org.za web.za
+co.ke or.ke ne.ke go.ke ac.ke
+com.ng edu.ng gov.ng net.ng org.ng
+com.br edu.br gov.br net.br org.br art.br
+com.ar edu.ar gob.ar net.ar org.ar
+com.mx edu.mx gob.mx net.mx org.mx
+com.co edu.co gov.co net.co org.co
+com.pe edu.pe gob.pe net.pe org.pe
+co.cr ac.cr go.cr or.cr
+com.ve co.ve net.ve org.ve
+com.ua net.ua org.ua gov.ua edu.ua in.ua
+com.pl net.pl org.pl edu.pl gov.pl
+co.at or.at ac.at gv.at
+com.es org.es nom.es gob.es edu.es
+com.pt org.pt edu.pt gov.pt
+com.gr edu.gr gov.gr net.gr org.gr
+com.ru net.ru org.ru pp.ru
+com.cy net.cy org.cy
+co.hu org.hu
+*.ck !www.ck
+*.bd *.er *.fk *.jm *.kh *.mm *.np *.pg
+";
+
+pub struct PublicSuffixes {
+    rules: HashSet<String>,
+    wildcards: HashSet<String>,
+    exceptions: HashSet<String>,
+}
+
+static BUILTIN: LazyLock<PublicSuffixes> =
+    LazyLock::new(|| PublicSuffixes::parse(BUILTIN_SUFFIXES));
+
+impl PublicSuffixes {
+    pub fn parse(list: &str) -> PublicSuffixes {
+        let mut suffixes = PublicSuffixes {
+            rules: HashSet::new(),
+            wildcards: HashSet::new(),
+            exceptions: HashSet::new(),
+        };
+        for line in list.lines() {
+            let line = line.split("//").next().unwrap_or("");
+            for rule in line.split_whitespace().map(str::to_lowercase) {
+                if let Some(rule) = rule.strip_prefix('!') {
+                    suffixes.exceptions.insert(rule.to_string());
+                } else if let Some(rule) = rule.strip_prefix("*.") {
+                    suffixes.wildcards.insert(rule.to_string());
+                } else {
+                    suffixes.rules.insert(rule);
+                }
+            }
+        }
+        suffixes
+    }
+
+    pub fn load(data_dir: &str) -> PublicSuffixes {
+        let path = Path::new(data_dir).join(PUBLIC_SUFFIX_LIST);
+        if !storage::exists(&path) {
+            return PublicSuffixes::parse(BUILTIN_SUFFIXES);
+        }
+        match storage::read_to_string(&path) {
+            Ok(list) => PublicSuffixes::parse(&list),
+            Err(err) => {
+                logging::warn(&format!("skipped: {}: {}", path.display(), err));
+                PublicSuffixes::parse(BUILTIN_SUFFIXES)
+            }
+        }
+    }
+
+    fn suffix_labels(&self, labels: &[&str]) -> usize {
+        for start in 0..labels.len() {
+            let candidate = labels[start..].join(".");
+            if self.exceptions.contains(&candidate) {
+                return labels.len() - start - 1;
+            }
+            let parent = labels[start + 1..].join(".");
+            if self.rules.contains(&candidate)
+                || start + 1 < labels.len() && self.wildcards.contains(&parent)
+            {
+                return labels.len() - start;
+            }
+        }
+        1
+    }
+
+    pub fn registrable(&self, domain: &str) -> Option<String> {
+        let domain = domain.trim().trim_matches('.').to_lowercase();
+        if domain.is_empty() || domain == "-" || domain.parse::<std::net::IpAddr>().is_ok() {
+            return None;
+        }
+        let labels: Vec<&str> = domain.split('.').collect();
+        if labels.iter().any(|label| label.is_empty()) {
+            return None;
+        }
+        let suffix = self.suffix_labels(&labels);
+        (labels.len() > suffix).then(|| labels[labels.len() - suffix - 1..].join("."))
+    }
+}
+
+pub fn registrable_domain(domain: &str) -> Option<String> {
+    BUILTIN.registrable(domain)
+}
+
+pub fn normalize_isp(dataset: Dataset, suffixes: &PublicSuffixes) -> Dataset {
+    let Dataset::Isp(strings, data) = dataset else {
+        return dataset;
+    };
+    let string = |idx: usize| idx.checked_sub(1).map_or("-", |i| strings[i].as_str());
+    let mut normalized = Vec::new();
+    let mut map = HashMap::new();
+    let mut index = |s: &str| intern_with_offset(s, &mut normalized, &mut map);
+    let data = data
+        .into_iter()
+        .map(|(from, to, isp, domain, provider)| {
+            let domain = suffixes.registrable(string(domain));
+            (
+                from,
+                to,
+                index(string(isp)),
+                index(domain.as_deref().unwrap_or("-")),
+                index(string(provider)),
+            )
+        })
+        .collect();
+    let mut dataset = Dataset::Isp(normalized, data);
+    order_by_frequency(&mut [&mut dataset]);
+    dataset
+}
+
+pub fn derive(
+    layers: Vec<Vec<(u128, u128, String)>>,
+    suffixes: &PublicSuffixes,
+) -> (Vec<String>, Vec<DomainRange>) {
+    let mut strings = Vec::new();
+    let mut map = HashMap::new();
+    let mut ranges = IntervalMap::new(|r: &DomainRange| (r.0, r.1), |r, from, to| (from, to, r.2));
+    for (priority, layer) in layers.into_iter().enumerate() {
+        let layer: Vec<DomainRange> = layer
+            .into_iter()
+            .filter_map(|(from, to, domain)| {
+                let domain = suffixes.registrable(&domain)?;
+                let idx = intern_with_offset(&domain, &mut strings, &mut map) - 1;
+                Some((from, to, idx as u32))
+            })
+            .collect();
+        ranges.extend(priority as u32, layer);
+    }
+    let ranges = join_adjacent(ranges.flatten());
+    (strings, ranges)
+}
+
+pub fn write(path: &str, strings: &[String], ranges: &[DomainRange]) -> Result<()> {
+    write_labeled(path, MAGIC, strings, ranges)
+}
+
+pub fn read(path: &str) -> Result<(Vec<String>, Vec<DomainRange>)> {
+    read_labeled(path, MAGIC, "domain")
+}
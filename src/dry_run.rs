@@ -0,0 +1,72 @@
+use std::env;
+use std::fs;
+use std::io::Result;
+use std::path::{self, Path, PathBuf};
+
+use crate::format::{Dataset, Kind};
+use crate::interval::coalesce;
+use crate::ip::ipv4_to_ipv6;
+use crate::storage;
+
+const GLOBAL_UNICAST: (u128, u128) = (0x2000 << 112, (0x4000 << 112) - 1);
+
+fn covered(ranges: &[(u128, u128)], from: u128, to: u128) -> f64 {
+    let count = ranges
+        .iter()
+        .filter(|r| r.0 <= to && r.1 >= from)
+        .fold(0.0, |count, r| {
+            count + (r.1.min(to) - r.0.max(from)) as f64 + 1.0
+        });
+    100.0 * count / ((to - from) as f64 + 1.0)
+}
+
+fn coverage(dataset: &Dataset) -> (f64, f64) {
+    let bounds = match dataset {
+        Dataset::Proxy(types) => types
+            .iter()
+            .flat_map(|(_, ranges)| ranges.iter().map(|r| (r.0, r.1)))
+            .collect(),
+        dataset => dataset.bounds(),
+    };
+    let ranges = coalesce(bounds);
+    (
+        covered(&ranges, ipv4_to_ipv6(0), ipv4_to_ipv6(u32::MAX)),
+        covered(&ranges, GLOBAL_UNICAST.0, GLOBAL_UNICAST.1),
+    )
+}
+
+pub fn report(dir: &Path, artifacts: &[(String, Option<usize>)]) -> Result<()> {
+    let mut total = 0;
+    for (path, records) in artifacts {
+        let file = dir.join(path).to_string_lossy().into_owned();
+        let size = fs::metadata(&file)?.len();
+        total += size;
+        let mut line = format!("{}: {} bytes", path, size);
+        if let Some(records) = records {
+            line.push_str(&format!(", {} records", records));
+        }
+        if let Some(kind) = Kind::from_artifact(path) {
+            let (v4, v6) = coverage(&Dataset::read(&file, kind)?);
+            line.push_str(&format!(", IPv4 {:.2}%, IPv6 2000::/3 {:.2}%", v4, v6));
+        }
+        println!("{}", line);
+    }
+    println!(
+        "Dry run: {} artifacts, {} bytes, nothing written",
+        artifacts.len(),
+        total
+    );
+    Ok(())
+}
+
+pub fn run(data_dir: &str, build: impl FnOnce(&str, &Path) -> Result<()>) -> Result<()> {
+    let data_dir = match storage::is_remote(data_dir) {
+        true => data_dir.to_string(),
+        false => path::absolute(data_dir)?.to_string_lossy().into_owned(),
+    };
+    let scratch: PathBuf = env::temp_dir().join(format!("ip2x-dry-run-{}", std::process::id()));
+    fs::create_dir_all(&scratch)?;
+    let result = build(&data_dir, &scratch);
+    fs::remove_dir_all(&scratch)?;
+    result
+}
@@ -0,0 +1,313 @@
+use std::io::{Error, ErrorKind, Read, Result, Write};
+
+use ip2x_core::bin::{self, Header, IPV4_BASE, IPV4_ONLY};
+use ip2x_core::varint::{decode, signed};
+pub use ip2x_core::varint::{unzigzag, zigzag, MAX_LEN};
+
+use crate::ip::u128_to_ip;
+
+pub fn write_varint(out: &mut impl Write, mut value: u128) -> Result<()> {
+    loop {
+        let mut byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value != 0 {
+            byte |= 0x80;
+        }
+        out.write_all(&[byte])?;
+        if value == 0 {
+            return Ok(());
+        }
+    }
+}
+
+pub fn read_varint(input: &mut impl Read) -> Result<u128> {
+    decode(|| {
+        let mut buf = [0u8; 1];
+        input.read_exact(&mut buf)?;
+        Ok(buf[0])
+    })
+}
+
+pub fn write_signed_varint(out: &mut impl Write, value: i64) -> Result<()> {
+    write_varint(out, zigzag(value) as u128)
+}
+
+pub fn read_signed_varint(input: &mut impl Read) -> Result<i64> {
+    Ok(signed(read_varint(input)?)?)
+}
+
+pub fn write_delta(out: &mut impl Write, prev: &mut i64, value: i64) -> Result<()> {
+    write_signed_varint(out, value.wrapping_sub(*prev))?;
+    *prev = value;
+    Ok(())
+}
+
+pub fn read_delta(input: &mut impl Read, prev: &mut i64) -> Result<i64> {
+    *prev = prev.wrapping_add(read_signed_varint(input)?);
+    Ok(*prev)
+}
+
+pub fn write_range(
+    out: &mut impl Write,
+    header: Header,
+    prev_from: u128,
+    from: u128,
+    to: u128,
+) -> Result<()> {
+    if !header.has(IPV4_ONLY) {
+        write_varint(out, from - prev_from)?;
+        return write_varint(out, to - from);
+    }
+    let ipv4 = |ip: u128| {
+        ip.checked_sub(IPV4_BASE)
+            .and_then(|offset| u32::try_from(offset).ok())
+    };
+    match (ipv4(from), ipv4(to)) {
+        (Some(from), Some(to)) => {
+            out.write_all(&from.to_le_bytes())?;
+            out.write_all(&to.to_le_bytes())
+        }
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "{}-{} is not an IPv4 range and cannot be written to an --ipv4-only bin",
+                u128_to_ip(from),
+                u128_to_ip(to)
+            ),
+        )),
+    }
+}
+
+pub fn read_range(input: &mut &[u8], header: Header, prev_from: u128) -> Result<(u128, u128)> {
+    Ok(bin::read_range(input, header, prev_from)?)
+}
+
+pub fn write_entries(out: &mut impl Write, strings: &[String], packed: bool) -> Result<()> {
+    let mut prev: &[u8] = &[];
+    for s in strings {
+        let bytes = s.as_bytes();
+        if bytes.len() > u16::MAX as usize {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "String of {} bytes is too long for a string table",
+                    bytes.len()
+                ),
+            ));
+        }
+        if packed {
+            let shared = prev.iter().zip(bytes).take_while(|(a, b)| a == b).count();
+            write_varint(out, shared as u128)?;
+            write_varint(out, (bytes.len() - shared) as u128)?;
+            out.write_all(&bytes[shared..])?;
+            prev = bytes;
+        } else {
+            out.write_all(&(bytes.len() as u16).to_le_bytes())?;
+            out.write_all(bytes)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn read_entries(input: &mut &[u8], count: usize, packed: bool) -> Result<Vec<String>> {
+    Ok(bin::read_entries(input, count, packed)?)
+}
+
+pub fn write_string_table(out: &mut impl Write, strings: &[String], packed: bool) -> Result<()> {
+    out.write_all(&((strings.len() + 1) as u32).to_le_bytes())?;
+    if packed {
+        write_varint(out, 0)?;
+        write_varint(out, 0)?;
+    } else {
+        out.write_all(&(0u16).to_le_bytes())?;
+    }
+    write_entries(out, strings, packed)
+}
+
+pub fn read_string_table(input: &mut &[u8], packed: bool) -> Result<Vec<String>> {
+    Ok(bin::read_string_table(input, packed)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ip2x_core::bin::SPLIT_FAMILIES;
+
+    const CASES: usize = 2000;
+
+    struct Gen(u64);
+
+    impl Gen {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, bound: u64) -> u64 {
+            self.next() % bound.max(1)
+        }
+
+        fn u128(&mut self) -> u128 {
+            let value = (self.next() as u128) << 64 | self.next() as u128;
+            value >> self.below(129).min(127)
+        }
+
+        fn i64(&mut self) -> i64 {
+            (self.next() as i64) >> self.below(64)
+        }
+
+        fn string(&mut self, prefixes: &[&str]) -> String {
+            let mut s = prefixes[self.below(prefixes.len() as u64) as usize].to_string();
+            for _ in 0..self.below(12) {
+                s.push(['a', 'Z', '0', ' ', '.', 'é', '東', '🦀'][self.below(8) as usize]);
+            }
+            s
+        }
+    }
+
+    fn cases(seed: u64, mut check: impl FnMut(&mut Gen)) {
+        let mut gen = Gen(seed);
+        for _ in 0..CASES {
+            check(&mut gen);
+        }
+    }
+
+    fn encode(write: impl FnOnce(&mut Vec<u8>) -> Result<()>) -> Vec<u8> {
+        let mut out = Vec::new();
+        write(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn varints_round_trip() {
+        cases(0x9e37_79b9_7f4a_7c15, |gen| {
+            let value = gen.u128();
+            let bytes = encode(|out| write_varint(out, value));
+            let decoded = read_varint(&mut bytes.as_slice()).unwrap();
+            assert_eq!(decoded, value);
+            assert_eq!(encode(|out| write_varint(out, decoded)), bytes);
+            let mut input = bytes.as_slice();
+            assert_eq!(ip2x_core::varint::read_varint(&mut input).unwrap(), value);
+            assert!(input.is_empty());
+        });
+    }
+
+    #[test]
+    fn signed_varints_round_trip() {
+        cases(0xd1b5_4a32_d192_ed03, |gen| {
+            let value = gen.i64();
+            let bytes = encode(|out| write_signed_varint(out, value));
+            let decoded = read_signed_varint(&mut bytes.as_slice()).unwrap();
+            assert_eq!(decoded, value);
+            assert_eq!(encode(|out| write_signed_varint(out, decoded)), bytes);
+            assert_eq!(unzigzag(zigzag(value)), value);
+        });
+    }
+
+    #[test]
+    fn deltas_round_trip() {
+        cases(0x2545_f491_4f6c_dd1d, |gen| {
+            let values: Vec<i64> = (0..gen.below(16)).map(|_| gen.i64()).collect();
+            let mut prev = 0;
+            let bytes = encode(|out| {
+                values
+                    .iter()
+                    .try_for_each(|&v| write_delta(out, &mut prev, v))
+            });
+            let (mut input, mut prev) = (bytes.as_slice(), 0);
+            for &value in &values {
+                assert_eq!(read_delta(&mut input, &mut prev).unwrap(), value);
+            }
+            assert!(input.is_empty());
+        });
+    }
+
+    #[test]
+    fn ranges_round_trip() {
+        let ipv4_only = Header {
+            flags: IPV4_ONLY,
+            ..Header::default()
+        };
+        let split = Header {
+            flags: SPLIT_FAMILIES,
+            ..Header::default()
+        };
+        cases(0x6a09_e667_f3bc_c908, |gen| {
+            let header = [Header::default(), ipv4_only, split][gen.below(3) as usize];
+            let limit = match header.has(IPV4_ONLY) {
+                true => u32::MAX as u128,
+                false => u128::MAX,
+            };
+            let mut ranges: Vec<(u128, u128)> = (0..gen.below(16))
+                .map(|_| {
+                    let (a, b) = (gen.u128() & limit, gen.u128() & limit);
+                    (a.min(b), a.max(b))
+                })
+                .collect();
+            if header.has(IPV4_ONLY) {
+                ranges
+                    .iter_mut()
+                    .for_each(|r| *r = (r.0 + IPV4_BASE, r.1 + IPV4_BASE));
+            }
+            ranges.sort();
+            let mut bytes = Vec::new();
+            let mut prev_from = 0;
+            for &(from, to) in &ranges {
+                write_range(&mut bytes, header, prev_from, from, to).unwrap();
+                prev_from = from;
+            }
+            let (mut input, mut prev_from) = (bytes.as_slice(), 0);
+            for &range in &ranges {
+                assert_eq!(read_range(&mut input, header, prev_from).unwrap(), range);
+                prev_from = range.0;
+            }
+            assert!(input.is_empty());
+        });
+    }
+
+    #[test]
+    fn ipv4_only_rejects_ipv6_ranges() {
+        let header = Header {
+            flags: IPV4_ONLY,
+            ..Header::default()
+        };
+        let err = write_range(&mut Vec::new(), header, 0, 1, 2).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn string_tables_round_trip() {
+        let prefixes = ["", "Cloudflare", "Cloudflare, Inc.", "AS13335", "東京"];
+        cases(0xbb67_ae85_84ca_a73b, |gen| {
+            let mut strings: Vec<String> =
+                (0..gen.below(24)).map(|_| gen.string(&prefixes)).collect();
+            if gen.below(2) == 0 {
+                strings.sort();
+            }
+            for packed in [false, true] {
+                let bytes = encode(|out| write_entries(out, &strings, packed));
+                let mut input = bytes.as_slice();
+                let decoded = read_entries(&mut input, strings.len(), packed).unwrap();
+                assert_eq!(decoded, strings);
+                assert!(input.is_empty());
+                assert_eq!(encode(|out| write_entries(out, &decoded, packed)), bytes);
+
+                let bytes = encode(|out| write_string_table(out, &strings, packed));
+                let mut input = bytes.as_slice();
+                assert_eq!(read_string_table(&mut input, packed).unwrap(), strings);
+                assert!(input.is_empty());
+            }
+        });
+    }
+
+    #[test]
+    fn string_tables_reject_long_entries() {
+        let strings = ["x".repeat(u16::MAX as usize + 1)];
+        for packed in [false, true] {
+            let err = write_entries(&mut Vec::new(), &strings, packed).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use std::io::{self, BufRead, BufReader, BufWriter, Error, ErrorKind, Result, Write};
+use std::net::IpAddr;
+
+use serde_json::Value;
+
+use crate::cli::Args;
+use crate::csv_reader::parse_line;
+use crate::csv_writer::quote;
+use crate::reader::{open_from_args, Ip2xReader};
+
+pub const COLUMNS: [&str; 6] = [
+    "country_code",
+    "latitude",
+    "longitude",
+    "asn",
+    "as_name",
+    "proxy_type",
+];
+const TRIM: &[char] = &['[', ']', '(', ')', '"', '\'', ',', ';'];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Plain,
+    Json,
+    Csv,
+}
+
+enum Field {
+    Index(usize),
+    Name(String),
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidInput, message)
+}
+
+fn coordinate(value: f32) -> Value {
+    value
+        .to_string()
+        .parse::<f64>()
+        .map_or(Value::Null, Value::from)
+}
+
+pub fn columns(reader: &Ip2xReader, ip: Option<IpAddr>) -> [Value; 6] {
+    let Some(ip) = ip else {
+        return Default::default();
+    };
+    let geo = reader.lookup_geo(ip);
+    let asn = reader.lookup_asn(ip);
+    [
+        reader
+            .lookup_country(ip)
+            .map_or(Value::Null, |country| country.country_code.into()),
+        geo.as_ref()
+            .map_or(Value::Null, |geo| coordinate(geo.latitude)),
+        geo.map_or(Value::Null, |geo| coordinate(geo.longitude)),
+        asn.as_ref()
+            .map_or(Value::Null, |asn| asn.asn.clone().into()),
+        asn.map_or(Value::Null, |asn| asn.as_name.into()),
+        reader
+            .lookup_proxy(ip)
+            .map_or(Value::Null, |proxy| proxy.proxy_type.into()),
+    ]
+}
+
+pub fn text(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(s) => Some(s.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+fn enrich_plain(line: &str, index: usize, reader: &Ip2xReader) -> String {
+    let ip = line
+        .split_whitespace()
+        .nth(index)
+        .and_then(|field| field.trim_matches(TRIM).parse().ok());
+    let mut out = line.to_string();
+    for value in columns(reader, ip) {
+        out.push(' ');
+        match text(&value) {
+            Some(s) if s.contains(char::is_whitespace) => out.push_str(&quote(&s)),
+            Some(s) => out.push_str(&s),
+            None => out.push('-'),
+        }
+    }
+    out
+}
+
+fn enrich_csv(line: &str, index: usize, reader: &Ip2xReader) -> String {
+    let ip = parse_line(line)
+        .get(index)
+        .and_then(|field| field.trim().parse().ok());
+    let mut out = line.to_string();
+    for value in columns(reader, ip) {
+        out.push(',');
+        out.push_str(&text(&value).map(|s| quote(&s)).unwrap_or_default());
+    }
+    out
+}
+
+fn enrich_json(line: &str, pointer: &str, reader: &Ip2xReader) -> String {
+    let Ok(value) = serde_json::from_str::<Value>(line) else {
+        return line.to_string();
+    };
+    let Some(object) = value.as_object() else {
+        return line.to_string();
+    };
+    let ip = value
+        .pointer(pointer)
+        .and_then(Value::as_str)
+        .and_then(|ip| ip.trim().parse().ok());
+    let body = line.trim_end();
+    let mut out = body[..body.len() - 1].to_string();
+    for (i, (name, value)) in COLUMNS.iter().zip(columns(reader, ip)).enumerate() {
+        if i > 0 || !object.is_empty() {
+            out.push(',');
+        }
+        out.push_str(&format!("\"{}\":{}", name, value));
+    }
+    out.push('}');
+    out
+}
+
+pub fn run(args: &Args) -> Result<()> {
+    let format = match args.value(&["--format"]).unwrap_or("plain") {
+        "plain" => Format::Plain,
+        "json" => Format::Json,
+        "csv" => Format::Csv,
+        other => {
+            return Err(invalid(&format!(
+                "Unknown input format: {} (expected plain, json or csv)",
+                other
+            )))
+        }
+    };
+    let field = match args.value(&["--field", "-f"]) {
+        Some(field) => match field.parse::<usize>() {
+            Ok(0) => return Err(invalid("--field columns are numbered from 1")),
+            Ok(n) => Field::Index(n - 1),
+            Err(_) => Field::Name(field.to_string()),
+        },
+        None if format == Format::Json => Field::Name("ip".to_string()),
+        None => Field::Index(0),
+    };
+    let pointer = match (&field, format) {
+        (Field::Name(name), Format::Json) => format!("/{}", name.replace('.', "/")),
+        (Field::Index(_), Format::Json) => {
+            return Err(invalid("--field must be a key name for json input"))
+        }
+        (Field::Name(_), Format::Plain) => {
+            return Err(invalid("--field must be a column number for plain input"))
+        }
+        _ => String::new(),
+    };
+
+    let reader = open_from_args(args)?;
+    let mut input = BufReader::new(io::stdin().lock());
+    let mut out = BufWriter::new(io::stdout().lock());
+    let mut line = String::new();
+    let mut index = match field {
+        Field::Index(index) => index,
+        Field::Name(_) => 0,
+    };
+
+    let header =
+        format == Format::Csv && (args.flag("--header") || matches!(field, Field::Name(_)));
+    if header && input.read_line(&mut line)? > 0 {
+        let names = line.trim_end_matches(['\r', '\n']);
+        if let Field::Name(name) = &field {
+            index = parse_line(names)
+                .iter()
+                .position(|column| column == name)
+                .ok_or_else(|| invalid(&format!("No column named {} in the header", name)))?;
+        }
+        writeln!(out, "{},{}", names, COLUMNS.join(","))?;
+    }
+
+    loop {
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            break;
+        }
+        let raw = line.trim_end_matches(['\r', '\n']);
+        let enriched = match format {
+            Format::Plain => enrich_plain(raw, index, &reader),
+            Format::Csv => enrich_csv(raw, index, &reader),
+            Format::Json => enrich_json(raw, &pointer, &reader),
+        };
+        writeln!(out, "{}", enriched)?;
+        if input.buffer().is_empty() {
+            out.flush()?;
+        }
+    }
+    out.flush()
+}
@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Result};
+
+use crate::cli::Args;
+use crate::format::{intern, intern_with_offset, AsnRange, Dataset, IspRange, Kind, ProxyRange};
+use crate::ip::parse_cidr;
+use crate::storage;
+
+pub fn run(args: &Args) -> Result<()> {
+    let [input] = args.positional() else {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Usage: ip2x extract <in.bin> --cidrs <cidrs.txt> -o <out.bin> [--kind geo|proxy|asn|isp|mobile|usage_type|elevation|country]",
+        ));
+    };
+    let cidrs_path = args.require(&["--cidrs"])?;
+    let output = args.require(&["-o", "--output"])?;
+    let kind = Kind::detect(args.value(&["--kind"]), input)?;
+
+    let prefixes = read_cidrs(cidrs_path)?;
+    let dataset = Dataset::read(input, kind)?;
+    let subset = extract(dataset, &prefixes);
+    subset.write(output)?;
+
+    println!(
+        "Extracted {} prefixes from {} into {}",
+        prefixes.len(),
+        input,
+        output
+    );
+    Ok(())
+}
+
+pub fn read_cidrs(path: &str) -> Result<Vec<(u128, u128)>> {
+    let mut prefixes = Vec::new();
+    for (number, line) in storage::read_to_string(path)?.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let prefix = parse_cidr(line).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("{}:{}: invalid CIDR '{}'", path, number + 1, line),
+            )
+        })?;
+        prefixes.push(prefix);
+    }
+    prefixes.sort_unstable();
+    Ok(prefixes)
+}
+
+pub fn extract(dataset: Dataset, prefixes: &[(u128, u128)]) -> Dataset {
+    let keep = |from: u128, to: u128| intersects(from, to, prefixes);
+
+    match dataset {
+        Dataset::Geo(ranges) => {
+            Dataset::Geo(ranges.into_iter().filter(|r| keep(r.0, r.1)).collect())
+        }
+        Dataset::Proxy(types) => Dataset::Proxy(
+            types
+                .into_iter()
+                .map(|(name, ranges)| {
+                    let kept = ranges.into_iter().filter(|r| keep(r.0, r.1)).collect();
+                    (name, kept)
+                })
+                .filter(|(_, ranges): &(String, Vec<ProxyRange>)| !ranges.is_empty())
+                .collect(),
+        ),
+        Dataset::Asn(strings, data) => {
+            let (strings, data) = remap_asn(&strings, data.into_iter().filter(|r| keep(r.0, r.1)));
+            Dataset::Asn(strings, data)
+        }
+        Dataset::Isp(strings, data) => {
+            let (strings, data) =
+                remap_offset(&strings, data.into_iter().filter(|r| keep(r.0, r.1)));
+            Dataset::Isp(strings, data)
+        }
+        Dataset::Mobile(strings, data) => {
+            let (strings, data) =
+                remap_offset(&strings, data.into_iter().filter(|r| keep(r.0, r.1)));
+            Dataset::Mobile(strings, data)
+        }
+        Dataset::Usage(ranges) => {
+            Dataset::Usage(ranges.into_iter().filter(|r| keep(r.0, r.1)).collect())
+        }
+        Dataset::Elevation(ranges) => {
+            Dataset::Elevation(ranges.into_iter().filter(|r| keep(r.0, r.1)).collect())
+        }
+        Dataset::Country(ranges) => {
+            Dataset::Country(ranges.into_iter().filter(|r| keep(r.0, r.1)).collect())
+        }
+    }
+}
+
+pub fn remap_asn(
+    strings: &[String],
+    data: impl IntoIterator<Item = AsnRange>,
+) -> (Vec<String>, Vec<AsnRange>) {
+    let mut kept_strings = Vec::new();
+    let mut string_map = HashMap::new();
+    let data = data
+        .into_iter()
+        .map(|(from, to, cidr, asn, name, org)| {
+            let mut remap = |idx: usize| match strings.get(idx) {
+                Some(s) => intern(s, &mut kept_strings, &mut string_map),
+                None => 0,
+            };
+            (
+                from,
+                to,
+                remap(cidr),
+                remap(asn),
+                remap(name),
+                org.map(remap),
+            )
+        })
+        .collect();
+    (kept_strings, data)
+}
+
+pub fn remap_offset(
+    strings: &[String],
+    data: impl IntoIterator<Item = IspRange>,
+) -> (Vec<String>, Vec<IspRange>) {
+    let mut kept_strings = Vec::new();
+    let mut string_map = HashMap::new();
+    let data = data
+        .into_iter()
+        .map(|(from, to, a, b, c)| {
+            let mut remap = |idx: usize| match idx.checked_sub(1).and_then(|i| strings.get(i)) {
+                Some(s) => intern_with_offset(s, &mut kept_strings, &mut string_map),
+                None => 0,
+            };
+            (from, to, remap(a), remap(b), remap(c))
+        })
+        .collect();
+    (kept_strings, data)
+}
+
+fn intersects(from: u128, to: u128, prefixes: &[(u128, u128)]) -> bool {
+    let candidates = prefixes.partition_point(|p| p.0 <= to);
+    prefixes[..candidates].iter().rev().any(|p| p.1 >= from)
+}
@@ -0,0 +1,1046 @@
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Cursor, Error, ErrorKind, Read, Result, Write};
+use std::path::Path;
+
+use ip2x_core::bin::{
+    self, Header, CONTINENTS, HAS_ACCURACY, HAS_SUBDIVISION, HEADER_MAGIC, IN_EUROPEAN_UNION,
+    IPV4_BASE, REGISTERED_COUNTRY, REPRESENTED_COUNTRY, UNLOCATED,
+};
+pub use ip2x_core::bin::{
+    sort_ranges, sort_ranges_by, Alternate, AlternateCountry, AsnRange, CountryRange, DomainRange,
+    ElevationRange, GeoRange, IspRange, MobileRange, NetworkTypeRange, Order, ProxyRange,
+    RdnsRange, SyncState, ThreatRange, UsageRange, ASN_ORGANIZATION, DELTA_COORDS, FORMAT_VERSION,
+    GEO_ACCURACY, GEO_CELLS, GEO_PAYLOAD, INDEXED, IPV4_ONLY, LEGACY_FORMAT_VERSION,
+    PACKED_STRINGS, PROXY_CONFIDENCE, PROXY_LAST_SEEN, SHARED_STRINGS, SPLIT_FAMILIES, TRIE,
+    UNKNOWN_LAST_SEEN,
+};
+
+use crate::cells::Grid;
+use crate::centroids::{centroid, CENTROID_ACCURACY_KM};
+use crate::compress;
+use crate::encoding::{
+    read_entries, write_delta, write_entries, write_range, write_signed_varint, write_string_table,
+    write_varint,
+};
+use crate::index;
+use crate::shard::SHARD_DIR;
+use crate::trie;
+
+pub const STRINGS_FILE: &str = "strings.bin";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Layout {
+    pub flags: u32,
+    pub geo_precision: u8,
+    pub geo_cells: Option<Grid>,
+    pub index_interval: u32,
+}
+
+impl Layout {
+    pub fn has(self, flag: u32) -> bool {
+        self.flags & flag != 0
+    }
+
+    pub fn with(self, flags: u32) -> Layout {
+        Layout {
+            flags: self.flags | flags,
+            ..self
+        }
+    }
+
+    pub fn header(self) -> Header {
+        Header {
+            flags: self.flags,
+            geo_precision: self.geo_precision,
+            geo_cells: self.geo_cells.map(Grid::to_bytes),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Geo,
+    Proxy,
+    Asn,
+    Isp,
+    Mobile,
+    Usage,
+    Elevation,
+    Country,
+}
+
+impl Kind {
+    pub const ALL: [Kind; 8] = [
+        Kind::Geo,
+        Kind::Proxy,
+        Kind::Asn,
+        Kind::Isp,
+        Kind::Mobile,
+        Kind::Usage,
+        Kind::Elevation,
+        Kind::Country,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Geo => "geo",
+            Kind::Proxy => "proxy_types",
+            Kind::Asn => "asn",
+            Kind::Isp => "isp",
+            Kind::Mobile => "mobile",
+            Kind::Usage => "usage_type",
+            Kind::Elevation => "elevation",
+            Kind::Country => "country",
+        }
+    }
+
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Kind::Geo => "geo.bin",
+            Kind::Proxy => "proxy_types.bin",
+            Kind::Asn => "asn.bin",
+            Kind::Isp => "isp.bin",
+            Kind::Mobile => "mobile.bin",
+            Kind::Usage => "usage_type.bin",
+            Kind::Elevation => "elevation.bin",
+            Kind::Country => "country.bin",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Kind> {
+        match name {
+            "geo" => Some(Kind::Geo),
+            "proxy" | "proxy_types" => Some(Kind::Proxy),
+            "asn" => Some(Kind::Asn),
+            "isp" => Some(Kind::Isp),
+            "mobile" => Some(Kind::Mobile),
+            "usage" | "usage_type" => Some(Kind::Usage),
+            "elevation" => Some(Kind::Elevation),
+            "country" => Some(Kind::Country),
+            _ => None,
+        }
+    }
+
+    pub fn from_path(path: &str) -> Option<Kind> {
+        let path = Path::new(path);
+        let name = path.file_name()?.to_str()?;
+        let parent = path
+            .parent()
+            .and_then(Path::file_name)
+            .and_then(|dir| dir.to_str());
+        Kind::ALL.into_iter().find(|kind| {
+            name == kind.file_name() || name.ends_with(".bin") && parent == Some(kind.name())
+        })
+    }
+
+    pub fn from_artifact(path: &str) -> Option<Kind> {
+        let parent = Path::new(path).parent()?;
+        Kind::ALL.into_iter().find(|kind| {
+            path == kind.file_name()
+                || path.ends_with(".bin") && parent == Path::new(SHARD_DIR).join(kind.name())
+        })
+    }
+
+    pub fn detect(explicit: Option<&str>, path: &str) -> Result<Kind> {
+        match explicit {
+            Some(name) => Self::parse(name),
+            None => Self::from_path(path),
+        }
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("Cannot determine bin type of {}, pass --kind", path),
+            )
+        })
+    }
+}
+
+pub enum Dataset {
+    Geo(Vec<GeoRange>),
+    Proxy(Vec<(String, Vec<ProxyRange>)>),
+    Asn(Vec<String>, Vec<AsnRange>),
+    Isp(Vec<String>, Vec<IspRange>),
+    Mobile(Vec<String>, Vec<MobileRange>),
+    Usage(Vec<UsageRange>),
+    Elevation(Vec<ElevationRange>),
+    Country(Vec<CountryRange>),
+}
+
+impl Dataset {
+    pub fn read(path: &str, kind: Kind) -> Result<Dataset> {
+        Self::read_from(&mut &read_bin(path)?[..], kind, path)
+    }
+
+    pub fn read_from(input: &mut &[u8], kind: Kind, path: &str) -> Result<Dataset> {
+        let layout = read_header(input)?;
+        let header = layout.header();
+        let shared = if layout.has(SHARED_STRINGS) {
+            let strings_path = Path::new(path).with_file_name(STRINGS_FILE);
+            Some(read_strings_file(&strings_path.to_string_lossy())?)
+        } else {
+            None
+        };
+
+        Ok(match kind {
+            Kind::Geo => Dataset::Geo(bin::read_geo(input, header, cell_center(layout))?),
+            Kind::Proxy => Dataset::Proxy(bin::read_proxy_types(input, header)?),
+            Kind::Asn => {
+                let (strings, data) = bin::read_asn(input, header, shared)?;
+                Dataset::Asn(strings, data)
+            }
+            Kind::Isp => {
+                let (strings, data) = bin::read_isp(input, header, shared)?;
+                Dataset::Isp(strings, data)
+            }
+            Kind::Mobile => {
+                let (strings, data) = bin::read_isp(input, header, shared)?;
+                Dataset::Mobile(strings, data)
+            }
+            Kind::Usage => Dataset::Usage(bin::read_usage(input, header)?),
+            Kind::Elevation => Dataset::Elevation(bin::read_elevation(input, header)?),
+            Kind::Country => Dataset::Country(bin::read_country(input, header)?),
+        })
+    }
+
+    pub fn kind(&self) -> Kind {
+        match self {
+            Dataset::Geo(_) => Kind::Geo,
+            Dataset::Proxy(_) => Kind::Proxy,
+            Dataset::Asn(..) => Kind::Asn,
+            Dataset::Isp(..) => Kind::Isp,
+            Dataset::Mobile(..) => Kind::Mobile,
+            Dataset::Usage(_) => Kind::Usage,
+            Dataset::Elevation(_) => Kind::Elevation,
+            Dataset::Country(_) => Kind::Country,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Dataset::Geo(ranges) => ranges.len(),
+            Dataset::Usage(ranges) => ranges.len(),
+            Dataset::Elevation(ranges) => ranges.len(),
+            Dataset::Country(ranges) => ranges.len(),
+            Dataset::Proxy(types) => types.iter().map(|(_, ranges)| ranges.len()).sum(),
+            Dataset::Asn(_, data) => data.len(),
+            Dataset::Isp(_, data) | Dataset::Mobile(_, data) => data.len(),
+        }
+    }
+
+    pub fn bounds(&self) -> Vec<(u128, u128)> {
+        match self {
+            Dataset::Geo(ranges) => ranges.iter().map(|r| (r.0, r.1)).collect(),
+            Dataset::Asn(_, data) => data.iter().map(|r| (r.0, r.1)).collect(),
+            Dataset::Isp(_, data) | Dataset::Mobile(_, data) => {
+                data.iter().map(|r| (r.0, r.1)).collect()
+            }
+            Dataset::Usage(ranges) => ranges.iter().map(|r| (r.0, r.1)).collect(),
+            Dataset::Elevation(ranges) => ranges.iter().map(|r| (r.0, r.1)).collect(),
+            Dataset::Country(ranges) => ranges.iter().map(|r| (r.0, r.1)).collect(),
+            Dataset::Proxy(_) => Vec::new(),
+        }
+    }
+
+    pub fn write(&self, path: &str) -> Result<()> {
+        let layout = match self {
+            Dataset::Proxy(types) => proxy_layout(types, Layout::default()),
+            Dataset::Geo(_) => Layout::default().with(GEO_PAYLOAD),
+            Dataset::Asn(_, data) => asn_layout(data, Layout::default()),
+            _ => Layout::default(),
+        };
+        self.write_with(path, layout)
+    }
+
+    pub fn write_with(&self, path: &str, layout: Layout) -> Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write_to(&mut out, layout)?;
+        out.flush()?;
+        if layout.has(INDEXED) {
+            index::append(path, self.kind(), layout.index_interval)?;
+        }
+        if layout.has(TRIE) {
+            trie::append(path, self.kind())?;
+        }
+        Ok(())
+    }
+
+    pub fn write_to(&self, out: &mut impl Write, layout: Layout) -> Result<()> {
+        write_header(out, layout)?;
+        match self {
+            Dataset::Geo(ranges) => write_geo(out, ranges, layout),
+            Dataset::Proxy(types) => write_proxy_types(out, types, layout),
+            Dataset::Asn(strings, data) => write_asn(out, strings, data, layout),
+            Dataset::Isp(strings, data) | Dataset::Mobile(strings, data) => {
+                write_isp(out, strings, data, layout)
+            }
+            Dataset::Usage(ranges) => write_usage(out, ranges, layout),
+            Dataset::Elevation(ranges) => write_elevation(out, ranges, layout),
+            Dataset::Country(ranges) => write_country(out, ranges, layout),
+        }
+    }
+}
+
+fn write_header(out: &mut impl Write, layout: Layout) -> Result<()> {
+    if layout == Layout::default() {
+        return Ok(());
+    }
+    out.write_all(HEADER_MAGIC)?;
+    out.write_all(&[layout.header().version()])?;
+    out.write_all(&layout.flags.to_le_bytes())?;
+    if layout.has(DELTA_COORDS) {
+        out.write_all(&[layout.geo_precision])?;
+    }
+    if let Some(grid) = layout.geo_cells.filter(|_| layout.has(GEO_CELLS)) {
+        out.write_all(&grid.to_bytes())?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Unlocated {
+    pub centroids: bool,
+    pub keep: bool,
+}
+
+pub fn locate(
+    lat: f32,
+    lon: f32,
+    country: &[u8],
+    unlocated: Unlocated,
+) -> Option<(f32, f32, Option<u16>)> {
+    if lat != 0.0 || lon != 0.0 {
+        return Some((lat, lon, None));
+    }
+    match centroid(country).filter(|_| unlocated.centroids) {
+        Some((lat, lon)) => Some((lat, lon, Some(CENTROID_ACCURACY_KM))),
+        None if unlocated.keep => Some((f32::NAN, f32::NAN, None)),
+        None => None,
+    }
+}
+
+pub fn read_version(path: &str) -> Result<u8> {
+    Ok(bin::format_version(open_bin(path)?.fill_buf()?)?)
+}
+
+pub fn read_layout(path: &str) -> Result<Layout> {
+    read_header(&mut open_bin(path)?.fill_buf()?)
+}
+
+fn read_header(input: &mut &[u8]) -> Result<Layout> {
+    let header = bin::read_header(input)?;
+    Ok(Layout {
+        flags: header.flags,
+        geo_precision: header.geo_precision,
+        geo_cells: header
+            .geo_cells
+            .map(|[system, level]| Grid::from_bytes(system, level))
+            .transpose()?,
+        ..Layout::default()
+    })
+}
+
+fn cell_center(layout: Layout) -> impl Fn(u64) -> Result<(f32, f32)> {
+    move |cell| match layout.geo_cells {
+        Some(grid) => grid.center(grid.expand(cell)),
+        None => Ok(bin::no_cells(cell)?),
+    }
+}
+
+fn string_refs(dataset: &mut Dataset) -> Option<(usize, &mut Vec<String>, Vec<&mut usize>)> {
+    match dataset {
+        Dataset::Asn(strings, data) => {
+            let slots = data
+                .iter_mut()
+                .flat_map(|r| [Some(&mut r.2), Some(&mut r.3), Some(&mut r.4), r.5.as_mut()])
+                .flatten()
+                .collect();
+            Some((0, strings, slots))
+        }
+        Dataset::Isp(strings, data) | Dataset::Mobile(strings, data) => {
+            let slots = data
+                .iter_mut()
+                .flat_map(|r| [&mut r.2, &mut r.3, &mut r.4])
+                .collect();
+            Some((1, strings, slots))
+        }
+        _ => None,
+    }
+}
+
+fn remap_records(slots: Vec<&mut usize>, offset: usize, remap: &[usize]) {
+    for slot in slots {
+        if let Some(i) = slot.checked_sub(offset) {
+            *slot = remap[i] + offset;
+        }
+    }
+}
+
+pub fn share_strings(asn: &mut Dataset, isp: &mut Dataset) -> Vec<String> {
+    let mut strings = Vec::new();
+    let mut map = HashMap::new();
+
+    for dataset in [&mut *asn, &mut *isp] {
+        let Some((offset, local, data)) = string_refs(dataset) else {
+            continue;
+        };
+        let remap: Vec<usize> = local
+            .iter()
+            .map(|s| {
+                *map.entry(s.clone()).or_insert_with(|| {
+                    strings.push(s.clone());
+                    strings.len() - 1
+                })
+            })
+            .collect();
+        remap_records(data, offset, &remap);
+    }
+
+    for dataset in [&mut *asn, &mut *isp] {
+        if let Some((_, local, _)) = string_refs(dataset) {
+            *local = strings.clone();
+        }
+    }
+    order_by_frequency(&mut [&mut *asn, &mut *isp]);
+    string_refs(asn)
+        .map(|(_, local, _)| local.clone())
+        .unwrap_or(strings)
+}
+
+pub fn order_by_frequency(datasets: &mut [&mut Dataset]) {
+    let mut counts = Vec::new();
+    for dataset in datasets.iter_mut() {
+        let Some((offset, strings, data)) = string_refs(dataset) else {
+            continue;
+        };
+        counts.resize(strings.len(), 0usize);
+        for idx in data {
+            if let Some(count) = idx.checked_sub(offset).and_then(|i| counts.get_mut(i)) {
+                *count += 1;
+            }
+        }
+    }
+
+    let mut order: Vec<usize> = (0..counts.len()).collect();
+    order.sort_by_key(|&i| Reverse(counts[i]));
+    let mut rank = vec![0; order.len()];
+    for (new, &old) in order.iter().enumerate() {
+        rank[old] = new;
+    }
+
+    for dataset in datasets.iter_mut() {
+        let Some((offset, strings, data)) = string_refs(dataset) else {
+            continue;
+        };
+        *strings = order
+            .iter()
+            .map(|&old| std::mem::take(&mut strings[old]))
+            .collect();
+        remap_records(data, offset, &rank);
+    }
+}
+
+pub fn write_strings_file(path: &str, strings: &[String], layout: Layout) -> Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    write_header(&mut out, layout)?;
+    out.write_all(&(strings.len() as u32).to_le_bytes())?;
+    write_entries(&mut out, strings, layout.has(PACKED_STRINGS))?;
+    out.flush()
+}
+
+pub fn read_preamble(input: &mut &[u8], kind: Kind, path: &str) -> Result<(Layout, Vec<String>)> {
+    let layout = read_header(input)?;
+    let packed = layout.has(PACKED_STRINGS);
+    let strings = if layout.has(SHARED_STRINGS) {
+        let strings_path = Path::new(path).with_file_name(STRINGS_FILE);
+        read_strings_file(&strings_path.to_string_lossy())?
+    } else {
+        match kind {
+            Kind::Asn => {
+                let count = bin::read_u32(input)? as usize;
+                read_entries(input, count, packed)?
+            }
+            Kind::Isp | Kind::Mobile => bin::read_string_table(input, packed)?,
+            _ => Vec::new(),
+        }
+    };
+    Ok((layout, strings))
+}
+
+pub fn read_strings_file(path: &str) -> Result<Vec<String>> {
+    let bytes = fs::read(path)
+        .map_err(|err| Error::new(err.kind(), format!("Cannot open shared {}: {}", path, err)))?;
+    Ok(bin::read_strings(&mut &bytes[..])?)
+}
+
+pub fn open_bin(path: &str) -> Result<Box<dyn BufRead>> {
+    let mut input = BufReader::new(File::open(path)?);
+    if input.fill_buf()?.starts_with(compress::MAGIC) {
+        input.consume(compress::MAGIC.len());
+        return Ok(Box::new(Cursor::new(compress::decompress(&mut input)?)));
+    }
+    Ok(Box::new(input))
+}
+
+pub fn read_bin(path: &str) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    open_bin(path)?.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+pub fn read_u32(input: &mut impl Read) -> Result<u32> {
+    let mut buf = [0u8; 4];
+    input.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn sections<T: Clone>(
+    items: &[T],
+    range: impl Fn(&T) -> (u128, u128),
+    layout: Layout,
+) -> Vec<(u128, Cow<'_, [T]>)> {
+    let order = layout.header().order();
+    let items = match order {
+        Order::SmallestFirst => Cow::Borrowed(items),
+        Order::LargestFirst => {
+            let mut items = items.to_vec();
+            sort_ranges_by(&mut items, order, &range);
+            Cow::Owned(items)
+        }
+    };
+    if layout.has(IPV4_ONLY) {
+        return vec![(IPV4_BASE, items)];
+    }
+    if !layout.has(SPLIT_FAMILIES) {
+        return vec![(0, items)];
+    }
+    let (v4, v6): (Vec<T>, Vec<T>) = items.iter().cloned().partition(|item| {
+        let (from, to) = range(item);
+        section_base(from, to, layout) == IPV4_BASE
+    });
+    vec![(IPV4_BASE, Cow::Owned(v4)), (0, Cow::Owned(v6))]
+}
+
+pub fn coordinate_scale(layout: Layout) -> f64 {
+    layout.header().coordinate_scale()
+}
+
+pub fn read_block(
+    input: &mut &[u8],
+    kind: Kind,
+    layout: Layout,
+    strings: usize,
+    state: &mut SyncState,
+    count: usize,
+) -> Result<Dataset> {
+    let wide = strings >= 65536;
+    let header = layout.header();
+    let center = cell_center(layout);
+    Ok(match kind {
+        Kind::Geo => Dataset::Geo(
+            (0..count)
+                .map(|_| bin::read_geo_record(input, header, state, &center))
+                .collect::<Result<_>>()?,
+        ),
+        Kind::Asn => Dataset::Asn(
+            Vec::new(),
+            (0..count)
+                .map(|_| bin::read_asn_record(input, header, state))
+                .collect::<ip2x_core::Result<_>>()?,
+        ),
+        Kind::Isp => Dataset::Isp(
+            Vec::new(),
+            (0..count)
+                .map(|_| bin::read_isp_record(input, header, wide, state))
+                .collect::<ip2x_core::Result<_>>()?,
+        ),
+        Kind::Mobile => Dataset::Mobile(
+            Vec::new(),
+            (0..count)
+                .map(|_| bin::read_isp_record(input, header, wide, state))
+                .collect::<ip2x_core::Result<_>>()?,
+        ),
+        Kind::Usage => Dataset::Usage(
+            (0..count)
+                .map(|_| bin::read_usage_record(input, header, state))
+                .collect::<ip2x_core::Result<_>>()?,
+        ),
+        Kind::Elevation => Dataset::Elevation(
+            (0..count)
+                .map(|_| bin::read_elevation_record(input, header, state))
+                .collect::<ip2x_core::Result<_>>()?,
+        ),
+        Kind::Country => Dataset::Country(
+            (0..count)
+                .map(|_| bin::read_country_record(input, header, state))
+                .collect::<ip2x_core::Result<_>>()?,
+        ),
+        Kind::Proxy => {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "proxy_types.bin has no block index",
+            ))
+        }
+    })
+}
+
+pub fn section_bases(layout: Layout) -> &'static [u128] {
+    layout.header().section_bases()
+}
+
+pub fn write_geo(out: &mut impl Write, ranges: &[GeoRange], layout: Layout) -> Result<()> {
+    for (base, ranges) in sections(ranges, |r| (r.0, r.1), layout) {
+        out.write_all(&(ranges.len() as u32).to_le_bytes())?;
+        write_geo_records(out, base, ranges.iter().map(|r| Ok(*r)), layout)?;
+    }
+    Ok(())
+}
+
+pub fn write_geo_sorted<I>(
+    out: &mut impl Write,
+    layout: Layout,
+    mut pass: impl FnMut() -> Result<I>,
+) -> Result<usize>
+where
+    I: Iterator<Item = Result<GeoRange>>,
+{
+    write_header(out, layout)?;
+    let mut total = 0;
+    for &base in section_bases(layout) {
+        let in_section = |r: &Result<GeoRange>| match r {
+            Ok((from, to, ..)) => section_base(*from, *to, layout) == base,
+            Err(_) => true,
+        };
+        let mut count = 0u32;
+        for range in pass()?.filter(in_section) {
+            range?;
+            count += 1;
+        }
+        out.write_all(&count.to_le_bytes())?;
+        write_geo_records(out, base, pass()?.filter(in_section), layout)?;
+        total += count as usize;
+    }
+    Ok(total)
+}
+
+fn section_base(from: u128, to: u128, layout: Layout) -> u128 {
+    let ipv4 = from >= IPV4_BASE && to <= IPV4_BASE + u32::MAX as u128;
+    if layout.has(IPV4_ONLY) || layout.has(SPLIT_FAMILIES) && ipv4 {
+        IPV4_BASE
+    } else {
+        0
+    }
+}
+
+fn write_geo_records(
+    out: &mut impl Write,
+    base: u128,
+    ranges: impl Iterator<Item = Result<GeoRange>>,
+    layout: Layout,
+) -> Result<()> {
+    let cells = layout.geo_cells.filter(|_| layout.has(GEO_CELLS));
+    let payload = layout.has(GEO_PAYLOAD);
+    let delta = layout.has(DELTA_COORDS) || payload;
+    let scale = coordinate_scale(layout);
+    let mut prev_from = base;
+    let (mut prev_lat, mut prev_lon) = (0i64, 0i64);
+    let mut prev_cell = 0i64;
+    for range in ranges {
+        let (from, to, lat, lon, accuracy) = range?;
+        write_range(out, layout.header(), prev_from, from, to)?;
+        let unlocated = lat.is_nan() || lon.is_nan();
+        if unlocated && !payload {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Unlocated geo ranges need a geo payload descriptor",
+            ));
+        }
+        if payload {
+            let mut descriptor = 0;
+            if accuracy.is_some() {
+                descriptor |= HAS_ACCURACY;
+            }
+            if unlocated {
+                descriptor |= UNLOCATED;
+            }
+            out.write_all(&[descriptor])?;
+        }
+
+        if unlocated {
+            let regs = if cells.is_some() { 1 } else { 2 };
+            out.write_all(&[0; 2][..regs])?;
+        } else if let Some(grid) = cells {
+            let cell = grid.compact(grid.cell(lat, lon)?);
+            write_delta(out, &mut prev_cell, cell as i64)?;
+        } else if delta {
+            let lat_fixed = (lat as f64 * scale).round() as i64;
+            let lon_fixed = (lon as f64 * scale).round() as i64;
+            write_delta(out, &mut prev_lat, lat_fixed)?;
+            write_delta(out, &mut prev_lon, lon_fixed)?;
+        } else {
+            let lat_i32 = (lat * 1000.0).round() as i32;
+            let lon_i32 = (lon * 1000.0).round() as i32;
+            out.write_all(&lat_i32.to_le_bytes())?;
+            out.write_all(&lon_i32.to_le_bytes())?;
+        }
+
+        if payload {
+            if let Some(radius) = accuracy {
+                write_varint(out, radius as u128)?;
+            }
+            if unlocated {
+                write_varint(out, 0)?;
+            }
+        } else if layout.has(GEO_ACCURACY) {
+            match accuracy {
+                Some(radius) => {
+                    out.write_all(&[HAS_ACCURACY])?;
+                    write_varint(out, radius as u128)?;
+                }
+                None => out.write_all(&[0])?,
+            }
+        }
+
+        prev_from = from;
+    }
+    Ok(())
+}
+
+pub fn write_usage(out: &mut impl Write, ranges: &[UsageRange], layout: Layout) -> Result<()> {
+    for (base, ranges) in sections(ranges, |r| (r.0, r.1), layout) {
+        out.write_all(&(ranges.len() as u32).to_le_bytes())?;
+
+        let mut prev_from = base;
+        for (from, to, mask) in ranges.iter() {
+            write_range(out, layout.header(), prev_from, *from, *to)?;
+            write_varint(out, *mask as u128)?;
+            prev_from = *from;
+        }
+    }
+    Ok(())
+}
+
+pub fn write_elevation(
+    out: &mut impl Write,
+    ranges: &[ElevationRange],
+    layout: Layout,
+) -> Result<()> {
+    for (base, ranges) in sections(ranges, |r| (r.0, r.1), layout) {
+        out.write_all(&(ranges.len() as u32).to_le_bytes())?;
+
+        let mut prev_from = base;
+        for (from, to, meters) in ranges.iter() {
+            write_range(out, layout.header(), prev_from, *from, *to)?;
+            write_signed_varint(out, *meters as i64)?;
+            prev_from = *from;
+        }
+    }
+    Ok(())
+}
+
+pub fn subdivision_code(range: &CountryRange) -> Option<String> {
+    let subdivision = range.6.as_ref()?;
+    Some(format!(
+        "{}-{}",
+        String::from_utf8_lossy(&range.2),
+        subdivision
+    ))
+}
+
+pub fn write_country(out: &mut impl Write, ranges: &[CountryRange], layout: Layout) -> Result<()> {
+    for (base, ranges) in sections(ranges, |r| (r.0, r.1), layout) {
+        out.write_all(&(ranges.len() as u32).to_le_bytes())?;
+
+        let mut prev_from = base;
+        for (from, to, country, continent, eu, alternate, subdivision) in ranges.iter() {
+            write_range(out, layout.header(), prev_from, *from, *to)?;
+            let continent = CONTINENTS
+                .iter()
+                .position(|c| *c == continent)
+                .map_or(0, |i| i as u8 + 1);
+            let eu = if *eu { IN_EUROPEAN_UNION } else { 0 };
+            let source = match alternate {
+                None => 0,
+                Some((AlternateCountry::Registered, _)) => REGISTERED_COUNTRY,
+                Some((AlternateCountry::Represented, _)) => REPRESENTED_COUNTRY,
+            };
+            let has_subdivision = if subdivision.is_some() {
+                HAS_SUBDIVISION
+            } else {
+                0
+            };
+            out.write_all(&[
+                country[0],
+                country[1],
+                continent | eu | source | has_subdivision,
+            ])?;
+            if let Some((_, alternate)) = alternate {
+                out.write_all(alternate)?;
+            }
+            if let Some(subdivision) = subdivision {
+                out.write_all(&[subdivision.len() as u8])?;
+                out.write_all(subdivision.as_bytes())?;
+            }
+            prev_from = *from;
+        }
+    }
+    Ok(())
+}
+
+pub fn proxy_layout(types: &[(String, Vec<ProxyRange>)], layout: Layout) -> Layout {
+    let ranges = || types.iter().flat_map(|(_, ranges)| ranges);
+    let layout = match ranges().any(|r| r.2 != 1) {
+        true => layout.with(PROXY_CONFIDENCE),
+        false => layout,
+    };
+    match ranges().any(|r| r.3 != UNKNOWN_LAST_SEEN) {
+        true => layout.with(PROXY_LAST_SEEN),
+        false => layout,
+    }
+}
+
+pub fn write_proxy_types(
+    out: &mut impl Write,
+    types: &[(String, Vec<ProxyRange>)],
+    layout: Layout,
+) -> Result<()> {
+    out.write_all(&(types.len() as u16).to_le_bytes())?;
+
+    for (proxy_type, ranges) in types {
+        let bytes = proxy_type.as_bytes();
+        out.write_all(&(bytes.len() as u8).to_le_bytes())?;
+        out.write_all(bytes)?;
+
+        for (base, ranges) in sections(ranges, |r| (r.0, r.1), layout) {
+            out.write_all(&(ranges.len() as u32).to_le_bytes())?;
+
+            let mut prev_from = base;
+            for (from, to, confidence, last_seen) in ranges.iter() {
+                write_range(out, layout.header(), prev_from, *from, *to)?;
+                if layout.has(PROXY_CONFIDENCE) {
+                    out.write_all(&[*confidence])?;
+                }
+                if layout.has(PROXY_LAST_SEEN) {
+                    let days = match *last_seen {
+                        UNKNOWN_LAST_SEEN => 0,
+                        days => days as u128 + 1,
+                    };
+                    write_varint(out, days)?;
+                }
+                prev_from = *from;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn asn_layout(data: &[AsnRange], layout: Layout) -> Layout {
+    match data.iter().any(|r| r.5.is_some()) {
+        true => layout.with(ASN_ORGANIZATION),
+        false => layout,
+    }
+}
+
+pub fn write_asn(
+    out: &mut impl Write,
+    strings: &[String],
+    data: &[AsnRange],
+    layout: Layout,
+) -> Result<()> {
+    if !layout.has(SHARED_STRINGS) {
+        out.write_all(&(strings.len() as u32).to_le_bytes())?;
+        write_entries(out, strings, layout.has(PACKED_STRINGS))?;
+    }
+
+    for (base, data) in sections(data, |r| (r.0, r.1), layout) {
+        out.write_all(&(data.len() as u32).to_le_bytes())?;
+
+        let mut prev_from = base;
+        let mut prev = [0i64; 3];
+
+        for (from, to, cidr_idx, asn_idx, name_idx, org_idx) in data.iter() {
+            write_range(out, layout.header(), prev_from, *from, *to)?;
+
+            for (prev, idx) in prev.iter_mut().zip([cidr_idx, asn_idx, name_idx]) {
+                write_delta(out, prev, *idx as i64)?;
+            }
+            if layout.has(ASN_ORGANIZATION) {
+                write_varint(out, org_idx.map_or(0, |idx| idx as u128 + 1))?;
+            }
+
+            prev_from = *from;
+        }
+    }
+    Ok(())
+}
+
+pub fn write_isp(
+    out: &mut impl Write,
+    strings: &[String],
+    data: &[IspRange],
+    layout: Layout,
+) -> Result<()> {
+    let use_u16 = strings.len() < 65536;
+    if !layout.has(SHARED_STRINGS) {
+        write_string_table(out, strings, layout.has(PACKED_STRINGS))?;
+    }
+
+    for (base, data) in sections(data, |r| (r.0, r.1), layout) {
+        out.write_all(&(data.len() as u32).to_le_bytes())?;
+
+        let mut prev_from = base;
+        for (from, to, isp_idx, domain_idx, provider_idx) in data.iter() {
+            write_range(out, layout.header(), prev_from, *from, *to)?;
+
+            for idx in [isp_idx, domain_idx, provider_idx] {
+                if use_u16 {
+                    out.write_all(&(*idx as u16).to_le_bytes())?;
+                } else {
+                    out.write_all(&(*idx as u32).to_le_bytes())?;
+                }
+            }
+
+            prev_from = *from;
+        }
+    }
+    Ok(())
+}
+
+pub fn intern(s: &str, strings: &mut Vec<String>, map: &mut HashMap<String, usize>) -> usize {
+    if s == "-" {
+        return 0;
+    }
+
+    if let Some(&idx) = map.get(s) {
+        return idx;
+    }
+
+    strings.push(s.to_string());
+    let idx = strings.len() - 1;
+    map.insert(s.to_string(), idx);
+    idx
+}
+
+pub fn intern_with_offset(
+    s: &str,
+    strings: &mut Vec<String>,
+    map: &mut HashMap<String, usize>,
+) -> usize {
+    if s == "-" {
+        return 0;
+    }
+
+    if let Some(&idx) = map.get(s) {
+        return idx;
+    }
+
+    strings.push(s.to_string());
+    let idx = strings.len();
+    map.insert(s.to_string(), idx);
+    idx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLOUDFLARE: (u128, u128) = (IPV4_BASE | 0x0101_0100, IPV4_BASE | 0x0101_01ff);
+
+    fn asn(org: Option<usize>) -> (Vec<String>, Vec<AsnRange>) {
+        let strings = ["1.1.1.0/24", "13335", "CLOUDFLARENET", "Cloudflare, Inc."];
+        (
+            strings.map(str::to_string).to_vec(),
+            vec![(CLOUDFLARE.0, CLOUDFLARE.1, 0, 1, 2, org)],
+        )
+    }
+
+    fn round_trip(strings: Vec<String>, data: Vec<AsnRange>) -> (Vec<u8>, Vec<AsnRange>) {
+        let layout = asn_layout(&data, Layout::default());
+        let mut out = Vec::new();
+        Dataset::Asn(strings, data)
+            .write_to(&mut out, layout)
+            .unwrap();
+        match Dataset::read_from(&mut &out[..], Kind::Asn, "asn.bin").unwrap() {
+            Dataset::Asn(_, data) => (out, data),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn kind_comes_from_exact_artifact_names() {
+        assert_eq!(Kind::from_path("out/geo.bin"), Some(Kind::Geo));
+        assert_eq!(Kind::from_path("proxy_types.bin"), Some(Kind::Proxy));
+        assert_eq!(Kind::from_path("shards/asn/0a.bin"), Some(Kind::Asn));
+        for aux in [
+            "asn_index.bin",
+            "country_index.bin",
+            "isp_index.bin",
+            "isp_group.bin",
+            "domain.bin",
+            "network_type.bin",
+            "strings.bin",
+            "geo.merged.bin",
+        ] {
+            assert_eq!(Kind::from_path(aux), None, "{}", aux);
+        }
+    }
+
+    #[test]
+    fn asn_organization_round_trips() {
+        let (strings, data) = asn(Some(3));
+        let (bytes, read) = round_trip(strings, data.clone());
+        assert_eq!(read, data);
+        assert_eq!(bin::format_version(&bytes).unwrap(), FORMAT_VERSION);
+    }
+
+    #[test]
+    fn asn_without_organization_keeps_headerless_layout() {
+        let (strings, data) = asn(None);
+        let (bytes, read) = round_trip(strings, data.clone());
+        assert_eq!(read, data);
+        assert_eq!(bin::format_version(&bytes).unwrap(), LEGACY_FORMAT_VERSION);
+    }
+
+    fn proxy_round_trip(last_seen: &[u16]) -> (Vec<u8>, Vec<ProxyRange>) {
+        let ranges: Vec<ProxyRange> = last_seen
+            .iter()
+            .enumerate()
+            .map(|(i, &days)| (i as u128 * 256, i as u128 * 256 + 255, 1, days))
+            .collect();
+        let types = vec![("VPN".to_string(), ranges)];
+        let mut out = Vec::new();
+        Dataset::Proxy(types.clone())
+            .write_to(&mut out, proxy_layout(&types, Layout::default()))
+            .unwrap();
+        match Dataset::read_from(&mut &out[..], Kind::Proxy, "proxy_types.bin").unwrap() {
+            Dataset::Proxy(mut types) => (out, types.remove(0).1),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn proxy_last_seen_round_trips_with_unknown_sentinel() {
+        let last_seen = [0, 27, UNKNOWN_LAST_SEEN, UNKNOWN_LAST_SEEN - 1];
+        let (bytes, ranges) = proxy_round_trip(&last_seen);
+        assert_eq!(ranges.iter().map(|r| r.3).collect::<Vec<_>>(), last_seen);
+        assert_eq!(bin::format_version(&bytes).unwrap(), FORMAT_VERSION);
+    }
+
+    #[test]
+    fn proxy_last_seen_is_rejected_by_older_readers() {
+        let (mut bytes, _) = proxy_round_trip(&[27]);
+        assert!(bin::format_version(&bytes).unwrap() > bin::ORDER_FORMAT_VERSION);
+        bytes[HEADER_MAGIC.len()] = bin::ORDER_FORMAT_VERSION;
+        assert!(bin::read_header(&mut &bytes[..]).is_err());
+    }
+
+    #[test]
+    fn asn_organization_is_rejected_under_older_versions() {
+        let (strings, data) = asn(Some(3));
+        let (mut bytes, _) = round_trip(strings, data);
+        bytes[HEADER_MAGIC.len()] = bin::ORDER_FORMAT_VERSION;
+        assert!(bin::read_header(&mut &bytes[..]).is_err());
+    }
+}
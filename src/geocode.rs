@@ -0,0 +1,517 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufWriter, Error, ErrorKind, Read, Result, Write};
+
+use crate::cli::Args;
+use crate::csv_reader::CsvReader;
+use crate::encoding::{read_entries, read_varint, write_entries, write_varint};
+use crate::format::{intern, read_u32};
+use crate::ip::u128_to_ip;
+use crate::maxmind::CitySource;
+use crate::progress;
+use crate::reader::Ip2xReader;
+use crate::storage;
+use crate::{parse_f32, GEO_V4_CSV, GEO_V6_CSV};
+
+pub const CITIES_FILE: &str = "cities.bin";
+
+const EARTH_RADIUS_KM: f64 = 6371.0088;
+const BOX_MARGIN_DEGREES: f64 = 1e-6;
+
+pub type City = ([f32; 3], usize, usize, usize);
+
+pub struct CityIndex {
+    strings: Vec<String>,
+    cities: Vec<City>,
+    locales: Vec<String>,
+    country_names: HashMap<usize, Vec<usize>>,
+    city_names: HashMap<(usize, usize), Vec<usize>>,
+    subdivisions: HashMap<(usize, usize), usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Place {
+    pub city: String,
+    pub region: String,
+    pub subdivision_code: Option<String>,
+    pub country_code: String,
+    pub latitude: f32,
+    pub longitude: f32,
+    pub distance_km: f64,
+    pub names: Vec<(String, String, String)>,
+}
+
+impl Place {
+    pub fn name(&self, locale: &str) -> &str {
+        self.names
+            .iter()
+            .find(|(l, city, _)| l == locale && city != "-")
+            .map_or(&self.city, |(_, city, _)| city)
+    }
+
+    pub fn country_name(&self, locale: &str) -> Option<&str> {
+        self.names
+            .iter()
+            .find(|(l, _, country)| l == locale && country != "-")
+            .map(|(_, _, country)| country.as_str())
+    }
+}
+
+pub fn run_reverse(args: &Args) -> Result<()> {
+    let usage = || {
+        Error::new(
+            ErrorKind::InvalidInput,
+            "Usage: ip2x reverse <latitude> <longitude> [--bins <dir>] [--locale <code>]",
+        )
+    };
+    let [lat, lon] = args.positional() else {
+        return Err(usage());
+    };
+    let (Ok(lat), Ok(lon)) = (lat.parse(), lon.parse()) else {
+        return Err(usage());
+    };
+
+    let reader = Ip2xReader::open(args.value(&["--bins"]).unwrap_or("."))?;
+    let locale = args.value(&["--locale"]);
+    match reader.reverse_lookup(lat, lon) {
+        Some(place) => println!(
+            "{}, {}{}, {} ({}, {}) {:.1} km",
+            locale.map_or(place.city.as_str(), |locale| place.name(locale)),
+            place.region,
+            place
+                .subdivision_code
+                .as_ref()
+                .map(|code| format!(" ({})", code))
+                .unwrap_or_default(),
+            locale
+                .and_then(|locale| place.country_name(locale))
+                .unwrap_or(&place.country_code),
+            place.latitude,
+            place.longitude,
+            place.distance_km
+        ),
+        None => println!("No {} loaded", CITIES_FILE),
+    }
+    Ok(())
+}
+
+pub fn run_distance(args: &Args) -> Result<()> {
+    let [a, b] = args.positional() else {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Usage: ip2x distance <ip> <ip> [--bins <dir>]",
+        ));
+    };
+    let parse = |ip: &String| {
+        ip.parse()
+            .map_err(|_| Error::new(ErrorKind::InvalidInput, format!("Bad IP address: {}", ip)))
+    };
+
+    let reader = Ip2xReader::open(args.value(&["--bins"]).unwrap_or("."))?;
+    match reader.distance_km(parse(a)?, parse(b)?) {
+        Some(km) => println!("{:.1} km", km),
+        None => println!("No location for {} or {}", a, b),
+    }
+    Ok(())
+}
+
+pub fn run_within(args: &Args) -> Result<()> {
+    let usage = || {
+        Error::new(
+            ErrorKind::InvalidInput,
+            "Usage: ip2x within <latitude> <longitude> <radius_km> [--bins <dir>]",
+        )
+    };
+    let [lat, lon, radius] = args.positional() else {
+        return Err(usage());
+    };
+    let (Ok(lat), Ok(lon), Ok(radius)) = (lat.parse(), lon.parse(), radius.parse()) else {
+        return Err(usage());
+    };
+
+    let reader = Ip2xReader::open(args.value(&["--bins"]).unwrap_or("."))?;
+    for (from, to, lat, lon, _) in reader.ranges_within(lat, lon, radius)? {
+        println!("{}-{} ({}, {})", u128_to_ip(from), u128_to_ip(to), lat, lon);
+    }
+    Ok(())
+}
+
+pub fn haversine_km(lat1: f32, lon1: f32, lat2: f32, lon2: f32) -> f64 {
+    let (lat1, lat2) = ((lat1 as f64).to_radians(), (lat2 as f64).to_radians());
+    let dlat = lat2 - lat1;
+    let dlon = (lon2 as f64 - lon1 as f64).to_radians();
+    let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * a.sqrt().min(1.0).asin()
+}
+
+pub struct Radius {
+    latitude: f32,
+    longitude: f32,
+    radius_km: f64,
+    lat_band: f64,
+    lon_band: Option<f64>,
+}
+
+impl Radius {
+    pub fn new(latitude: f32, longitude: f32, radius_km: f64) -> Radius {
+        let angle = radius_km / EARTH_RADIUS_KM;
+        let lat_band = angle.to_degrees();
+        let lon_band = ((latitude as f64).abs() + lat_band < 90.0).then(|| {
+            (angle.sin() / (latitude as f64).to_radians().cos())
+                .min(1.0)
+                .asin()
+                .to_degrees()
+        });
+        Radius {
+            latitude,
+            longitude,
+            radius_km,
+            lat_band: lat_band + BOX_MARGIN_DEGREES,
+            lon_band: lon_band.map(|band| band + BOX_MARGIN_DEGREES),
+        }
+    }
+
+    pub fn contains(&self, latitude: f32, longitude: f32) -> bool {
+        if (latitude as f64 - self.latitude as f64).abs() > self.lat_band {
+            return false;
+        }
+        if let Some(band) = self.lon_band {
+            let diff = (longitude as f64 - self.longitude as f64).abs() % 360.0;
+            if diff.min(360.0 - diff) > band {
+                return false;
+            }
+        }
+        haversine_km(self.latitude, self.longitude, latitude, longitude) <= self.radius_km
+    }
+}
+
+fn unit_vector(lat: f32, lon: f32) -> [f32; 3] {
+    let (lat, lon) = ((lat as f64).to_radians(), (lon as f64).to_radians());
+    [
+        (lat.cos() * lon.cos()) as f32,
+        (lat.cos() * lon.sin()) as f32,
+        lat.sin() as f32,
+    ]
+}
+
+fn chord_squared(a: &[f32; 3], b: &[f32; 3]) -> f64 {
+    a.iter()
+        .zip(b)
+        .map(|(a, b)| (*a as f64 - *b as f64).powi(2))
+        .sum()
+}
+
+fn arrange(cities: &mut [City], depth: usize) {
+    if cities.len() <= 1 {
+        return;
+    }
+    let axis = depth % 3;
+    let mid = cities.len() / 2;
+    cities.select_nth_unstable_by(mid, |a, b| a.0[axis].total_cmp(&b.0[axis]));
+    let (left, right) = cities.split_at_mut(mid);
+    arrange(left, depth + 1);
+    arrange(&mut right[1..], depth + 1);
+}
+
+fn nearest(
+    cities: &[City],
+    target: &[f32; 3],
+    depth: usize,
+    best: &mut Option<(usize, f64)>,
+    offset: usize,
+) {
+    if cities.is_empty() {
+        return;
+    }
+    let mid = cities.len() / 2;
+    let distance = chord_squared(&cities[mid].0, target);
+    if best.is_none_or(|(_, d)| distance < d) {
+        *best = Some((offset + mid, distance));
+    }
+
+    let axis = depth % 3;
+    let diff = target[axis] as f64 - cities[mid].0[axis] as f64;
+    let (left, right) = (&cities[..mid], &cities[mid + 1..]);
+    let (near, far, near_offset, far_offset) = if diff < 0.0 {
+        (left, right, offset, offset + mid + 1)
+    } else {
+        (right, left, offset + mid + 1, offset)
+    };
+    nearest(near, target, depth + 1, best, near_offset);
+    if best.is_none_or(|(_, d)| diff * diff < d) {
+        nearest(far, target, depth + 1, best, far_offset);
+    }
+}
+
+fn optional_u32(input: &mut impl Read) -> Result<Option<u32>> {
+    match read_u32(input) {
+        Ok(value) => Ok(Some(value)),
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn chord_km(chord_squared: f64) -> f64 {
+    2.0 * EARTH_RADIUS_KM * (chord_squared.sqrt() / 2.0).min(1.0).asin()
+}
+
+impl CityIndex {
+    pub fn build(data_dir: &str, maxmind: &[String], locales: &[String]) -> Result<CityIndex> {
+        let mut strings = vec!["-".to_string()];
+        let mut string_map = HashMap::new();
+        let mut seen = HashSet::new();
+        let mut cities = Vec::new();
+
+        for csv in [GEO_V4_CSV, GEO_V6_CSV] {
+            let path = format!("{}/{}", data_dir, csv);
+            if !storage::exists(&path) {
+                continue;
+            }
+            let mut reader = CsvReader::new(progress::open(path)?);
+            while let Some(parts) = reader.next_record() {
+                let parts = parts?;
+                if parts.len() < 8 || &parts[5] == "-" {
+                    continue;
+                }
+                let (lat, lon) = (parse_f32(&parts[6]), parse_f32(&parts[7]));
+                if lat == 0.0 && lon == 0.0 {
+                    continue;
+                }
+                let key = (
+                    lat.to_bits(),
+                    lon.to_bits(),
+                    parts[2].to_string(),
+                    parts[4].to_string(),
+                    parts[5].to_string(),
+                );
+                if !seen.insert(key) {
+                    continue;
+                }
+                cities.push((
+                    unit_vector(lat, lon),
+                    intern(&parts[5], &mut strings, &mut string_map),
+                    intern(&parts[4], &mut strings, &mut string_map),
+                    intern(&parts[2], &mut strings, &mut string_map),
+                ));
+            }
+        }
+
+        let maxmind = maxmind
+            .iter()
+            .filter(|path| storage::exists(path))
+            .map(|path| CitySource::open(path))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut country_names = HashMap::new();
+        let mut city_names = HashMap::new();
+        let places: HashSet<(usize, usize)> = cities.iter().map(|c| (c.1, c.3)).collect();
+        for maxmind in maxmind.iter().filter(|_| !locales.is_empty()) {
+            for (country, city, localized_city, localized_country) in
+                maxmind.load_all_names(locales)
+            {
+                let Some(&country) = string_map.get(&country) else {
+                    continue;
+                };
+                country_names.entry(country).or_insert_with(|| {
+                    localized_country
+                        .iter()
+                        .map(|name| intern(name, &mut strings, &mut string_map))
+                        .collect()
+                });
+                let Some(&city) = string_map.get(&city) else {
+                    continue;
+                };
+                if places.contains(&(city, country)) {
+                    city_names.entry((city, country)).or_insert_with(|| {
+                        localized_city
+                            .iter()
+                            .map(|name| intern(name, &mut strings, &mut string_map))
+                            .collect()
+                    });
+                }
+            }
+        }
+
+        let mut subdivisions = HashMap::new();
+        for maxmind in &maxmind {
+            let codes = maxmind.subdivision_codes();
+            let regions: HashSet<(usize, usize)> = cities.iter().map(|c| (c.2, c.3)).collect();
+            for (region, country) in regions {
+                let Ok(code) = <[u8; 2]>::try_from(strings[country].as_bytes()) else {
+                    continue;
+                };
+                if subdivisions.contains_key(&(region, country)) {
+                    continue;
+                }
+                if let Some(subdivision) = codes.get(&(code, strings[region].clone())) {
+                    let subdivision = intern(subdivision, &mut strings, &mut string_map);
+                    subdivisions.insert((region, country), subdivision);
+                }
+            }
+        }
+
+        arrange(&mut cities, 0);
+        Ok(CityIndex {
+            strings,
+            cities,
+            locales: locales.to_vec(),
+            country_names,
+            city_names,
+            subdivisions,
+        })
+    }
+
+    pub fn city_count(&self) -> usize {
+        self.cities.len()
+    }
+
+    pub fn write(&self, path: &str) -> Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(&(self.strings.len() as u32).to_le_bytes())?;
+        write_entries(&mut out, &self.strings, true)?;
+
+        out.write_all(&(self.cities.len() as u32).to_le_bytes())?;
+        for (point, city, region, country) in &self.cities {
+            for coordinate in point {
+                out.write_all(&coordinate.to_le_bytes())?;
+            }
+            write_varint(&mut out, *city as u128)?;
+            write_varint(&mut out, *region as u128)?;
+            write_varint(&mut out, *country as u128)?;
+        }
+
+        out.write_all(&(self.locales.len() as u32).to_le_bytes())?;
+        write_entries(&mut out, &self.locales, true)?;
+        out.write_all(&(self.country_names.len() as u32).to_le_bytes())?;
+        for (country, names) in &self.country_names {
+            write_varint(&mut out, *country as u128)?;
+            for name in names {
+                write_varint(&mut out, *name as u128)?;
+            }
+        }
+        out.write_all(&(self.city_names.len() as u32).to_le_bytes())?;
+        for ((city, country), names) in &self.city_names {
+            write_varint(&mut out, *city as u128)?;
+            write_varint(&mut out, *country as u128)?;
+            for name in names {
+                write_varint(&mut out, *name as u128)?;
+            }
+        }
+
+        out.write_all(&(self.subdivisions.len() as u32).to_le_bytes())?;
+        for ((region, country), subdivision) in &self.subdivisions {
+            write_varint(&mut out, *region as u128)?;
+            write_varint(&mut out, *country as u128)?;
+            write_varint(&mut out, *subdivision as u128)?;
+        }
+        out.flush()
+    }
+
+    pub fn read(path: &str) -> Result<CityIndex> {
+        let bytes = fs::read(path)?;
+        let mut input = &bytes[..];
+        let count = read_u32(&mut input)? as usize;
+        let strings = read_entries(&mut input, count, true)?;
+
+        let count = read_u32(&mut input)? as usize;
+        let mut cities = Vec::with_capacity(count.min(1 << 20));
+        let string = |input: &mut &[u8]| -> Result<usize> {
+            let idx = read_varint(input)? as usize;
+            if idx >= strings.len() {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "City string out of range",
+                ));
+            }
+            Ok(idx)
+        };
+        for _ in 0..count {
+            let mut point = [0f32; 3];
+            for coordinate in &mut point {
+                let mut bytes = [0u8; 4];
+                input.read_exact(&mut bytes)?;
+                *coordinate = f32::from_le_bytes(bytes);
+            }
+            cities.push((
+                point,
+                string(&mut input)?,
+                string(&mut input)?,
+                string(&mut input)?,
+            ));
+        }
+
+        let section = optional_u32(&mut input)?;
+        let locales = match section {
+            Some(count) => read_entries(&mut input, count as usize, true)?,
+            None => Vec::new(),
+        };
+        let mut country_names = HashMap::new();
+        let mut city_names = HashMap::new();
+        if section.is_some() {
+            let names = |input: &mut &[u8]| -> Result<Vec<usize>> {
+                locales.iter().map(|_| string(input)).collect()
+            };
+            for _ in 0..read_u32(&mut input)? {
+                let country = string(&mut input)?;
+                country_names.insert(country, names(&mut input)?);
+            }
+            for _ in 0..read_u32(&mut input)? {
+                let key = (string(&mut input)?, string(&mut input)?);
+                city_names.insert(key, names(&mut input)?);
+            }
+        }
+
+        let mut subdivisions = HashMap::new();
+        for _ in 0..optional_u32(&mut input)?.unwrap_or(0) {
+            let key = (string(&mut input)?, string(&mut input)?);
+            subdivisions.insert(key, string(&mut input)?);
+        }
+
+        Ok(CityIndex {
+            strings,
+            cities,
+            locales,
+            country_names,
+            city_names,
+            subdivisions,
+        })
+    }
+
+    pub fn nearest(&self, lat: f32, lon: f32) -> Option<Place> {
+        let mut best = None;
+        nearest(&self.cities, &unit_vector(lat, lon), 0, &mut best, 0);
+        let (idx, chord_squared) = best?;
+        let ([x, y, z], city, region, country) = self.cities[idx];
+        let (x, y, z) = (x as f64, y as f64, z as f64);
+        let no_names = Vec::new();
+        let city_names = self.city_names.get(&(city, country)).unwrap_or(&no_names);
+        let country_names = self.country_names.get(&country).unwrap_or(&no_names);
+        let names = self
+            .locales
+            .iter()
+            .enumerate()
+            .map(|(i, locale)| {
+                let name = |names: &Vec<usize>| names.get(i).map_or("-", |&n| &self.strings[n]);
+                (
+                    locale.clone(),
+                    name(city_names).to_string(),
+                    name(country_names).to_string(),
+                )
+            })
+            .collect();
+        Some(Place {
+            city: self.strings[city].clone(),
+            region: self.strings[region].clone(),
+            subdivision_code: self
+                .subdivisions
+                .get(&(region, country))
+                .map(|&code| format!("{}-{}", self.strings[country], self.strings[code])),
+            country_code: self.strings[country].clone(),
+            latitude: z.atan2((x * x + y * y).sqrt()).to_degrees() as f32,
+            longitude: y.atan2(x).to_degrees() as f32,
+            distance_km: chord_km(chord_squared),
+            names,
+        })
+    }
+}
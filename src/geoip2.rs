@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+
+use crate::maxmind::Value;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Named {
+    pub geoname_id: Option<u32>,
+    pub names: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Continent {
+    pub code: Option<String>,
+    pub geoname_id: Option<u32>,
+    pub names: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CountryInfo {
+    pub iso_code: Option<String>,
+    pub geoname_id: Option<u32>,
+    pub names: HashMap<String, String>,
+    pub is_in_european_union: bool,
+    pub kind: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Subdivision {
+    pub iso_code: Option<String>,
+    pub geoname_id: Option<u32>,
+    pub names: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Location {
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub accuracy_radius: Option<u16>,
+    pub metro_code: Option<u16>,
+    pub time_zone: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Country {
+    pub continent: Option<Continent>,
+    pub country: Option<CountryInfo>,
+    pub registered_country: Option<CountryInfo>,
+    pub represented_country: Option<CountryInfo>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct City {
+    pub city: Option<Named>,
+    pub continent: Option<Continent>,
+    pub country: Option<CountryInfo>,
+    pub location: Option<Location>,
+    pub postal_code: Option<String>,
+    pub registered_country: Option<CountryInfo>,
+    pub represented_country: Option<CountryInfo>,
+    pub subdivisions: Vec<Subdivision>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Asn {
+    pub autonomous_system_number: Option<u32>,
+    pub autonomous_system_organization: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AnonymousIp {
+    pub is_anonymous: bool,
+    pub is_anonymous_vpn: bool,
+    pub is_hosting_provider: bool,
+    pub is_public_proxy: bool,
+    pub is_residential_proxy: bool,
+    pub is_tor_exit_node: bool,
+}
+
+fn string(value: &Value, key: &str) -> Option<String> {
+    value.get(key)?.as_str().map(str::to_string)
+}
+
+fn number<T: TryFrom<u64>>(value: &Value, key: &str) -> Option<T> {
+    T::try_from(value.get(key)?.as_u64()?).ok()
+}
+
+fn flag(value: &Value, key: &str) -> bool {
+    matches!(value.get(key), Some(Value::Bool(true)))
+}
+
+fn names(value: &Value) -> HashMap<String, String> {
+    let Some(names) = value.get("names").and_then(Value::as_map) else {
+        return HashMap::new();
+    };
+    names
+        .iter()
+        .filter_map(|(locale, name)| match name {
+            Value::String(name) => Some((locale.clone(), name.clone())),
+            _ => None,
+        })
+        .collect()
+}
+
+impl From<&Value> for Named {
+    fn from(value: &Value) -> Named {
+        Named {
+            geoname_id: number(value, "geoname_id"),
+            names: names(value),
+        }
+    }
+}
+
+impl From<&Value> for Continent {
+    fn from(value: &Value) -> Continent {
+        Continent {
+            code: string(value, "code"),
+            geoname_id: number(value, "geoname_id"),
+            names: names(value),
+        }
+    }
+}
+
+impl From<&Value> for CountryInfo {
+    fn from(value: &Value) -> CountryInfo {
+        CountryInfo {
+            iso_code: string(value, "iso_code"),
+            geoname_id: number(value, "geoname_id"),
+            names: names(value),
+            is_in_european_union: flag(value, "is_in_european_union"),
+            kind: string(value, "type"),
+        }
+    }
+}
+
+impl From<&Value> for Subdivision {
+    fn from(value: &Value) -> Subdivision {
+        Subdivision {
+            iso_code: string(value, "iso_code"),
+            geoname_id: number(value, "geoname_id"),
+            names: names(value),
+        }
+    }
+}
+
+impl From<&Value> for Location {
+    fn from(value: &Value) -> Location {
+        Location {
+            latitude: value.get("latitude").and_then(Value::as_f64),
+            longitude: value.get("longitude").and_then(Value::as_f64),
+            accuracy_radius: number(value, "accuracy_radius"),
+            metro_code: number(value, "metro_code"),
+            time_zone: string(value, "time_zone"),
+        }
+    }
+}
+
+impl From<&Value> for Country {
+    fn from(value: &Value) -> Country {
+        Country {
+            continent: value.get("continent").map(Continent::from),
+            country: value.get("country").map(CountryInfo::from),
+            registered_country: value.get("registered_country").map(CountryInfo::from),
+            represented_country: value.get("represented_country").map(CountryInfo::from),
+        }
+    }
+}
+
+impl From<&Value> for City {
+    fn from(value: &Value) -> City {
+        let Country {
+            continent,
+            country,
+            registered_country,
+            represented_country,
+        } = Country::from(value);
+        let subdivisions = match value.get("subdivisions") {
+            Some(Value::Array(subdivisions)) => {
+                subdivisions.iter().map(Subdivision::from).collect()
+            }
+            _ => Vec::new(),
+        };
+        City {
+            city: value.get("city").map(Named::from),
+            continent,
+            country,
+            location: value.get("location").map(Location::from),
+            postal_code: value
+                .get("postal")
+                .and_then(|postal| string(postal, "code")),
+            registered_country,
+            represented_country,
+            subdivisions,
+        }
+    }
+}
+
+impl From<&Value> for Asn {
+    fn from(value: &Value) -> Asn {
+        Asn {
+            autonomous_system_number: number(value, "autonomous_system_number"),
+            autonomous_system_organization: string(value, "autonomous_system_organization"),
+        }
+    }
+}
+
+impl From<&Value> for AnonymousIp {
+    fn from(value: &Value) -> AnonymousIp {
+        AnonymousIp {
+            is_anonymous: flag(value, "is_anonymous"),
+            is_anonymous_vpn: flag(value, "is_anonymous_vpn"),
+            is_hosting_provider: flag(value, "is_hosting_provider"),
+            is_public_proxy: flag(value, "is_public_proxy"),
+            is_residential_proxy: flag(value, "is_residential_proxy"),
+            is_tor_exit_node: flag(value, "is_tor_exit_node"),
+        }
+    }
+}
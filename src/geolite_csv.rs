@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Error, ErrorKind, Result};
+use std::path::Path;
+
+use crate::csv_reader::parse_line;
+use crate::ip::parse_cidr;
+use crate::maxmind::Value;
+use crate::progress;
+use crate::storage;
+
+pub const GEOLITE_BLOCKS_V4: &str = "GeoLite2-City-Blocks-IPv4.csv";
+const BLOCKS_V4_SUFFIX: &str = "-Blocks-IPv4.csv";
+
+type Columns = HashMap<String, usize>;
+
+struct Block {
+    from: u128,
+    to: u128,
+    geoname_id: Option<u64>,
+    registered: Option<u64>,
+    represented: Option<u64>,
+    location: Option<(f64, f64, Option<u64>)>,
+}
+
+pub struct GeoLiteCsv {
+    locations: HashMap<u64, HashMap<String, Value>>,
+    blocks: Vec<Block>,
+}
+
+fn read_rows(path: &Path) -> Result<(Columns, Vec<Vec<String>>)> {
+    let mut lines = progress::open(path)?.lines();
+    let header = match lines.next() {
+        Some(line) => parse_line(&line?),
+        None => Vec::new(),
+    };
+    let columns = header
+        .into_iter()
+        .enumerate()
+        .map(|(i, name)| (name, i))
+        .collect();
+    let rows = lines
+        .map(|line| line.map(|line| parse_line(&line)))
+        .collect::<Result<_>>()?;
+    Ok((columns, rows))
+}
+
+fn field<'a>(columns: &Columns, row: &'a [String], name: &str) -> Option<&'a str> {
+    let value = row.get(*columns.get(name)?)?;
+    (!value.is_empty()).then_some(value.as_str())
+}
+
+fn named(code: Option<(&str, &str)>, name: Option<&str>, locale: &str) -> HashMap<String, Value> {
+    let mut map = HashMap::new();
+    if let Some((key, code)) = code {
+        map.insert(key.to_string(), Value::String(code.to_string()));
+    }
+    add_name(&mut map, locale, name);
+    map
+}
+
+fn add_name(map: &mut HashMap<String, Value>, locale: &str, name: Option<&str>) {
+    let Some(name) = name else {
+        return;
+    };
+    if let Value::Map(names) = map
+        .entry("names".to_string())
+        .or_insert_with(|| Value::Map(HashMap::new()))
+    {
+        names.insert(locale.to_string(), Value::String(name.to_string()));
+    }
+}
+
+#[cfg(feature = "watch")]
+pub fn is_companion(blocks_v4: &Path, path: &Path) -> bool {
+    let name = |path: &Path| {
+        path.file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()
+    };
+    match name(blocks_v4).strip_suffix(BLOCKS_V4_SUFFIX) {
+        Some(edition) => {
+            name(path).starts_with(&format!("{}-", edition)) && name(path).ends_with(".csv")
+        }
+        None => false,
+    }
+}
+
+impl GeoLiteCsv {
+    pub fn open(blocks_v4: &str) -> Result<GeoLiteCsv> {
+        let blocks_v4 = Path::new(blocks_v4);
+        let file_name = blocks_v4.file_name().unwrap_or_default().to_string_lossy();
+        let Some(edition) = file_name.strip_suffix(BLOCKS_V4_SUFFIX) else {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Not a GeoLite2 Blocks-IPv4 CSV: {}", blocks_v4.display()),
+            ));
+        };
+        let dir = blocks_v4.parent().unwrap_or(Path::new("."));
+
+        let mut locations = HashMap::new();
+        let prefix = format!("{}-Locations-", edition);
+        let mut files = vec![dir.join(format!("{}en.csv", prefix))];
+        for path in storage::list(dir)? {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            if name.starts_with(&prefix) && name.ends_with(".csv") && !files.contains(&path) {
+                files.push(path);
+            }
+        }
+        for path in files {
+            let (columns, rows) = read_rows(&path)?;
+            for row in rows {
+                let get = |name: &str| field(&columns, &row, name);
+                let Some(id) = get("geoname_id").and_then(|id| id.parse().ok()) else {
+                    continue;
+                };
+                let locale = get("locale_code").unwrap_or("en");
+                let record: &mut HashMap<String, Value> = locations.entry(id).or_default();
+                if !record.is_empty() {
+                    for (key, value) in record.iter_mut() {
+                        match (key.as_str(), value) {
+                            ("continent", Value::Map(map)) => {
+                                add_name(map, locale, get("continent_name"))
+                            }
+                            ("country", Value::Map(map)) => {
+                                add_name(map, locale, get("country_name"))
+                            }
+                            ("city", Value::Map(map)) => add_name(map, locale, get("city_name")),
+                            ("subdivisions", Value::Array(subdivisions)) => {
+                                for (i, subdivision) in subdivisions.iter_mut().enumerate() {
+                                    if let Value::Map(map) = subdivision {
+                                        let column = format!("subdivision_{}_name", i + 1);
+                                        add_name(map, locale, get(&column));
+                                    }
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                    continue;
+                }
+
+                let continent = get("continent_code").map(|code| ("code", code));
+                let continent = named(continent, get("continent_name"), locale);
+                record.insert("continent".to_string(), Value::Map(continent));
+                let country = get("country_iso_code").map(|code| ("iso_code", code));
+                let mut country = named(country, get("country_name"), locale);
+                if get("is_in_european_union") == Some("1") {
+                    country.insert("is_in_european_union".to_string(), Value::Bool(true));
+                }
+                record.insert("country".to_string(), Value::Map(country));
+                let subdivisions: Vec<Value> = (1..=2)
+                    .map(|i| {
+                        let code = get(&format!("subdivision_{}_iso_code", i));
+                        let name = get(&format!("subdivision_{}_name", i));
+                        named(code.map(|code| ("iso_code", code)), name, locale)
+                    })
+                    .take_while(|subdivision| !subdivision.is_empty())
+                    .map(Value::Map)
+                    .collect();
+                if !subdivisions.is_empty() {
+                    record.insert("subdivisions".to_string(), Value::Array(subdivisions));
+                }
+                if let Some(city) = get("city_name") {
+                    record.insert(
+                        "city".to_string(),
+                        Value::Map(named(None, Some(city), locale)),
+                    );
+                }
+            }
+        }
+
+        let mut blocks = Vec::new();
+        let blocks_v6 = dir.join(format!("{}-Blocks-IPv6.csv", edition));
+        for path in [blocks_v4.to_path_buf(), blocks_v6] {
+            if !storage::exists(&path) {
+                continue;
+            }
+            let (columns, rows) = read_rows(&path)?;
+            for row in rows {
+                let get = |name: &str| field(&columns, &row, name);
+                let Some((from, to)) = get("network").and_then(parse_cidr) else {
+                    continue;
+                };
+                let id = |name: &str| get(name).and_then(|id| id.parse().ok());
+                let coordinate = |name: &str| get(name).and_then(|value| value.parse().ok());
+                let location = match (coordinate("latitude"), coordinate("longitude")) {
+                    (Some(lat), Some(lon)) => Some((lat, lon, id("accuracy_radius"))),
+                    _ => None,
+                };
+                blocks.push(Block {
+                    from,
+                    to,
+                    geoname_id: id("geoname_id"),
+                    registered: id("registered_country_geoname_id"),
+                    represented: id("represented_country_geoname_id"),
+                    location,
+                });
+            }
+        }
+
+        Ok(GeoLiteCsv { locations, blocks })
+    }
+
+    pub fn records_with<T>(
+        &self,
+        extract: impl Fn(u128, u128, &HashMap<String, Value>) -> Option<T>,
+    ) -> Vec<T> {
+        let country = |id: Option<u64>| self.locations.get(&id?)?.get("country").cloned();
+        self.blocks
+            .iter()
+            .filter_map(|block| {
+                let mut record = block
+                    .geoname_id
+                    .and_then(|id| self.locations.get(&id))
+                    .cloned()
+                    .unwrap_or_default();
+                if let Some(registered) = country(block.registered) {
+                    record.insert("registered_country".to_string(), registered);
+                }
+                if let Some(represented) = country(block.represented) {
+                    record.insert("represented_country".to_string(), represented);
+                }
+                if let Some((lat, lon, radius)) = block.location {
+                    let mut location = HashMap::from([
+                        ("latitude".to_string(), Value::Double(lat)),
+                        ("longitude".to_string(), Value::Double(lon)),
+                    ]);
+                    if let Some(radius) = radius {
+                        location.insert("accuracy_radius".to_string(), Value::UInt(radius));
+                    }
+                    record.insert("location".to_string(), Value::Map(location));
+                }
+                extract(block.from, block.to, &record)
+            })
+            .collect()
+    }
+}
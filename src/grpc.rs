@@ -0,0 +1,237 @@
+use std::io::{Error, Result};
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use tokio::net::TcpListener;
+use tonic::transport::server::TcpIncoming;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status, Streaming};
+
+use crate::logging;
+use crate::metrics;
+use crate::reader;
+use crate::serve::Live;
+
+include!(concat!(env!("OUT_DIR"), "/ip2x.v1.Ip2x.rs"));
+
+use ip2x_server::Ip2xServer;
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct LookupRequest {
+    #[prost(string, tag = "1")]
+    pub ip: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Geo {
+    #[prost(float, tag = "1")]
+    pub latitude: f32,
+    #[prost(float, tag = "2")]
+    pub longitude: f32,
+    #[prost(uint32, optional, tag = "3")]
+    pub accuracy_radius: Option<u32>,
+    #[prost(uint64, optional, tag = "4")]
+    pub cell: Option<u64>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Country {
+    #[prost(string, tag = "1")]
+    pub country_code: String,
+    #[prost(string, tag = "2")]
+    pub continent_code: String,
+    #[prost(bool, tag = "3")]
+    pub is_in_european_union: bool,
+    #[prost(string, optional, tag = "4")]
+    pub registered_country_code: Option<String>,
+    #[prost(string, optional, tag = "5")]
+    pub represented_country_code: Option<String>,
+    #[prost(string, optional, tag = "6")]
+    pub subdivision_code: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Asn {
+    #[prost(string, tag = "1")]
+    pub cidr: String,
+    #[prost(string, tag = "2")]
+    pub asn: String,
+    #[prost(string, tag = "3")]
+    pub as_name: String,
+    #[prost(string, optional, tag = "4")]
+    pub organization: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Proxy {
+    #[prost(string, tag = "1")]
+    pub proxy_type: String,
+    #[prost(uint32, tag = "2")]
+    pub confidence: u32,
+    #[prost(uint32, optional, tag = "3")]
+    pub last_seen: Option<u32>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Isp {
+    #[prost(string, tag = "1")]
+    pub isp: String,
+    #[prost(string, tag = "2")]
+    pub domain: String,
+    #[prost(string, tag = "3")]
+    pub provider: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Mobile {
+    #[prost(string, tag = "1")]
+    pub mcc: String,
+    #[prost(string, tag = "2")]
+    pub mnc: String,
+    #[prost(string, tag = "3")]
+    pub mobile_brand: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct UsageType {
+    #[prost(string, tag = "1")]
+    pub usage_type: String,
+    #[prost(uint32, tag = "2")]
+    pub mask: u32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Record {
+    #[prost(string, tag = "1")]
+    pub ip: String,
+    #[prost(message, optional, tag = "2")]
+    pub geo: Option<Geo>,
+    #[prost(message, optional, tag = "3")]
+    pub country: Option<Country>,
+    #[prost(message, optional, tag = "4")]
+    pub asn: Option<Asn>,
+    #[prost(message, optional, tag = "5")]
+    pub proxy: Option<Proxy>,
+    #[prost(message, optional, tag = "6")]
+    pub isp: Option<Isp>,
+    #[prost(message, optional, tag = "7")]
+    pub mobile: Option<Mobile>,
+    #[prost(message, optional, tag = "8")]
+    pub usage_type: Option<UsageType>,
+    #[prost(sint32, optional, tag = "9")]
+    pub elevation: Option<i32>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct LookupBatchResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub records: Vec<Record>,
+}
+
+impl From<reader::Record> for Record {
+    fn from(record: reader::Record) -> Record {
+        Record {
+            ip: record.ip.to_string(),
+            geo: record.geo.map(|geo| Geo {
+                latitude: geo.latitude,
+                longitude: geo.longitude,
+                accuracy_radius: geo.accuracy_radius.map(u32::from),
+                cell: geo.cell,
+            }),
+            country: record.country.map(|country| Country {
+                country_code: country.country_code,
+                continent_code: country.continent_code,
+                is_in_european_union: country.is_in_european_union,
+                registered_country_code: country.registered_country_code,
+                represented_country_code: country.represented_country_code,
+                subdivision_code: country.subdivision_code,
+            }),
+            asn: record.asn.map(|asn| Asn {
+                cidr: asn.cidr,
+                asn: asn.asn,
+                as_name: asn.as_name,
+                organization: asn.organization,
+            }),
+            proxy: record.proxy.map(|proxy| Proxy {
+                proxy_type: proxy.proxy_type,
+                confidence: proxy.confidence.into(),
+                last_seen: proxy.last_seen.map(u32::from),
+            }),
+            isp: record.isp.map(|isp| Isp {
+                isp: isp.isp,
+                domain: isp.domain,
+                provider: isp.provider,
+            }),
+            mobile: record.mobile.map(|mobile| Mobile {
+                mcc: mobile.mcc,
+                mnc: mobile.mnc,
+                mobile_brand: mobile.mobile_brand,
+            }),
+            usage_type: record.usage_type.map(|usage| UsageType {
+                usage_type: usage.usage_type,
+                mask: usage.mask.into(),
+            }),
+            elevation: record.elevation.map(i32::from),
+        }
+    }
+}
+
+struct Service(Arc<Live>);
+
+fn parse_ip(request: &LookupRequest) -> std::result::Result<IpAddr, Status> {
+    request
+        .ip
+        .parse()
+        .map_err(|_| Status::invalid_argument(format!("Bad IP address: {}", request.ip)))
+}
+
+#[tonic::async_trait]
+impl ip2x_server::Ip2x for Service {
+    async fn lookup(
+        &self,
+        request: Request<LookupRequest>,
+    ) -> std::result::Result<Response<Record>, Status> {
+        let ip = parse_ip(request.get_ref())?;
+        let timer = metrics::timer("grpc");
+        let record = self.0.get().lookup_many(&[ip]).remove(0);
+        timer.observe(1);
+        Ok(Response::new(record.into()))
+    }
+
+    async fn lookup_batch(
+        &self,
+        request: Request<Streaming<LookupRequest>>,
+    ) -> std::result::Result<Response<LookupBatchResponse>, Status> {
+        let mut stream = request.into_inner();
+        let mut ips = Vec::new();
+        while let Some(request) = stream.message().await? {
+            ips.push(parse_ip(&request)?);
+        }
+        let timer = metrics::timer("grpc_batch");
+        let records = self.0.get().lookup_many(&ips);
+        timer.observe(ips.len());
+        Ok(Response::new(LookupBatchResponse {
+            records: records.into_iter().map(Record::from).collect(),
+        }))
+    }
+}
+
+pub async fn bind(
+    live: Arc<Live>,
+    addr: &str,
+) -> Result<impl std::future::Future<Output = Result<()>>> {
+    let listener = TcpListener::bind(addr)
+        .await
+        .map_err(|err| Error::new(err.kind(), format!("Cannot bind {}: {}", addr, err)))?;
+    logging::info(&format!(
+        "serving gRPC lookups on {}",
+        listener.local_addr()?
+    ));
+    let server = Server::builder().add_service(Ip2xServer::new(Service(live)));
+    Ok(async move {
+        server
+            .serve_with_incoming(TcpIncoming::from(listener))
+            .await
+            .map_err(Error::other)
+    })
+}
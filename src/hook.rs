@@ -0,0 +1,47 @@
+use std::io::{Error, ErrorKind, Result};
+use std::process::Command;
+
+use crate::format::Kind;
+use crate::manifest::sha256;
+use crate::validate::validate_file;
+
+fn shell(command: &str) -> Command {
+    let (program, flag) = match cfg!(windows) {
+        true => ("cmd", "/C"),
+        false => ("sh", "-c"),
+    };
+    let mut shell = Command::new(program);
+    shell.args([flag, command]);
+    shell
+}
+
+pub fn run(command: &str, artifacts: &[(String, Option<usize>)]) -> Result<()> {
+    for (path, records) in artifacts {
+        if let Some(kind) = Kind::from_artifact(path) {
+            if let Some(issue) = validate_file(path, kind).first() {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "{} failed validation, post-build hook not run: {}",
+                        path, issue
+                    ),
+                ));
+            }
+        }
+        let status = shell(&command.replace("{artifact}", path))
+            .env("IP2X_ARTIFACT", path)
+            .env("IP2X_SHA256", sha256(path)?)
+            .env(
+                "IP2X_RECORDS",
+                records.map_or(String::new(), |r| r.to_string()),
+            )
+            .status()?;
+        if !status.success() {
+            return Err(Error::other(format!(
+                "Post-build hook failed for {}: {}",
+                path, status
+            )));
+        }
+    }
+    Ok(())
+}
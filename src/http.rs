@@ -0,0 +1,524 @@
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read, Result, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::tls::Tls;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_REDIRECTS: usize = 5;
+const CREDENTIAL_HEADERS: &[&str] = &["Authorization", "Cookie", "x-amz-security-token"];
+
+pub struct Response {
+    pub status: u16,
+    headers: Vec<(String, String)>,
+}
+
+impl Response {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+trait Stream: Read + Write {}
+
+impl<T: Read + Write> Stream for T {}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+pub fn split_url(url: &str) -> Result<(bool, &str, &str)> {
+    let (secure, rest) = match (url.strip_prefix("http://"), url.strip_prefix("https://")) {
+        (Some(rest), _) => (false, rest),
+        (_, Some(rest)) => (true, rest),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Bad URL: {}", url),
+            ))
+        }
+    };
+    Ok(match rest.find('/') {
+        Some(at) => (secure, &rest[..at], &rest[at..]),
+        None => (secure, rest, "/"),
+    })
+}
+
+fn with_port(host: &str, port: u16) -> String {
+    match host.contains(':') && !host.ends_with(']') {
+        true => host.to_string(),
+        false => format!("{}:{}", host, port),
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().fold(0u32, |n, &byte| n << 8 | byte as u32) << (8 * (3 - chunk.len()));
+        for i in 0..4 {
+            out.push(match i <= chunk.len() {
+                true => ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char,
+                false => '=',
+            });
+        }
+    }
+    out
+}
+
+fn env(names: &[&str]) -> Option<String> {
+    names
+        .iter()
+        .find_map(|name| std::env::var(name).ok().filter(|value| !value.is_empty()))
+}
+
+fn host_name(host: &str) -> &str {
+    let name = match host.rsplit_once(':') {
+        Some((name, port)) if !name.is_empty() && port.parse::<u16>().is_ok() => name,
+        _ => host,
+    };
+    name.trim_start_matches('[').trim_end_matches(']')
+}
+
+fn bypass(host: &str) -> bool {
+    let name = host_name(host);
+    env(&["no_proxy", "NO_PROXY"]).is_some_and(|list| {
+        list.split(',').map(str::trim).any(|entry| {
+            let entry = entry.trim_start_matches('.');
+            entry == "*"
+                || !entry.is_empty() && (name == entry || name.ends_with(&format!(".{}", entry)))
+        })
+    })
+}
+
+fn socks5(
+    stream: &mut TcpStream,
+    address: &str,
+    remote_dns: bool,
+    auth: Option<&str>,
+) -> Result<()> {
+    let fail = |message: &str| {
+        Error::new(
+            ErrorKind::ConnectionRefused,
+            format!("SOCKS5 proxy: {}", message),
+        )
+    };
+    let methods: &[u8] = match auth {
+        Some(_) => &[0, 2],
+        None => &[0],
+    };
+    stream.write_all(&[&[5, methods.len() as u8], methods].concat())?;
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply)?;
+    match (reply[1], auth) {
+        (0, _) => {}
+        (2, Some(auth)) => {
+            let (user, password) = auth.split_once(':').unwrap_or((auth, ""));
+            let mut request = vec![1, user.len() as u8];
+            request.extend_from_slice(user.as_bytes());
+            request.push(password.len() as u8);
+            request.extend_from_slice(password.as_bytes());
+            stream.write_all(&request)?;
+            stream.read_exact(&mut reply)?;
+            if reply[1] != 0 {
+                return Err(fail("authentication failed"));
+            }
+        }
+        _ => return Err(fail("no acceptable authentication method")),
+    }
+
+    let (name, port) = address.rsplit_once(':').unwrap_or((address, ""));
+    let port: u16 = port.parse().unwrap_or(80);
+    let resolved = match address.parse::<SocketAddr>() {
+        Ok(address) => Some(address),
+        Err(_) if remote_dns => None,
+        Err(_) => Some(
+            address
+                .to_socket_addrs()?
+                .next()
+                .ok_or_else(|| fail(&format!("cannot resolve {}", address)))?,
+        ),
+    };
+    let mut request = vec![5, 1, 0];
+    match resolved {
+        Some(SocketAddr::V4(address)) => {
+            request.push(1);
+            request.extend_from_slice(&address.ip().octets());
+        }
+        Some(SocketAddr::V6(address)) => {
+            request.push(4);
+            request.extend_from_slice(&address.ip().octets());
+        }
+        None => {
+            request.extend_from_slice(&[3, name.len() as u8]);
+            request.extend_from_slice(name.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request)?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply)?;
+    if reply[1] != 0 {
+        return Err(fail(&format!(
+            "connect to {} failed with code {}",
+            address, reply[1]
+        )));
+    }
+    let skip = match reply[3] {
+        1 => 4,
+        4 => 16,
+        _ => {
+            let mut len = [0u8; 1];
+            stream.read_exact(&mut len)?;
+            len[0] as usize
+        }
+    };
+    stream.read_exact(&mut vec![0u8; skip + 2])
+}
+
+fn tunnel(stream: &mut TcpStream, address: &str, headers: &str) -> Result<()> {
+    let request = format!(
+        "CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n{1}\r\n",
+        address, headers
+    );
+    stream.write_all(request.as_bytes())?;
+    let mut reply = Vec::new();
+    let mut byte = [0u8; 1];
+    while !reply.ends_with(b"\r\n\r\n") {
+        stream.read_exact(&mut byte)?;
+        reply.push(byte[0]);
+    }
+    let reply = String::from_utf8_lossy(&reply);
+    let status = reply.lines().next().unwrap_or("");
+    match status.split_whitespace().nth(1) {
+        Some("200") => Ok(()),
+        _ => Err(Error::new(
+            ErrorKind::ConnectionRefused,
+            format!("HTTP proxy: CONNECT to {} failed: {}", address, status),
+        )),
+    }
+}
+
+fn connect(host: &str, secure: bool) -> Result<(TcpStream, Option<String>)> {
+    let (names, address): (&[&str], _) = match secure {
+        true => (
+            &["https_proxy", "HTTPS_PROXY", "all_proxy", "ALL_PROXY"],
+            with_port(host, 443),
+        ),
+        false => (
+            &["http_proxy", "HTTP_PROXY", "all_proxy", "ALL_PROXY"],
+            with_port(host, 80),
+        ),
+    };
+    let proxy = env(names).filter(|_| !bypass(host));
+    let Some(proxy) = proxy else {
+        let stream = TcpStream::connect(&address).map_err(|err| {
+            Error::new(err.kind(), format!("Cannot connect to {}: {}", host, err))
+        })?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        return Ok((stream, None));
+    };
+
+    let (scheme, rest) = proxy.split_once("://").unwrap_or(("http", &proxy));
+    let rest = rest.trim_end_matches('/');
+    let (auth, proxy_address) = match rest.rsplit_once('@') {
+        Some((auth, address)) => (Some(auth), address),
+        None => (None, rest),
+    };
+    let port = match scheme {
+        "http" => 80,
+        "socks5" | "socks5h" => 1080,
+        _ => {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                format!(
+                    "Unsupported proxy: {} (expected http://, socks5:// or socks5h://)",
+                    proxy
+                ),
+            ))
+        }
+    };
+    let mut stream = TcpStream::connect(with_port(proxy_address, port)).map_err(|err| {
+        Error::new(
+            err.kind(),
+            format!("Cannot connect to proxy {}: {}", proxy_address, err),
+        )
+    })?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    match scheme {
+        "http" => {
+            let headers = auth.map_or(String::new(), |auth| {
+                format!("Proxy-Authorization: Basic {}\r\n", base64(auth.as_bytes()))
+            });
+            match secure {
+                true => {
+                    tunnel(&mut stream, &address, &headers)?;
+                    Ok((stream, None))
+                }
+                false => Ok((stream, Some(headers))),
+            }
+        }
+        _ => {
+            socks5(&mut stream, &address, scheme == "socks5h", auth)?;
+            Ok((stream, None))
+        }
+    }
+}
+
+fn resolve(base: &str, location: &str) -> Result<String> {
+    if location.starts_with("http://") || location.starts_with("https://") {
+        return Ok(location.to_string());
+    }
+    let (secure, host, path) = split_url(base)?;
+    let scheme = match secure {
+        true => "https",
+        false => "http",
+    };
+    Ok(match location {
+        _ if location.starts_with("//") => format!("{}:{}", scheme, location),
+        _ if location.starts_with('/') => format!("{}://{}{}", scheme, host, location),
+        _ => {
+            let path = path.split(['?', '#']).next().unwrap_or("/");
+            let dir = &path[..path.rfind('/').map_or(0, |at| at + 1)];
+            format!("{}://{}{}{}", scheme, host, dir, location)
+        }
+    })
+}
+
+pub fn get<W: Write>(
+    url: &str,
+    headers: &[(&str, &str)],
+    tls: Option<&Tls>,
+    body: impl FnOnce(&Response) -> Result<W>,
+) -> Result<Response> {
+    let mut url = url.to_string();
+    let mut headers = headers.to_vec();
+    for _ in 0..=MAX_REDIRECTS {
+        let (response, reader) = send(&url, &headers, tls)?;
+        let location = match response.status {
+            301 | 302 | 303 | 307 | 308 => response.header("Location"),
+            _ => None,
+        };
+        let Some(location) = location else {
+            return read_body(&url, response, reader, body);
+        };
+        let next = resolve(&url, location)?;
+        let (secure, host, _) = split_url(&url)?;
+        let (next_secure, next_host, _) = split_url(&next)?;
+        if secure && !next_secure {
+            return Err(invalid(format!(
+                "{}: refusing redirect from https to {}",
+                url, next
+            )));
+        }
+        if !next_host.eq_ignore_ascii_case(host) {
+            headers.retain(|(name, _)| {
+                !CREDENTIAL_HEADERS
+                    .iter()
+                    .any(|credential| name.eq_ignore_ascii_case(credential))
+            });
+        }
+        url = next;
+    }
+    Err(invalid(format!(
+        "{}: more than {} redirects",
+        url, MAX_REDIRECTS
+    )))
+}
+
+fn send(
+    url: &str,
+    headers: &[(&str, &str)],
+    tls: Option<&Tls>,
+) -> Result<(Response, BufReader<Box<dyn Stream>>)> {
+    let (secure, host, path) = split_url(url)?;
+    let (stream, proxy_headers) = connect(host, secure)?;
+    let target = match proxy_headers {
+        Some(_) => url,
+        None => path,
+    };
+    let mut stream: Box<dyn Stream> = match (secure, tls) {
+        (false, _) => Box::new(stream),
+        (true, Some(tls)) => Box::new(tls.connect(host_name(host), stream)?),
+        (true, None) => Box::new(Tls::open(None)?.connect(host_name(host), stream)?),
+    };
+
+    let mut request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: ip2x/{}\r\nConnection: close\r\n",
+        target,
+        host,
+        env!("CARGO_PKG_VERSION")
+    );
+    request.push_str(proxy_headers.as_deref().unwrap_or(""));
+    for (name, value) in headers {
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes())?;
+
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let status = line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| invalid(format!("{}: bad HTTP status line", url)))?;
+    let mut response = Response {
+        status,
+        headers: Vec::new(),
+    };
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            response
+                .headers
+                .push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    Ok((response, reader))
+}
+
+fn read_body<W: Write>(
+    url: &str,
+    response: Response,
+    mut reader: BufReader<Box<dyn Stream>>,
+    body: impl FnOnce(&Response) -> Result<W>,
+) -> Result<Response> {
+    let mut line = String::new();
+    if response.status == 304 || response.status == 204 {
+        return Ok(response);
+    }
+    let mut body = body(&response)?;
+    let chunked = response
+        .header("Transfer-Encoding")
+        .is_some_and(|encoding| encoding.eq_ignore_ascii_case("chunked"));
+    if chunked {
+        loop {
+            line.clear();
+            reader.read_line(&mut line)?;
+            let size = line.trim().split(';').next().unwrap_or("");
+            let size = u64::from_str_radix(size, 16)
+                .map_err(|_| invalid(format!("{}: bad chunk size", url)))?;
+            if size == 0 {
+                break;
+            }
+            let copied = std::io::copy(&mut (&mut reader).take(size), &mut body)?;
+            if copied < size {
+                return Err(invalid(format!("{}: truncated response", url)));
+            }
+            line.clear();
+            reader.read_line(&mut line)?;
+        }
+    } else if let Some(len) = response.header("Content-Length") {
+        let len: u64 = len
+            .parse()
+            .map_err(|_| invalid(format!("{}: bad Content-Length", url)))?;
+        if std::io::copy(&mut reader.take(len), &mut body)? < len {
+            return Err(invalid(format!("{}: truncated response", url)));
+        }
+    } else {
+        std::io::copy(&mut reader, &mut body)?;
+    }
+    body.flush()?;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn serve(listener: TcpListener, replies: Vec<String>) -> thread::JoinHandle<Vec<String>> {
+        thread::spawn(move || {
+            let mut requests = Vec::new();
+            for reply in replies {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = Vec::new();
+                let mut byte = [0u8; 1];
+                while !request.ends_with(b"\r\n\r\n") {
+                    stream.read_exact(&mut byte).unwrap();
+                    request.push(byte[0]);
+                }
+                requests.push(String::from_utf8_lossy(&request).into_owned());
+                stream.write_all(reply.as_bytes()).unwrap();
+            }
+            requests
+        })
+    }
+
+    fn redirect(location: &str) -> String {
+        format!(
+            "HTTP/1.1 302 Found\r\nLocation: {}\r\nContent-Length: 0\r\n\r\n",
+            location
+        )
+    }
+
+    #[test]
+    fn resolves_redirect_locations() {
+        let base = "http://builder:8080/v1/dataset/manifest.json?x=1";
+        assert_eq!(
+            resolve(base, "geo.bin").unwrap(),
+            "http://builder:8080/v1/dataset/geo.bin"
+        );
+        assert_eq!(
+            resolve(base, "/bins/geo.bin").unwrap(),
+            "http://builder:8080/bins/geo.bin"
+        );
+        assert_eq!(
+            resolve(base, "//cdn/geo.bin").unwrap(),
+            "http://cdn/geo.bin"
+        );
+        assert_eq!(
+            resolve(base, "https://cdn/geo.bin").unwrap(),
+            "https://cdn/geo.bin"
+        );
+    }
+
+    #[test]
+    fn follows_redirects_and_drops_credentials_across_hosts() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = serve(
+            listener,
+            vec![
+                redirect("/moved/geo.bin"),
+                redirect(&format!("http://localhost:{}/final/geo.bin", port)),
+                "HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\nbins".to_string(),
+            ],
+        );
+
+        let mut body = Vec::new();
+        let url = format!("http://127.0.0.1:{}/geo.bin", port);
+        let headers = [("Authorization", "secret"), ("Range", "bytes=0-")];
+        let response = get(&url, &headers, None, |_| Ok(&mut body)).unwrap();
+        assert_eq!((response.status, body.as_slice()), (200, &b"bins"[..]));
+
+        let requests = server.join().unwrap();
+        assert!(requests[1].starts_with("GET /moved/geo.bin "));
+        assert!(requests[1].contains("Authorization: secret"));
+        assert!(requests[2].starts_with("GET /final/geo.bin "));
+        assert!(!requests[2].contains("Authorization"));
+        assert!(requests[2].contains("Range: bytes=0-"));
+    }
+
+    #[test]
+    fn redirect_loops_are_bounded() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/geo.bin", listener.local_addr().unwrap());
+        let server = serve(listener, vec![redirect("/geo.bin"); MAX_REDIRECTS + 1]);
+        let err = get(&url, &[], None, |_| Ok(std::io::sink())).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(server.join().unwrap().len(), MAX_REDIRECTS + 1);
+    }
+}
@@ -0,0 +1,320 @@
+use std::collections::VecDeque;
+use std::fs::{self, OpenOptions};
+use std::io::{BufWriter, Error, ErrorKind, Result, Write};
+use std::ops::{Deref, Range};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::format::{
+    read_block, read_preamble, read_u32, section_bases, Dataset, Kind, Layout, SyncState,
+};
+
+pub const TRAILER_MAGIC: &[u8; 4] = b"IP2I";
+pub const DEFAULT_INTERVAL: u32 = 256;
+pub const DEFAULT_CACHE_BLOCKS: usize = 64;
+const CACHE_SHARDS: usize = 16;
+const ENTRY_LEN: usize = 85;
+const TAIL_LEN: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyncPoint {
+    pub section: u8,
+    pub first_from: u128,
+    pub max_end: u128,
+    pub offset: u64,
+    pub count: u32,
+    pub state: SyncState,
+}
+
+pub fn build(bytes: &[u8], kind: Kind, path: &str, interval: u32) -> Result<Vec<SyncPoint>> {
+    let interval = match interval {
+        0 => DEFAULT_INTERVAL,
+        interval => interval,
+    } as usize;
+    let mut input = bytes;
+    let (layout, strings) = read_preamble(&mut input, kind, path)?;
+    let mut points = Vec::new();
+
+    for (section, &base) in section_bases(layout).iter().enumerate() {
+        let count = read_u32(&mut input)? as usize;
+        let mut state = SyncState {
+            prev_from: base,
+            ..SyncState::default()
+        };
+        let mut max_end = 0;
+        for start in (0..count).step_by(interval) {
+            let offset = (bytes.len() - input.len()) as u64;
+            let block_state = state;
+            let block_count = interval.min(count - start);
+            let block = read_block(
+                &mut input,
+                kind,
+                layout,
+                strings.len(),
+                &mut state,
+                block_count,
+            )?;
+            let bounds = block.bounds();
+            max_end = bounds.iter().fold(max_end, |max, r| max.max(r.1));
+            points.push(SyncPoint {
+                section: section as u8,
+                first_from: bounds[0].0,
+                max_end,
+                offset,
+                count: block_count as u32,
+                state: block_state,
+            });
+        }
+    }
+    Ok(points)
+}
+
+pub fn append(path: &str, kind: Kind, interval: u32) -> Result<()> {
+    let bytes = fs::read(path)?;
+    let points = build(&bytes, kind, path, interval)?;
+    let mut out = BufWriter::new(OpenOptions::new().append(true).open(path)?);
+    for point in &points {
+        out.write_all(&[point.section])?;
+        out.write_all(&point.first_from.to_le_bytes())?;
+        out.write_all(&point.max_end.to_le_bytes())?;
+        out.write_all(&point.offset.to_le_bytes())?;
+        out.write_all(&point.count.to_le_bytes())?;
+        out.write_all(&point.state.prev_from.to_le_bytes())?;
+        for reg in point.state.regs {
+            out.write_all(&reg.to_le_bytes())?;
+        }
+    }
+    out.write_all(&(points.len() as u32).to_le_bytes())?;
+    out.write_all(&(bytes.len() as u64).to_le_bytes())?;
+    out.write_all(TRAILER_MAGIC)?;
+    out.flush()
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+fn take<const N: usize>(input: &mut &[u8]) -> [u8; N] {
+    let (head, rest) = input.split_at(N);
+    *input = rest;
+    head.try_into().unwrap()
+}
+
+pub fn read_trailer(bytes: &[u8]) -> Result<(Vec<SyncPoint>, usize)> {
+    let tail = bytes
+        .len()
+        .checked_sub(TAIL_LEN)
+        .map(|start| &bytes[start..])
+        .filter(|tail| tail.ends_with(TRAILER_MAGIC))
+        .ok_or_else(|| invalid("Missing block index trailer"))?;
+    let count = u32::from_le_bytes(tail[..4].try_into().unwrap()) as usize;
+    let records_end = u64::from_le_bytes(tail[4..12].try_into().unwrap()) as usize;
+    let mut input = count
+        .checked_mul(ENTRY_LEN)
+        .and_then(|len| bytes.get(records_end..records_end.checked_add(len)?))
+        .filter(|entries| records_end + entries.len() + TAIL_LEN == bytes.len())
+        .ok_or_else(|| invalid("Bad block index trailer"))?;
+
+    let mut points = Vec::with_capacity(count);
+    for _ in 0..count {
+        let section = take::<1>(&mut input)[0];
+        let first_from = u128::from_le_bytes(take(&mut input));
+        let max_end = u128::from_le_bytes(take(&mut input));
+        let offset = u64::from_le_bytes(take(&mut input));
+        let count = u32::from_le_bytes(take(&mut input));
+        let prev_from = u128::from_le_bytes(take(&mut input));
+        let regs = [0; 3].map(|_: i64| i64::from_le_bytes(take(&mut input)));
+        if offset as usize >= records_end {
+            return Err(invalid("Block offset past the record stream"));
+        }
+        points.push(SyncPoint {
+            section,
+            first_from,
+            max_end,
+            offset,
+            count,
+            state: SyncState { prev_from, regs },
+        });
+    }
+    Ok((points, records_end))
+}
+
+pub fn check(path: &str, kind: Kind) -> Result<usize> {
+    let bytes = fs::read(path)?;
+    let (points, records_end) = read_trailer(&bytes)?;
+    let interval = points.iter().map(|point| point.count).max().unwrap_or(0);
+    if build(&bytes[..records_end], kind, path, interval)? != points {
+        return Err(invalid("Block index does not match the records"));
+    }
+    Ok(bytes.len() - records_end)
+}
+
+enum Bytes {
+    Owned(Vec<u8>),
+    #[cfg(feature = "mmap")]
+    Mapped(memmap2::Mmap),
+}
+
+impl Deref for Bytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Bytes::Owned(bytes) => bytes,
+            #[cfg(feature = "mmap")]
+            Bytes::Mapped(map) => map,
+        }
+    }
+}
+
+pub struct IndexedBin {
+    bytes: Bytes,
+    path: String,
+    kind: Kind,
+    layout: Layout,
+    strings: usize,
+    points: Vec<SyncPoint>,
+    sections: Vec<Range<usize>>,
+}
+
+impl IndexedBin {
+    pub fn open(path: &str, kind: Kind) -> Result<(IndexedBin, Vec<String>)> {
+        Self::from_bytes(Bytes::Owned(fs::read(path)?), path, kind)
+    }
+
+    #[cfg(feature = "mmap")]
+    pub fn map(path: &str, kind: Kind) -> Result<(IndexedBin, Vec<String>)> {
+        let file = fs::File::open(path)?;
+        // The bins are written once by build and replaced, never modified in place.
+        let map = unsafe { memmap2::Mmap::map(&file)? };
+        Self::from_bytes(Bytes::Mapped(map), path, kind)
+    }
+
+    #[cfg(not(feature = "mmap"))]
+    pub fn map(_path: &str, _kind: Kind) -> Result<(IndexedBin, Vec<String>)> {
+        Err(Error::new(
+            ErrorKind::Unsupported,
+            "Memory-mapped bins require building with --features mmap",
+        ))
+    }
+
+    fn from_bytes(bytes: Bytes, path: &str, kind: Kind) -> Result<(IndexedBin, Vec<String>)> {
+        let (points, _) = read_trailer(&bytes)?;
+        let (layout, strings) = read_preamble(&mut &bytes[..], kind, path)?;
+        let mut sections = Vec::new();
+        for (start, point) in points.iter().enumerate() {
+            match sections.last_mut() {
+                Some((section, range)) if *section == point.section => {
+                    let range: &mut Range<usize> = range;
+                    range.end = start + 1;
+                }
+                _ => sections.push((point.section, start..start + 1)),
+            }
+        }
+        let bin = IndexedBin {
+            bytes,
+            path: path.to_string(),
+            kind,
+            layout,
+            strings: strings.len(),
+            points,
+            sections: sections.into_iter().map(|(_, range)| range).collect(),
+        };
+        Ok((bin, strings))
+    }
+
+    pub fn kind(&self) -> Kind {
+        self.kind
+    }
+
+    pub fn candidates(&self, ip: u128) -> impl Iterator<Item = &SyncPoint> {
+        self.sections.iter().flat_map(move |range| {
+            let points = &self.points[range.clone()];
+            let upper = points.partition_point(|point| point.first_from <= ip);
+            points[..upper]
+                .iter()
+                .rev()
+                .take_while(move |point| point.max_end >= ip)
+        })
+    }
+
+    pub fn block(&self, point: &SyncPoint) -> Result<Dataset> {
+        let mut input = &self.bytes[point.offset as usize..];
+        let mut state = point.state;
+        read_block(
+            &mut input,
+            self.kind,
+            self.layout,
+            self.strings,
+            &mut state,
+            point.count as usize,
+        )
+    }
+
+    pub fn read_all(&self) -> Result<Dataset> {
+        Dataset::read_from(&mut &self.bytes[..], self.kind, &self.path)
+    }
+}
+
+type Shard<T> = Mutex<VecDeque<(u64, Arc<Vec<T>>)>>;
+
+pub struct BlockCache<T> {
+    capacity: usize,
+    shards: Vec<Shard<T>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<T> BlockCache<T> {
+    pub fn new(capacity: usize) -> BlockCache<T> {
+        let capacity = capacity.div_ceil(CACHE_SHARDS);
+        BlockCache {
+            capacity,
+            shards: (0..CACHE_SHARDS)
+                .map(|_| Mutex::new(VecDeque::with_capacity(capacity)))
+                .collect(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn stats(&self) -> (u64, u64) {
+        (
+            self.hits.load(Ordering::Relaxed),
+            self.misses.load(Ordering::Relaxed),
+        )
+    }
+
+    fn shard(&self, offset: u64) -> &Shard<T> {
+        let hash = offset.wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 32;
+        &self.shards[hash as usize % CACHE_SHARDS]
+    }
+
+    pub fn get_or_load(
+        &self,
+        offset: u64,
+        load: impl FnOnce() -> Result<Vec<T>>,
+    ) -> Result<Arc<Vec<T>>> {
+        let shard = self.shard(offset);
+        let mut blocks = shard.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some(i) = blocks.iter().position(|(cached, _)| *cached == offset) {
+            let entry = blocks.remove(i).expect("cached block");
+            let block = entry.1.clone();
+            blocks.push_front(entry);
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(block);
+        }
+        drop(blocks);
+        self.misses.fetch_add(1, Ordering::Relaxed);
+
+        let block = Arc::new(load()?);
+        if self.capacity > 0 {
+            let mut blocks = shard.lock().unwrap_or_else(|err| err.into_inner());
+            if !blocks.iter().any(|(cached, _)| *cached == offset) {
+                blocks.push_front((offset, block.clone()));
+                blocks.truncate(self.capacity);
+            }
+        }
+        Ok(block)
+    }
+}
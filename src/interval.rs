@@ -0,0 +1,172 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashSet};
+
+use crate::format::sort_ranges;
+
+pub struct IntervalMap<T, R, S> {
+    items: Vec<(u32, T)>,
+    range: R,
+    slice: S,
+}
+
+impl<T, R, S> IntervalMap<T, R, S>
+where
+    R: Fn(&T) -> (u128, u128),
+    S: Fn(&T, u128, u128) -> T,
+{
+    pub fn new(range: R, slice: S) -> Self {
+        IntervalMap {
+            items: Vec::new(),
+            range,
+            slice,
+        }
+    }
+
+    pub fn extend(&mut self, priority: u32, items: impl IntoIterator<Item = T>) {
+        self.items
+            .extend(items.into_iter().map(|item| (priority, item)));
+    }
+
+    pub fn layered(mut self) -> Vec<T> {
+        self.items.sort_by_key(|(priority, _)| *priority);
+        let mut merged = Vec::with_capacity(self.items.len());
+        let mut covered = Vec::new();
+        let mut coverage = Vec::new();
+        let mut layer = None;
+        for (priority, item) in &self.items {
+            if layer != Some(*priority) {
+                coverage = coalesce(covered.clone());
+                layer = Some(*priority);
+            }
+            let (from, to) = (self.range)(item);
+            for (start, end) in subtract(from, to, &coverage) {
+                merged.push((self.slice)(item, start, end));
+            }
+            covered.push((from, to));
+        }
+        sort_ranges(&mut merged, &self.range);
+        merged
+    }
+
+    pub fn flatten(self) -> Vec<T> {
+        let IntervalMap {
+            items,
+            range,
+            slice,
+        } = self;
+        let mut events = Vec::with_capacity(items.len() * 2);
+        for (i, (_, item)) in items.iter().enumerate() {
+            let (from, to) = range(item);
+            events.push((from, i, true));
+            if let Some(end) = to.checked_add(1) {
+                events.push((end, i, false));
+            }
+        }
+        events.sort_unstable_by_key(|e| e.0);
+
+        let mut active = BTreeSet::new();
+        let mut current: Option<(usize, u128)> = None;
+        let mut flat = Vec::new();
+        let mut i = 0;
+        while i < events.len() {
+            let pos = events[i].0;
+            while let Some(&(_, index, opening)) = events.get(i).filter(|e| e.0 == pos) {
+                let (priority, item) = &items[index];
+                let (from, to) = range(item);
+                let key = (*priority, to - from, Reverse(index));
+                if opening {
+                    active.insert(key);
+                } else {
+                    active.remove(&key);
+                }
+                i += 1;
+            }
+            let winner = active.first().map(|&(_, _, Reverse(index))| index);
+            if current.map(|(index, _)| index) != winner {
+                if let Some((index, start)) = current {
+                    flat.push(slice(&items[index].1, start, pos - 1));
+                }
+                current = winner.map(|index| (index, pos));
+            }
+        }
+        if let Some((index, start)) = current {
+            flat.push(slice(&items[index].1, start, u128::MAX));
+        }
+        flat
+    }
+
+    pub fn unique(mut self) -> Vec<T> {
+        self.items.sort_by_key(|(priority, _)| *priority);
+        let IntervalMap { items, range, .. } = self;
+        let mut seen = HashSet::with_capacity(items.len());
+        let mut kept: Vec<T> = items
+            .into_iter()
+            .filter(|(_, item)| seen.insert(range(item)))
+            .map(|(_, item)| item)
+            .collect();
+        sort_ranges(&mut kept, &range);
+        kept
+    }
+}
+
+pub fn by_priority<T>(
+    layers: Vec<Vec<T>>,
+    range: impl Fn(&T) -> (u128, u128),
+    slice: impl Fn(&T, u128, u128) -> T,
+) -> Vec<T> {
+    let mut map = IntervalMap::new(range, slice);
+    for (priority, layer) in layers.into_iter().enumerate() {
+        map.extend(priority as u32, layer);
+    }
+    map.layered()
+}
+
+pub fn dedup<T: PartialEq>(
+    items: &mut Vec<T>,
+    range: impl Fn(&T) -> (u128, u128),
+) -> Vec<(u128, u128)> {
+    sort_ranges(items, &range);
+    let mut conflicts = Vec::new();
+    items.dedup_by(|item, kept| {
+        let same = range(item) == range(kept);
+        if same && item != kept {
+            conflicts.push(range(item));
+        }
+        same
+    });
+    conflicts
+}
+
+pub fn coalesce(mut ranges: Vec<(u128, u128)>) -> Vec<(u128, u128)> {
+    ranges.sort_unstable();
+    let mut merged: Vec<(u128, u128)> = Vec::with_capacity(ranges.len());
+    for (from, to) in ranges {
+        match merged.last_mut() {
+            Some(last) if from <= last.1.saturating_add(1) => last.1 = last.1.max(to),
+            _ => merged.push((from, to)),
+        }
+    }
+    merged
+}
+
+pub fn subtract(from: u128, to: u128, coverage: &[(u128, u128)]) -> Vec<(u128, u128)> {
+    let mut pieces = Vec::new();
+    let mut start = from;
+    let first = coverage.partition_point(|c| c.1 < from);
+
+    for &(cover_from, cover_to) in &coverage[first..] {
+        if cover_from > to {
+            break;
+        }
+        if cover_from > start {
+            pieces.push((start, cover_from - 1));
+        }
+        if cover_to >= to {
+            return pieces;
+        }
+        start = cover_to + 1;
+    }
+
+    pieces.push((start, to));
+    pieces
+}
@@ -0,0 +1,281 @@
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use std::net::IpAddr;
+use std::str::FromStr;
+
+pub fn ipv4_to_ipv6(ipv4: u32) -> u128 {
+    (0xffffu128 << 32) | ipv4 as u128
+}
+
+pub fn ip_to_u128(ip: IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(v4) => ipv4_to_ipv6(u32::from(v4)),
+        IpAddr::V6(v6) => u128::from(v6),
+    }
+}
+
+pub fn u128_to_ip(value: u128) -> IpAddr {
+    match value >> 32 {
+        0xffff => IpAddr::V4((value as u32).into()),
+        _ => IpAddr::V6(value.into()),
+    }
+}
+
+pub fn format_cidr(network: u128, prefix: u8) -> String {
+    match u128_to_ip(network) {
+        IpAddr::V4(v4) if prefix >= 96 => format!("{}/{}", v4, prefix - 96),
+        _ => format!("{}/{}", std::net::Ipv6Addr::from(network), prefix),
+    }
+}
+
+pub fn parse_cidr(s: &str) -> Option<(u128, u128)> {
+    let (addr, prefix) = match s.split_once('/') {
+        Some((addr, prefix)) => (addr, Some(prefix)),
+        None => (s, None),
+    };
+    let ip: IpAddr = addr.trim().parse().ok()?;
+    let (bits, value) = match ip {
+        IpAddr::V4(v4) => (32, u32::from(v4) as u128),
+        IpAddr::V6(v6) => (128, u128::from(v6)),
+    };
+    let prefix: u32 = match prefix {
+        Some(p) => p.trim().parse().ok()?,
+        None => bits,
+    };
+    if prefix > bits {
+        return None;
+    }
+
+    let host_mask = if prefix == 0 {
+        u128::MAX >> (128 - bits)
+    } else {
+        (1u128 << (bits - prefix)) - 1
+    };
+    let start = value & !host_mask;
+    let end = start | host_mask;
+
+    if bits == 32 {
+        Some((ipv4_to_ipv6(start as u32), ipv4_to_ipv6(end as u32)))
+    } else {
+        Some((start, end))
+    }
+}
+
+pub fn range_to_cidrs(from: u128, to: u128) -> Vec<(u128, u8)> {
+    let mut cidrs = Vec::new();
+    let mut start = from;
+
+    loop {
+        let span = to - start;
+        let align = start.trailing_zeros().min(128);
+        let fit = if span == u128::MAX {
+            128
+        } else {
+            127 - (span + 1).leading_zeros()
+        };
+        let host_bits = align.min(fit);
+        cidrs.push((start, (128 - host_bits) as u8));
+
+        let size_minus_one = if host_bits == 128 {
+            u128::MAX
+        } else {
+            (1u128 << host_bits) - 1
+        };
+        match (start + size_minus_one).checked_add(1) {
+            Some(next) if next <= to => start = next,
+            _ => break,
+        }
+    }
+
+    cidrs
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct IpRange {
+    pub from: u128,
+    pub to: u128,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RangeRecord<T> {
+    pub range: IpRange,
+    pub value: T,
+}
+
+impl IpRange {
+    pub fn new(from: IpAddr, to: IpAddr) -> Option<IpRange> {
+        let (from, to) = (ip_to_u128(from), ip_to_u128(to));
+        (from <= to).then_some(IpRange { from, to })
+    }
+
+    pub fn start(&self) -> IpAddr {
+        u128_to_ip(self.from)
+    }
+
+    pub fn end(&self) -> IpAddr {
+        u128_to_ip(self.to)
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        (self.from..=self.to).contains(&ip_to_u128(ip))
+    }
+
+    pub fn to_cidrs(&self) -> Vec<(IpAddr, u8)> {
+        range_to_cidrs(self.from, self.to)
+            .into_iter()
+            .map(|(network, prefix)| match u128_to_ip(network) {
+                IpAddr::V4(v4) if prefix >= 96 => (IpAddr::V4(v4), prefix - 96),
+                _ => (IpAddr::V6(network.into()), prefix),
+            })
+            .collect()
+    }
+}
+
+impl From<(u128, u128)> for IpRange {
+    fn from((from, to): (u128, u128)) -> IpRange {
+        IpRange { from, to }
+    }
+}
+
+impl fmt::Display for IpRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} - {}", self.start(), self.end())
+    }
+}
+
+impl FromStr for IpRange {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<IpRange, Error> {
+        let range = match s.split_once('-') {
+            Some((from, to)) => match (from.trim().parse(), to.trim().parse()) {
+                (Ok(from), Ok(to)) => IpRange::new(from, to),
+                _ => None,
+            },
+            None => parse_cidr(s).map(IpRange::from),
+        };
+        range.ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("Bad IP range: {} (expected a CIDR or <from> - <to>)", s),
+            )
+        })
+    }
+}
+
+impl<T> RangeRecord<T> {
+    pub fn new(range: IpRange, value: T) -> RangeRecord<T> {
+        RangeRecord { range, value }
+    }
+}
+
+impl<T> From<(u128, u128, T)> for RangeRecord<T> {
+    fn from((from, to, value): (u128, u128, T)) -> RangeRecord<T> {
+        RangeRecord::new(IpRange { from, to }, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(text: &str) -> IpAddr {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn ipv4_space_maps_onto_the_v4_mapped_block() {
+        let range = IpRange::new(ip("0.0.0.0"), ip("255.255.255.255")).unwrap();
+        assert_eq!(
+            (range.from, range.to),
+            (ipv4_to_ipv6(0), ipv4_to_ipv6(u32::MAX))
+        );
+        assert_eq!(
+            (range.start(), range.end()),
+            (ip("0.0.0.0"), ip("255.255.255.255"))
+        );
+        assert_eq!(range.to_cidrs(), [(ip("0.0.0.0"), 0)]);
+        assert_eq!(range.to_string(), "0.0.0.0 - 255.255.255.255");
+
+        assert!(range.contains(ip("::ffff:0.0.0.0")));
+        assert!(range.contains(ip("::ffff:255.255.255.255")));
+        assert!(!range.contains(ip("::fffe:ffff:ffff")));
+        assert!(!range.contains(ip("::1:0:0:0")));
+    }
+
+    #[test]
+    fn ranges_crossing_the_v4_mapped_boundary_keep_both_families() {
+        let range = IpRange::from((ipv4_to_ipv6(0) - 1, ipv4_to_ipv6(1)));
+        assert_eq!(range.start(), ip("::fffe:ffff:ffff"));
+        assert_eq!(range.end(), ip("0.0.0.1"));
+        assert_eq!(
+            range.to_cidrs(),
+            [(ip("::fffe:ffff:ffff"), 128), (ip("0.0.0.0"), 31)]
+        );
+        assert_eq!(IpRange::new(ip("1.1.1.1"), ip("::1")), None);
+    }
+
+    #[test]
+    fn contains_both_ends() {
+        let range: IpRange = "10.0.0.0 - 10.0.0.255".parse().unwrap();
+        assert_eq!(range, "10.0.0.0/24".parse().unwrap());
+        assert!(range.contains(ip("10.0.0.0")));
+        assert!(range.contains(ip("10.0.0.255")));
+        assert!(!range.contains(ip("9.255.255.255")));
+        assert!(!range.contains(ip("10.0.1.0")));
+
+        let range: IpRange = "2001:db8::/127".parse().unwrap();
+        assert!(range.contains(ip("2001:db8::")));
+        assert!(range.contains(ip("2001:db8::1")));
+        assert!(!range.contains(ip("2001:db8::2")));
+        assert!(!range.contains(ip("2001:db7:ffff:ffff:ffff:ffff:ffff:ffff")));
+    }
+
+    #[test]
+    fn single_address_ranges() {
+        for text in ["8.8.8.8", "8.8.8.8/32", "8.8.8.8 - 8.8.8.8"] {
+            let range: IpRange = text.parse().unwrap();
+            assert_eq!(range.from, range.to, "{}", text);
+            assert!(range.contains(ip("8.8.8.8")));
+            assert!(!range.contains(ip("8.8.8.7")));
+            assert!(!range.contains(ip("8.8.8.9")));
+            assert_eq!(range.to_cidrs(), [(ip("8.8.8.8"), 32)]);
+            assert_eq!(range.to_string(), "8.8.8.8 - 8.8.8.8");
+        }
+        let range: IpRange = "::1".parse().unwrap();
+        assert_eq!(range.to_cidrs(), [(ip("::1"), 128)]);
+    }
+
+    #[test]
+    fn rejects_bad_ranges() {
+        for text in [
+            "10.0.0.2 - 10.0.0.1",
+            "10.0.0.0/33",
+            "::/129",
+            "nope",
+            "1.1.1.1 -",
+        ] {
+            let err = text.parse::<IpRange>().unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidInput, "{}", text);
+        }
+    }
+
+    #[test]
+    fn range_records_wrap_a_range_and_value() {
+        let record = RangeRecord::from((ipv4_to_ipv6(16777216), ipv4_to_ipv6(16777471), "AU"));
+        assert_eq!(
+            record,
+            RangeRecord::new("1.0.0.0/24".parse().unwrap(), "AU")
+        );
+        assert_eq!(record.range.start(), ip("1.0.0.0"));
+        assert_eq!(record.range.end(), ip("1.0.0.255"));
+        assert!(record.range.contains(ip("1.0.0.255")));
+        assert_ne!(
+            record.clone(),
+            RangeRecord {
+                value: "CN",
+                ..record
+            }
+        );
+    }
+}
@@ -0,0 +1,135 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Result;
+
+use crate::domain::PublicSuffixes;
+use crate::format::{sort_ranges, Dataset, DomainRange};
+use crate::network_type::{join_adjacent, read_labeled, write_labeled};
+
+pub const ISP_GROUP_FILE: &str = "isp_group.bin";
+const MAGIC: &[u8; 4] = b"IP2G";
+const TRAILING_WORDS: [&str; 37] = [
+    "inc",
+    "incorporated",
+    "llc",
+    "ltd",
+    "limited",
+    "corp",
+    "corporation",
+    "co",
+    "company",
+    "plc",
+    "lp",
+    "llp",
+    "gmbh",
+    "ag",
+    "kg",
+    "sa",
+    "sas",
+    "sarl",
+    "srl",
+    "spa",
+    "bv",
+    "nv",
+    "ab",
+    "as",
+    "asa",
+    "oy",
+    "pty",
+    "pvt",
+    "kk",
+    "jsc",
+    "ooo",
+    "communications",
+    "communication",
+    "telecommunications",
+    "holdings",
+    "holding",
+    "group",
+];
+
+pub fn organization_key(name: &str, suffixes: &PublicSuffixes) -> String {
+    let name = name.to_lowercase();
+    let mut words: Vec<String> = name
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .flat_map(|chunk| {
+            let chunk = chunk.trim_matches(|c: char| !c.is_alphanumeric());
+            let domain = chunk
+                .rsplit_once('.')
+                .filter(|(_, tld)| tld.len() > 1 && tld.chars().all(|c| c.is_ascii_alphabetic()))
+                .and_then(|_| suffixes.registrable(chunk));
+            match domain {
+                Some(domain) => vec![domain.split('.').next().unwrap_or("").to_string()],
+                None => chunk
+                    .replace('.', "")
+                    .split(|c: char| !c.is_alphanumeric())
+                    .map(str::to_string)
+                    .collect(),
+            }
+        })
+        .filter(|word| !word.is_empty())
+        .collect();
+    if words.first().is_some_and(|word| word == "the") {
+        words.remove(0);
+    }
+    while words.len() > 1
+        && words
+            .last()
+            .is_some_and(|word| TRAILING_WORDS.contains(&word.as_str()))
+    {
+        words.pop();
+    }
+    words.join(" ")
+}
+
+pub fn derive(isp: &Dataset, suffixes: &PublicSuffixes) -> (Vec<String>, Vec<DomainRange>) {
+    let Dataset::Isp(strings, data) = isp else {
+        return (Vec::new(), Vec::new());
+    };
+    let mut counts = vec![0usize; strings.len()];
+    for r in data {
+        if let Some(isp) = r.2.checked_sub(1) {
+            counts[isp] += 1;
+        }
+    }
+
+    let mut groups: BTreeMap<String, (usize, usize)> = BTreeMap::new();
+    let keys: Vec<String> = strings
+        .iter()
+        .enumerate()
+        .map(|(isp, name)| {
+            let key = organization_key(name, suffixes);
+            if counts[isp] > 0 && !key.is_empty() {
+                let best = groups.entry(key.clone()).or_insert((isp, 0));
+                if (counts[isp], Reverse(name)) > (best.1, Reverse(&strings[best.0])) {
+                    *best = (isp, counts[isp]);
+                }
+            }
+            key
+        })
+        .collect();
+    let ids: HashMap<&str, u32> = groups
+        .keys()
+        .enumerate()
+        .map(|(id, key)| (key.as_str(), id as u32))
+        .collect();
+
+    let mut ranges: Vec<DomainRange> = data
+        .iter()
+        .filter_map(|r| Some((r.0, r.1, *ids.get(keys[r.2.checked_sub(1)?].as_str())?)))
+        .collect();
+    sort_ranges(&mut ranges, |r| (r.0, r.1));
+    let names = groups
+        .values()
+        .map(|&(isp, _)| strings[isp].clone())
+        .collect();
+    (names, join_adjacent(ranges))
+}
+
+pub fn write(path: &str, names: &[String], ranges: &[DomainRange]) -> Result<()> {
+    write_labeled(path, MAGIC, names, ranges)
+}
+
+pub fn read(path: &str) -> Result<(Vec<String>, Vec<DomainRange>)> {
+    read_labeled(path, MAGIC, "ISP group")
+}
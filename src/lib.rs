@@ -0,0 +1,2050 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+#[cfg(feature = "tokio")]
+pub mod async_reader;
+mod bench;
+mod bloom;
+mod cells;
+mod centroids;
+pub mod cli;
+mod compress;
+mod connection_type;
+mod csv_reader;
+mod csv_writer;
+mod domain;
+mod dry_run;
+pub mod encoding;
+mod enrich;
+mod extract;
+mod format;
+mod geocode;
+pub mod geoip2;
+mod geolite_csv;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod hook;
+mod http;
+mod index;
+mod interval;
+mod ip;
+mod isp_group;
+mod list;
+pub mod logging;
+mod manifest;
+pub mod maxmind;
+mod merge;
+mod metrics;
+#[cfg(feature = "middleware")]
+pub mod middleware;
+mod mmdb_map;
+mod mmdb_writer;
+mod network_type;
+#[cfg(feature = "parquet")]
+mod parquet_writer;
+mod patch;
+mod pcap;
+mod prefixes;
+mod profile;
+mod progress;
+mod protobuf_writer;
+mod proxy_sets;
+mod rdns;
+pub mod reader;
+mod selftest;
+#[cfg(feature = "serve")]
+mod serve;
+mod shard;
+mod signing;
+mod sigv4;
+mod spill;
+#[cfg(feature = "sqlite")]
+mod sqlite_writer;
+pub mod storage;
+mod threat;
+mod tls;
+mod trie;
+pub mod update;
+mod validate;
+pub mod varint;
+#[cfg(feature = "watch")]
+mod watch;
+
+use bloom::{ProxyFilter, BLOOM_FILE};
+use cells::Grid;
+use cli::Args;
+use connection_type::CONNECTION_TYPE_FILE;
+use csv_reader::CsvReader;
+use domain::{PublicSuffixes, DOMAIN_FILE};
+use format::{
+    asn_layout, intern, intern_with_offset, locate, order_by_frequency, share_strings,
+    write_strings_file, AsnRange, CountryRange, Dataset, GeoRange, Kind, Layout, Order, Unlocated,
+    UsageRange, DELTA_COORDS, GEO_ACCURACY, GEO_CELLS, GEO_PAYLOAD, INDEXED, IPV4_ONLY,
+    PACKED_STRINGS, PROXY_CONFIDENCE, PROXY_LAST_SEEN, SHARED_STRINGS, SPLIT_FAMILIES,
+    STRINGS_FILE, TRIE, UNKNOWN_LAST_SEEN,
+};
+use geocode::{CityIndex, CITIES_FILE};
+use geolite_csv::GEOLITE_BLOCKS_V4;
+use interval::IntervalMap;
+use ip::{ipv4_to_ipv6, u128_to_ip};
+use isp_group::ISP_GROUP_FILE;
+use logging::Stage;
+use manifest::MANIFEST_FILE;
+use maxmind::{CitySource, MaxMindReader};
+use merge::{CsvPriority, GeoPriority, Shape};
+use network_type::NETWORK_TYPE_FILE;
+use prefixes::{PrefixIndex, ASN_INDEX_FILE, COUNTRY_INDEX_FILE, ISP_INDEX_FILE};
+use profile::Profile;
+use progress::Tracked;
+use proxy_sets::{ProxySets, PROXY_SETS_FILE};
+use rdns::{RdnsTable, RDNS_FILE};
+use spill::Spill;
+use storage::Source;
+use threat::{ThreatLists, THREAT_FILE};
+
+const GEO_V4_CSV: &str = "IP2LOCATION-LITE-DB5.CSV";
+const GEO_V6_CSV: &str = "IP2LOCATION-LITE-DB5.IPV6.CSV";
+const PROXY_V4_CSV: &str = "IP2PROXY-LITE-PX12.CSV";
+const PROXY_V6_CSV: &str = "IP2PROXY-LITE-PX12.IPV6.CSV";
+const ASN_V4_CSV: &str = "IP2LOCATION-LITE-ASN.CSV";
+const ASN_V6_CSV: &str = "IP2LOCATION-LITE-ASN.IPV6.CSV";
+const MOBILE_V4_CSV: &str = "IP2LOCATION-DB24.CSV";
+const MOBILE_V6_CSV: &str = "IP2LOCATION-DB24.IPV6.CSV";
+const MAX_REPORTED_CONFLICTS: usize = 10;
+const MAXMIND_CITY: &str = "GeoLite2-City.mmdb";
+const MAXMIND_ASN: &str = "GeoLite2-ASN.mmdb";
+const MAXMIND_ANONYMOUS: &str = "GeoIP2-Anonymous-IP.mmdb";
+const MAXMIND_CONNECTION_TYPE: &str = "GeoIP2-Connection-Type.mmdb";
+const CLOUD_RANGES: &str = "cloud-ranges.txt";
+const PROXY_LAST_SEEN_COLUMN: usize = 12;
+const MOBILE_DOMAIN_COLUMN: usize = 11;
+const NET_SPEED_COLUMN: usize = 12;
+
+pub const BUILD_SWITCHES: &[&str] = &[
+    "--shared-strings",
+    "--split-families",
+    "--ipv4-only",
+    "--geo-accuracy",
+    "--keep-unlocated",
+    "--country-centroids",
+    "--reverse-geocode",
+    "--elevation",
+    "--require-all",
+    "--quiet",
+    "-q",
+    "--verbose",
+    "-v",
+    "--watch",
+    "--index",
+    "--flatten",
+    "--cidr-aligned",
+    "--roaring",
+    "--bloom",
+    "--asn-index",
+    "--country-index",
+    "--isp-index",
+    "--network-type",
+    "--connection-type",
+    "--domain",
+    "--normalize-domains",
+    "--isp-groups",
+    "--dry-run",
+];
+
+pub fn run(raw: &[String]) -> std::io::Result<()> {
+    let (command, rest) = match raw.split_first() {
+        Some((command, rest)) => (command.as_str(), rest),
+        None => ("build", raw),
+    };
+
+    match command {
+        "build" => build(&Args::parse(rest, BUILD_SWITCHES)),
+        "merge" => merge::run(&Args::parse(rest, &[])),
+        "extract" => extract::run(&Args::parse(rest, &[])),
+        "validate" => validate::run(&Args::parse(rest, &[])),
+        "patch" => patch::run(&Args::parse(rest, &[])),
+        "selftest" => selftest::run(&Args::parse(rest, &[])),
+        "enrich" => enrich::run(&Args::parse(rest, &["--mmap", "--header"])),
+        "pcap" => pcap::run(&Args::parse(rest, &["--mmap"])),
+        "list" => list::run(&Args::parse(rest, &["--mmap"])),
+        "lookup" => reader::run(&Args::parse(rest, &["--mmap"])),
+        "bench" => bench::run(&Args::parse(rest, &["--mmap", "--proxy-only"])),
+        "reverse" => geocode::run_reverse(&Args::parse(rest, &[])),
+        "distance" => geocode::run_distance(&Args::parse(rest, &[])),
+        "within" => geocode::run_within(&Args::parse(rest, &[])),
+        #[cfg(feature = "serve")]
+        "serve" => serve::run(&Args::parse(rest, &["--mmap", "--watch"])),
+        #[cfg(not(feature = "serve"))]
+        "serve" => Err(Error::new(
+            ErrorKind::Unsupported,
+            "ip2x serve requires building with --features serve",
+        )),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Unknown command: {}", command),
+        )),
+    }
+}
+
+fn default_data_dir() -> String {
+    std::env::var("DATA_DIR").unwrap_or_else(|_| "data".to_string())
+}
+
+fn out_dir(args: &Args) -> Option<String> {
+    args.value(&["--out-dir"]).map(str::to_string).or_else(|| {
+        std::env::var("OUT_DIR")
+            .ok()
+            .filter(|dir| storage::is_remote(dir))
+    })
+}
+
+pub fn build(args: &Args) -> std::io::Result<()> {
+    let data_dir = args
+        .positional()
+        .first()
+        .cloned()
+        .unwrap_or_else(default_data_dir);
+
+    let out_dir = out_dir(args);
+    let upload = out_dir.as_deref().is_some_and(storage::is_remote);
+    if !cfg!(feature = "object-store") && (storage::is_remote(&data_dir) || upload) {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            "s3:// and gs:// paths require building with --features object-store",
+        ));
+    }
+    if args.flag("--watch") && args.flag("--dry-run") {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "--dry-run cannot be combined with --watch",
+        ));
+    }
+    if args.flag("--watch") && storage::is_remote(&data_dir) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "--watch needs a local data directory",
+        ));
+    }
+
+    if let Some(profile) = args.value(&["--profile"]) {
+        if Profile::parse(profile).is_none() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Unknown --profile: {} (expected minimal, standard or full)",
+                    profile
+                ),
+            ));
+        }
+    }
+    let has_db24 = storage::exists(Path::new(&data_dir).join(MOBILE_V4_CSV));
+    let expanded = Profile::from_args(args).expand(args, has_db24);
+    let args = &expanded;
+
+    if args.flag("--elevation") && !has_db24 {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("--elevation needs {} in {}", MOBILE_V4_CSV, data_dir),
+        ));
+    }
+
+    if args.flag("--quiet") || args.flag("-q") {
+        progress::set_level(progress::QUIET);
+    } else if args.flag("--verbose") || args.flag("-v") {
+        progress::set_level(progress::VERBOSE);
+    }
+    if let Some(format) = args.value(&["--log-format"]) {
+        logging::init(format)?;
+    }
+
+    if args.flag("--watch") && !cfg!(feature = "watch") {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            "--watch requires building with --features watch",
+        ));
+    }
+    if args.value(&["--pushgateway"]).is_some() && !cfg!(feature = "metrics") {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            "--pushgateway requires building with --features metrics",
+        ));
+    }
+    if args.flag("--roaring") && !cfg!(feature = "roaring") {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            "--roaring requires building with --features roaring",
+        ));
+    }
+
+    let reshaped = args.flag("--flatten") || args.flag("--cidr-aligned") || trie_layout(args);
+    if reshaped && max_memory(args)?.is_some() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "--flatten, --cidr-aligned and --layout trie cannot be combined with --max-memory",
+        ));
+    }
+
+    if let Some(priority) = args.value(&["--geo-priority"]) {
+        if GeoPriority::parse(priority).is_none() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Unknown --geo-priority: {} (expected ip2location-first, maxmind-first or most-specific)",
+                    priority
+                ),
+            ));
+        }
+        if geo_priority(args) == GeoPriority::MostSpecific && max_memory(args)?.is_some() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "--geo-priority most-specific cannot be combined with --max-memory",
+            ));
+        }
+    }
+
+    if let Some(priority) = args.value(&["--csv-priority"]) {
+        if CsvPriority::parse(priority).is_none() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Unknown --csv-priority: {} (expected ipv4-first or ipv6-first)",
+                    priority
+                ),
+            ));
+        }
+    }
+
+    if args.flag("--ipv4-only") && args.flag("--split-families") {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "--ipv4-only cannot be combined with --split-families",
+        ));
+    }
+
+    if args.flag("--keep-unlocated") && args.value(&["--format"]).unwrap_or("bin") != "bin" {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "--keep-unlocated only applies to --format bin",
+        ));
+    }
+
+    if max_memory(args)?.is_some() {
+        let format = args.value(&["--format"]).unwrap_or("bin");
+        let compress = args.value(&["--compress"]).unwrap_or("none");
+        if format != "bin" || compress != "none" {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "--max-memory only applies to uncompressed --format bin",
+            ));
+        }
+    }
+
+    if shard_bits(args)?.is_some() {
+        if args.value(&["--format"]).unwrap_or("bin") != "bin" {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "--shard-bits only applies to --format bin",
+            ));
+        }
+        if let Some(option) = ["--max-memory", "--shared-strings"]
+            .into_iter()
+            .find(|option| args.flag(option))
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("--shard-bits cannot be combined with {}", option),
+            ));
+        }
+    }
+
+    let signer = args
+        .value(&["--sign-key"])
+        .map(signing::Signer::open)
+        .transpose()?;
+
+    let maxmind = maxmind_paths(args, &data_dir);
+    let missing = missing_sources(&data_dir, &maxmind, &source_kinds(args, &data_dir));
+    if args.flag("--require-all") && !missing.is_empty() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("Source files not found: {}", missing.join(", ")),
+        ));
+    }
+    for path in &missing {
+        logging::warn(&format!("skipped: {}: file not found", path));
+    }
+
+    if args.flag("--mmdb") {
+        if let Some(missing) = maxmind.iter().find(|path| !storage::exists(path)) {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("MaxMind database not found: {}", missing),
+            ));
+        }
+    }
+
+    if let Some(value) = args.value(&["--mmdb-map"]) {
+        let mappings = mmdb_map::parse(value, &data_dir)?;
+        if let Some(missing) = mappings
+            .iter()
+            .find(|mapping| !storage::exists(&mapping.path))
+        {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("MaxMind database not found: {}", missing.path),
+            ));
+        }
+        if mappings.iter().any(|mapping| mapping.kind == Kind::Geo) && max_memory(args)?.is_some() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "--mmdb-map geo cannot be combined with --max-memory",
+            ));
+        }
+    }
+
+    for option in [
+        "--network-type",
+        "--connection-type",
+        "--domain",
+        "--isp-groups",
+    ] {
+        if args.flag(option) && args.value(&["--format"]).unwrap_or("bin") != "bin" {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("{} only applies to --format bin", option),
+            ));
+        }
+    }
+    for (option, source) in [("--threat", "Threat list"), ("--rdns", "rDNS dump")] {
+        if !args.flag(option) {
+            continue;
+        }
+        if args.value(&["--format"]).unwrap_or("bin") != "bin" {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("{} only applies to --format bin", option),
+            ));
+        }
+        if let Some(missing) = data_paths(args, &data_dir, option)
+            .iter()
+            .find(|path| !storage::exists(path))
+        {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("{} not found: {}", source, missing),
+            ));
+        }
+    }
+    if !cfg!(feature = "gzip")
+        && data_paths(args, &data_dir, "--rdns")
+            .iter()
+            .any(|path| path.ends_with(".gz"))
+    {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            "reading .gz rDNS dumps requires building with --features gzip",
+        ));
+    }
+
+    if args.flag("--locales") {
+        if !args.flag("--reverse-geocode") {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "--locales needs --reverse-geocode",
+            ));
+        }
+        if !maxmind.iter().any(storage::exists) {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!(
+                    "--locales needs {} or {} in {}",
+                    MAXMIND_CITY, GEOLITE_BLOCKS_V4, data_dir
+                ),
+            ));
+        }
+    }
+
+    if args.flag("--dry-run") {
+        return dry_run::run(&data_dir, |data_dir, scratch| {
+            build_artifacts(args, data_dir, scratch, &Kind::ALL, None)
+        });
+    }
+    let out = match out_dir {
+        Some(dir) if !upload => PathBuf::from(dir),
+        _ => PathBuf::new(),
+    };
+    if !out.as_os_str().is_empty() {
+        fs::create_dir_all(&out)?;
+    }
+    build_artifacts(args, &data_dir, &out, &Kind::ALL, signer.as_ref())?;
+    #[cfg(feature = "watch")]
+    if args.flag("--watch") {
+        watch::run(&data_dir, |changed| {
+            let kinds = affected_kinds(&data_dir, &maxmind, changed);
+            if kinds.is_empty() {
+                return Ok(());
+            }
+            build_artifacts(args, &data_dir, &out, &kinds, signer.as_ref())
+        })?;
+    }
+    Ok(())
+}
+
+fn build_artifacts(
+    args: &Args,
+    data_dir: &str,
+    out: &Path,
+    kinds: &[Kind],
+    signer: Option<&signing::Signer>,
+) -> std::io::Result<()> {
+    let maxmind = maxmind_paths(args, data_dir);
+    let selected = || {
+        source_kinds(args, data_dir)
+            .into_iter()
+            .filter(|kind| kinds.contains(kind))
+    };
+    let mut artifacts = Vec::new();
+    match args.value(&["--format"]).unwrap_or("bin") {
+        "bin" => artifacts = build_bins(args, data_dir, out, kinds)?,
+        "mmdb" if !kinds.contains(&Kind::Geo) && !kinds.contains(&Kind::Asn) => {}
+        "mmdb" => {
+            let geo = load_dataset(args, Kind::Geo, data_dir, &maxmind);
+            let asn = load_dataset(args, Kind::Asn, data_dir, &maxmind);
+            mmdb_writer::write_geo_asn(&output(out, "ip2x.mmdb"), &geo, &asn)?;
+            artifacts.push(("ip2x.mmdb".to_string(), None));
+        }
+        "csv" => {
+            for kind in selected() {
+                let dataset = load_dataset(args, kind, data_dir, &maxmind);
+                let name = csv_writer::file_name(kind);
+                csv_writer::write(&output(out, &name), &dataset, args.flag("--cidr-aligned"))?;
+                artifacts.push((name, Some(dataset.len())));
+            }
+        }
+        "protobuf" => {
+            for kind in selected() {
+                let dataset = load_dataset(args, kind, data_dir, &maxmind);
+                let name = protobuf_writer::file_name(kind);
+                protobuf_writer::write(&output(out, &name), &dataset)?;
+                artifacts.push((name, Some(dataset.len())));
+            }
+        }
+        #[cfg(feature = "parquet")]
+        "parquet" => {
+            for kind in selected() {
+                let dataset = load_dataset(args, kind, data_dir, &maxmind);
+                let name = parquet_writer::file_name(kind);
+                parquet_writer::write(&output(out, &name), &dataset)?;
+                artifacts.push((name, Some(dataset.len())));
+            }
+        }
+        #[cfg(not(feature = "parquet"))]
+        "parquet" => {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "Parquet output requires building with --features parquet",
+            ))
+        }
+        #[cfg(feature = "sqlite")]
+        "sqlite" => {
+            let mut records = 0;
+            let datasets = source_kinds(args, data_dir)
+                .into_iter()
+                .map(|kind| load_dataset(args, kind, data_dir, &maxmind))
+                .inspect(|dataset| records += dataset.len());
+            sqlite_writer::write(&output(out, "ip2x.sqlite"), datasets)?;
+            artifacts.push(("ip2x.sqlite".to_string(), Some(records)));
+        }
+        #[cfg(not(feature = "sqlite"))]
+        "sqlite" => {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "SQLite output requires building with --features sqlite",
+            ))
+        }
+        other => {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Unknown output format: {}", other),
+            ))
+        }
+    }
+    manifest::write(out, &artifacts, kinds.len() < Kind::ALL.len())?;
+    let written: Vec<(String, Option<usize>)> = artifacts
+        .iter()
+        .map(|(name, records)| (output(out, name), *records))
+        .collect();
+    if args.flag("--dry-run") {
+        return dry_run::report(out, &artifacts);
+    }
+    if let Some(signer) = signer {
+        for (path, _) in &written {
+            signer.sign(path)?;
+        }
+        signer.sign(&output(out, MANIFEST_FILE))?;
+    }
+    if let Some(command) = args.value(&["--post-build"]) {
+        hook::run(command, &written)?;
+    }
+    if let Some(dir) = out_dir(args).filter(|dir| storage::is_remote(dir)) {
+        let stage = Stage::enter("upload", &dir);
+        let mut files: Vec<String> = artifacts.iter().map(|(path, _)| path.clone()).collect();
+        if signer.is_some() {
+            files.extend(artifacts.iter().map(|(path, _)| format!("{}.sig", path)));
+            files.push(format!("{}.sig", MANIFEST_FILE));
+        }
+        files.push(MANIFEST_FILE.to_string());
+        storage::upload(&dir, &files)?;
+        stage.finish(files.len());
+    }
+    if let Some(url) = args.value(&["--pushgateway"]) {
+        metrics::push(url)?;
+    }
+    Ok(())
+}
+
+fn build_bins(
+    args: &Args,
+    data_dir: &str,
+    out: &Path,
+    kinds: &[Kind],
+) -> std::io::Result<Vec<(String, Option<usize>)>> {
+    let maxmind = maxmind_paths(args, data_dir);
+    let wanted: Vec<Kind> = source_kinds(args, data_dir)
+        .into_iter()
+        .filter(|kind| kinds.contains(kind))
+        .collect();
+    let invalid = |message: String| Error::new(ErrorKind::InvalidInput, message);
+    let block_size = match args.value(&["--block-size"]) {
+        Some(size) => size
+            .parse()
+            .map_err(|_| invalid("--block-size must be a number".to_string()))?,
+        None => compress::DEFAULT_BLOCK_SIZE,
+    };
+    let zstd = match args.value(&["--compress"]).unwrap_or("none") {
+        "none" => false,
+        "zstd" => true,
+        other => return Err(invalid(format!("Unknown compression: {}", other))),
+    };
+
+    let mut layout = Layout::default();
+    if args.flag("--split-families") {
+        layout = layout.with(SPLIT_FAMILIES);
+    }
+    if args.flag("--ipv4-only") {
+        layout = layout.with(IPV4_ONLY);
+    }
+    if let Some(order) = args.value(&["--order"]) {
+        let order = Order::parse(order).ok_or_else(|| {
+            invalid(format!(
+                "Unknown --order: {} (expected smallest-first or largest-first)",
+                order
+            ))
+        })?;
+        layout = layout.with(order.flag());
+    }
+    match args.value(&["--layout"]).unwrap_or("ranges") {
+        "ranges" => {}
+        "trie" if zstd || args.flag("--index") => {
+            return Err(invalid(
+                "--layout trie cannot be combined with --compress zstd or --index".to_string(),
+            ))
+        }
+        "trie" => layout = layout.with(TRIE),
+        other => return Err(invalid(format!("Unknown layout: {}", other))),
+    }
+    if args.flag("--index") {
+        if zstd {
+            return Err(invalid(
+                "--index cannot be combined with --compress zstd".to_string(),
+            ));
+        }
+        layout = Layout {
+            index_interval: match args.value(&["--index-interval"]) {
+                Some(interval) => interval
+                    .parse()
+                    .ok()
+                    .filter(|&interval| interval > 0)
+                    .ok_or_else(|| {
+                        invalid("--index-interval must be a positive number".to_string())
+                    })?,
+                None => index::DEFAULT_INTERVAL,
+            },
+            ..layout.with(INDEXED)
+        };
+    }
+    let bloom_fpr = match args.value(&["--bloom-fpr"]) {
+        Some(rate) => Some(
+            rate.parse()
+                .ok()
+                .filter(|&rate: &f64| rate > 0.0 && rate < 1.0)
+                .ok_or_else(|| invalid("--bloom-fpr must be between 0 and 1".to_string()))?,
+        ),
+        None if args.flag("--bloom") => Some(bloom::DEFAULT_FPR),
+        None => None,
+    };
+    let geo_layout = match args.value(&["--geo-precision"]) {
+        Some(digits) => match digits.parse() {
+            Ok(precision @ 2..=5) => Layout {
+                geo_precision: precision,
+                ..layout.with(GEO_PAYLOAD | DELTA_COORDS)
+            },
+            _ => return Err(invalid("--geo-precision must be 2, 3, 4 or 5".to_string())),
+        },
+        None => layout.with(GEO_PAYLOAD),
+    };
+    let geo_layout = match args.value(&["--geo-cells"]) {
+        Some(_) if geo_layout.has(DELTA_COORDS) => {
+            return Err(invalid(
+                "--geo-cells cannot be combined with --geo-precision".to_string(),
+            ))
+        }
+        Some(spec) => Layout {
+            geo_cells: Some(Grid::parse(spec)?),
+            ..geo_layout.with(GEO_CELLS)
+        },
+        None => geo_layout,
+    };
+    let geo_layout = if args.flag("--geo-accuracy") {
+        geo_layout.with(GEO_ACCURACY)
+    } else {
+        geo_layout
+    };
+    let table = match args.value(&["--string-table"]).unwrap_or("plain") {
+        "plain" => Layout::default(),
+        "packed" => Layout::default().with(PACKED_STRINGS),
+        other => return Err(invalid(format!("Unknown string table format: {}", other))),
+    };
+
+    let shard_bits = shard_bits(args)?;
+
+    let mut artifacts = Vec::new();
+    let write_bin = |dataset: &Dataset, path: &str, layout: Layout| match zstd {
+        true => compress::write_zstd(dataset, path, layout, block_size),
+        false => dataset.write_with(path, layout),
+    };
+    let write = |dataset: &Dataset, kind: Kind, layout: Layout| {
+        let stage = Stage::enter("write", kind.file_name());
+        let started = Instant::now();
+        write_bin(dataset, &output(out, kind.file_name()), layout)?;
+        progress::report(kind.file_name(), dataset.len(), "records", started);
+        stage.finish(dataset.len());
+        let mut written = vec![(kind.file_name().to_string(), Some(dataset.len()))];
+        if let Some(bits) = shard_bits {
+            let stage = Stage::enter("shard", kind.file_name());
+            let started = Instant::now();
+            let shards = shard::write(out, dataset, bits, |shard, path| {
+                write_bin(shard, path, layout)
+            })?;
+            let count = shards.len() - 1;
+            progress::report(&shards[count].0, count, "shards", started);
+            stage.finish(count);
+            written.extend(shards);
+        }
+        Ok::<_, Error>(written)
+    };
+
+    if let (true, Some(budget)) = (wanted.contains(&Kind::Geo), max_memory(args)?) {
+        artifacts.push(write_geo_spilled(
+            args, data_dir, out, &maxmind, budget, geo_layout,
+        )?);
+    } else if wanted.contains(&Kind::Geo) {
+        artifacts.extend(write(
+            &load_dataset(args, Kind::Geo, data_dir, &maxmind),
+            Kind::Geo,
+            geo_layout,
+        )?);
+    }
+    if wanted.contains(&Kind::Country) {
+        let country = load_dataset(args, Kind::Country, data_dir, &maxmind);
+        artifacts.extend(write(&country, Kind::Country, layout)?);
+        if args.flag("--country-index") {
+            artifacts.push(write_prefix_index(out, COUNTRY_INDEX_FILE, country)?);
+        }
+    }
+    if wanted.contains(&Kind::Proxy) {
+        let layout = Layout {
+            flags: layout.flags & !(INDEXED | TRIE),
+            ..layout
+        };
+        let scored = Profile::from_args(args) != Profile::Minimal
+            && [MAXMIND_ANONYMOUS, CLOUD_RANGES]
+                .iter()
+                .any(|name| storage::exists(Path::new(data_dir).join(name)));
+        let proxy_layout = if scored {
+            layout.with(PROXY_CONFIDENCE)
+        } else {
+            layout
+        };
+        let proxy = load_dataset(args, Kind::Proxy, data_dir, &maxmind);
+        let proxy_layout = match &proxy {
+            Dataset::Proxy(types)
+                if types
+                    .iter()
+                    .flat_map(|(_, r)| r)
+                    .any(|r| r.3 != UNKNOWN_LAST_SEEN) =>
+            {
+                proxy_layout.with(PROXY_LAST_SEEN)
+            }
+            _ => proxy_layout,
+        };
+        artifacts.extend(write(&proxy, Kind::Proxy, proxy_layout)?);
+        if let (true, Dataset::Proxy(types)) = (args.flag("--roaring"), &proxy) {
+            let sets = ProxySets::build(types)?;
+            sets.write(&output(out, PROXY_SETS_FILE))?;
+            artifacts.push((PROXY_SETS_FILE.to_string(), Some(types.len() + 1)));
+        }
+        if let (Some(fpr), Dataset::Proxy(types)) = (bloom_fpr, &proxy) {
+            ProxyFilter::build(types, fpr).write(&output(out, BLOOM_FILE))?;
+            artifacts.push((BLOOM_FILE.to_string(), None));
+        }
+    }
+    if args.flag("--threat") {
+        let stage = Stage::enter("write", THREAT_FILE);
+        let started = Instant::now();
+        let threats = ThreatLists::load(&data_paths(args, data_dir, "--threat"))?;
+        threats.write(&output(out, THREAT_FILE))?;
+        progress::report(THREAT_FILE, threats.range_count(), "ranges", started);
+        stage.finish(threats.range_count());
+        artifacts.push((THREAT_FILE.to_string(), Some(threats.range_count())));
+    }
+    if args.flag("--rdns") {
+        let stage = Stage::enter("write", RDNS_FILE);
+        let started = Instant::now();
+        let rdns = RdnsTable::load(&data_paths(args, data_dir, "--rdns"))?;
+        rdns.write(&output(out, RDNS_FILE))?;
+        progress::report(RDNS_FILE, rdns.ranges.len(), "ranges", started);
+        stage.finish(rdns.ranges.len());
+        artifacts.push((RDNS_FILE.to_string(), Some(rdns.ranges.len())));
+    }
+    if args.flag("--network-type") {
+        let load = |kind| load_dataset(args, kind, data_dir, &maxmind);
+        let ranges = network_type::derive(
+            &load(Kind::Proxy),
+            &load(Kind::Usage),
+            &load(Kind::Mobile),
+            &load(Kind::Asn),
+        );
+        let stage = Stage::enter("write", NETWORK_TYPE_FILE);
+        let started = Instant::now();
+        network_type::write(&output(out, NETWORK_TYPE_FILE), &ranges)?;
+        progress::report(NETWORK_TYPE_FILE, ranges.len(), "ranges", started);
+        stage.finish(ranges.len());
+        artifacts.push((NETWORK_TYPE_FILE.to_string(), Some(ranges.len())));
+    }
+    if args.flag("--connection-type") {
+        let maxmind_path = format!("{}/{}", data_dir, MAXMIND_CONNECTION_TYPE);
+        let mmdb = match storage::exists(&maxmind_path) {
+            true => connection_type::load_maxmind(&maxmind_path)?,
+            false => Vec::new(),
+        };
+        let mut net_speed = Vec::new();
+        for (csv, is_v4) in csv_priority(args).order(MOBILE_V4_CSV, MOBILE_V6_CSV) {
+            process_net_speed_csv(&format!("{}/{}", data_dir, csv), is_v4, &mut net_speed);
+        }
+        drop_duplicates(CONNECTION_TYPE_FILE, &mut net_speed, |r| (r.0, r.1));
+        let ranges = connection_type::derive(
+            mmdb,
+            net_speed,
+            &load_dataset(args, Kind::Usage, data_dir, &maxmind),
+        );
+        let stage = Stage::enter("write", CONNECTION_TYPE_FILE);
+        let started = Instant::now();
+        connection_type::write(&output(out, CONNECTION_TYPE_FILE), &ranges)?;
+        progress::report(CONNECTION_TYPE_FILE, ranges.len(), "ranges", started);
+        stage.finish(ranges.len());
+        artifacts.push((CONNECTION_TYPE_FILE.to_string(), Some(ranges.len())));
+    }
+    if args.flag("--domain") {
+        let isp = match load_dataset(args, Kind::Isp, data_dir, &maxmind) {
+            Dataset::Isp(strings, data) => data
+                .iter()
+                .filter_map(|r| Some((r.0, r.1, strings.get(r.3.checked_sub(1)?)?.clone())))
+                .collect(),
+            _ => Vec::new(),
+        };
+        let mut mobile = Vec::new();
+        for (csv, is_v4) in csv_priority(args).order(MOBILE_V4_CSV, MOBILE_V6_CSV) {
+            process_domain_csv(&format!("{}/{}", data_dir, csv), is_v4, &mut mobile);
+        }
+        drop_duplicates(DOMAIN_FILE, &mut mobile, |r| (r.0, r.1));
+        let (strings, ranges) = domain::derive(vec![isp, mobile], &PublicSuffixes::load(data_dir));
+        let stage = Stage::enter("write", DOMAIN_FILE);
+        let started = Instant::now();
+        domain::write(&output(out, DOMAIN_FILE), &strings, &ranges)?;
+        progress::report(DOMAIN_FILE, ranges.len(), "ranges", started);
+        stage.finish(ranges.len());
+        artifacts.push((DOMAIN_FILE.to_string(), Some(ranges.len())));
+    }
+    if args.flag("--isp-groups") {
+        let (names, ranges) = isp_group::derive(
+            &load_dataset(args, Kind::Isp, data_dir, &maxmind),
+            &PublicSuffixes::load(data_dir),
+        );
+        let stage = Stage::enter("write", ISP_GROUP_FILE);
+        let started = Instant::now();
+        isp_group::write(&output(out, ISP_GROUP_FILE), &names, &ranges)?;
+        progress::report(ISP_GROUP_FILE, ranges.len(), "ranges", started);
+        stage.finish(ranges.len());
+        artifacts.push((ISP_GROUP_FILE.to_string(), Some(ranges.len())));
+    }
+
+    let layout = layout.with(table.flags);
+    let strings_changed = wanted.contains(&Kind::Asn) || wanted.contains(&Kind::Isp);
+    if args.flag("--shared-strings") && strings_changed {
+        let mut asn = load_dataset(args, Kind::Asn, data_dir, &maxmind);
+        let mut isp = load_dataset(args, Kind::Isp, data_dir, &maxmind);
+        let strings = share_strings(&mut asn, &mut isp);
+        write_strings_file(&output(out, STRINGS_FILE), &strings, table)?;
+        artifacts.push((STRINGS_FILE.to_string(), Some(strings.len())));
+        let asn_layout = match &asn {
+            Dataset::Asn(_, data) => asn_layout(data, layout.with(SHARED_STRINGS)),
+            _ => layout.with(SHARED_STRINGS),
+        };
+        artifacts.extend(write(&asn, Kind::Asn, asn_layout)?);
+        if args.flag("--asn-index") {
+            artifacts.push(write_prefix_index(out, ASN_INDEX_FILE, asn)?);
+        }
+        artifacts.extend(write(&isp, Kind::Isp, layout.with(SHARED_STRINGS))?);
+        if args.flag("--isp-index") {
+            artifacts.push(write_prefix_index(out, ISP_INDEX_FILE, isp)?);
+        }
+    } else if !args.flag("--shared-strings") {
+        for kind in [Kind::Asn, Kind::Isp] {
+            if wanted.contains(&kind) {
+                let dataset = load_dataset(args, kind, data_dir, &maxmind);
+                let layout = match &dataset {
+                    Dataset::Asn(_, data) => asn_layout(data, layout),
+                    _ => layout,
+                };
+                artifacts.extend(write(&dataset, kind, layout)?);
+                match kind {
+                    Kind::Asn if args.flag("--asn-index") => {
+                        artifacts.push(write_prefix_index(out, ASN_INDEX_FILE, dataset)?)
+                    }
+                    Kind::Isp if args.flag("--isp-index") => {
+                        artifacts.push(write_prefix_index(out, ISP_INDEX_FILE, dataset)?)
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    for kind in [Kind::Mobile, Kind::Usage, Kind::Elevation] {
+        if wanted.contains(&kind) {
+            artifacts.extend(write(
+                &load_dataset(args, kind, data_dir, &maxmind),
+                kind,
+                layout,
+            )?);
+        }
+    }
+
+    if args.flag("--reverse-geocode") && wanted.contains(&Kind::Geo) {
+        let stage = Stage::enter("reverse_geocode", CITIES_FILE);
+        let started = Instant::now();
+        let index = CityIndex::build(data_dir, &maxmind, &locales(args))?;
+        index.write(&output(out, CITIES_FILE))?;
+        progress::report(CITIES_FILE, index.city_count(), "cities", started);
+        stage.finish(index.city_count());
+        artifacts.push((CITIES_FILE.to_string(), Some(index.city_count())));
+    }
+    Ok(artifacts)
+}
+
+fn output(out: &Path, name: &str) -> String {
+    out.join(name).to_string_lossy().into_owned()
+}
+
+fn write_prefix_index(
+    out: &Path,
+    path: &str,
+    dataset: Dataset,
+) -> std::io::Result<(String, Option<usize>)> {
+    let stage = Stage::enter("write", path);
+    let started = Instant::now();
+    let count = PrefixIndex::write(&output(out, path), prefixes::entries(dataset).into_iter())?;
+    progress::report(path, count, "keys", started);
+    stage.finish(count);
+    Ok((path.to_string(), Some(count)))
+}
+
+fn trie_layout(args: &Args) -> bool {
+    args.value(&["--layout"]) == Some("trie")
+}
+
+fn shard_bits(args: &Args) -> std::io::Result<Option<u32>> {
+    args.value(&["--shard-bits"])
+        .map(|value| {
+            value
+                .parse()
+                .ok()
+                .filter(|bits| (1..=16).contains(bits))
+                .ok_or_else(|| {
+                    Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("Invalid --shard-bits: {} (expected 1 to 16)", value),
+                    )
+                })
+        })
+        .transpose()
+}
+
+fn max_memory(args: &Args) -> std::io::Result<Option<usize>> {
+    args.value(&["--max-memory"])
+        .map(|value| {
+            spill::parse_size(value)
+                .filter(|&budget| budget > 0)
+                .ok_or_else(|| {
+                    Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("Invalid --max-memory: {} (expected e.g. 512M or 2G)", value),
+                    )
+                })
+        })
+        .transpose()
+}
+
+fn write_geo_spilled(
+    args: &Args,
+    data_dir: &str,
+    out: &Path,
+    maxmind: &[String],
+    budget: usize,
+    layout: Layout,
+) -> std::io::Result<(String, Option<usize>)> {
+    let stage = Stage::enter("load", &sources(Kind::Geo, data_dir, maxmind).join(","));
+    let mut ranges = Spill::new(budget, layout.header().order());
+    let unlocated = unlocated(args);
+    let priority = geo_priority(args);
+    let layers = load_maxmind(maxmind, |source| source.load_all_geo(unlocated));
+    let maxmind_entries = interval::by_priority(
+        layers,
+        |r| (r.0, r.1),
+        |r, from, to| (from, to, r.2, r.3, r.4),
+    );
+
+    let mut db5 = Carve {
+        inner: &mut ranges,
+        coverage: match priority {
+            GeoPriority::MaxMindFirst => {
+                interval::coalesce(maxmind_entries.iter().map(|r| (r.0, r.1)).collect())
+            }
+            _ => Vec::new(),
+        },
+        covered: Vec::new(),
+    };
+    for (csv, is_v4) in csv_priority(args).order(GEO_V4_CSV, GEO_V6_CSV) {
+        let path = format!("{}/{}", data_dir, csv);
+        process_geo_csv(&path, is_v4, unlocated, &mut db5);
+    }
+    let covered = match priority {
+        GeoPriority::Ip2LocationFirst => interval::coalesce(db5.covered),
+        _ => Vec::new(),
+    };
+    let db5_count = ranges.pushed();
+
+    let maxmind_entries = match args.flag("--ipv4-only") {
+        true => merge::ipv4_only(
+            maxmind_entries,
+            |r| (r.0, r.1),
+            |r, from, to| (from, to, r.2, r.3, r.4),
+        ),
+        false => maxmind_entries,
+    };
+    for (from, to, lat, lon, accuracy) in maxmind_entries {
+        for (start, end) in interval::subtract(from, to, &covered) {
+            ranges.push((start, end, lat, lon, accuracy))?;
+        }
+    }
+    ranges.finish();
+    stage.finish(ranges.pushed() as usize);
+
+    let path = Kind::Geo.file_name();
+    let stage = Stage::enter("write", path);
+    let started = Instant::now();
+    let file = output(out, path);
+    let mut out = BufWriter::new(File::create(&file)?);
+    let conflicts = RefCell::new(Vec::new());
+    let mut first_pass = true;
+    let count = format::write_geo_sorted(&mut out, layout, || {
+        let report = std::mem::take(&mut first_pass);
+        let conflicts = &conflicts;
+        let mut db5: Option<GeoRange> = None;
+        let same = |kept: &Option<GeoRange>, range: &GeoRange| {
+            kept.is_some_and(|kept| (kept.0, kept.1) == (range.0, range.1))
+        };
+        Ok(ranges.sorted()?.filter_map(move |item| match item {
+            Ok((seq, range)) if seq < db5_count && same(&db5, &range) => {
+                if report && db5 != Some(range) {
+                    conflicts.borrow_mut().push((range.0, range.1));
+                }
+                None
+            }
+            Ok((seq, range)) if seq < db5_count => {
+                db5 = Some(range);
+                Some(Ok(range))
+            }
+            Ok((_, range)) if same(&db5, &range) => None,
+            Ok((_, range)) => Some(Ok(range)),
+            Err(err) => Some(Err(err)),
+        }))
+    })?;
+    out.flush()?;
+    drop(out);
+    report_conflicts(path, &conflicts.into_inner());
+    if layout.has(INDEXED) {
+        index::append(&file, Kind::Geo, layout.index_interval)?;
+    }
+    progress::report(path, count, "records", started);
+    stage.finish(count);
+    Ok((path.to_string(), Some(count)))
+}
+
+struct Carve<'a, E> {
+    inner: &'a mut E,
+    coverage: Vec<(u128, u128)>,
+    covered: Vec<(u128, u128)>,
+}
+
+impl<E: Extend<GeoRange>> Extend<GeoRange> for Carve<'_, E> {
+    fn extend<I: IntoIterator<Item = GeoRange>>(&mut self, items: I) {
+        for (from, to, lat, lon, accuracy) in items {
+            match self.covered.last_mut() {
+                Some(last) if (last.0..=last.1.saturating_add(1)).contains(&from) => {
+                    last.1 = last.1.max(to)
+                }
+                _ => self.covered.push((from, to)),
+            }
+            let pieces = interval::subtract(from, to, &self.coverage);
+            self.inner.extend(
+                pieces
+                    .into_iter()
+                    .map(|(start, end)| (start, end, lat, lon, accuracy)),
+            );
+        }
+    }
+}
+
+fn locales(args: &Args) -> Vec<String> {
+    args.value(&["--locales"])
+        .map(|value| {
+            value
+                .split(',')
+                .map(|locale| locale.trim().to_string())
+                .filter(|locale| !locale.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn data_paths(args: &Args, data_dir: &str, option: &str) -> Vec<String> {
+    args.value(&[option])
+        .unwrap_or("")
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            Path::new(data_dir)
+                .join(name)
+                .to_string_lossy()
+                .into_owned()
+        })
+        .collect()
+}
+
+fn maxmind_paths(args: &Args, data_dir: &str) -> Vec<String> {
+    let names = match args.value(&["--mmdb"]) {
+        Some(value) => value.split(',').map(str::trim).collect(),
+        None if !storage::exists(Path::new(data_dir).join(MAXMIND_CITY))
+            && storage::exists(Path::new(data_dir).join(GEOLITE_BLOCKS_V4)) =>
+        {
+            vec![GEOLITE_BLOCKS_V4]
+        }
+        None => vec![MAXMIND_CITY],
+    };
+    names
+        .into_iter()
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            Path::new(data_dir)
+                .join(name)
+                .to_string_lossy()
+                .into_owned()
+        })
+        .collect()
+}
+
+#[cfg(feature = "watch")]
+fn affected_kinds(data_dir: &str, maxmind: &[String], changed: &[PathBuf]) -> Vec<Kind> {
+    let feeds = |source: &str, path: &Path| {
+        let source = Path::new(source);
+        source.file_name() == path.file_name() || geolite_csv::is_companion(source, path)
+    };
+    Kind::ALL
+        .into_iter()
+        .filter(|&kind| {
+            sources(kind, data_dir, maxmind)
+                .iter()
+                .any(|source| changed.iter().any(|path| feeds(source, path)))
+        })
+        .collect()
+}
+
+fn missing_sources(data_dir: &str, maxmind: &[String], kinds: &[Kind]) -> Vec<String> {
+    let needed: Vec<String> = kinds
+        .iter()
+        .flat_map(|&kind| sources(kind, data_dir, maxmind))
+        .collect();
+    let mut sources = vec![
+        GEO_V4_CSV,
+        GEO_V6_CSV,
+        PROXY_V4_CSV,
+        PROXY_V6_CSV,
+        ASN_V4_CSV,
+        ASN_V6_CSV,
+    ];
+    if storage::exists(Path::new(data_dir).join(MOBILE_V4_CSV)) {
+        sources.push(MOBILE_V6_CSV);
+    }
+    sources
+        .into_iter()
+        .map(|name| format!("{}/{}", data_dir, name))
+        .chain(maxmind.iter().cloned())
+        .filter(|path| needed.contains(path) && !storage::exists(path))
+        .collect()
+}
+
+fn open_csv(path: &str) -> Option<CsvReader<BufReader<Tracked<Source>>>> {
+    if !storage::exists(path) {
+        return None;
+    }
+    Some(CsvReader::new(progress::open(path).unwrap()))
+}
+
+fn load_maxmind<T>(maxmind: &[String], load: impl Fn(&CitySource) -> T) -> Vec<T> {
+    maxmind
+        .iter()
+        .filter_map(|path| CitySource::open(path).ok())
+        .map(|reader| load(&reader))
+        .collect()
+}
+
+fn load_dataset(args: &Args, kind: Kind, data_dir: &str, maxmind: &[String]) -> Dataset {
+    let stage = Stage::enter("load", &sources(kind, data_dir, maxmind).join(","));
+    let dataset = match kind {
+        Kind::Geo => build_geo(
+            data_dir,
+            maxmind,
+            unlocated(args),
+            geo_priority(args),
+            csv_priority(args),
+        ),
+        Kind::Proxy => build_proxy_types(data_dir, csv_priority(args)),
+        Kind::Asn => build_asn(data_dir, csv_priority(args)),
+        Kind::Isp => build_isp(data_dir, csv_priority(args)),
+        Kind::Mobile => build_mobile(data_dir, csv_priority(args)),
+        Kind::Usage => build_usage(data_dir, csv_priority(args)),
+        Kind::Elevation => build_elevation(data_dir, csv_priority(args)),
+        Kind::Country => build_country(data_dir, maxmind, csv_priority(args)),
+    };
+    let dataset = match args.value(&["--mmdb-map"]) {
+        Some(value) => mmdb_map::splice(dataset, &mmdb_map::parse(value, data_dir).unwrap()),
+        None => dataset,
+    };
+    let dataset = match args.flag("--normalize-domains") {
+        true => domain::normalize_isp(dataset, &PublicSuffixes::load(data_dir)),
+        false => dataset,
+    };
+    let dataset = Profile::from_args(args).reduce(dataset);
+    let dataset = match args.flag("--ipv4-only") {
+        true => merge::reshape(dataset, Shape::Ipv4Only),
+        false => dataset,
+    };
+    let dataset = match args.flag("--flatten") || trie_layout(args) {
+        true => merge::reshape(dataset, Shape::Flatten),
+        false => dataset,
+    };
+    let dataset = match args.flag("--cidr-aligned") {
+        true => merge::reshape(dataset, Shape::CidrAligned),
+        false => dataset,
+    };
+    stage.finish(dataset.len());
+    dataset
+}
+
+fn sources(kind: Kind, data_dir: &str, maxmind: &[String]) -> Vec<String> {
+    let files = match kind {
+        Kind::Geo | Kind::Country => vec![GEO_V4_CSV, GEO_V6_CSV],
+        Kind::Proxy => vec![PROXY_V4_CSV, PROXY_V6_CSV, MAXMIND_ANONYMOUS, CLOUD_RANGES],
+        Kind::Asn => vec![ASN_V4_CSV, ASN_V6_CSV, MAXMIND_ASN],
+        Kind::Isp => vec![PROXY_V4_CSV, PROXY_V6_CSV],
+        Kind::Mobile | Kind::Elevation => vec![MOBILE_V4_CSV, MOBILE_V6_CSV],
+        Kind::Usage => vec![MOBILE_V4_CSV, MOBILE_V6_CSV, PROXY_V4_CSV, PROXY_V6_CSV],
+    };
+    let files = files
+        .into_iter()
+        .map(|name| format!("{}/{}", data_dir, name));
+    match kind {
+        Kind::Geo | Kind::Country => files.chain(maxmind.iter().cloned()).collect(),
+        _ => files.collect(),
+    }
+}
+
+fn source_kinds(args: &Args, data_dir: &str) -> Vec<Kind> {
+    let has_db24 = storage::exists(Path::new(data_dir).join(MOBILE_V4_CSV));
+    let profile = Profile::from_args(args);
+    Kind::ALL
+        .into_iter()
+        .filter(|&kind| profile.includes(kind))
+        .filter(|kind| match kind {
+            Kind::Mobile => has_db24,
+            Kind::Elevation => has_db24 && args.flag("--elevation"),
+            _ => true,
+        })
+        .collect()
+}
+
+fn build_geo(
+    data_dir: &str,
+    maxmind: &[String],
+    unlocated: Unlocated,
+    priority: GeoPriority,
+    csv_priority: CsvPriority,
+) -> Dataset {
+    let mut ranges = Vec::new();
+    for (csv, is_v4) in csv_priority.order(GEO_V4_CSV, GEO_V6_CSV) {
+        let path = format!("{}/{}", data_dir, csv);
+        process_geo_csv(&path, is_v4, unlocated, &mut ranges);
+    }
+    drop_duplicates(Kind::Geo.file_name(), &mut ranges, |r| (r.0, r.1));
+
+    let layers = load_maxmind(maxmind, |source| source.load_all_geo(unlocated));
+    if !layers.is_empty() {
+        let maxmind_entries = interval::by_priority(
+            layers,
+            |r| (r.0, r.1),
+            |r, from, to| (from, to, r.2, r.3, r.4),
+        );
+        return Dataset::Geo(merge::by_source(
+            ranges,
+            maxmind_entries,
+            priority,
+            |r| (r.0, r.1),
+            |r, from, to| (from, to, r.2, r.3, r.4),
+        ));
+    }
+
+    Dataset::Geo(ranges)
+}
+
+fn unlocated(args: &Args) -> Unlocated {
+    Unlocated {
+        centroids: args.flag("--country-centroids"),
+        keep: args.flag("--keep-unlocated"),
+    }
+}
+
+fn geo_priority(args: &Args) -> GeoPriority {
+    args.value(&["--geo-priority"])
+        .and_then(GeoPriority::parse)
+        .unwrap_or(GeoPriority::Ip2LocationFirst)
+}
+
+fn csv_priority(args: &Args) -> CsvPriority {
+    match args.flag("--ipv4-only") {
+        true => CsvPriority::Ipv4Only,
+        false => args
+            .value(&["--csv-priority"])
+            .and_then(CsvPriority::parse)
+            .unwrap_or_default(),
+    }
+}
+
+fn report_conflicts(name: &str, conflicts: &[(u128, u128)]) {
+    for &(from, to) in conflicts.iter().take(MAX_REPORTED_CONFLICTS) {
+        logging::warn(&format!(
+            "{}: duplicate range {}-{} has conflicting values, keeping the higher-priority source",
+            name,
+            u128_to_ip(from),
+            u128_to_ip(to)
+        ));
+    }
+    if conflicts.len() > MAX_REPORTED_CONFLICTS {
+        logging::warn(&format!(
+            "{}: {} more conflicting duplicate ranges",
+            name,
+            conflicts.len() - MAX_REPORTED_CONFLICTS
+        ));
+    }
+}
+
+fn drop_duplicates<T: PartialEq>(
+    name: &str,
+    items: &mut Vec<T>,
+    range: impl Fn(&T) -> (u128, u128),
+) {
+    report_conflicts(name, &interval::dedup(items, range));
+}
+
+fn process_geo_csv(
+    path: &str,
+    is_v4: bool,
+    unlocated: Unlocated,
+    ranges: &mut impl Extend<GeoRange>,
+) {
+    let Some(mut reader) = open_csv(path) else {
+        return;
+    };
+
+    while let Some(parts) = reader.next_record() {
+        let parts = parts.unwrap();
+
+        if parts.len() < 8 {
+            continue;
+        }
+
+        let mut from = parse_u128(&parts[0]);
+        let mut to = parse_u128(&parts[1]);
+        let (lat, lon) = (parse_f32(&parts[6]), parse_f32(&parts[7]));
+        let Some((lat, lon, accuracy)) = locate(lat, lon, parts[2].as_bytes(), unlocated) else {
+            continue;
+        };
+
+        if is_v4 {
+            from = ipv4_to_ipv6(from as u32);
+            to = ipv4_to_ipv6(to as u32);
+        }
+
+        ranges.extend([(from, to, lat, lon, accuracy)]);
+    }
+}
+
+fn build_country(data_dir: &str, maxmind: &[String], csv_priority: CsvPriority) -> Dataset {
+    let mut subdivisions = HashMap::new();
+    for codes in load_maxmind(maxmind, CitySource::subdivision_codes) {
+        for (key, code) in codes {
+            subdivisions.entry(key).or_insert(code);
+        }
+    }
+
+    let mut ranges = Vec::new();
+    for (csv, is_v4) in csv_priority.order(GEO_V4_CSV, GEO_V6_CSV) {
+        let path = format!("{}/{}", data_dir, csv);
+        process_country_csv(&path, is_v4, &subdivisions, &mut ranges);
+    }
+    drop_duplicates(Kind::Country.file_name(), &mut ranges, |r| (r.0, r.1));
+
+    let layers = load_maxmind(maxmind, CitySource::load_all_country);
+    if !layers.is_empty() {
+        let maxmind_entries = interval::by_priority(
+            layers,
+            |r| (r.0, r.1),
+            |r, from, to| (from, to, r.2, r.3, r.4, r.5, r.6.clone()),
+        );
+
+        let mut countries = HashMap::new();
+        let mut alternates = HashMap::new();
+        for (from, to, country, continent, eu, alternate, _) in &maxmind_entries {
+            countries.entry(*country).or_insert((*continent, *eu));
+            if let Some(alternate) = alternate {
+                alternates.insert((*from, *to), *alternate);
+            }
+        }
+        for (from, to, country, continent, eu, alternate, _) in ranges.iter_mut() {
+            if let Some(&(known_continent, known_eu)) = countries.get(country) {
+                (*continent, *eu) = (known_continent, known_eu);
+            }
+            *alternate = alternates
+                .get(&(*from, *to))
+                .filter(|(_, code)| code != country)
+                .copied();
+        }
+
+        return Dataset::Country(merge::by_source(
+            ranges,
+            maxmind_entries,
+            GeoPriority::Ip2LocationFirst,
+            |r| (r.0, r.1),
+            |r, from, to| (from, to, r.2, r.3, r.4, r.5, r.6.clone()),
+        ));
+    }
+
+    Dataset::Country(ranges)
+}
+
+fn process_country_csv(
+    path: &str,
+    is_v4: bool,
+    subdivisions: &HashMap<([u8; 2], String), String>,
+    ranges: &mut Vec<CountryRange>,
+) {
+    let Some(mut reader) = open_csv(path) else {
+        return;
+    };
+
+    while let Some(parts) = reader.next_record() {
+        let parts = parts.unwrap();
+
+        if parts.len() < 5 {
+            continue;
+        }
+
+        let Ok(country) = <[u8; 2]>::try_from(parts[2].as_bytes()) else {
+            continue;
+        };
+
+        let mut from = parse_u128(&parts[0]);
+        let mut to = parse_u128(&parts[1]);
+
+        if is_v4 {
+            from = ipv4_to_ipv6(from as u32);
+            to = ipv4_to_ipv6(to as u32);
+        }
+
+        let subdivision = subdivisions.get(&(country, parts[4].to_string())).cloned();
+        ranges.push((from, to, country, *b"--", false, None, subdivision));
+    }
+}
+
+fn build_proxy_types(data_dir: &str, csv_priority: CsvPriority) -> Dataset {
+    let mut types: HashMap<String, Vec<(u128, u128, u16)>> = HashMap::new();
+
+    for (csv, is_v4) in csv_priority.order(PROXY_V4_CSV, PROXY_V6_CSV) {
+        process_proxy_csv(&format!("{}/{}", data_dir, csv), is_v4, &mut types);
+    }
+    for (name, ranges) in &mut types {
+        let name = format!("{} type {}", Kind::Proxy.file_name(), name);
+        drop_duplicates(&name, ranges, |r| (r.0, r.1));
+    }
+
+    let mut sources = vec![types
+        .values()
+        .flatten()
+        .map(|r| (r.0, r.1))
+        .collect::<Vec<_>>()];
+
+    let maxmind_path = format!("{}/{}", data_dir, MAXMIND_ANONYMOUS);
+    if let Ok(reader) = MaxMindReader::open(&maxmind_path) {
+        let mut flagged = Vec::new();
+        for (from, to, record) in reader.load_all() {
+            if MaxMindReader::is_anonymous(&record) {
+                flagged.push((from, to));
+            }
+            for proxy_type in MaxMindReader::anonymous_types(&record) {
+                types.entry(proxy_type.to_string()).or_default().push((
+                    from,
+                    to,
+                    UNKNOWN_LAST_SEEN,
+                ));
+            }
+        }
+        sources.push(flagged);
+    }
+
+    let cloud_path = format!("{}/{}", data_dir, CLOUD_RANGES);
+    if storage::exists(&cloud_path) {
+        match extract::read_cidrs(&cloud_path) {
+            Ok(cloud) => {
+                types.entry("DCH".to_string()).or_default().extend(
+                    cloud
+                        .iter()
+                        .map(|&(from, to)| (from, to, UNKNOWN_LAST_SEEN)),
+                );
+                sources.push(cloud);
+            }
+            Err(err) => logging::warn(&format!("skipped: {}", err)),
+        }
+    }
+
+    if sources.len() == 1 {
+        let mut unscored: Vec<_> = types
+            .into_iter()
+            .map(|(name, ranges)| {
+                let mut ranges: Vec<_> = ranges
+                    .into_iter()
+                    .map(|(from, to, last_seen)| (from, to, 1, last_seen))
+                    .collect();
+                ranges.sort_by_key(|r| r.0);
+                (name, ranges)
+            })
+            .collect();
+        unscored.sort_by(|a, b| a.0.cmp(&b.0));
+        return Dataset::Proxy(unscored);
+    }
+
+    let agreement = merge::agreement(&sources);
+    let mut scored: Vec<_> = types
+        .into_iter()
+        .map(|(name, ranges)| {
+            let mut map = IntervalMap::new(
+                |r: &(u128, u128, u16)| (r.0, r.1),
+                |r, from, to| (from, to, r.2),
+            );
+            map.extend(0, ranges);
+            let mut merged: Vec<(u128, u128, u16)> = Vec::new();
+            for (from, to, last_seen) in map.flatten() {
+                match merged.last_mut() {
+                    Some(last) if last.2 == last_seen && last.1.checked_add(1) == Some(from) => {
+                        last.1 = to
+                    }
+                    _ => merged.push((from, to, last_seen)),
+                }
+            }
+            let mut pieces = Vec::new();
+            for (from, to, last_seen) in merged {
+                let first = agreement.partition_point(|r| r.1 < from);
+                for &(start, end, count) in agreement[first..].iter().take_while(|r| r.0 <= to) {
+                    pieces.push((start.max(from), end.min(to), count, last_seen));
+                }
+            }
+            (name, pieces)
+        })
+        .collect();
+    scored.sort_by(|a, b| a.0.cmp(&b.0));
+    Dataset::Proxy(scored)
+}
+
+fn process_proxy_csv(path: &str, is_v4: bool, types: &mut HashMap<String, Vec<(u128, u128, u16)>>) {
+    if let Some(reader) = open_csv(path) {
+        read_proxy_rows(reader, is_v4, types);
+    }
+}
+
+fn read_proxy_rows(
+    mut reader: CsvReader<impl BufRead>,
+    is_v4: bool,
+    types: &mut HashMap<String, Vec<(u128, u128, u16)>>,
+) {
+    while let Some(parts) = reader.next_record() {
+        let parts = parts.unwrap();
+
+        if parts.len() < 3 {
+            continue;
+        }
+
+        let mut from = parse_u128(&parts[0]);
+        let mut to = parse_u128(&parts[1]);
+        let proxy_type = &parts[2];
+        let last_seen = parts
+            .get(PROXY_LAST_SEEN_COLUMN)
+            .and_then(|days| days.trim().parse().ok())
+            .filter(|&days| days != UNKNOWN_LAST_SEEN)
+            .unwrap_or(UNKNOWN_LAST_SEEN);
+
+        if is_v4 {
+            from = ipv4_to_ipv6(from as u32);
+            to = ipv4_to_ipv6(to as u32);
+        }
+
+        match types.get_mut(proxy_type) {
+            Some(ranges) => ranges.push((from, to, last_seen)),
+            None => {
+                types.insert(proxy_type.to_string(), vec![(from, to, last_seen)]);
+            }
+        }
+    }
+}
+
+fn build_asn(data_dir: &str, csv_priority: CsvPriority) -> Dataset {
+    let mut strings = Vec::new();
+    let mut string_map = HashMap::new();
+    let mut data = Vec::new();
+    let organizations = load_asn_organizations(&format!("{}/{}", data_dir, MAXMIND_ASN));
+
+    for (csv, is_v4) in csv_priority.order(ASN_V4_CSV, ASN_V6_CSV) {
+        let path = format!("{}/{}", data_dir, csv);
+        process_asn_csv(
+            &path,
+            is_v4,
+            &organizations,
+            &mut data,
+            &mut strings,
+            &mut string_map,
+        );
+    }
+    drop_duplicates(Kind::Asn.file_name(), &mut data, |r| (r.0, r.1));
+    let mut dataset = Dataset::Asn(strings, data);
+    order_by_frequency(&mut [&mut dataset]);
+    dataset
+}
+
+fn load_asn_organizations(path: &str) -> HashMap<u64, String> {
+    let Ok(reader) = MaxMindReader::open(path) else {
+        return HashMap::new();
+    };
+    reader
+        .load_paths(&["autonomous_system_number", "autonomous_system_organization"])
+        .into_iter()
+        .filter_map(|(_, _, values)| {
+            let asn = values[0].as_ref()?.as_u64()?;
+            let organization = values[1].as_ref()?.as_str()?;
+            (!organization.is_empty()).then(|| (asn, organization.to_string()))
+        })
+        .collect()
+}
+
+fn process_asn_csv(
+    path: &str,
+    is_v4: bool,
+    organizations: &HashMap<u64, String>,
+    data: &mut Vec<AsnRange>,
+    strings: &mut Vec<String>,
+    string_map: &mut HashMap<String, usize>,
+) {
+    let Some(mut reader) = open_csv(path) else {
+        return;
+    };
+
+    while let Some(parts) = reader.next_record() {
+        let parts = parts.unwrap();
+
+        if parts.len() < 5 {
+            continue;
+        }
+
+        let mut from = parse_u128(&parts[0]);
+        let mut to = parse_u128(&parts[1]);
+        let cidr = &parts[2];
+        let asn = &parts[3];
+        let as_name = &parts[4];
+
+        if asn == "-" {
+            continue;
+        }
+
+        if is_v4 {
+            from = ipv4_to_ipv6(from as u32);
+            to = ipv4_to_ipv6(to as u32);
+        }
+
+        let cidr_idx = intern(cidr, strings, string_map);
+        let asn_idx = intern(asn, strings, string_map);
+        let name_idx = intern(as_name, strings, string_map);
+        let org_idx = asn
+            .parse()
+            .ok()
+            .and_then(|asn: u64| organizations.get(&asn))
+            .map(|org| intern(org, strings, string_map));
+
+        data.push((from, to, cidr_idx, asn_idx, name_idx, org_idx));
+    }
+}
+
+fn build_isp(data_dir: &str, csv_priority: CsvPriority) -> Dataset {
+    let mut strings = Vec::new();
+    let mut string_map = HashMap::new();
+    let mut data = Vec::new();
+
+    for (csv, is_v4) in csv_priority.order(PROXY_V4_CSV, PROXY_V6_CSV) {
+        let path = format!("{}/{}", data_dir, csv);
+        process_isp_csv(&path, is_v4, &mut data, &mut strings, &mut string_map);
+    }
+    drop_duplicates(Kind::Isp.file_name(), &mut data, |r| (r.0, r.1));
+    let mut dataset = Dataset::Isp(strings, data);
+    order_by_frequency(&mut [&mut dataset]);
+    dataset
+}
+
+fn process_isp_csv(
+    path: &str,
+    is_v4: bool,
+    data: &mut Vec<(u128, u128, usize, usize, usize)>,
+    strings: &mut Vec<String>,
+    string_map: &mut HashMap<String, usize>,
+) {
+    let Some(mut reader) = open_csv(path) else {
+        return;
+    };
+
+    while let Some(parts) = reader.next_record() {
+        let parts = parts.unwrap();
+
+        if parts.len() < 9 {
+            continue;
+        }
+
+        let mut from = parse_u128(&parts[0]);
+        let mut to = parse_u128(&parts[1]);
+        let isp = &parts[7];
+        let domain = &parts[8];
+        let provider = if parts.len() > 13 { &parts[13] } else { "-" };
+
+        if is_v4 {
+            from = ipv4_to_ipv6(from as u32);
+            to = ipv4_to_ipv6(to as u32);
+        }
+
+        let isp_idx = intern_with_offset(isp, strings, string_map);
+        let domain_idx = intern_with_offset(domain, strings, string_map);
+        let provider_idx = intern_with_offset(provider, strings, string_map);
+
+        data.push((from, to, isp_idx, domain_idx, provider_idx));
+    }
+}
+
+fn build_mobile(data_dir: &str, csv_priority: CsvPriority) -> Dataset {
+    let mut strings = Vec::new();
+    let mut string_map = HashMap::new();
+    let mut data = Vec::new();
+
+    for (csv, is_v4) in csv_priority.order(MOBILE_V4_CSV, MOBILE_V6_CSV) {
+        let path = format!("{}/{}", data_dir, csv);
+        process_mobile_csv(&path, is_v4, &mut data, &mut strings, &mut string_map);
+    }
+    drop_duplicates(Kind::Mobile.file_name(), &mut data, |r| (r.0, r.1));
+    let mut dataset = Dataset::Mobile(strings, data);
+    order_by_frequency(&mut [&mut dataset]);
+    dataset
+}
+
+fn process_mobile_csv(
+    path: &str,
+    is_v4: bool,
+    data: &mut Vec<(u128, u128, usize, usize, usize)>,
+    strings: &mut Vec<String>,
+    string_map: &mut HashMap<String, usize>,
+) {
+    let Some(mut reader) = open_csv(path) else {
+        return;
+    };
+
+    while let Some(parts) = reader.next_record() {
+        let parts = parts.unwrap();
+
+        if parts.len() < 20 {
+            continue;
+        }
+
+        let mut from = parse_u128(&parts[0]);
+        let mut to = parse_u128(&parts[1]);
+        let (mcc, mnc, brand) = (&parts[17], &parts[18], &parts[19]);
+
+        if mcc == "-" && mnc == "-" && brand == "-" {
+            continue;
+        }
+
+        if is_v4 {
+            from = ipv4_to_ipv6(from as u32);
+            to = ipv4_to_ipv6(to as u32);
+        }
+
+        let mcc_idx = intern_with_offset(mcc, strings, string_map);
+        let mnc_idx = intern_with_offset(mnc, strings, string_map);
+        let brand_idx = intern_with_offset(brand, strings, string_map);
+
+        data.push((from, to, mcc_idx, mnc_idx, brand_idx));
+    }
+}
+
+fn build_usage(data_dir: &str, csv_priority: CsvPriority) -> Dataset {
+    let mut map = IntervalMap::new(|r: &UsageRange| (r.0, r.1), |r, from, to| (from, to, r.2));
+
+    let sources = [
+        (csv_priority.order(MOBILE_V4_CSV, MOBILE_V6_CSV), 21),
+        (csv_priority.order(PROXY_V4_CSV, PROXY_V6_CSV), 9),
+    ];
+    for (files, column) in sources {
+        let mut ranges = Vec::new();
+        for (csv, is_v4) in files {
+            process_usage_csv(&format!("{}/{}", data_dir, csv), is_v4, column, &mut ranges);
+        }
+        drop_duplicates(Kind::Usage.file_name(), &mut ranges, |r| (r.0, r.1));
+        map.extend(0, ranges);
+    }
+    Dataset::Usage(map.unique())
+}
+
+fn process_usage_csv(path: &str, is_v4: bool, column: usize, ranges: &mut Vec<UsageRange>) {
+    let Some(mut reader) = open_csv(path) else {
+        return;
+    };
+
+    while let Some(parts) = reader.next_record() {
+        let parts = parts.unwrap();
+
+        if parts.len() <= column {
+            continue;
+        }
+
+        let mask = ip2x_core::usage::parse(&parts[column]);
+        if mask == 0 {
+            continue;
+        }
+
+        let mut from = parse_u128(&parts[0]);
+        let mut to = parse_u128(&parts[1]);
+
+        if is_v4 {
+            from = ipv4_to_ipv6(from as u32);
+            to = ipv4_to_ipv6(to as u32);
+        }
+
+        ranges.push((from, to, mask));
+    }
+}
+
+fn process_domain_csv(path: &str, is_v4: bool, ranges: &mut Vec<(u128, u128, String)>) {
+    let Some(mut reader) = open_csv(path) else {
+        return;
+    };
+
+    while let Some(parts) = reader.next_record() {
+        let parts = parts.unwrap();
+
+        if parts.len() <= MOBILE_DOMAIN_COLUMN || &parts[MOBILE_DOMAIN_COLUMN] == "-" {
+            continue;
+        }
+
+        let mut from = parse_u128(&parts[0]);
+        let mut to = parse_u128(&parts[1]);
+
+        if is_v4 {
+            from = ipv4_to_ipv6(from as u32);
+            to = ipv4_to_ipv6(to as u32);
+        }
+
+        ranges.push((from, to, parts[MOBILE_DOMAIN_COLUMN].to_string()));
+    }
+}
+
+fn process_net_speed_csv(path: &str, is_v4: bool, ranges: &mut Vec<(u128, u128, u8)>) {
+    let Some(mut reader) = open_csv(path) else {
+        return;
+    };
+
+    while let Some(parts) = reader.next_record() {
+        let parts = parts.unwrap();
+
+        if parts.len() <= NET_SPEED_COLUMN {
+            continue;
+        }
+
+        let Some(connection_type) = connection_type::from_net_speed(&parts[NET_SPEED_COLUMN])
+        else {
+            continue;
+        };
+
+        let mut from = parse_u128(&parts[0]);
+        let mut to = parse_u128(&parts[1]);
+
+        if is_v4 {
+            from = ipv4_to_ipv6(from as u32);
+            to = ipv4_to_ipv6(to as u32);
+        }
+
+        ranges.push((from, to, connection_type));
+    }
+}
+
+fn build_elevation(data_dir: &str, csv_priority: CsvPriority) -> Dataset {
+    let mut ranges = Vec::new();
+
+    for (csv, is_v4) in csv_priority.order(MOBILE_V4_CSV, MOBILE_V6_CSV) {
+        process_elevation_csv(&format!("{}/{}", data_dir, csv), is_v4, &mut ranges);
+    }
+    drop_duplicates(Kind::Elevation.file_name(), &mut ranges, |r| (r.0, r.1));
+    Dataset::Elevation(ranges)
+}
+
+fn process_elevation_csv(path: &str, is_v4: bool, ranges: &mut Vec<(u128, u128, i16)>) {
+    let Some(mut reader) = open_csv(path) else {
+        return;
+    };
+
+    while let Some(parts) = reader.next_record() {
+        let parts = parts.unwrap();
+
+        if parts.len() < 21 {
+            continue;
+        }
+
+        let Ok(meters) = parts[20].trim().parse::<i16>() else {
+            continue;
+        };
+
+        let mut from = parse_u128(&parts[0]);
+        let mut to = parse_u128(&parts[1]);
+
+        if is_v4 {
+            from = ipv4_to_ipv6(from as u32);
+            to = ipv4_to_ipv6(to as u32);
+        }
+
+        ranges.push((from, to, meters));
+    }
+}
+
+fn parse_u128(s: &str) -> u128 {
+    s.trim_matches('"').parse().unwrap_or(0)
+}
+
+fn parse_f32(s: &str) -> f32 {
+    let cleaned = s.trim_matches('"');
+    if cleaned == "-" {
+        return 0.0;
+    }
+    cleaned.parse().unwrap_or(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PX12_ROW: &str = "\"16777216\",\"16777471\",\"DCH\",\"AU\",\"Australia\",\"Queensland\",\"Brisbane\",\"APNIC and Cloudflare DNS Resolver Project\",\"cloudflare.com\",\"CDN\",\"13335\",\"Cloudflare Inc\",\"27\",\"-\",\"-\",\"0\"";
+
+    fn last_seen(rows: &[String]) -> Vec<u16> {
+        let mut types = HashMap::new();
+        let csv = rows.join("\n");
+        read_proxy_rows(CsvReader::new(csv.as_bytes()), true, &mut types);
+        types["DCH"].iter().map(|r| r.2).collect()
+    }
+
+    #[test]
+    fn reads_px12_last_seen_as_days() {
+        let mut types = HashMap::new();
+        read_proxy_rows(CsvReader::new(PX12_ROW.as_bytes()), true, &mut types);
+        assert_eq!(
+            types["DCH"],
+            [(ipv4_to_ipv6(16777216), ipv4_to_ipv6(16777471), 27)]
+        );
+    }
+
+    #[test]
+    fn seen_today_is_distinct_from_unknown() {
+        let row = |days: &str| PX12_ROW.replace("\"27\"", &format!("\"{}\"", days));
+        assert_eq!(
+            last_seen(&[row("0"), row("-"), row("65535")]),
+            [0, UNKNOWN_LAST_SEEN, UNKNOWN_LAST_SEEN]
+        );
+    }
+
+    #[test]
+    fn builds_into_out_dir() {
+        let root = std::env::temp_dir().join(format!("ip2x-out-dir-{}", std::process::id()));
+        let (data, out) = (root.join("data"), root.join("out"));
+        fs::create_dir_all(&data).unwrap();
+        fs::write(
+            data.join(GEO_V4_CSV),
+            "\"16777216\",\"16777471\",\"AU\",\"-\",\"-\",\"-\",\"-27.46794\",\"153.02809\"\n",
+        )
+        .unwrap();
+        let raw = [&data, &out].map(|path| path.to_string_lossy().into_owned());
+        let raw = [&raw[0], "--out-dir", &raw[1], "--quiet"].map(str::to_string);
+        build(&Args::parse(&raw, BUILD_SWITCHES)).unwrap();
+
+        let manifest: serde_json::Value =
+            serde_json::from_slice(&fs::read(out.join(MANIFEST_FILE)).unwrap()).unwrap();
+        let artifacts = manifest["artifacts"].as_array().unwrap();
+        assert!(artifacts.iter().any(|a| a["path"] == Kind::Geo.file_name()));
+        for artifact in artifacts {
+            assert!(out.join(artifact["path"].as_str().unwrap()).is_file());
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,40 @@
+use std::io::{self, BufWriter, Error, ErrorKind, Result, Write};
+
+use crate::cli::Args;
+use crate::reader::open_from_args;
+
+pub fn run(args: &Args) -> Result<()> {
+    let (asn, country, isp) = (
+        args.value(&["--asn"]),
+        args.value(&["--country"]),
+        args.value(&["--isp"]),
+    );
+    if [asn, country, isp].iter().filter(|v| v.is_some()).count() != 1 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Usage: ip2x list --asn <number> | --country <code> | --isp <text> [--bins <dir>] [--mmap]",
+        ));
+    }
+
+    let reader = open_from_args(args)?;
+    let mut out = BufWriter::new(io::stdout().lock());
+    if let Some(isp) = isp {
+        for found in reader.search_isp(isp)? {
+            for (network, prefix) in found.range.to_cidrs() {
+                writeln!(out, "{}/{}\t{}", network, prefix, found.value)?;
+            }
+        }
+        return out.flush();
+    }
+    let ranges = match (asn, country) {
+        (Some(asn), _) => reader.asn_prefixes(asn.trim_start_matches(['A', 'S', 'a', 's']))?,
+        (_, Some(country)) => reader.country_prefixes(&country.to_ascii_uppercase())?,
+        _ => Vec::new(),
+    };
+    for range in ranges {
+        for (network, prefix) in range.to_cidrs() {
+            writeln!(out, "{}/{}", network, prefix)?;
+        }
+    }
+    out.flush()
+}
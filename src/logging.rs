@@ -0,0 +1,130 @@
+use std::io::{Error, ErrorKind, Result};
+#[cfg(feature = "tracing")]
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "tracing")]
+use std::time::Instant;
+
+use crate::metrics;
+#[cfg(any(feature = "tracing", feature = "watch", feature = "serve"))]
+use crate::progress;
+
+#[cfg(feature = "tracing")]
+static STRUCTURED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(feature = "tracing")]
+pub fn init(format: &str) -> Result<()> {
+    use tracing_subscriber::fmt::format::FmtSpan;
+
+    let builder = tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_span_events(FmtSpan::CLOSE);
+    let installed = match format {
+        "text" => builder.try_init(),
+        "json" => builder.json().try_init(),
+        other => {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Unknown log format: {}", other),
+            ))
+        }
+    };
+    installed.map_err(|err| Error::other(err.to_string()))?;
+    STRUCTURED.store(true, Ordering::Relaxed);
+    progress::set_level(progress::QUIET);
+
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        tracing::error!(panic = %info, "build panicked");
+        default_hook(info);
+    }));
+    Ok(())
+}
+
+#[cfg(not(feature = "tracing"))]
+pub fn init(_format: &str) -> Result<()> {
+    Err(Error::new(
+        ErrorKind::Unsupported,
+        "--log-format requires building with --features tracing",
+    ))
+}
+
+#[cfg(any(feature = "watch", feature = "serve"))]
+pub fn info(message: &str) {
+    #[cfg(feature = "tracing")]
+    if STRUCTURED.load(Ordering::Relaxed) {
+        tracing::info!("{}", message);
+        return;
+    }
+    if progress::level() >= progress::NORMAL {
+        eprintln!("{}", message);
+    }
+}
+
+pub fn warn(message: &str) {
+    #[cfg(feature = "tracing")]
+    if STRUCTURED.load(Ordering::Relaxed) {
+        tracing::warn!("{}", message);
+        return;
+    }
+    eprintln!("{}", message);
+}
+
+pub fn error(err: &Error) {
+    #[cfg(feature = "tracing")]
+    if STRUCTURED.load(Ordering::Relaxed) {
+        tracing::error!(error = %err, kind = ?err.kind(), "build failed");
+        return;
+    }
+    eprintln!("Error: {}", err);
+}
+
+#[cfg(feature = "tracing")]
+pub struct Stage {
+    started: Instant,
+    records_in: u64,
+    span: tracing::span::EnteredSpan,
+    timer: metrics::StageTimer,
+}
+
+#[cfg(feature = "tracing")]
+impl Stage {
+    pub fn enter(name: &'static str, source: &str) -> Stage {
+        Stage {
+            started: Instant::now(),
+            records_in: progress::records_read(),
+            span: tracing::info_span!(
+                "stage",
+                stage = name,
+                source,
+                records_in = tracing::field::Empty,
+                records_out = tracing::field::Empty,
+                duration_ms = tracing::field::Empty,
+            )
+            .entered(),
+            timer: metrics::StageTimer::start(name, source),
+        }
+    }
+
+    pub fn finish(self, records_out: usize) {
+        let records_in = progress::records_read() - self.records_in;
+        self.span.record("records_in", records_in);
+        self.span.record("records_out", records_out as u64);
+        self.span
+            .record("duration_ms", self.started.elapsed().as_millis() as u64);
+        self.timer.finish();
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+pub struct Stage(metrics::StageTimer);
+
+#[cfg(not(feature = "tracing"))]
+impl Stage {
+    pub fn enter(name: &'static str, source: &str) -> Stage {
+        Stage(metrics::StageTimer::start(name, source))
+    }
+
+    pub fn finish(self, _records_out: usize) {
+        self.0.finish();
+    }
+}
@@ -0,0 +1,213 @@
+use std::collections::BTreeSet;
+#[cfg(feature = "serve")]
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Result, Write};
+#[cfg(feature = "serve")]
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::storage;
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+static SOURCES: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+pub fn record_source(path: &str) {
+    SOURCES.lock().unwrap().insert(path.to_string());
+}
+
+pub fn sha256(path: impl AsRef<Path>) -> Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 1 << 16];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+pub fn utc_timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, rem) = (secs / 86400, secs % 86400);
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+#[cfg(feature = "serve")]
+fn parse_version(text: &str) -> Option<Vec<u64>> {
+    text.split('.').map(|part| part.parse().ok()).collect()
+}
+
+#[cfg(feature = "serve")]
+pub fn verify(dir: &str) -> Result<()> {
+    let invalid = |message: String| Error::new(ErrorKind::InvalidData, message);
+    let dir = Path::new(dir);
+    let manifest: Value = match fs::read(dir.join(MANIFEST_FILE)) {
+        Ok(bytes) => serde_json::from_slice(&bytes)?,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+    if manifest["tool"] != json!(env!("CARGO_PKG_NAME")) {
+        return Err(invalid(format!(
+            "{} was not written by ip2x",
+            MANIFEST_FILE
+        )));
+    }
+    let version = manifest["version"].as_str().unwrap_or("");
+    match (
+        parse_version(version),
+        parse_version(env!("CARGO_PKG_VERSION")),
+    ) {
+        (Some(built), Some(ours)) if built <= ours => {}
+        _ => {
+            return Err(invalid(format!(
+                "Bins built by ip2x {} cannot be read by ip2x {}",
+                version,
+                env!("CARGO_PKG_VERSION")
+            )))
+        }
+    }
+    for artifact in manifest["artifacts"].as_array().into_iter().flatten() {
+        let (Some(path), Some(expected)) = (artifact["path"].as_str(), artifact["sha256"].as_str())
+        else {
+            continue;
+        };
+        if sha256(dir.join(path))? != expected {
+            return Err(invalid(format!("Checksum mismatch: {}", path)));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "serve")]
+pub fn checksums(dir: &str) -> Result<HashMap<String, String>> {
+    let manifest: Value = match fs::read(Path::new(dir).join(MANIFEST_FILE)) {
+        Ok(bytes) => serde_json::from_slice(&bytes)?,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(err) => return Err(err),
+    };
+    Ok(manifest["artifacts"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|artifact| {
+            let path = artifact["path"].as_str()?;
+            Some((path.to_string(), artifact["sha256"].as_str()?.to_string()))
+        })
+        .collect())
+}
+
+#[cfg(all(feature = "metrics", feature = "serve"))]
+pub fn parse_utc_timestamp(text: &str) -> Option<u64> {
+    let field = |at: usize, len: usize| -> Option<i64> { text.get(at..at + len)?.parse().ok() };
+    let (year, month, day) = (field(0, 4)?, field(5, 2)?, field(8, 2)?);
+    let (hour, minute, second) = (field(11, 2)?, field(14, 2)?, field(17, 2)?);
+    let year = year - (month <= 2) as i64;
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+    u64::try_from(days * 86400 + hour * 3600 + minute * 60 + second).ok()
+}
+
+fn describe(path: &str) -> Result<Value> {
+    if let Some((size, sha256, modified)) = storage::describe(path) {
+        return Ok(json!({
+            "path": path,
+            "size": size,
+            "sha256": sha256,
+            "modified": utc_timestamp(modified),
+        }));
+    }
+    let metadata = fs::metadata(path)?;
+    Ok(json!({
+        "path": path,
+        "size": metadata.len(),
+        "sha256": sha256(path)?,
+        "modified": utc_timestamp(metadata.modified()?),
+    }))
+}
+
+fn previous_artifacts(path: &Path, replaced: &[(String, Option<usize>)]) -> Vec<Value> {
+    let previous: Value = fs::read(path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default();
+    let Value::Array(entries) = &previous["artifacts"] else {
+        return Vec::new();
+    };
+    entries
+        .iter()
+        .filter(|entry| {
+            !replaced
+                .iter()
+                .any(|(artifact, _)| entry["path"] == json!(artifact))
+        })
+        .cloned()
+        .collect()
+}
+
+pub fn write(dir: &Path, artifacts: &[(String, Option<usize>)], merge: bool) -> Result<()> {
+    let path = dir.join(MANIFEST_FILE);
+    let mut entries = if merge {
+        previous_artifacts(&path, artifacts)
+    } else {
+        Vec::new()
+    };
+    for (artifact, records) in artifacts {
+        let mut entry = describe(&dir.join(artifact).to_string_lossy())?;
+        entry["path"] = json!(artifact);
+        entry["records"] = json!(records);
+        entries.push(entry);
+    }
+    let sources = SOURCES
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|source| storage::is_remote(source) || Path::new(source).exists())
+        .map(|source| describe(source))
+        .collect::<Result<Vec<_>>>()?;
+    let manifest = json!({
+        "tool": env!("CARGO_PKG_NAME"),
+        "version": env!("CARGO_PKG_VERSION"),
+        "created": utc_timestamp(SystemTime::now()),
+        "artifacts": entries,
+        "sources": sources,
+    });
+
+    let mut out = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut out, &manifest)?;
+    out.write_all(b"\n")?;
+    out.flush()
+}
@@ -1,12 +1,37 @@
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::{Error, ErrorKind, Read, Result};
+use std::io::{Error, ErrorKind, Result};
 use std::net::{Ipv4Addr, Ipv6Addr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use crate::format::{locate, Alternate, AlternateCountry, CountryRange, GeoRange, Unlocated};
+use crate::geoip2::{AnonymousIp, Asn, City, Country};
+use crate::geolite_csv::GeoLiteCsv;
+use crate::ip::ipv4_to_ipv6;
+use crate::manifest;
+use crate::progress::{self, Progress};
+use crate::storage;
 
 const DATA_SEPARATOR_SIZE: usize = 16;
+const SPLIT_DEPTH: usize = 12;
 const METADATA_MARKER: &[u8] = b"\xab\xcd\xefMaxMind.com";
+const ANONYMOUS_FLAGS: [(&str, &str); 5] = [
+    ("is_anonymous_vpn", "VPN"),
+    ("is_public_proxy", "PUB"),
+    ("is_tor_exit_node", "TOR"),
+    ("is_residential_proxy", "RES"),
+    ("is_hosting_provider", "DCH"),
+];
 
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(untagged)
+)]
 #[allow(dead_code)]
 pub enum Value {
     String(String),
@@ -21,7 +46,7 @@ pub enum Value {
 }
 
 impl Value {
-    fn as_map(&self) -> Option<&HashMap<String, Value>> {
+    pub fn as_map(&self) -> Option<&HashMap<String, Value>> {
         if let Value::Map(m) = self {
             Some(m)
         } else {
@@ -29,7 +54,7 @@ impl Value {
         }
     }
 
-    fn as_u64(&self) -> Option<u64> {
+    pub fn as_u64(&self) -> Option<u64> {
         match self {
             Value::UInt(n) => Some(*n),
             Value::Int(n) => Some(*n as u64),
@@ -37,13 +62,134 @@ impl Value {
         }
     }
 
-    fn as_f64(&self) -> Option<f64> {
+    pub fn as_f64(&self) -> Option<f64> {
         match self {
             Value::Double(f) => Some(*f),
             Value::Float(f) => Some(*f as f64),
             _ => None,
         }
     }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Map(map) => map.get(key),
+            Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+            _ => None,
+        }
+    }
+
+    pub fn get_path(&self, path: &str) -> Option<&Value> {
+        path.split('.').try_fold(self, |value, key| value.get(key))
+    }
+
+    pub fn get_path_str(&self, path: &str) -> Option<&str> {
+        self.get_path(path)?.as_str()
+    }
+
+    pub fn get_path_u64(&self, path: &str) -> Option<u64> {
+        self.get_path(path)?.as_u64()
+    }
+
+    pub fn get_path_f64(&self, path: &str) -> Option<f64> {
+        self.get_path(path)?.as_f64()
+    }
+
+    pub fn get_path_bool(&self, path: &str) -> Option<bool> {
+        match self.get_path(path)? {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+}
+
+pub enum CitySource {
+    Mmdb(MaxMindReader),
+    Csv(GeoLiteCsv),
+}
+
+impl CitySource {
+    pub fn open(path: &str) -> Result<CitySource> {
+        if path.ends_with(".csv") {
+            GeoLiteCsv::open(path).map(CitySource::Csv)
+        } else {
+            MaxMindReader::open(path).map(CitySource::Mmdb)
+        }
+    }
+
+    fn records_with<T: Send>(
+        &self,
+        extract: impl Fn(u128, u128, &HashMap<String, Value>) -> Option<T> + Sync,
+    ) -> Vec<T> {
+        match self {
+            CitySource::Mmdb(reader) => reader.records_with(extract),
+            CitySource::Csv(csv) => csv.records_with(extract),
+        }
+    }
+
+    pub fn load_all_geo(&self, unlocated: Unlocated) -> Vec<GeoRange> {
+        self.records_with(|start, end, record| {
+            let country = MaxMindReader::country_codes(record)[0].unwrap_or(*b"--");
+            let (lat, lon, fallback) = match MaxMindReader::location(record) {
+                Some((lat, lon)) => locate(lat, lon, &country, unlocated)?,
+                None => locate(
+                    0.0,
+                    0.0,
+                    &country,
+                    Unlocated {
+                        keep: false,
+                        ..unlocated
+                    },
+                )?,
+            };
+            let accuracy = fallback.or_else(|| MaxMindReader::accuracy_radius(record));
+            Some((start, end, lat, lon, accuracy))
+        })
+    }
+
+    pub fn load_all_country(&self) -> Vec<CountryRange> {
+        self.records_with(|start, end, record| {
+            let (country, continent, eu, alternate) = MaxMindReader::country(record)?;
+            let subdivision = MaxMindReader::subdivision(record).map(|(code, _)| code);
+            Some((start, end, country, continent, eu, alternate, subdivision))
+        })
+    }
+
+    pub fn subdivision_codes(&self) -> HashMap<([u8; 2], String), String> {
+        self.records_with(|_, _, record| {
+            let country = get_nested(record, &["country", "iso_code"]).and_then(two_letters)?;
+            let (code, name) = MaxMindReader::subdivision(record)?;
+            Some(((country, name?), code))
+        })
+        .into_iter()
+        .collect()
+    }
+
+    pub fn load_all_names(
+        &self,
+        locales: &[String],
+    ) -> Vec<(String, String, Vec<String>, Vec<String>)> {
+        self.records_with(|_, _, record| {
+            let Value::String(country) = get_nested(record, &["country", "iso_code"])? else {
+                return None;
+            };
+            let Value::String(city) = get_nested(record, &["city", "names", "en"])? else {
+                return None;
+            };
+            Some((
+                country.clone(),
+                city.clone(),
+                MaxMindReader::names(record, "city", locales),
+                MaxMindReader::names(record, "country", locales),
+            ))
+        })
+    }
 }
 
 struct Metadata {
@@ -53,19 +199,68 @@ struct Metadata {
     search_tree_size: usize,
 }
 
+#[derive(Debug, Clone, Copy)]
+struct Limits {
+    max_depth: usize,
+    max_map_size: usize,
+    max_string_len: usize,
+    record_budget: usize,
+    total_budget: usize,
+}
+
+impl Limits {
+    const TRUSTED: Limits = Limits {
+        max_depth: usize::MAX,
+        max_map_size: usize::MAX,
+        max_string_len: usize::MAX,
+        record_budget: usize::MAX,
+        total_budget: usize::MAX,
+    };
+    const UNTRUSTED: Limits = Limits {
+        max_depth: 32,
+        max_map_size: 1 << 16,
+        max_string_len: 1 << 16,
+        record_budget: 1 << 20,
+        total_budget: 64 << 20,
+    };
+}
+
 pub struct MaxMindReader {
+    path: String,
     buffer: Vec<u8>,
     metadata: Metadata,
     ipv4_start: u32,
+    limits: Limits,
 }
 
 impl MaxMindReader {
     pub fn open(path: &str) -> Result<Self> {
-        let mut buffer = Vec::new();
-        File::open(path)?.read_to_end(&mut buffer)?;
+        let buffer = storage::read(path)?;
+        manifest::record_source(path);
+        Self::with_limits(path, buffer, Limits::TRUSTED)
+    }
+
+    pub fn open_untrusted(path: &str) -> Result<Self> {
+        Self::from_untrusted(path, storage::read(path)?)
+    }
+
+    pub fn from_untrusted_bytes(buffer: Vec<u8>) -> Result<Self> {
+        Self::from_untrusted("<memory>", buffer)
+    }
+
+    fn from_untrusted(path: &str, buffer: Vec<u8>) -> Result<Self> {
+        let limits = Limits {
+            total_budget: Limits::UNTRUSTED.total_budget.max(64 * buffer.len()),
+            ..Limits::UNTRUSTED
+        };
+        let reader = Self::with_limits(path, buffer, limits)?;
+        reader.check_tree()?;
+        Ok(reader)
+    }
 
+    fn with_limits(path: &str, buffer: Vec<u8>, limits: Limits) -> Result<Self> {
         let metadata_start = Self::find_metadata_start(&buffer)?;
-        let metadata = Self::parse_metadata(&buffer, metadata_start)?;
+        let metadata = Self::parse_metadata(&buffer, metadata_start, limits)?;
         let ipv4_start = Self::find_ipv4_start(
             &buffer,
             metadata.node_count,
@@ -74,12 +269,46 @@ impl MaxMindReader {
         )?;
 
         Ok(Self {
+            path: path.to_string(),
             buffer,
             metadata,
             ipv4_start,
+            limits,
         })
     }
 
+    fn check_tree(&self) -> Result<()> {
+        let bits = if self.metadata.ip_version == 6 {
+            128
+        } else {
+            32
+        };
+        let mut seen = vec![false; self.metadata.node_count as usize];
+        let mut stack = vec![(0u32, 0usize, true)];
+        while let Some((node, depth, zero)) = stack.pop() {
+            if node >= self.metadata.node_count || !zero && node == self.ipv4_start {
+                continue;
+            }
+            if seen[node as usize] {
+                return Err(invalid(format!(
+                    "MaxMind search tree reaches node {} twice",
+                    node
+                )));
+            }
+            if depth >= bits {
+                return Err(invalid(format!(
+                    "MaxMind search tree is deeper than {} bits",
+                    bits
+                )));
+            }
+            seen[node as usize] = true;
+            for index in 0..2 {
+                stack.push((self.read_node(node, index)?, depth + 1, zero && index == 0));
+            }
+        }
+        Ok(())
+    }
+
     fn find_metadata_start(buffer: &[u8]) -> Result<usize> {
         buffer
             .windows(METADATA_MARKER.len())
@@ -88,8 +317,8 @@ impl MaxMindReader {
             .ok_or_else(|| Error::new(ErrorKind::InvalidData, "No metadata"))
     }
 
-    fn parse_metadata(buffer: &[u8], start: usize) -> Result<Metadata> {
-        let mut decoder = Decoder::new(buffer, start);
+    fn parse_metadata(buffer: &[u8], start: usize, limits: Limits) -> Result<Metadata> {
+        let mut decoder = Decoder::new(buffer, start, limits);
         let (value, _) = decoder.decode(start)?;
         let map = value
             .as_map()
@@ -104,12 +333,21 @@ impl MaxMindReader {
         let record_size = map
             .get("record_size")
             .and_then(|v| v.as_u64())
-            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "No record_size"))?
-            as u16;
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "No record_size"))?;
+        let record_size = match record_size {
+            24 | 28 | 32 => record_size as u16,
+            _ => return Err(Error::new(ErrorKind::InvalidData, "Bad record size")),
+        };
 
         let ip_version = map.get("ip_version").and_then(|v| v.as_u64()).unwrap_or(6) as u16;
 
         let search_tree_size = node_count as usize * (record_size / 4) as usize;
+        if search_tree_size + DATA_SEPARATOR_SIZE > buffer.len() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "MaxMind search tree is larger than the file",
+            ));
+        }
 
         Ok(Metadata {
             node_count,
@@ -142,32 +380,110 @@ impl MaxMindReader {
         Ok(node)
     }
 
-    #[allow(dead_code)]
     pub fn load_all(&self) -> Vec<(u128, u128, HashMap<String, Value>)> {
-        let pointers = self.collect_pointers();
-        self.decode_all(pointers)
+        self.records_with(|start, end, record| Some((start, end, record.clone())))
+    }
+
+    pub fn load_paths(&self, paths: &[&str]) -> Vec<(u128, u128, Vec<Option<Value>>)> {
+        self.records_with(|start, end, record| {
+            let values: Vec<Option<Value>> = paths
+                .iter()
+                .map(|path| get_path(record, path).cloned())
+                .collect();
+            values
+                .iter()
+                .any(Option::is_some)
+                .then_some((start, end, values))
+        })
+    }
+
+    fn records_with<T: Send>(
+        &self,
+        extract: impl Fn(u128, u128, &HashMap<String, Value>) -> Option<T> + Sync,
+    ) -> Vec<T> {
+        let (roots, expanded) = self.subtrees();
+        let progress = Mutex::new(Progress::new(
+            &self.path,
+            "nodes",
+            self.metadata.node_count as u64,
+        ));
+        progress.lock().unwrap().tick(expanded, expanded);
+        let total = AtomicUsize::new(self.limits.total_budget);
+        let walk = |root| {
+            let (items, nodes, pointers) = self.walk(root, &total, &extract);
+            progress.lock().unwrap().tick(nodes, nodes);
+            progress::count_read(pointers);
+            items
+        };
+
+        #[cfg(feature = "rayon")]
+        let chunks: Vec<Vec<T>> = roots.into_par_iter().map(walk).collect();
+        #[cfg(not(feature = "rayon"))]
+        let chunks: Vec<Vec<T>> = roots.into_iter().map(walk).collect();
+        chunks.into_iter().flatten().collect()
     }
 
-    pub fn load_all_geo(&self) -> Vec<(u128, u128, f32, f32)> {
-        let pointers = self.collect_pointers();
-        self.decode_geo(pointers)
+    fn subtrees(&self) -> (Vec<(u32, usize, u128)>, u64) {
+        let split_depth = |ip_acc: u128| {
+            if self.metadata.ip_version == 6 && ip_acc == 0 {
+                96 + SPLIT_DEPTH
+            } else {
+                SPLIT_DEPTH
+            }
+        };
+        let mut roots = Vec::new();
+        let mut expanded = 0;
+        let mut stack = vec![(0u32, 0usize, 0u128)];
+
+        while let Some((node, depth, ip_acc)) = stack.pop() {
+            let aliased = ip_acc != 0 && node == self.ipv4_start;
+            if node < self.metadata.node_count && depth < split_depth(ip_acc) && !aliased {
+                expanded += 1;
+                self.push_children(&mut stack, node, depth, ip_acc);
+            } else {
+                roots.push((node, depth, ip_acc));
+            }
+        }
+
+        (roots, expanded)
     }
 
-    fn collect_pointers(&self) -> Vec<(usize, u128, u128)> {
-        let capacity = (self.metadata.node_count / 2) as usize;
-        let mut pointers = Vec::with_capacity(capacity);
-        let mut stack = Vec::with_capacity(1024);
-        stack.push((0u32, 0usize, 0u128));
+    fn walk<T>(
+        &self,
+        root: (u32, usize, u128),
+        total: &AtomicUsize,
+        extract: &impl Fn(u128, u128, &HashMap<String, Value>) -> Option<T>,
+    ) -> (Vec<T>, u64, usize) {
+        let data_base = self.metadata.search_tree_size + DATA_SEPARATOR_SIZE;
+        let mut decoder = Decoder {
+            total: Some(total),
+            ..Decoder::new(&self.buffer, data_base, self.limits)
+        };
+        let mut records: HashMap<usize, Option<Value>> = HashMap::new();
+        let mut results = Vec::new();
+        let (mut nodes, mut pointers) = (0, 0);
+        let mut stack = vec![root];
 
         while let Some((node, depth, ip_acc)) = stack.pop() {
+            nodes += 1;
             if ip_acc != 0 && node == self.ipv4_start {
                 continue;
             }
 
             if node > self.metadata.node_count {
+                pointers += 1;
                 let (start, end) = self.calculate_range(depth, ip_acc);
                 let offset = self.node_to_offset(node);
-                pointers.push((offset, start, end));
+                let record = records
+                    .entry(offset)
+                    .or_insert_with(|| decoder.decode(offset).ok().map(|(value, _)| value));
+                if let Some(item) = record
+                    .as_ref()
+                    .and_then(Value::as_map)
+                    .and_then(|map| extract(start, end, map))
+                {
+                    results.push(item);
+                }
                 continue;
             }
 
@@ -178,7 +494,7 @@ impl MaxMindReader {
             self.push_children(&mut stack, node, depth, ip_acc);
         }
 
-        pointers
+        (results, nodes, pointers)
     }
 
     fn calculate_range(&self, depth: usize, ip_acc: u128) -> (u128, u128) {
@@ -188,14 +504,11 @@ impl MaxMindReader {
             32
         };
         let start = ip_acc << (bits - depth);
+        let end = start | host_mask(bits - depth);
 
-        let (prefix_len, effective_bits) = if bits == 128 && start <= (1u128 << 32) {
-            (depth.saturating_sub(96), 32)
-        } else {
-            (depth, bits)
-        };
-
-        let end = start | ((1u128 << (effective_bits - prefix_len)) - 1);
+        if bits == 32 || (depth >= 96 && end <= u32::MAX as u128) {
+            return (ipv4_to_ipv6(start as u32), ipv4_to_ipv6(end as u32));
+        }
         (start, end)
     }
 
@@ -225,53 +538,30 @@ impl MaxMindReader {
         }
     }
 
-    fn decode_all(
-        &self,
-        pointers: Vec<(usize, u128, u128)>,
-    ) -> Vec<(u128, u128, HashMap<String, Value>)> {
-        let mut results = Vec::with_capacity(pointers.len());
-        let data_base = self.metadata.search_tree_size + DATA_SEPARATOR_SIZE;
-        let mut decoder = Decoder::new(&self.buffer, data_base);
-
-        for (offset, start, end) in pointers {
-            if let Ok((value, _)) = decoder.decode(offset) {
-                if let Some(map) = value.as_map().cloned() {
-                    results.push((start, end, map));
-                }
-            }
+    pub fn lookup(&self, ip: &str) -> Option<HashMap<String, Value>> {
+        match self.lookup_value(ip)? {
+            Value::Map(map) => Some(map),
+            _ => None,
         }
+    }
 
-        results
+    pub fn lookup_city(&self, ip: &str) -> Option<City> {
+        self.lookup_value(ip).as_ref().map(City::from)
     }
 
-    fn decode_geo(&self, pointers: Vec<(usize, u128, u128)>) -> Vec<(u128, u128, f32, f32)> {
-        let mut results = Vec::with_capacity(pointers.len());
-        let data_base = self.metadata.search_tree_size + DATA_SEPARATOR_SIZE;
-        let mut decoder = Decoder::new(&self.buffer, data_base);
-
-        for (offset, start, end) in pointers {
-            if let Ok((value, _)) = decoder.decode(offset) {
-                if let Some((lat, lon)) = Self::extract_location(&value) {
-                    if lat != 0.0 || lon != 0.0 {
-                        results.push((start, end, lat, lon));
-                    }
-                }
-            }
-        }
+    pub fn lookup_country(&self, ip: &str) -> Option<Country> {
+        self.lookup_value(ip).as_ref().map(Country::from)
+    }
 
-        results
+    pub fn lookup_asn(&self, ip: &str) -> Option<Asn> {
+        self.lookup_value(ip).as_ref().map(Asn::from)
     }
 
-    fn extract_location(value: &Value) -> Option<(f32, f32)> {
-        let map = value.as_map()?;
-        let location = map.get("location")?.as_map()?;
-        let lat = location.get("latitude")?.as_f64()? as f32;
-        let lon = location.get("longitude")?.as_f64()? as f32;
-        Some((lat, lon))
+    pub fn lookup_anonymous_ip(&self, ip: &str) -> Option<AnonymousIp> {
+        self.lookup_value(ip).as_ref().map(AnonymousIp::from)
     }
 
-    #[allow(dead_code)]
-    pub fn lookup(&self, ip: &str) -> Option<HashMap<String, Value>> {
+    fn lookup_value(&self, ip: &str) -> Option<Value> {
         let (packed, bit_count) = self.parse_ip(ip)?;
         let (pointer, _) = self.find_in_tree(&packed, bit_count)?;
 
@@ -281,10 +571,102 @@ impl MaxMindReader {
 
         let offset = self.node_to_offset(pointer);
         let data_base = self.metadata.search_tree_size + DATA_SEPARATOR_SIZE;
-        let mut decoder = Decoder::new(&self.buffer, data_base);
+        let mut decoder = Decoder::new(&self.buffer, data_base, self.limits);
+        let (value, _) = decoder.decode(offset).ok()?;
+
+        value.as_map().is_some().then_some(value)
+    }
+
+    pub fn lookup_network(&self, ip: &str) -> Option<(u128, u128, HashMap<String, Value>)> {
+        let (packed, bit_count) = self.parse_ip(ip)?;
+        let (pointer, prefix_len) = self.find_in_tree(&packed, bit_count)?;
+
+        if pointer == 0 {
+            return None;
+        }
+
+        let mut address = 0u128;
+        for byte in &packed {
+            address = (address << 8) | *byte as u128;
+        }
+        let mask = host_mask(bit_count - prefix_len);
+        let (start, end) = (address & !mask, address | mask);
+        let (start, end) = if bit_count == 32 {
+            (ipv4_to_ipv6(start as u32), ipv4_to_ipv6(end as u32))
+        } else {
+            (start, end)
+        };
+
+        let offset = self.node_to_offset(pointer);
+        let data_base = self.metadata.search_tree_size + DATA_SEPARATOR_SIZE;
+        let mut decoder = Decoder::new(&self.buffer, data_base, self.limits);
         let (value, _) = decoder.decode(offset).ok()?;
 
-        value.as_map().cloned()
+        Some((start, end, value.as_map().cloned()?))
+    }
+
+    pub fn location(record: &HashMap<String, Value>) -> Option<(f32, f32)> {
+        let lat = get_path(record, "location.latitude")?.as_f64()? as f32;
+        let lon = get_path(record, "location.longitude")?.as_f64()? as f32;
+        Some((lat, lon))
+    }
+
+    pub fn country_codes(record: &HashMap<String, Value>) -> [Option<[u8; 2]>; 3] {
+        ["country", "registered_country", "represented_country"]
+            .map(|key| get_nested(record, &[key, "iso_code"]).and_then(two_letters))
+    }
+
+    pub fn country(record: &HashMap<String, Value>) -> Option<([u8; 2], [u8; 2], bool, Alternate)> {
+        let country = record.get("country")?.as_map()?;
+        let code = two_letters(country.get("iso_code")?)?;
+        let continent = get_path(record, "continent.code")
+            .and_then(two_letters)
+            .unwrap_or(*b"--");
+        let eu = matches!(country.get("is_in_european_union"), Some(Value::Bool(true)));
+        let alternate = match Self::country_codes(record) {
+            [_, _, Some(represented)] if represented != code => {
+                Some((AlternateCountry::Represented, represented))
+            }
+            [_, Some(registered), _] if registered != code => {
+                Some((AlternateCountry::Registered, registered))
+            }
+            _ => None,
+        };
+        Some((code, continent, eu, alternate))
+    }
+
+    pub fn names(record: &HashMap<String, Value>, key: &str, locales: &[String]) -> Vec<String> {
+        locales
+            .iter()
+            .map(|locale| match get_nested(record, &[key, "names", locale]) {
+                Some(Value::String(name)) => name.clone(),
+                _ => "-".to_string(),
+            })
+            .collect()
+    }
+
+    pub fn subdivision(record: &HashMap<String, Value>) -> Option<(String, Option<String>)> {
+        let code = get_path(record, "subdivisions.0.iso_code")?.as_str()?;
+        let name = get_path(record, "subdivisions.0.names.en").and_then(Value::as_str);
+        Some((code.to_string(), name.map(str::to_string)))
+    }
+
+    pub fn anonymous_types(record: &HashMap<String, Value>) -> Vec<&'static str> {
+        ANONYMOUS_FLAGS
+            .iter()
+            .filter(|(flag, _)| matches!(record.get(*flag), Some(Value::Bool(true))))
+            .map(|(_, proxy_type)| *proxy_type)
+            .collect()
+    }
+
+    pub fn is_anonymous(record: &HashMap<String, Value>) -> bool {
+        matches!(record.get("is_anonymous"), Some(Value::Bool(true)))
+            || !Self::anonymous_types(record).is_empty()
+    }
+
+    pub fn accuracy_radius(record: &HashMap<String, Value>) -> Option<u16> {
+        let radius = get_path(record, "location.accuracy_radius")?.as_u64()?;
+        Some(radius.min(u16::MAX as u64) as u16)
     }
 
     fn parse_ip(&self, ip: &str) -> Option<(Vec<u8>, usize)> {
@@ -347,20 +729,24 @@ impl MaxMindReader {
             _ => return Err(Error::new(ErrorKind::InvalidData, "Bad record size")),
         };
 
-        Ok(u32::from_be_bytes(bytes))
+        bytes.map(u32::from_be_bytes).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                "MaxMind search tree runs past the end of the file",
+            )
+        })
     }
 
-    fn read_24bit(buffer: &[u8], base: usize, index: usize) -> [u8; 4] {
+    fn read_24bit(buffer: &[u8], base: usize, index: usize) -> Option<[u8; 4]> {
         let offset = base + index * 3;
         let mut bytes = [0u8; 4];
-        bytes[1..4].copy_from_slice(&buffer[offset..offset + 3]);
-        bytes
+        bytes[1..4].copy_from_slice(buffer.get(offset..offset + 3)?);
+        Some(bytes)
     }
 
-    fn read_28bit(buffer: &[u8], base: usize, index: usize) -> [u8; 4] {
+    fn read_28bit(buffer: &[u8], base: usize, index: usize) -> Option<[u8; 4]> {
         let offset = base + 3 * index;
-        let mut bytes = [0u8; 4];
-        bytes.copy_from_slice(&buffer[offset..offset + 4]);
+        let mut bytes: [u8; 4] = buffer.get(offset..offset + 4)?.try_into().ok()?;
 
         if index == 1 {
             bytes[0] &= 0x0F;
@@ -372,43 +758,96 @@ impl MaxMindReader {
             bytes[0] = middle;
         }
 
-        bytes
+        Some(bytes)
     }
 
-    fn read_32bit(buffer: &[u8], base: usize, index: usize) -> [u8; 4] {
+    fn read_32bit(buffer: &[u8], base: usize, index: usize) -> Option<[u8; 4]> {
         let offset = base + index * 4;
-        let mut bytes = [0u8; 4];
-        bytes.copy_from_slice(&buffer[offset..offset + 4]);
-        bytes
+        buffer.get(offset..offset + 4)?.try_into().ok()
     }
 }
 
 struct Decoder<'a> {
     buffer: &'a [u8],
     pointer_base: usize,
+    limits: Limits,
+    total: Option<&'a AtomicUsize>,
+    depth: usize,
+    budget: usize,
 }
 
 impl<'a> Decoder<'a> {
-    fn new(buffer: &'a [u8], pointer_base: usize) -> Self {
+    fn new(buffer: &'a [u8], pointer_base: usize, limits: Limits) -> Self {
         Self {
             buffer,
             pointer_base,
+            limits,
+            total: None,
+            depth: 0,
+            budget: limits.record_budget,
         }
     }
 
-    fn decode(&mut self, offset: usize) -> std::io::Result<(Value, usize)> {
-        let ctrl_byte = self.buffer[offset];
+    fn decode(&mut self, offset: usize) -> Result<(Value, usize)> {
+        self.depth = 0;
+        self.budget = self.limits.record_budget;
+        let decoded = self.decode_value(offset)?;
+        let spent = self.limits.record_budget - self.budget;
+        if let Some(total) = self.total {
+            total
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |left| {
+                    left.checked_sub(spent)
+                })
+                .map_err(|_| {
+                    invalid(format!(
+                        "MaxMind file exceeds the total decode budget of {} bytes",
+                        self.limits.total_budget
+                    ))
+                })?;
+        }
+        Ok(decoded)
+    }
+
+    fn spend(&mut self, cost: usize) -> Result<()> {
+        self.budget = self.budget.checked_sub(cost).ok_or_else(|| {
+            invalid(format!(
+                "MaxMind record exceeds the decode budget of {} bytes",
+                self.limits.record_budget
+            ))
+        })?;
+        Ok(())
+    }
+
+    fn bytes(&mut self, offset: usize, len: usize) -> Result<&'a [u8]> {
+        self.spend(len)?;
+        let buffer = self.buffer;
+        offset
+            .checked_add(len)
+            .and_then(|end| buffer.get(offset..end))
+            .ok_or_else(|| invalid("MaxMind data runs past the end of the file".to_string()))
+    }
+
+    fn decode_value(&mut self, offset: usize) -> Result<(Value, usize)> {
+        if self.depth >= self.limits.max_depth {
+            return Err(invalid(format!(
+                "MaxMind data nests deeper than {} levels",
+                self.limits.max_depth
+            )));
+        }
+        self.spend(std::mem::size_of::<Value>())?;
+        let ctrl_byte = self.bytes(offset, 1)?[0];
         let mut type_num = (ctrl_byte >> 5) as usize;
         let mut new_offset = offset + 1;
 
         if type_num == 0 {
-            type_num = self.buffer[new_offset] as usize + 7;
+            type_num = self.bytes(new_offset, 1)?[0] as usize + 7;
             new_offset += 1;
         }
 
         let (size, new_offset) = self.size_from_ctrl_byte(ctrl_byte, new_offset, type_num)?;
 
-        match type_num {
+        self.depth += 1;
+        let value = match type_num {
             1 => self.decode_pointer(size, new_offset),
             2 => self.decode_string(size, new_offset),
             3 => self.decode_double(size, new_offset),
@@ -419,16 +858,15 @@ impl<'a> Decoder<'a> {
             11 => self.decode_array(size, new_offset),
             14 => Ok((Value::Bool(size != 0), new_offset)),
             15 => self.decode_float(size, new_offset),
-            _ => Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Unknown type",
-            )),
-        }
+            _ => Err(invalid("Unknown type".to_string())),
+        };
+        self.depth -= 1;
+        value
     }
 
-    fn decode_pointer(&mut self, size: usize, offset: usize) -> std::io::Result<(Value, usize)> {
+    fn decode_pointer(&mut self, size: usize, offset: usize) -> Result<(Value, usize)> {
         let pointer_size = (size >> 3) + 1;
-        let buf = &self.buffer[offset..offset + pointer_size];
+        let buf = self.bytes(offset, pointer_size)?;
         let new_offset = offset + pointer_size;
 
         let pointer = match pointer_size {
@@ -447,77 +885,87 @@ impl<'a> Decoder<'a> {
             _ => u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize + self.pointer_base,
         };
 
-        let (value, _) = self.decode(pointer)?;
+        let (value, _) = self.decode_value(pointer)?;
         Ok((value, new_offset))
     }
 
-    fn decode_string(&self, size: usize, offset: usize) -> std::io::Result<(Value, usize)> {
-        let new_offset = offset + size;
-        let s = String::from_utf8_lossy(&self.buffer[offset..new_offset]).into_owned();
-        Ok((Value::String(s), new_offset))
+    fn check_len(&self, size: usize) -> Result<()> {
+        if size > self.limits.max_string_len {
+            return Err(invalid(format!(
+                "MaxMind string of {} bytes exceeds the limit of {}",
+                size, self.limits.max_string_len
+            )));
+        }
+        Ok(())
     }
 
-    fn decode_double(&self, size: usize, offset: usize) -> std::io::Result<(Value, usize)> {
-        if size != 8 {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Invalid double size",
-            ));
-        }
-        let new_offset = offset + size;
-        let mut bytes = [0u8; 8];
-        bytes.copy_from_slice(&self.buffer[offset..new_offset]);
-        Ok((Value::Double(f64::from_be_bytes(bytes)), new_offset))
+    fn decode_string(&mut self, size: usize, offset: usize) -> Result<(Value, usize)> {
+        self.check_len(size)?;
+        let s = String::from_utf8_lossy(self.bytes(offset, size)?).into_owned();
+        Ok((Value::String(s), offset + size))
     }
 
-    fn decode_float(&self, size: usize, offset: usize) -> std::io::Result<(Value, usize)> {
-        if size != 4 {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Invalid float size",
-            ));
-        }
-        let new_offset = offset + size;
-        let mut bytes = [0u8; 4];
-        bytes.copy_from_slice(&self.buffer[offset..new_offset]);
-        Ok((Value::Float(f32::from_be_bytes(bytes)), new_offset))
+    fn decode_double(&mut self, size: usize, offset: usize) -> Result<(Value, usize)> {
+        let bytes = self
+            .bytes(offset, size)?
+            .try_into()
+            .map_err(|_| invalid("Invalid double size".to_string()))?;
+        Ok((Value::Double(f64::from_be_bytes(bytes)), offset + size))
+    }
+
+    fn decode_float(&mut self, size: usize, offset: usize) -> Result<(Value, usize)> {
+        let bytes = self
+            .bytes(offset, size)?
+            .try_into()
+            .map_err(|_| invalid("Invalid float size".to_string()))?;
+        Ok((Value::Float(f32::from_be_bytes(bytes)), offset + size))
     }
 
-    fn decode_bytes(&self, size: usize, offset: usize) -> std::io::Result<(Value, usize)> {
-        let new_offset = offset + size;
+    fn decode_bytes(&mut self, size: usize, offset: usize) -> Result<(Value, usize)> {
+        self.check_len(size)?;
         Ok((
-            Value::Bytes(self.buffer[offset..new_offset].to_vec()),
-            new_offset,
+            Value::Bytes(self.bytes(offset, size)?.to_vec()),
+            offset + size,
         ))
     }
 
-    fn decode_uint(&self, size: usize, offset: usize) -> std::io::Result<(Value, usize)> {
-        let new_offset = offset + size;
+    fn decode_uint(&mut self, size: usize, offset: usize) -> Result<(Value, usize)> {
+        if size > 16 {
+            return Err(invalid("Invalid unsigned integer size".to_string()));
+        }
         let mut value = 0u64;
-        for &byte in &self.buffer[offset..new_offset] {
+        for &byte in self.bytes(offset, size)? {
             value = (value << 8) | byte as u64;
         }
-        Ok((Value::UInt(value), new_offset))
+        Ok((Value::UInt(value), offset + size))
     }
 
-    fn decode_int32(&self, size: usize, offset: usize) -> std::io::Result<(Value, usize)> {
-        if size == 0 {
-            return Ok((Value::Int(0), offset));
+    fn decode_int32(&mut self, size: usize, offset: usize) -> Result<(Value, usize)> {
+        if size > 4 {
+            return Err(invalid("Invalid int32 size".to_string()));
         }
-        let new_offset = offset + size;
-        let bytes = &self.buffer[offset..new_offset];
-
         let mut padded = [0u8; 4];
-        padded[4 - size..].copy_from_slice(bytes);
-        Ok((Value::Int(i32::from_be_bytes(padded)), new_offset))
+        padded[4 - size..].copy_from_slice(self.bytes(offset, size)?);
+        Ok((Value::Int(i32::from_be_bytes(padded)), offset + size))
+    }
+
+    fn check_entries(&self, size: usize) -> Result<()> {
+        if size > self.limits.max_map_size {
+            return Err(invalid(format!(
+                "MaxMind map or array of {} entries exceeds the limit of {}",
+                size, self.limits.max_map_size
+            )));
+        }
+        Ok(())
     }
 
-    fn decode_map(&mut self, size: usize, mut offset: usize) -> std::io::Result<(Value, usize)> {
+    fn decode_map(&mut self, size: usize, mut offset: usize) -> Result<(Value, usize)> {
+        self.check_entries(size)?;
         let mut map = HashMap::new();
         for _ in 0..size {
-            let (key, new_offset) = self.decode(offset)?;
+            let (key, new_offset) = self.decode_value(offset)?;
             offset = new_offset;
-            let (value, new_offset) = self.decode(offset)?;
+            let (value, new_offset) = self.decode_value(offset)?;
             offset = new_offset;
             if let Value::String(k) = key {
                 map.insert(k, value);
@@ -526,10 +974,11 @@ impl<'a> Decoder<'a> {
         Ok((Value::Map(map), offset))
     }
 
-    fn decode_array(&mut self, size: usize, mut offset: usize) -> std::io::Result<(Value, usize)> {
+    fn decode_array(&mut self, size: usize, mut offset: usize) -> Result<(Value, usize)> {
+        self.check_entries(size)?;
         let mut array = Vec::new();
         for _ in 0..size {
-            let (value, new_offset) = self.decode(offset)?;
+            let (value, new_offset) = self.decode_value(offset)?;
             offset = new_offset;
             array.push(value);
         }
@@ -537,43 +986,57 @@ impl<'a> Decoder<'a> {
     }
 
     fn size_from_ctrl_byte(
-        &self,
+        &mut self,
         ctrl_byte: u8,
         offset: usize,
         type_num: usize,
-    ) -> std::io::Result<(usize, usize)> {
-        let mut size = (ctrl_byte & 0x1F) as usize;
+    ) -> Result<(usize, usize)> {
+        let size = (ctrl_byte & 0x1F) as usize;
         if type_num == 1 || size < 29 {
             return Ok((size, offset));
         }
 
-        if size == 29 {
-            size = 29 + self.buffer[offset] as usize;
-            return Ok((size, offset + 1));
-        }
+        let (len, base) = match size {
+            29 => (1, 29),
+            30 => (2, 285),
+            _ => (3, 65821),
+        };
+        let extra = self
+            .bytes(offset, len)?
+            .iter()
+            .fold(0usize, |n, &byte| n << 8 | byte as usize);
+        Ok((base + extra, offset + len))
+    }
+}
 
-        if size == 30 {
-            let bytes = [self.buffer[offset], self.buffer[offset + 1]];
-            size = 285 + u16::from_be_bytes(bytes) as usize;
-            return Ok((size, offset + 2));
-        }
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
 
-        let bytes = [
-            0,
-            self.buffer[offset],
-            self.buffer[offset + 1],
-            self.buffer[offset + 2],
-        ];
-        size = u32::from_be_bytes(bytes) as usize + 65821;
-        Ok((size, offset + 3))
+fn two_letters(value: &Value) -> Option<[u8; 2]> {
+    match value {
+        Value::String(s) => s.as_bytes().try_into().ok(),
+        _ => None,
+    }
+}
+
+fn host_mask(host_bits: usize) -> u128 {
+    if host_bits >= 128 {
+        u128::MAX
+    } else {
+        (1u128 << host_bits) - 1
     }
 }
 
-#[allow(dead_code)]
 pub fn get_nested<'a>(map: &'a HashMap<String, Value>, keys: &[&str]) -> Option<&'a Value> {
-    let mut current = map.get(keys[0])?;
-    for &key in &keys[1..] {
-        current = current.as_map()?.get(key)?;
+    let (first, rest) = keys.split_first()?;
+    rest.iter()
+        .try_fold(map.get(*first)?, |value, key| value.get(key))
+}
+
+pub fn get_path<'a>(map: &'a HashMap<String, Value>, path: &str) -> Option<&'a Value> {
+    match path.split_once('.') {
+        Some((first, rest)) => map.get(first)?.get_path(rest),
+        None => map.get(path),
     }
-    Some(current)
 }
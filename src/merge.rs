@@ -0,0 +1,446 @@
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Result};
+
+use ip2x_core::bin::IPV4_BASE;
+
+use crate::cli::Args;
+use crate::format::{
+    intern, intern_with_offset, sort_ranges, AsnRange, Dataset, IspRange, Kind, ProxyRange,
+};
+use crate::interval::{coalesce, subtract, IntervalMap};
+use crate::ip::range_to_cidrs;
+
+pub fn run(args: &Args) -> Result<()> {
+    let [base_path, override_path] = args.positional() else {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Usage: ip2x merge <base.bin> <override.bin> -o <out.bin> [--kind geo|proxy|asn|isp|mobile|usage_type|elevation|country]",
+        ));
+    };
+    let output = args.require(&["-o", "--output"])?;
+    let kind = Kind::detect(args.value(&["--kind"]), base_path)?;
+
+    let base = Dataset::read(base_path, kind)?;
+    let overrides = Dataset::read(override_path, kind)?;
+    let merged = merge(base, overrides)?;
+    merged.write(output)?;
+
+    println!("Merged {} and {} into {}", base_path, override_path, output);
+    Ok(())
+}
+
+pub fn merge(base: Dataset, overrides: Dataset) -> Result<Dataset> {
+    match (base, overrides) {
+        (Dataset::Geo(base), Dataset::Geo(overrides)) => Ok(Dataset::Geo(overlay(
+            base,
+            overrides,
+            |r| (r.0, r.1),
+            |r, from, to| (from, to, r.2, r.3, r.4),
+        ))),
+        (Dataset::Proxy(base), Dataset::Proxy(overrides)) => {
+            let coverage = coalesce(
+                overrides
+                    .iter()
+                    .flat_map(|(_, ranges)| ranges.iter().map(|r| (r.0, r.1)))
+                    .collect(),
+            );
+
+            let mut types: Vec<(String, Vec<ProxyRange>)> = base
+                .into_iter()
+                .map(|(name, ranges)| {
+                    let kept = ranges
+                        .into_iter()
+                        .flat_map(|(from, to, confidence, last_seen)| {
+                            subtract(from, to, &coverage)
+                                .into_iter()
+                                .map(move |(start, end)| (start, end, confidence, last_seen))
+                        })
+                        .collect();
+                    (name, kept)
+                })
+                .collect();
+
+            for (name, ranges) in overrides {
+                match types.iter_mut().find(|(existing, _)| *existing == name) {
+                    Some((_, existing)) => existing.extend(ranges),
+                    None => types.push((name, ranges)),
+                }
+            }
+
+            types.retain(|(_, ranges)| !ranges.is_empty());
+            for (_, ranges) in &mut types {
+                ranges.sort_by_key(|r| r.0);
+            }
+            Ok(Dataset::Proxy(types))
+        }
+        (Dataset::Asn(mut strings, base), Dataset::Asn(override_strings, overrides)) => {
+            let mut string_map = index_strings(&strings, 0);
+            let remap: Vec<usize> = override_strings
+                .iter()
+                .map(|s| intern(s, &mut strings, &mut string_map))
+                .collect();
+            let lookup = |idx: usize| remap_index(&remap, idx);
+
+            let overrides = overrides
+                .into_iter()
+                .map(|(from, to, cidr, asn, name, org)| {
+                    Ok((
+                        from,
+                        to,
+                        lookup(cidr)?,
+                        lookup(asn)?,
+                        lookup(name)?,
+                        org.map(lookup).transpose()?,
+                    ))
+                })
+                .collect::<Result<_>>()?;
+            Ok(Dataset::Asn(
+                strings,
+                overlay(base, overrides, |r| (r.0, r.1), slice_asn),
+            ))
+        }
+        (Dataset::Isp(strings, base), Dataset::Isp(override_strings, overrides)) => {
+            let (strings, data) = merge_offset(strings, base, override_strings, overrides)?;
+            Ok(Dataset::Isp(strings, data))
+        }
+        (Dataset::Mobile(strings, base), Dataset::Mobile(override_strings, overrides)) => {
+            let (strings, data) = merge_offset(strings, base, override_strings, overrides)?;
+            Ok(Dataset::Mobile(strings, data))
+        }
+        (Dataset::Usage(base), Dataset::Usage(overrides)) => Ok(Dataset::Usage(overlay(
+            base,
+            overrides,
+            |r| (r.0, r.1),
+            |r, from, to| (from, to, r.2),
+        ))),
+        (Dataset::Elevation(base), Dataset::Elevation(overrides)) => {
+            Ok(Dataset::Elevation(overlay(
+                base,
+                overrides,
+                |r| (r.0, r.1),
+                |r, from, to| (from, to, r.2),
+            )))
+        }
+        (Dataset::Country(base), Dataset::Country(overrides)) => Ok(Dataset::Country(overlay(
+            base,
+            overrides,
+            |r| (r.0, r.1),
+            |r, from, to| (from, to, r.2, r.3, r.4, r.5, r.6.clone()),
+        ))),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Cannot merge bins of different types",
+        )),
+    }
+}
+
+fn merge_offset(
+    mut strings: Vec<String>,
+    base: Vec<IspRange>,
+    override_strings: Vec<String>,
+    overrides: Vec<IspRange>,
+) -> Result<(Vec<String>, Vec<IspRange>)> {
+    let mut string_map = index_strings(&strings, 1);
+    let mut remap = vec![0];
+    for s in &override_strings {
+        remap.push(intern_with_offset(s, &mut strings, &mut string_map));
+    }
+    let lookup = |idx: usize| remap_index(&remap, idx);
+
+    let overrides = overrides
+        .into_iter()
+        .map(|(from, to, a, b, c)| Ok((from, to, lookup(a)?, lookup(b)?, lookup(c)?)))
+        .collect::<Result<_>>()?;
+    Ok((strings, overlay(base, overrides, bounds, slice)))
+}
+
+fn overlay<T>(
+    base: Vec<T>,
+    overrides: Vec<T>,
+    range: impl Fn(&T) -> (u128, u128),
+    slice: impl Fn(&T, u128, u128) -> T,
+) -> Vec<T> {
+    let mut map = IntervalMap::new(range, slice);
+    map.extend(0, overrides);
+    map.extend(1, base);
+    map.layered()
+}
+
+fn bounds(r: &IspRange) -> (u128, u128) {
+    (r.0, r.1)
+}
+
+fn slice(r: &IspRange, from: u128, to: u128) -> IspRange {
+    (from, to, r.2, r.3, r.4)
+}
+
+fn slice_asn(r: &AsnRange, from: u128, to: u128) -> AsnRange {
+    (from, to, r.2, r.3, r.4, r.5)
+}
+
+fn index_strings(strings: &[String], offset: usize) -> HashMap<String, usize> {
+    strings
+        .iter()
+        .enumerate()
+        .map(|(i, s)| (s.clone(), i + offset))
+        .collect()
+}
+
+fn remap_index(remap: &[usize], idx: usize) -> Result<usize> {
+    remap
+        .get(idx)
+        .copied()
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "String index out of bounds"))
+}
+
+pub fn agreement(sources: &[Vec<(u128, u128)>]) -> Vec<(u128, u128, u8)> {
+    let mut events = Vec::new();
+    for (source, ranges) in sources.iter().enumerate() {
+        for &(from, to) in ranges {
+            events.push((from, source, true));
+            if let Some(end) = to.checked_add(1) {
+                events.push((end, source, false));
+            }
+        }
+    }
+    events.sort_unstable_by_key(|e| e.0);
+
+    let mut depth = vec![0usize; sources.len()];
+    let mut active = 0u8;
+    let mut start = 0u128;
+    let mut ranges = Vec::new();
+    let mut i = 0;
+    while i < events.len() {
+        let pos = events[i].0;
+        if active > 0 {
+            ranges.push((start, pos - 1, active));
+        }
+        while let Some(&(_, source, opening)) = events.get(i).filter(|e| e.0 == pos) {
+            if opening {
+                depth[source] += 1;
+                active += (depth[source] == 1) as u8;
+            } else {
+                depth[source] -= 1;
+                active -= (depth[source] == 0) as u8;
+            }
+            i += 1;
+        }
+        start = pos;
+    }
+    if active > 0 {
+        ranges.push((start, u128::MAX, active));
+    }
+    ranges
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CsvPriority {
+    #[default]
+    Ipv4First,
+    Ipv6First,
+    Ipv4Only,
+}
+
+impl CsvPriority {
+    pub fn parse(name: &str) -> Option<CsvPriority> {
+        match name {
+            "ipv4-first" => Some(CsvPriority::Ipv4First),
+            "ipv6-first" => Some(CsvPriority::Ipv6First),
+            _ => None,
+        }
+    }
+
+    pub fn order<'a>(self, v4: &'a str, v6: &'a str) -> Vec<(&'a str, bool)> {
+        match self {
+            CsvPriority::Ipv4First => vec![(v4, true), (v6, false)],
+            CsvPriority::Ipv6First => vec![(v6, false), (v4, true)],
+            CsvPriority::Ipv4Only => vec![(v4, true)],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeoPriority {
+    Ip2LocationFirst,
+    MaxMindFirst,
+    MostSpecific,
+}
+
+impl GeoPriority {
+    pub fn parse(name: &str) -> Option<GeoPriority> {
+        match name {
+            "ip2location-first" => Some(GeoPriority::Ip2LocationFirst),
+            "maxmind-first" => Some(GeoPriority::MaxMindFirst),
+            "most-specific" => Some(GeoPriority::MostSpecific),
+            _ => None,
+        }
+    }
+}
+
+pub fn by_source<T>(
+    ip2location: Vec<T>,
+    maxmind: Vec<T>,
+    priority: GeoPriority,
+    range: impl Fn(&T) -> (u128, u128),
+    slice: impl Fn(&T, u128, u128) -> T,
+) -> Vec<T> {
+    let mut map = IntervalMap::new(range, slice);
+    match priority {
+        GeoPriority::Ip2LocationFirst => {
+            map.extend(0, ip2location);
+            map.extend(1, maxmind);
+        }
+        GeoPriority::MaxMindFirst => {
+            map.extend(0, maxmind);
+            map.extend(1, ip2location);
+        }
+        GeoPriority::MostSpecific => {
+            map.extend(0, maxmind);
+            map.extend(0, ip2location);
+        }
+    }
+    match priority {
+        GeoPriority::MostSpecific => map.flatten(),
+        _ => map.layered(),
+    }
+}
+
+pub fn split_cidrs<T>(
+    items: Vec<T>,
+    range: impl Fn(&T) -> (u128, u128),
+    slice: impl Fn(&T, u128, u128) -> T,
+) -> Vec<T> {
+    let mut split = Vec::with_capacity(items.len());
+    for item in &items {
+        let (from, to) = range(item);
+        for (network, prefix) in range_to_cidrs(from, to) {
+            let hosts = u128::MAX.checked_shr(prefix as u32).unwrap_or(0);
+            split.push(slice(item, network, network + hosts));
+        }
+    }
+    sort_ranges(&mut split, &range);
+    split.reverse();
+    split.dedup_by_key(|item| range(item));
+    split.reverse();
+    split
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shape {
+    Flatten,
+    CidrAligned,
+    Ipv4Only,
+}
+
+fn apply<T>(
+    shape: Shape,
+    items: Vec<T>,
+    range: impl Fn(&T) -> (u128, u128),
+    slice: impl Fn(&T, u128, u128) -> T,
+) -> Vec<T> {
+    match shape {
+        Shape::Flatten => {
+            let mut map = IntervalMap::new(range, slice);
+            map.extend(0, items);
+            map.flatten()
+        }
+        Shape::CidrAligned => split_cidrs(items, range, slice),
+        Shape::Ipv4Only => ipv4_only(items, range, slice),
+    }
+}
+
+pub fn ipv4_only<T>(
+    items: Vec<T>,
+    range: impl Fn(&T) -> (u128, u128),
+    slice: impl Fn(&T, u128, u128) -> T,
+) -> Vec<T> {
+    items
+        .iter()
+        .filter_map(|item| {
+            let (from, to) = range(item);
+            let (from, to) = (from.max(IPV4_BASE), to.min(IPV4_BASE + u32::MAX as u128));
+            (from <= to).then(|| slice(item, from, to))
+        })
+        .collect()
+}
+
+pub fn reshape(dataset: Dataset, shape: Shape) -> Dataset {
+    match dataset {
+        Dataset::Geo(ranges) => Dataset::Geo(apply(
+            shape,
+            ranges,
+            |r| (r.0, r.1),
+            |r, from, to| (from, to, r.2, r.3, r.4),
+        )),
+        Dataset::Proxy(types) => Dataset::Proxy(
+            types
+                .into_iter()
+                .map(|(name, ranges)| {
+                    let ranges = apply(
+                        shape,
+                        ranges,
+                        |r| (r.0, r.1),
+                        |r, from, to| (from, to, r.2, r.3),
+                    );
+                    (name, ranges)
+                })
+                .collect(),
+        ),
+        Dataset::Asn(table, data) => {
+            Dataset::Asn(table, apply(shape, data, |r| (r.0, r.1), slice_asn))
+        }
+        Dataset::Isp(table, data) => Dataset::Isp(table, apply(shape, data, bounds, slice)),
+        Dataset::Mobile(table, data) => Dataset::Mobile(table, apply(shape, data, bounds, slice)),
+        Dataset::Usage(ranges) => Dataset::Usage(apply(
+            shape,
+            ranges,
+            |r| (r.0, r.1),
+            |r, from, to| (from, to, r.2),
+        )),
+        Dataset::Elevation(ranges) => Dataset::Elevation(apply(
+            shape,
+            ranges,
+            |r| (r.0, r.1),
+            |r, from, to| (from, to, r.2),
+        )),
+        Dataset::Country(ranges) => Dataset::Country(apply(
+            shape,
+            ranges,
+            |r| (r.0, r.1),
+            |r, from, to| (from, to, r.2, r.3, r.4, r.5, r.6.clone()),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merge(priority: GeoPriority) -> Vec<(u128, u128, char)> {
+        let ip2location = vec![(10, 19, 'i'), (40, 49, 'i')];
+        let maxmind = vec![(15, 24, 'm'), (30, 44, 'm')];
+        by_source(
+            ip2location,
+            maxmind,
+            priority,
+            |r| (r.0, r.1),
+            |r, from, to| (from, to, r.2),
+        )
+    }
+
+    #[test]
+    fn ip2location_first_fills_gaps_from_partially_overlapping_maxmind() {
+        assert_eq!(
+            merge(GeoPriority::Ip2LocationFirst),
+            [(10, 19, 'i'), (20, 24, 'm'), (30, 39, 'm'), (40, 49, 'i')]
+        );
+    }
+
+    #[test]
+    fn maxmind_first_keeps_maxmind_where_sources_overlap() {
+        assert_eq!(
+            merge(GeoPriority::MaxMindFirst),
+            [(10, 14, 'i'), (15, 24, 'm'), (30, 44, 'm'), (45, 49, 'i')]
+        );
+    }
+}
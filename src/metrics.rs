@@ -0,0 +1,286 @@
+#[cfg(feature = "metrics")]
+use prometheus::core::Collector;
+#[cfg(feature = "metrics")]
+use prometheus::Registry;
+
+#[cfg(feature = "metrics")]
+fn register<T: Collector + Clone + 'static>(registry: &Registry, metric: T) -> T {
+    registry
+        .register(Box::new(metric.clone()))
+        .expect("unique metric");
+    metric
+}
+
+#[cfg(feature = "metrics")]
+mod build {
+    use std::collections::HashMap;
+    use std::io::{Error, Result};
+    use std::sync::LazyLock;
+    use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+    use prometheus::{GaugeVec, IntGauge, Opts, Registry};
+
+    use super::register;
+
+    const JOB: &str = "ip2x";
+
+    struct Build {
+        registry: Registry,
+        stages: GaugeVec,
+        finished: IntGauge,
+    }
+
+    static BUILD: LazyLock<Build> = LazyLock::new(|| {
+        let registry = Registry::new();
+        Build {
+            stages: register(
+                &registry,
+                GaugeVec::new(
+                    Opts::new(
+                        "ip2x_build_stage_duration_seconds",
+                        "Duration of the last run of each build stage",
+                    ),
+                    &["stage", "source"],
+                )
+                .expect("metric"),
+            ),
+            finished: register(
+                &registry,
+                IntGauge::new(
+                    "ip2x_build_last_success_timestamp_seconds",
+                    "Unix time the last build finished",
+                )
+                .expect("metric"),
+            ),
+            registry,
+        }
+    });
+
+    pub struct StageTimer {
+        name: &'static str,
+        source: String,
+        started: Instant,
+    }
+
+    impl StageTimer {
+        pub fn start(name: &'static str, source: &str) -> StageTimer {
+            StageTimer {
+                name,
+                source: source.to_string(),
+                started: Instant::now(),
+            }
+        }
+
+        pub fn finish(self) {
+            BUILD
+                .stages
+                .with_label_values(&[self.name, &self.source])
+                .set(self.started.elapsed().as_secs_f64());
+        }
+    }
+
+    pub fn push(url: &str) -> Result<()> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        BUILD.finished.set(now as i64);
+        prometheus::push_metrics(JOB, HashMap::new(), url, BUILD.registry.gather(), None)
+            .map_err(|err| Error::other(format!("Cannot push to {}: {}", url, err)))
+    }
+}
+
+#[cfg(not(feature = "metrics"))]
+mod build {
+    use std::io::{Error, ErrorKind, Result};
+
+    pub struct StageTimer;
+
+    impl StageTimer {
+        pub fn start(_name: &'static str, _source: &str) -> StageTimer {
+            StageTimer
+        }
+
+        pub fn finish(self) {}
+    }
+
+    pub fn push(_url: &str) -> Result<()> {
+        Err(Error::new(
+            ErrorKind::Unsupported,
+            "--pushgateway requires building with --features metrics",
+        ))
+    }
+}
+
+#[cfg(all(feature = "metrics", feature = "serve"))]
+mod server {
+    use std::fs;
+    use std::path::Path;
+    use std::sync::{LazyLock, Mutex};
+    use std::time::Instant;
+
+    use prometheus::{
+        exponential_buckets, Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec,
+        IntGauge, IntGaugeVec, Opts, Registry, TextEncoder,
+    };
+    use serde_json::Value;
+
+    use super::register;
+    use crate::logging;
+    use crate::manifest::{parse_utc_timestamp, MANIFEST_FILE};
+    use crate::reader::Ip2xReader;
+
+    struct Server {
+        registry: Registry,
+        lookups: IntCounterVec,
+        latency: HistogramVec,
+        cache_hits: IntCounter,
+        cache_misses: IntCounter,
+        dataset: IntGaugeVec,
+        built: IntGauge,
+        records: IntGaugeVec,
+    }
+
+    static SERVER: LazyLock<Server> = LazyLock::new(|| {
+        let registry = Registry::new();
+        let latency = HistogramOpts::new("ip2x_lookup_duration_seconds", "Lookup request latency")
+            .buckets(exponential_buckets(0.00001, 4.0, 10).expect("buckets"));
+        Server {
+            lookups: register(
+                &registry,
+                IntCounterVec::new(
+                    Opts::new("ip2x_lookups_total", "IP addresses looked up"),
+                    &["endpoint"],
+                )
+                .expect("metric"),
+            ),
+            latency: register(
+                &registry,
+                HistogramVec::new(latency, &["endpoint"]).expect("metric"),
+            ),
+            cache_hits: register(
+                &registry,
+                IntCounter::new("ip2x_block_cache_hits_total", "Block cache hits").expect("metric"),
+            ),
+            cache_misses: register(
+                &registry,
+                IntCounter::new("ip2x_block_cache_misses_total", "Block cache misses")
+                    .expect("metric"),
+            ),
+            dataset: register(
+                &registry,
+                IntGaugeVec::new(
+                    Opts::new("ip2x_dataset_info", "Loaded dataset version"),
+                    &["version", "created"],
+                )
+                .expect("metric"),
+            ),
+            built: register(
+                &registry,
+                IntGauge::new(
+                    "ip2x_dataset_build_timestamp_seconds",
+                    "Unix time the loaded dataset was built",
+                )
+                .expect("metric"),
+            ),
+            records: register(
+                &registry,
+                IntGaugeVec::new(
+                    Opts::new("ip2x_artifact_records", "Records per loaded artifact"),
+                    &["artifact"],
+                )
+                .expect("metric"),
+            ),
+            registry,
+        }
+    });
+
+    static SCRAPED: Mutex<(usize, u64, u64)> = Mutex::new((0, 0, 0));
+
+    pub struct Timer {
+        endpoint: &'static str,
+        started: Instant,
+    }
+
+    pub fn timer(endpoint: &'static str) -> Timer {
+        Timer {
+            endpoint,
+            started: Instant::now(),
+        }
+    }
+
+    impl Timer {
+        pub fn observe(self, ips: usize) {
+            SERVER
+                .lookups
+                .with_label_values(&[self.endpoint])
+                .inc_by(ips as u64);
+            SERVER
+                .latency
+                .with_label_values(&[self.endpoint])
+                .observe(self.started.elapsed().as_secs_f64());
+        }
+    }
+
+    pub fn load_dataset(dir: &str) {
+        let manifest: Value = match fs::read(Path::new(dir).join(MANIFEST_FILE)) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_default(),
+            Err(err) => {
+                logging::warn(&format!("no dataset metrics: {}: {}", MANIFEST_FILE, err));
+                return;
+            }
+        };
+        let version = manifest["version"].as_str().unwrap_or("");
+        let created = manifest["created"].as_str().unwrap_or("");
+        SERVER.dataset.reset();
+        SERVER.dataset.with_label_values(&[version, created]).set(1);
+        if let Some(built) = parse_utc_timestamp(created) {
+            SERVER.built.set(built as i64);
+        }
+        SERVER.records.reset();
+        for artifact in manifest["artifacts"].as_array().into_iter().flatten() {
+            if let (Some(path), Some(records)) =
+                (artifact["path"].as_str(), artifact["records"].as_i64())
+            {
+                SERVER.records.with_label_values(&[path]).set(records);
+            }
+        }
+    }
+
+    pub fn render(reader: &Ip2xReader) -> String {
+        let mut scraped = SCRAPED.lock().unwrap_or_else(|err| err.into_inner());
+        let id = reader as *const Ip2xReader as usize;
+        if scraped.0 != id {
+            *scraped = (id, 0, 0);
+        }
+        let (hits, misses) = reader.cache_stats();
+        SERVER.cache_hits.inc_by(hits - scraped.1);
+        SERVER.cache_misses.inc_by(misses - scraped.2);
+        *scraped = (id, hits, misses);
+        let mut out = Vec::new();
+        TextEncoder::new()
+            .encode(&SERVER.registry.gather(), &mut out)
+            .expect("text encoding");
+        String::from_utf8(out).expect("utf-8 metrics")
+    }
+}
+
+#[cfg(all(not(feature = "metrics"), feature = "serve"))]
+mod server {
+    pub struct Timer;
+
+    pub fn timer(_endpoint: &'static str) -> Timer {
+        Timer
+    }
+
+    impl Timer {
+        pub fn observe(self, _ips: usize) {}
+    }
+
+    pub fn load_dataset(_dir: &str) {}
+}
+
+pub use build::{push, StageTimer};
+#[cfg(all(feature = "metrics", feature = "serve"))]
+pub use server::render;
+#[cfg(feature = "serve")]
+pub use server::{load_dataset, timer};
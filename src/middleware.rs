@@ -0,0 +1,133 @@
+use std::net::{IpAddr, SocketAddr};
+use std::ops::Deref;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use axum::extract::{ConnectInfo, FromRequestParts, OptionalFromRequestParts};
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, HeaderName, Request, StatusCode};
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::reader::{Ip2xReader, Record};
+
+const FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+
+#[derive(Debug, Clone)]
+pub struct Ip2xInfo(pub Record);
+
+impl Deref for Ip2xInfo {
+    type Target = Record;
+
+    fn deref(&self) -> &Record {
+        &self.0
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for Ip2xInfo {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<Ip2xInfo>().cloned().ok_or((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "No client IP: add Ip2xLayer and serve with connect info",
+        ))
+    }
+}
+
+impl<S: Send + Sync> OptionalFromRequestParts<S> for Ip2xInfo {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        _state: &S,
+    ) -> Result<Option<Self>, Self::Rejection> {
+        Ok(parts.extensions.get::<Ip2xInfo>().cloned())
+    }
+}
+
+#[derive(Clone)]
+pub struct Ip2xLayer {
+    reader: Arc<Ip2xReader>,
+    header: HeaderName,
+    trusted_hops: usize,
+}
+
+impl Ip2xLayer {
+    pub fn new(reader: Arc<Ip2xReader>) -> Ip2xLayer {
+        Ip2xLayer {
+            reader,
+            header: FORWARDED_FOR,
+            trusted_hops: 0,
+        }
+    }
+
+    pub fn trusted_hops(mut self, hops: usize) -> Ip2xLayer {
+        self.trusted_hops = hops;
+        self
+    }
+
+    pub fn header(mut self, header: HeaderName) -> Ip2xLayer {
+        self.header = header;
+        self
+    }
+
+    pub fn client_ip(&self, headers: &HeaderMap, peer: Option<IpAddr>) -> Option<IpAddr> {
+        if self.trusted_hops == 0 {
+            return peer;
+        }
+        let forwarded: Vec<&str> = headers
+            .get_all(&self.header)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect();
+        match forwarded.len().checked_sub(self.trusted_hops) {
+            Some(i) => forwarded[i].parse().ok(),
+            None => forwarded.first().and_then(|ip| ip.parse().ok()).or(peer),
+        }
+    }
+}
+
+impl<S> Layer<S> for Ip2xLayer {
+    type Service = Ip2xService<S>;
+
+    fn layer(&self, inner: S) -> Ip2xService<S> {
+        Ip2xService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Ip2xService<S> {
+    inner: S,
+    layer: Ip2xLayer,
+}
+
+impl<S, B> Service<Request<B>> for Ip2xService<S>
+where
+    S: Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<B>) -> S::Future {
+        let peer = request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        if let Some(ip) = self.layer.client_ip(request.headers(), peer) {
+            let record = self.layer.reader.lookup_many(&[ip]).remove(0);
+            request.extensions_mut().insert(Ip2xInfo(record));
+        }
+        self.inner.call(request)
+    }
+}
@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Result};
+use std::iter;
+use std::path::Path;
+
+use crate::format::{intern, intern_with_offset, order_by_frequency, Dataset, Kind};
+use crate::interval;
+use crate::ip::IpRange;
+use crate::logging;
+use crate::maxmind::{MaxMindReader, Value};
+use crate::storage;
+
+type StringRange = (u128, u128, usize, usize, usize);
+type Layer = Vec<(u128, u128, Vec<Option<Value>>)>;
+
+pub struct Mapping {
+    pub path: String,
+    pub kind: Kind,
+    fields: Vec<(usize, String)>,
+}
+
+fn fields(kind: Kind) -> &'static [&'static str] {
+    match kind {
+        Kind::Geo => &["latitude", "longitude", "accuracy"],
+        Kind::Asn => &["asn", "name"],
+        Kind::Isp => &["isp", "domain", "provider"],
+        Kind::Mobile => &["mcc", "mnc", "brand"],
+        _ => &[],
+    }
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidInput, message)
+}
+
+fn parse_mapping(entry: &str, data_dir: &str) -> Result<Mapping> {
+    let mut parts = entry.splitn(3, ':').map(str::trim);
+    let (Some(file), Some(artifact), Some(spec)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return Err(invalid(format!(
+            "Bad --mmdb-map entry: {} (expected <file.mmdb>:<artifact>:<field>=<path>,...)",
+            entry
+        )));
+    };
+    let kind = Kind::parse(artifact.trim_end_matches(".bin"))
+        .filter(|&kind| !fields(kind).is_empty())
+        .ok_or_else(|| {
+            invalid(format!(
+                "Unsupported --mmdb-map artifact: {} (expected geo, asn, isp or mobile)",
+                artifact
+            ))
+        })?;
+    let mapped = spec
+        .split(',')
+        .map(str::trim)
+        .filter(|field| !field.is_empty())
+        .map(|field| {
+            let (name, path) = field.split_once('=').unwrap_or((field, field));
+            let slot = fields(kind)
+                .iter()
+                .position(|&known| known == name.trim())
+                .ok_or_else(|| {
+                    invalid(format!(
+                        "Unknown {} field in --mmdb-map: {} (expected {})",
+                        kind.name(),
+                        name.trim(),
+                        fields(kind).join(", ")
+                    ))
+                })?;
+            Ok((slot, path.trim().to_string()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let has = |slot| mapped.iter().any(|&(mapped, _)| mapped == slot);
+    let required: &[usize] = match kind {
+        Kind::Geo => &[0, 1],
+        Kind::Asn => &[0],
+        _ => &[],
+    };
+    if mapped.is_empty() || !required.iter().all(|&slot| has(slot)) {
+        return Err(invalid(format!(
+            "--mmdb-map {} needs at least {}",
+            entry,
+            match required.is_empty() {
+                true => "one field".to_string(),
+                false => required
+                    .iter()
+                    .map(|&slot| fields(kind)[slot])
+                    .collect::<Vec<_>>()
+                    .join(" and "),
+            }
+        )));
+    }
+    Ok(Mapping {
+        path: Path::new(data_dir)
+            .join(file)
+            .to_string_lossy()
+            .into_owned(),
+        kind,
+        fields: mapped,
+    })
+}
+
+pub fn parse(value: &str, data_dir: &str) -> Result<Vec<Mapping>> {
+    let entries = match storage::exists(value) {
+        true => storage::read_to_string(value)?,
+        false => value.replace(';', "\n"),
+    };
+    entries
+        .lines()
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty() && !entry.starts_with('#'))
+        .map(|entry| parse_mapping(entry, data_dir))
+        .collect()
+}
+
+fn load(mapping: &Mapping) -> Result<Layer> {
+    let reader = MaxMindReader::open(&mapping.path)?;
+    let paths: Vec<&str> = mapping
+        .fields
+        .iter()
+        .map(|(_, path)| path.as_str())
+        .collect();
+    let slots = fields(mapping.kind).len();
+    Ok(reader
+        .load_paths(&paths)
+        .into_iter()
+        .map(|(from, to, values)| {
+            let mut row = vec![None; slots];
+            for (&(slot, _), value) in mapping.fields.iter().zip(values) {
+                row[slot] = value;
+            }
+            (from, to, row)
+        })
+        .collect())
+}
+
+fn text(value: &Value) -> Option<String> {
+    let text = match value {
+        Value::String(s) => s.clone(),
+        Value::Int(n) => n.to_string(),
+        Value::UInt(n) => n.to_string(),
+        Value::Float(n) => n.to_string(),
+        Value::Double(n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        _ => return None,
+    };
+    (!text.is_empty()).then_some(text)
+}
+
+fn intern_layers(
+    strings: &mut Vec<String>,
+    layers: Vec<Layer>,
+    offset: usize,
+    columns: impl Fn(u128, u128, Vec<String>) -> [String; 3],
+) -> Vec<Vec<StringRange>> {
+    let mut map: HashMap<String, usize> = strings
+        .iter()
+        .enumerate()
+        .map(|(i, s)| (s.clone(), i + offset))
+        .collect();
+    let mut index = |s: &str| match offset {
+        0 => intern(s, strings, &mut map),
+        _ => intern_with_offset(s, strings, &mut map),
+    };
+    layers
+        .into_iter()
+        .map(|layer| {
+            layer
+                .into_iter()
+                .map(|(from, to, values)| {
+                    let values = values
+                        .iter()
+                        .map(|value| value.as_ref().and_then(text))
+                        .map(|value| value.unwrap_or_else(|| "-".to_string()))
+                        .collect();
+                    let [a, b, c] = columns(from, to, values);
+                    (from, to, index(&a), index(&b), index(&c))
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn splice_strings(
+    mut strings: Vec<String>,
+    data: Vec<StringRange>,
+    layers: Vec<Layer>,
+    columns: impl Fn(u128, u128, Vec<String>) -> [String; 3],
+) -> (Vec<String>, Vec<StringRange>) {
+    let layers = intern_layers(&mut strings, layers, 1, columns);
+    let data = interval::by_priority(
+        iter::once(data).chain(layers).collect(),
+        |r| (r.0, r.1),
+        |r, from, to| (from, to, r.2, r.3, r.4),
+    );
+    (strings, data)
+}
+
+pub fn splice(dataset: Dataset, mappings: &[Mapping]) -> Dataset {
+    let layers: Vec<Layer> = mappings
+        .iter()
+        .filter(|mapping| mapping.kind == dataset.kind())
+        .filter_map(|mapping| match load(mapping) {
+            Ok(layer) => Some(layer),
+            Err(err) => {
+                logging::warn(&format!("skipped: {}: {}", mapping.path, err));
+                None
+            }
+        })
+        .collect();
+    if layers.is_empty() {
+        return dataset;
+    }
+
+    let mut dataset = match dataset {
+        Dataset::Geo(ranges) => {
+            let layers = layers.into_iter().map(|layer| {
+                layer
+                    .into_iter()
+                    .filter_map(|(from, to, values)| {
+                        let number = |slot: usize| values[slot].as_ref().and_then(Value::as_f64);
+                        let accuracy = number(2).map(|radius| radius.min(u16::MAX as f64) as u16);
+                        Some((from, to, number(0)? as f32, number(1)? as f32, accuracy))
+                    })
+                    .collect()
+            });
+            return Dataset::Geo(interval::by_priority(
+                iter::once(ranges).chain(layers).collect(),
+                |r| (r.0, r.1),
+                |r, from, to| (from, to, r.2, r.3, r.4),
+            ));
+        }
+        Dataset::Asn(mut strings, data) => {
+            let layers = layers
+                .into_iter()
+                .map(|layer| layer.into_iter().filter(|r| r.2[0].is_some()).collect())
+                .collect();
+            let layers = intern_layers(&mut strings, layers, 0, |from, to, values| {
+                let cidr = IpRange::from((from, to))
+                    .to_cidrs()
+                    .first()
+                    .map_or("-".to_string(), |(network, prefix)| {
+                        format!("{}/{}", network, prefix)
+                    });
+                [cidr, values[0].clone(), values[1].clone()]
+            });
+            let layers = layers.into_iter().map(|layer| {
+                layer
+                    .into_iter()
+                    .map(|(from, to, cidr, asn, name)| (from, to, cidr, asn, name, None))
+                    .collect()
+            });
+            let data = interval::by_priority(
+                iter::once(data).chain(layers).collect(),
+                |r| (r.0, r.1),
+                |r, from, to| (from, to, r.2, r.3, r.4, r.5),
+            );
+            Dataset::Asn(strings, data)
+        }
+        Dataset::Isp(strings, data) => {
+            let (strings, data) = splice_strings(strings, data, layers, |_, _, values| {
+                [values[0].clone(), values[1].clone(), values[2].clone()]
+            });
+            Dataset::Isp(strings, data)
+        }
+        Dataset::Mobile(strings, data) => {
+            let (strings, data) = splice_strings(strings, data, layers, |_, _, values| {
+                [values[0].clone(), values[1].clone(), values[2].clone()]
+            });
+            Dataset::Mobile(strings, data)
+        }
+        dataset => return dataset,
+    };
+    order_by_frequency(&mut [&mut dataset]);
+    dataset
+}
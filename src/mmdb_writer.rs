@@ -0,0 +1,351 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Error, ErrorKind, Result, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::format::Dataset;
+use crate::ip::range_to_cidrs;
+use crate::maxmind::Value;
+
+const DATA_SEPARATOR_SIZE: usize = 16;
+const METADATA_MARKER: &[u8] = b"\xab\xcd\xefMaxMind.com";
+const IPV4_MAPPED_PREFIX: u128 = 0xffff << 32;
+
+type Record = HashMap<String, Value>;
+
+#[derive(Clone, Copy)]
+enum Child {
+    Empty,
+    Node(u32),
+    Data(u32),
+}
+
+pub struct MmdbWriter {
+    nodes: Vec<[Child; 2]>,
+    records: Vec<Record>,
+    database_type: String,
+    description: String,
+}
+
+impl MmdbWriter {
+    pub fn new(database_type: &str, description: &str) -> Self {
+        Self {
+            nodes: vec![[Child::Empty, Child::Empty]],
+            records: Vec::new(),
+            database_type: database_type.to_string(),
+            description: description.to_string(),
+        }
+    }
+
+    pub fn insert_range(&mut self, from: u128, to: u128, apply: &impl Fn(&mut Record)) {
+        for (network, prefix_len) in range_to_cidrs(from, to) {
+            self.insert(network, prefix_len, apply);
+        }
+    }
+
+    pub fn insert(&mut self, network: u128, prefix_len: u8, apply: &impl Fn(&mut Record)) {
+        let (network, prefix_len) = if prefix_len >= 96 && network >> 32 == 0xffff {
+            (network & u32::MAX as u128, prefix_len)
+        } else {
+            (network, prefix_len)
+        };
+
+        if prefix_len == 0 {
+            self.apply_child(Child::Node(0), apply);
+            return;
+        }
+
+        let mut node = 0usize;
+        for depth in 0..prefix_len as usize {
+            let bit = ((network >> (127 - depth)) & 1) as usize;
+            let child = self.nodes[node][bit];
+
+            if depth + 1 == prefix_len as usize {
+                self.nodes[node][bit] = self.apply_child(child, apply);
+                return;
+            }
+
+            node = match child {
+                Child::Node(next) => next as usize,
+                Child::Empty | Child::Data(_) => {
+                    let next = self.nodes.len();
+                    self.nodes.push([child, child]);
+                    self.nodes[node][bit] = Child::Node(next as u32);
+                    next
+                }
+            };
+        }
+    }
+
+    fn apply_child(&mut self, child: Child, apply: &impl Fn(&mut Record)) -> Child {
+        match child {
+            Child::Node(node) => {
+                for bit in 0..2 {
+                    let inner = self.nodes[node as usize][bit];
+                    self.nodes[node as usize][bit] = self.apply_child(inner, apply);
+                }
+                child
+            }
+            Child::Empty | Child::Data(_) => {
+                let mut record = match child {
+                    Child::Data(idx) => self.records[idx as usize].clone(),
+                    _ => Record::new(),
+                };
+                apply(&mut record);
+                self.records.push(record);
+                Child::Data((self.records.len() - 1) as u32)
+            }
+        }
+    }
+
+    fn alias_ipv4_mapped(&mut self) {
+        let mut node = 0usize;
+        for _ in 0..96 {
+            node = self.descend(node, 0);
+        }
+        let ipv4_root = node as u32;
+
+        node = 0;
+        for depth in 0..95 {
+            let bit = ((IPV4_MAPPED_PREFIX >> (127 - depth)) & 1) as usize;
+            node = self.descend(node, bit);
+        }
+        self.nodes[node][1] = Child::Node(ipv4_root);
+    }
+
+    fn descend(&mut self, node: usize, bit: usize) -> usize {
+        match self.nodes[node][bit] {
+            Child::Node(next) => next as usize,
+            child => {
+                let next = self.nodes.len();
+                self.nodes.push([child, child]);
+                self.nodes[node][bit] = Child::Node(next as u32);
+                next
+            }
+        }
+    }
+
+    pub fn write(mut self, path: &str) -> Result<()> {
+        self.alias_ipv4_mapped();
+
+        let mut data = Vec::new();
+        let mut offsets = Vec::with_capacity(self.records.len());
+        let mut dedup: HashMap<Vec<u8>, usize> = HashMap::new();
+        for record in &self.records {
+            let mut encoded = Vec::new();
+            encode_map(&mut encoded, record);
+            let offset = *dedup.entry(encoded).or_insert_with_key(|bytes| {
+                data.extend_from_slice(bytes);
+                data.len() - bytes.len()
+            });
+            offsets.push(offset);
+        }
+
+        let node_count = self.nodes.len();
+        let max_value = node_count + DATA_SEPARATOR_SIZE + data.len();
+        let record_size = match max_value {
+            v if v < 1 << 24 => 24,
+            v if v < 1 << 28 => 28,
+            v if v < 1 << 32 => 32,
+            _ => return Err(Error::new(ErrorKind::InvalidData, "MMDB too large")),
+        };
+
+        let mut out = BufWriter::new(File::create(path)?);
+        for node in &self.nodes {
+            let [left, right] = node.map(|child| match child {
+                Child::Empty => node_count as u32,
+                Child::Node(next) => next,
+                Child::Data(idx) => {
+                    (node_count + DATA_SEPARATOR_SIZE + offsets[idx as usize]) as u32
+                }
+            });
+            write_node(&mut out, record_size, left, right)?;
+        }
+
+        out.write_all(&[0u8; DATA_SEPARATOR_SIZE])?;
+        out.write_all(&data)?;
+        out.write_all(METADATA_MARKER)?;
+
+        let build_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let mut metadata = Vec::new();
+        encode_ctrl(&mut metadata, 7, 9);
+        encode_string(&mut metadata, "binary_format_major_version");
+        encode_uint(&mut metadata, 5, 2);
+        encode_string(&mut metadata, "binary_format_minor_version");
+        encode_uint(&mut metadata, 5, 0);
+        encode_string(&mut metadata, "build_epoch");
+        encode_uint(&mut metadata, 9, build_epoch);
+        encode_string(&mut metadata, "database_type");
+        encode(&mut metadata, &Value::String(self.database_type.clone()));
+        encode_string(&mut metadata, "description");
+        let description = HashMap::from([("en".to_string(), Value::String(self.description))]);
+        encode(&mut metadata, &Value::Map(description));
+        encode_string(&mut metadata, "ip_version");
+        encode_uint(&mut metadata, 5, 6);
+        encode_string(&mut metadata, "languages");
+        encode(
+            &mut metadata,
+            &Value::Array(vec![Value::String("en".to_string())]),
+        );
+        encode_string(&mut metadata, "node_count");
+        encode_uint(&mut metadata, 6, node_count as u64);
+        encode_string(&mut metadata, "record_size");
+        encode_uint(&mut metadata, 5, record_size as u64);
+        out.write_all(&metadata)?;
+
+        out.flush()
+    }
+}
+
+fn write_node(out: &mut impl Write, record_size: u32, left: u32, right: u32) -> Result<()> {
+    match record_size {
+        24 => {
+            out.write_all(&left.to_be_bytes()[1..])?;
+            out.write_all(&right.to_be_bytes()[1..])
+        }
+        28 => {
+            let l = left.to_be_bytes();
+            let r = right.to_be_bytes();
+            out.write_all(&l[1..])?;
+            out.write_all(&[(l[0] << 4) | (r[0] & 0x0F)])?;
+            out.write_all(&r[1..])
+        }
+        _ => {
+            out.write_all(&left.to_be_bytes())?;
+            out.write_all(&right.to_be_bytes())
+        }
+    }
+}
+
+fn encode_ctrl(out: &mut Vec<u8>, type_num: u8, size: usize) {
+    let (type_bits, extended) = if type_num > 7 {
+        (0u8, Some(type_num - 7))
+    } else {
+        (type_num << 5, None)
+    };
+
+    let (size_bits, size_bytes): (u8, Vec<u8>) = match size {
+        s if s < 29 => (s as u8, Vec::new()),
+        s if s < 285 => (29, vec![(s - 29) as u8]),
+        s if s < 65821 => (30, ((s - 285) as u16).to_be_bytes().to_vec()),
+        s => (31, ((s - 65821) as u32).to_be_bytes()[1..].to_vec()),
+    };
+
+    out.push(type_bits | size_bits);
+    if let Some(extended) = extended {
+        out.push(extended);
+    }
+    out.extend_from_slice(&size_bytes);
+}
+
+fn encode_uint(out: &mut Vec<u8>, type_num: u8, value: u64) {
+    let bytes = value.to_be_bytes();
+    let skip = bytes.iter().take_while(|b| **b == 0).count();
+    encode_ctrl(out, type_num, bytes.len() - skip);
+    out.extend_from_slice(&bytes[skip..]);
+}
+
+fn encode_string(out: &mut Vec<u8>, s: &str) {
+    encode_ctrl(out, 2, s.len());
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn encode(out: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::String(s) => encode_string(out, s),
+        Value::Double(f) => {
+            encode_ctrl(out, 3, 8);
+            out.extend_from_slice(&f.to_be_bytes());
+        }
+        Value::Bytes(bytes) => {
+            encode_ctrl(out, 4, bytes.len());
+            out.extend_from_slice(bytes);
+        }
+        Value::UInt(n) if *n <= u32::MAX as u64 => encode_uint(out, 6, *n),
+        Value::UInt(n) => encode_uint(out, 9, *n),
+        Value::Map(map) => encode_map(out, map),
+        Value::Int(n) => {
+            let bytes = n.to_be_bytes();
+            let skip = if *n < 0 {
+                0
+            } else {
+                bytes.iter().take_while(|b| **b == 0).count()
+            };
+            encode_ctrl(out, 8, bytes.len() - skip);
+            out.extend_from_slice(&bytes[skip..]);
+        }
+        Value::Array(items) => {
+            encode_ctrl(out, 11, items.len());
+            for item in items {
+                encode(out, item);
+            }
+        }
+        Value::Bool(b) => encode_ctrl(out, 14, *b as usize),
+        Value::Float(f) => {
+            encode_ctrl(out, 15, 4);
+            out.extend_from_slice(&f.to_be_bytes());
+        }
+    }
+}
+
+fn encode_map(out: &mut Vec<u8>, map: &Record) {
+    encode_ctrl(out, 7, map.len());
+    let mut keys: Vec<&String> = map.keys().collect();
+    keys.sort();
+    for key in keys {
+        encode_string(out, key);
+        encode(out, &map[key]);
+    }
+}
+
+fn coordinate(value: f32) -> Value {
+    Value::Double((value as f64 * 10000.0).round() / 10000.0)
+}
+
+pub fn write_geo_asn(path: &str, geo: &Dataset, asn: &Dataset) -> Result<()> {
+    let mut writer = MmdbWriter::new(
+        "IP2X-City-ASN",
+        "IP2Location LITE and GeoLite2 geolocation merged with IP2Location ASN data",
+    );
+
+    if let Dataset::Geo(ranges) = geo {
+        let mut by_size: Vec<_> = ranges.iter().collect();
+        by_size.sort_by_key(|r| Reverse(r.1 - r.0));
+        for (from, to, lat, lon, accuracy) in by_size {
+            let mut location = HashMap::from([
+                ("latitude".to_string(), coordinate(*lat)),
+                ("longitude".to_string(), coordinate(*lon)),
+            ]);
+            if let Some(radius) = accuracy {
+                location.insert("accuracy_radius".to_string(), Value::UInt(*radius as u64));
+            }
+            writer.insert_range(*from, *to, &|record: &mut Record| {
+                record.insert("location".to_string(), Value::Map(location.clone()));
+            });
+        }
+    }
+
+    if let Dataset::Asn(strings, data) = asn {
+        let mut by_size: Vec<_> = data.iter().collect();
+        by_size.sort_by_key(|r| Reverse(r.1 - r.0));
+        for (from, to, _, asn_idx, name_idx, _) in by_size {
+            let number = strings.get(*asn_idx).and_then(|s| s.parse::<u64>().ok());
+            let name = strings.get(*name_idx).cloned().unwrap_or_default();
+            writer.insert_range(*from, *to, &|record: &mut Record| {
+                if let Some(number) = number {
+                    record.insert("autonomous_system_number".to_string(), Value::UInt(number));
+                }
+                record.insert(
+                    "autonomous_system_organization".to_string(),
+                    Value::String(name.clone()),
+                );
+            });
+        }
+    }
+
+    writer.write(path)
+}
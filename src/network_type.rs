@@ -0,0 +1,219 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Error, ErrorKind, Result, Write};
+
+use ip2x_core::usage::USAGE_TYPES;
+use ip2x_core::varint::read_varint;
+
+use crate::encoding::write_varint;
+use crate::format::{Dataset, NetworkTypeRange};
+use crate::interval::IntervalMap;
+
+type Labeled<L> = (Vec<String>, Vec<(u128, u128, L)>);
+
+pub const NETWORK_TYPE_FILE: &str = "network_type.bin";
+const MAGIC: &[u8; 4] = b"IP2N";
+pub const NETWORK_TYPES: [&str; 6] = [
+    "residential",
+    "mobile",
+    "hosting",
+    "business",
+    "education",
+    "government",
+];
+const RESIDENTIAL: u8 = 0;
+const MOBILE: u8 = 1;
+const HOSTING: u8 = 2;
+const BUSINESS: u8 = 3;
+const EDUCATION: u8 = 4;
+const GOVERNMENT: u8 = 5;
+
+const USAGE_RULES: [(&[&str], u8); 6] = [
+    (&["DCH", "CDN", "SES"], HOSTING),
+    (&["MOB"], MOBILE),
+    (&["GOV", "MIL"], GOVERNMENT),
+    (&["EDU", "LIB"], EDUCATION),
+    (&["ISP"], RESIDENTIAL),
+    (&["COM", "ORG"], BUSINESS),
+];
+const PROXY_RULES: [(&str, u8); 3] = [("DCH", HOSTING), ("SES", HOSTING), ("RES", RESIDENTIAL)];
+const KEYWORD_RULES: [(&[&str], u8); 5] = [
+    (
+        &[
+            "hosting",
+            "cloud",
+            "data center",
+            "datacenter",
+            "server",
+            "vps",
+            "colocation",
+            "cdn",
+        ],
+        HOSTING,
+    ),
+    (&["mobile", "wireless", "cellular"], MOBILE),
+    (
+        &["university", "college", "school", "academ", "research"],
+        EDUCATION,
+    ),
+    (
+        &["government", "ministry", "federal", "municipal", "military"],
+        GOVERNMENT,
+    ),
+    (
+        &[
+            "broadband",
+            "cable",
+            "dsl",
+            "fiber",
+            "fibre",
+            "telecom",
+            "internet service",
+        ],
+        RESIDENTIAL,
+    ),
+];
+
+pub fn from_usage(mask: u16) -> Option<u8> {
+    USAGE_RULES.iter().find_map(|&(codes, network_type)| {
+        codes
+            .iter()
+            .filter_map(|code| USAGE_TYPES.iter().position(|t| t == code))
+            .any(|bit| mask & (1 << bit) != 0)
+            .then_some(network_type)
+    })
+}
+
+pub fn from_as_name(name: &str) -> Option<u8> {
+    let name = name.to_ascii_lowercase();
+    KEYWORD_RULES.iter().find_map(|&(keywords, network_type)| {
+        keywords
+            .iter()
+            .any(|keyword| name.contains(keyword))
+            .then_some(network_type)
+    })
+}
+
+pub fn derive(
+    proxy: &Dataset,
+    usage: &Dataset,
+    mobile: &Dataset,
+    asn: &Dataset,
+) -> Vec<NetworkTypeRange> {
+    let mut map = IntervalMap::new(
+        |r: &NetworkTypeRange| (r.0, r.1),
+        |r, from, to| (from, to, r.2),
+    );
+    if let Dataset::Proxy(types) = proxy {
+        for (name, ranges) in types {
+            if let Some(&(_, network_type)) = PROXY_RULES.iter().find(|(t, _)| t == name) {
+                map.extend(0, ranges.iter().map(|r| (r.0, r.1, network_type)));
+            }
+        }
+    }
+    if let Dataset::Usage(ranges) = usage {
+        map.extend(
+            1,
+            ranges
+                .iter()
+                .filter_map(|r| Some((r.0, r.1, from_usage(r.2)?))),
+        );
+    }
+    if let Dataset::Mobile(_, ranges) = mobile {
+        map.extend(2, ranges.iter().map(|r| (r.0, r.1, MOBILE)));
+    }
+    if let Dataset::Asn(strings, ranges) = asn {
+        map.extend(
+            3,
+            ranges
+                .iter()
+                .filter_map(|r| Some((r.0, r.1, from_as_name(strings.get(r.4)?)?))),
+        );
+    }
+
+    join_adjacent(map.flatten())
+}
+
+pub fn join_adjacent<L: PartialEq>(ranges: Vec<(u128, u128, L)>) -> Vec<(u128, u128, L)> {
+    let mut merged: Vec<(u128, u128, L)> = Vec::new();
+    for (from, to, label) in ranges {
+        match merged.last_mut() {
+            Some(last) if last.2 == label && last.1.checked_add(1) == Some(from) => last.1 = to,
+            _ => merged.push((from, to, label)),
+        }
+    }
+    merged
+}
+
+pub fn write(path: &str, ranges: &[NetworkTypeRange]) -> Result<()> {
+    write_labeled(path, MAGIC, &NETWORK_TYPES, ranges)
+}
+
+pub fn read(path: &str) -> Result<(Vec<String>, Vec<NetworkTypeRange>)> {
+    read_labeled(path, MAGIC, "network type")
+}
+
+pub fn write_labeled<L: Copy + Into<u128>>(
+    path: &str,
+    magic: &[u8; 4],
+    names: &[impl AsRef<str>],
+    ranges: &[(u128, u128, L)],
+) -> Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    out.write_all(magic)?;
+    write_varint(&mut out, names.len() as u128)?;
+    for name in names {
+        let name = name.as_ref();
+        write_varint(&mut out, name.len() as u128)?;
+        out.write_all(name.as_bytes())?;
+    }
+    write_varint(&mut out, ranges.len() as u128)?;
+    let mut next = 0u128;
+    for &(from, to, label) in ranges {
+        write_varint(&mut out, from - next)?;
+        write_varint(&mut out, to - from)?;
+        write_varint(&mut out, label.into())?;
+        next = to.saturating_add(1);
+    }
+    out.flush()
+}
+
+pub fn read_labeled<L: TryFrom<u128>>(
+    path: &str,
+    magic: &[u8; 4],
+    what: &str,
+) -> Result<Labeled<L>> {
+    let bytes = fs::read(path)?;
+    let invalid =
+        |message: String| Error::new(ErrorKind::InvalidData, format!("{}: {}", path, message));
+    let mut input = bytes
+        .strip_prefix(magic)
+        .ok_or_else(|| invalid(format!("not a {} file", what)))?;
+
+    let count = read_varint(&mut input)? as usize;
+    let mut names = Vec::with_capacity(count.min(256));
+    for _ in 0..count {
+        let len = read_varint(&mut input)? as usize;
+        if input.len() < len {
+            return Err(invalid(format!("truncated {} file", what)));
+        }
+        let (head, rest) = input.split_at(len);
+        names.push(String::from_utf8_lossy(head).into_owned());
+        input = rest;
+    }
+    let count = read_varint(&mut input)? as usize;
+    let mut ranges = Vec::with_capacity(count.min(1 << 20));
+    let mut next = 0u128;
+    for _ in 0..count {
+        let from = next
+            .checked_add(read_varint(&mut input)?)
+            .ok_or_else(|| invalid(format!("{} range overflow", what)))?;
+        let to = from
+            .checked_add(read_varint(&mut input)?)
+            .ok_or_else(|| invalid(format!("{} range overflow", what)))?;
+        let label = L::try_from(read_varint(&mut input)?)
+            .map_err(|_| invalid(format!("{} label out of range", what)))?;
+        ranges.push((from, to, label));
+        next = to.saturating_add(1);
+    }
+    Ok((names, ranges))
+}
@@ -0,0 +1,282 @@
+use std::fs::File;
+use std::io::{Error, Result};
+use std::sync::Arc;
+
+use ip2x_core::usage;
+use parquet::basic::Compression;
+use parquet::data_type::{
+    ByteArray, ByteArrayType, FixedLenByteArray, FixedLenByteArrayType, FloatType, Int64Type,
+};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+
+use crate::format::{subdivision_code, Dataset, Kind, ProxyRange};
+
+const ROW_GROUP_SIZE: usize = 1 << 20;
+
+enum Column {
+    Ip(Vec<u128>),
+    Float(Vec<f32>),
+    Int64(Vec<Option<i64>>),
+    Text(Vec<Option<String>>),
+}
+
+impl Column {
+    fn write(
+        &self,
+        writer: &mut parquet::file::writer::SerializedColumnWriter<'_>,
+        rows: std::ops::Range<usize>,
+    ) -> parquet::errors::Result<()> {
+        match self {
+            Column::Ip(values) => {
+                let values: Vec<FixedLenByteArray> = values[rows]
+                    .iter()
+                    .map(|v| FixedLenByteArray::from(v.to_be_bytes().to_vec()))
+                    .collect();
+                writer
+                    .typed::<FixedLenByteArrayType>()
+                    .write_batch(&values, None, None)?;
+            }
+            Column::Float(values) => {
+                writer
+                    .typed::<FloatType>()
+                    .write_batch(&values[rows], None, None)?;
+            }
+            Column::Int64(values) => {
+                let (levels, present) = split_optional(&values[rows], |v| *v);
+                writer
+                    .typed::<Int64Type>()
+                    .write_batch(&present, Some(&levels), None)?;
+            }
+            Column::Text(values) => {
+                let (levels, present) =
+                    split_optional(&values[rows], |v| ByteArray::from(v.as_str()));
+                writer
+                    .typed::<ByteArrayType>()
+                    .write_batch(&present, Some(&levels), None)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn split_optional<T, U>(values: &[Option<T>], convert: impl Fn(&T) -> U) -> (Vec<i16>, Vec<U>) {
+    let mut levels = Vec::with_capacity(values.len());
+    let mut present = Vec::with_capacity(values.len());
+    for value in values {
+        match value {
+            Some(v) => {
+                levels.push(1);
+                present.push(convert(v));
+            }
+            None => levels.push(0),
+        }
+    }
+    (levels, present)
+}
+
+fn text(value: &str) -> Option<String> {
+    if value.is_empty() || value == "-" {
+        None
+    } else {
+        Some(value.to_string())
+    }
+}
+
+fn code(bytes: &[u8; 2]) -> Option<String> {
+    text(&String::from_utf8_lossy(bytes)).filter(|c| c != "--")
+}
+
+fn table(dataset: &Dataset) -> (&'static str, Vec<Column>) {
+    match dataset {
+        Dataset::Geo(ranges) => (
+            "message geo {
+                REQUIRED FIXED_LEN_BYTE_ARRAY (16) start_ip;
+                REQUIRED FIXED_LEN_BYTE_ARRAY (16) end_ip;
+                REQUIRED FLOAT latitude;
+                REQUIRED FLOAT longitude;
+                OPTIONAL INT64 accuracy_radius;
+            }",
+            vec![
+                Column::Ip(ranges.iter().map(|r| r.0).collect()),
+                Column::Ip(ranges.iter().map(|r| r.1).collect()),
+                Column::Float(ranges.iter().map(|r| r.2).collect()),
+                Column::Float(ranges.iter().map(|r| r.3).collect()),
+                Column::Int64(ranges.iter().map(|r| r.4.map(i64::from)).collect()),
+            ],
+        ),
+        Dataset::Proxy(types) => {
+            let rows: Vec<(&String, &ProxyRange)> = types
+                .iter()
+                .flat_map(|(name, ranges)| ranges.iter().map(move |r| (name, r)))
+                .collect();
+            (
+                "message proxy_types {
+                    REQUIRED FIXED_LEN_BYTE_ARRAY (16) start_ip;
+                    REQUIRED FIXED_LEN_BYTE_ARRAY (16) end_ip;
+                    OPTIONAL BYTE_ARRAY proxy_type (UTF8);
+                    OPTIONAL INT64 confidence;
+                }",
+                vec![
+                    Column::Ip(rows.iter().map(|(_, r)| r.0).collect()),
+                    Column::Ip(rows.iter().map(|(_, r)| r.1).collect()),
+                    Column::Text(rows.iter().map(|(name, _)| text(name)).collect()),
+                    Column::Int64(rows.iter().map(|(_, r)| Some(r.2 as i64)).collect()),
+                ],
+            )
+        }
+        Dataset::Asn(strings, data) => {
+            let string = |idx: usize| strings.get(idx).and_then(|s| text(s));
+            (
+                "message asn {
+                    REQUIRED FIXED_LEN_BYTE_ARRAY (16) start_ip;
+                    REQUIRED FIXED_LEN_BYTE_ARRAY (16) end_ip;
+                    OPTIONAL BYTE_ARRAY cidr (UTF8);
+                    OPTIONAL INT64 asn;
+                    OPTIONAL BYTE_ARRAY as_name (UTF8);
+                }",
+                vec![
+                    Column::Ip(data.iter().map(|r| r.0).collect()),
+                    Column::Ip(data.iter().map(|r| r.1).collect()),
+                    Column::Text(data.iter().map(|r| string(r.2)).collect()),
+                    Column::Int64(
+                        data.iter()
+                            .map(|r| string(r.3).and_then(|s| s.parse().ok()))
+                            .collect(),
+                    ),
+                    Column::Text(data.iter().map(|r| string(r.4)).collect()),
+                ],
+            )
+        }
+        Dataset::Isp(strings, data) | Dataset::Mobile(strings, data) => {
+            let string = |idx: usize| {
+                idx.checked_sub(1)
+                    .and_then(|i| strings.get(i))
+                    .and_then(|s| text(s))
+            };
+            (
+                match dataset {
+                    Dataset::Mobile(..) => {
+                        "message mobile {
+                            REQUIRED FIXED_LEN_BYTE_ARRAY (16) start_ip;
+                            REQUIRED FIXED_LEN_BYTE_ARRAY (16) end_ip;
+                            OPTIONAL BYTE_ARRAY mcc (UTF8);
+                            OPTIONAL BYTE_ARRAY mnc (UTF8);
+                            OPTIONAL BYTE_ARRAY mobile_brand (UTF8);
+                        }"
+                    }
+                    _ => {
+                        "message isp {
+                            REQUIRED FIXED_LEN_BYTE_ARRAY (16) start_ip;
+                            REQUIRED FIXED_LEN_BYTE_ARRAY (16) end_ip;
+                            OPTIONAL BYTE_ARRAY isp (UTF8);
+                            OPTIONAL BYTE_ARRAY domain (UTF8);
+                            OPTIONAL BYTE_ARRAY provider (UTF8);
+                        }"
+                    }
+                },
+                vec![
+                    Column::Ip(data.iter().map(|r| r.0).collect()),
+                    Column::Ip(data.iter().map(|r| r.1).collect()),
+                    Column::Text(data.iter().map(|r| string(r.2)).collect()),
+                    Column::Text(data.iter().map(|r| string(r.3)).collect()),
+                    Column::Text(data.iter().map(|r| string(r.4)).collect()),
+                ],
+            )
+        }
+        Dataset::Usage(ranges) => (
+            "message usage_type {
+                REQUIRED FIXED_LEN_BYTE_ARRAY (16) start_ip;
+                REQUIRED FIXED_LEN_BYTE_ARRAY (16) end_ip;
+                OPTIONAL BYTE_ARRAY usage_type (UTF8);
+            }",
+            vec![
+                Column::Ip(ranges.iter().map(|r| r.0).collect()),
+                Column::Ip(ranges.iter().map(|r| r.1).collect()),
+                Column::Text(ranges.iter().map(|r| text(&usage::names(r.2))).collect()),
+            ],
+        ),
+        Dataset::Elevation(ranges) => (
+            "message elevation {
+                REQUIRED FIXED_LEN_BYTE_ARRAY (16) start_ip;
+                REQUIRED FIXED_LEN_BYTE_ARRAY (16) end_ip;
+                OPTIONAL INT64 elevation;
+            }",
+            vec![
+                Column::Ip(ranges.iter().map(|r| r.0).collect()),
+                Column::Ip(ranges.iter().map(|r| r.1).collect()),
+                Column::Int64(ranges.iter().map(|r| Some(r.2 as i64)).collect()),
+            ],
+        ),
+        Dataset::Country(ranges) => (
+            "message country {
+                REQUIRED FIXED_LEN_BYTE_ARRAY (16) start_ip;
+                REQUIRED FIXED_LEN_BYTE_ARRAY (16) end_ip;
+                OPTIONAL BYTE_ARRAY country_code (UTF8);
+                OPTIONAL BYTE_ARRAY continent_code (UTF8);
+                OPTIONAL INT64 is_in_european_union;
+                OPTIONAL BYTE_ARRAY alternate_country_code (UTF8);
+                OPTIONAL BYTE_ARRAY alternate_country_type (UTF8);
+                OPTIONAL BYTE_ARRAY subdivision_code (UTF8);
+            }",
+            vec![
+                Column::Ip(ranges.iter().map(|r| r.0).collect()),
+                Column::Ip(ranges.iter().map(|r| r.1).collect()),
+                Column::Text(ranges.iter().map(|r| code(&r.2)).collect()),
+                Column::Text(ranges.iter().map(|r| code(&r.3)).collect()),
+                Column::Int64(ranges.iter().map(|r| Some(r.4 as i64)).collect()),
+                Column::Text(
+                    ranges
+                        .iter()
+                        .map(|r| r.5.and_then(|a| code(&a.1)))
+                        .collect(),
+                ),
+                Column::Text(
+                    ranges
+                        .iter()
+                        .map(|r| r.5.map(|a| a.0.name().to_string()))
+                        .collect(),
+                ),
+                Column::Text(ranges.iter().map(subdivision_code).collect()),
+            ],
+        ),
+    }
+}
+
+pub fn file_name(kind: Kind) -> String {
+    format!("{}.parquet", kind.name())
+}
+
+pub fn write(path: &str, dataset: &Dataset) -> Result<()> {
+    write_file(path, dataset).map_err(Error::other)
+}
+
+fn write_file(path: &str, dataset: &Dataset) -> parquet::errors::Result<()> {
+    let (schema, columns) = table(dataset);
+    let schema = Arc::new(parse_message_type(schema)?);
+    let props = Arc::new(
+        WriterProperties::builder()
+            .set_compression(Compression::UNCOMPRESSED)
+            .build(),
+    );
+
+    let file = File::create(path)?;
+    let mut writer = SerializedFileWriter::new(file, schema, props)?;
+    let rows = dataset.len();
+
+    for start in (0..rows.max(1)).step_by(ROW_GROUP_SIZE) {
+        let end = (start + ROW_GROUP_SIZE).min(rows);
+        let mut row_group = writer.next_row_group()?;
+        let mut index = 0;
+        while let Some(mut column) = row_group.next_column()? {
+            columns[index].write(&mut column, start..end)?;
+            column.close()?;
+            index += 1;
+        }
+        row_group.close()?;
+    }
+
+    writer.close()?;
+    Ok(())
+}
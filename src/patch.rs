@@ -0,0 +1,452 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fs::{self, File};
+use std::io::{BufWriter, Error, ErrorKind, Result, Write};
+use std::path::{Component, Path};
+
+use ip2x_core::varint::{read_signed_varint, read_varint};
+use sha2::{Digest, Sha256};
+
+use crate::cli::Args;
+use crate::encoding::{write_signed_varint, write_varint};
+
+const MAGIC: &[u8; 4] = b"IP2P";
+const BLOCK: usize = 32;
+const BASE: u64 = 0x100000001b3;
+const KEEP: u8 = 0;
+const REMOVE: u8 = 1;
+const FULL: u8 = 2;
+const DELTA: u8 = 3;
+const COPY: u128 = 0;
+const INSERT: u128 = 1;
+const END: u128 = 2;
+const STAGED: &str = ".ip2xp.tmp";
+
+enum Op<'a> {
+    Copy(usize, usize),
+    Insert(&'a [u8]),
+}
+
+enum Change<'a> {
+    Keep([u8; 32]),
+    Remove,
+    Full(&'a [u8]),
+    Delta([u8; 32], [u8; 32], usize, Vec<Op<'a>>),
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+pub fn run(args: &Args) -> Result<()> {
+    match args.positional() {
+        [command, old, new] if command == "create" => {
+            create(old, new, args.require(&["-o", "--output"])?)
+        }
+        [command, patch, dir] if command == "apply" => {
+            apply(patch, dir, args.value(&["-o", "--output"]).unwrap_or(dir))
+        }
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Usage: ip2x patch create <old-dir> <new-dir> -o <update.ip2xp>\n       ip2x patch apply <update.ip2xp> <dir> [-o <out-dir>]",
+        )),
+    }
+}
+
+fn files(dir: &Path) -> Result<BTreeSet<String>> {
+    let mut found = BTreeSet::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(path) = pending.pop() {
+        for entry in fs::read_dir(&path)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else if let Ok(relative) = path.strip_prefix(dir) {
+                let parts: Vec<_> = relative.iter().map(|part| part.to_string_lossy()).collect();
+                found.insert(parts.join("/"));
+            }
+        }
+    }
+    Ok(found)
+}
+
+fn sha256(bytes: &[u8]) -> [u8; 32] {
+    Sha256::digest(bytes).into()
+}
+
+fn hash(block: &[u8]) -> u64 {
+    block
+        .iter()
+        .fold(0u64, |h, &b| h.wrapping_mul(BASE).wrapping_add(b as u64))
+}
+
+fn diff<'a>(old: &[u8], new: &'a [u8]) -> Vec<Op<'a>> {
+    let mut blocks: HashMap<u64, usize> = HashMap::new();
+    for (i, block) in old.chunks_exact(BLOCK).enumerate() {
+        blocks.entry(hash(block)).or_insert(i * BLOCK);
+    }
+    let outgoing = (1..BLOCK).fold(1u64, |p, _| p.wrapping_mul(BASE));
+    let matches =
+        |offset: usize, at: usize| old.get(offset..offset + BLOCK) == new.get(at..at + BLOCK);
+
+    let mut ops = Vec::new();
+    let (mut literal, mut at, mut expected) = (0, 0, 0);
+    let mut rolling = None;
+    while at + BLOCK <= new.len() {
+        let h = rolling.unwrap_or_else(|| hash(&new[at..at + BLOCK]));
+        let found = [Some(expected), blocks.get(&h).copied()]
+            .into_iter()
+            .flatten()
+            .find(|&offset| matches(offset, at));
+        let Some(mut offset) = found else {
+            rolling = new.get(at + BLOCK).map(|&incoming| {
+                h.wrapping_sub((new[at] as u64).wrapping_mul(outgoing))
+                    .wrapping_mul(BASE)
+                    .wrapping_add(incoming as u64)
+            });
+            at += 1;
+            continue;
+        };
+        let mut start = at;
+        while start > literal && offset > 0 && new[start - 1] == old[offset - 1] {
+            start -= 1;
+            offset -= 1;
+        }
+        let mut end = at + BLOCK;
+        while end < new.len()
+            && offset + end - start < old.len()
+            && new[end] == old[offset + end - start]
+        {
+            end += 1;
+        }
+        if literal < start {
+            ops.push(Op::Insert(&new[literal..start]));
+        }
+        ops.push(Op::Copy(offset, end - start));
+        (literal, at, expected) = (end, end, offset + end - start);
+        rolling = None;
+    }
+    if literal < new.len() {
+        ops.push(Op::Insert(&new[literal..]));
+    }
+    ops
+}
+
+fn write_text(out: &mut impl Write, text: &str) -> Result<()> {
+    write_varint(out, text.len() as u128)?;
+    out.write_all(text.as_bytes())
+}
+
+fn create(old_dir: &str, new_dir: &str, output: &str) -> Result<()> {
+    let (old_dir, new_dir) = (Path::new(old_dir), Path::new(new_dir));
+    let old_files = files(old_dir)?;
+    let new_files = files(new_dir)?;
+    let removed: Vec<&String> = old_files.difference(&new_files).collect();
+
+    let mut out = BufWriter::new(File::create(output)?);
+    out.write_all(MAGIC)?;
+    write_varint(&mut out, (new_files.len() + removed.len()) as u128)?;
+    let mut counts = [0usize; 4];
+    for name in &new_files {
+        let new = fs::read(new_dir.join(name))?;
+        write_text(&mut out, name)?;
+        let old = match old_files.contains(name) {
+            true => Some(fs::read(old_dir.join(name))?),
+            false => None,
+        };
+        match old {
+            Some(old) if old == new => {
+                out.write_all(&[KEEP])?;
+                out.write_all(&sha256(&new))?;
+                counts[KEEP as usize] += 1;
+            }
+            Some(old) => {
+                out.write_all(&[DELTA])?;
+                out.write_all(&sha256(&old))?;
+                out.write_all(&sha256(&new))?;
+                write_varint(&mut out, new.len() as u128)?;
+                let mut cursor = 0;
+                for op in diff(&old, &new) {
+                    match op {
+                        Op::Copy(offset, len) => {
+                            write_varint(&mut out, COPY)?;
+                            write_signed_varint(&mut out, offset as i64 - cursor as i64)?;
+                            write_varint(&mut out, len as u128)?;
+                            cursor = offset + len;
+                        }
+                        Op::Insert(bytes) => {
+                            write_varint(&mut out, INSERT)?;
+                            write_varint(&mut out, bytes.len() as u128)?;
+                            out.write_all(bytes)?;
+                        }
+                    }
+                }
+                write_varint(&mut out, END)?;
+                counts[DELTA as usize] += 1;
+            }
+            None => {
+                out.write_all(&[FULL])?;
+                write_varint(&mut out, new.len() as u128)?;
+                out.write_all(&new)?;
+                counts[FULL as usize] += 1;
+            }
+        }
+    }
+    for name in &removed {
+        write_text(&mut out, name)?;
+        out.write_all(&[REMOVE])?;
+    }
+    out.flush()?;
+
+    println!(
+        "Wrote {} ({} bytes): {} changed, {} added, {} removed, {} unchanged",
+        output,
+        fs::metadata(output)?.len(),
+        counts[DELTA as usize],
+        counts[FULL as usize],
+        removed.len(),
+        counts[KEEP as usize]
+    );
+    Ok(())
+}
+
+fn parse<'a>(mut input: &'a [u8], path: &str) -> Result<Vec<(String, Change<'a>)>> {
+    let truncated = || invalid(format!("{}: truncated patch", path));
+    let take = |input: &mut &'a [u8], len: usize| -> Result<&'a [u8]> {
+        if input.len() < len {
+            return Err(truncated());
+        }
+        let (head, rest) = input.split_at(len);
+        *input = rest;
+        Ok(head)
+    };
+    let digest =
+        |input: &mut &'a [u8]| -> Result<[u8; 32]> { Ok(take(input, 32)?.try_into().unwrap()) };
+
+    let count = read_varint(&mut input)? as usize;
+    let mut changes = Vec::with_capacity(count.min(1 << 16));
+    for _ in 0..count {
+        let len = read_varint(&mut input)? as usize;
+        let name = String::from_utf8_lossy(take(&mut input, len)?).into_owned();
+        let unsafe_name = Path::new(&name)
+            .components()
+            .any(|part| !matches!(part, Component::Normal(_)));
+        if name.is_empty() || unsafe_name {
+            return Err(invalid(format!("{}: unsafe file name '{}'", path, name)));
+        }
+        let change = match take(&mut input, 1)?[0] {
+            KEEP => Change::Keep(digest(&mut input)?),
+            REMOVE => Change::Remove,
+            FULL => {
+                let len = read_varint(&mut input)? as usize;
+                Change::Full(take(&mut input, len)?)
+            }
+            DELTA => {
+                let (old, new) = (digest(&mut input)?, digest(&mut input)?);
+                let len = read_varint(&mut input)? as usize;
+                let mut ops = Vec::new();
+                let mut cursor = 0i64;
+                loop {
+                    match read_varint(&mut input)? {
+                        COPY => {
+                            let offset = cursor + read_signed_varint(&mut input)?;
+                            let len = read_varint(&mut input)? as usize;
+                            let offset = usize::try_from(offset)
+                                .map_err(|_| invalid(format!("{}: negative copy offset", path)))?;
+                            ops.push(Op::Copy(offset, len));
+                            cursor = (offset + len) as i64;
+                        }
+                        INSERT => {
+                            let len = read_varint(&mut input)? as usize;
+                            ops.push(Op::Insert(take(&mut input, len)?));
+                        }
+                        END => break,
+                        op => return Err(invalid(format!("{}: unknown patch op {}", path, op))),
+                    }
+                }
+                Change::Delta(old, new, len, ops)
+            }
+            kind => return Err(invalid(format!("{}: unknown change {}", path, kind))),
+        };
+        changes.push((name, change));
+    }
+    Ok(changes)
+}
+
+fn rebuild(old: &[u8], len: usize, ops: &[Op]) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(len);
+    for op in ops {
+        match *op {
+            Op::Copy(offset, len) => {
+                out.extend_from_slice(old.get(offset..offset.checked_add(len)?)?)
+            }
+            Op::Insert(bytes) => out.extend_from_slice(bytes),
+        }
+    }
+    Some(out)
+}
+
+fn apply(patch: &str, dir: &str, output: &str) -> Result<()> {
+    let bytes = fs::read(patch)?;
+    let input = bytes
+        .strip_prefix(MAGIC)
+        .ok_or_else(|| invalid(format!("{}: not an ip2x patch", patch)))?;
+    let changes = parse(input, patch)?;
+    let (dir, output) = (Path::new(dir), Path::new(output));
+    let in_place = dir == output;
+
+    for (name, change) in &changes {
+        let expected = match change {
+            Change::Keep(digest) | Change::Delta(digest, ..) => digest,
+            _ => continue,
+        };
+        let path = dir.join(name);
+        if sha256(&fs::read(&path)?) != *expected {
+            return Err(invalid(format!(
+                "{} does not match the version this patch was made from",
+                path.display()
+            )));
+        }
+    }
+
+    let mut staged = Vec::new();
+    let result = changes.iter().try_for_each(|(name, change)| {
+        let contents = match change {
+            Change::Keep(_) if in_place => return Ok(()),
+            Change::Remove => return Ok(()),
+            Change::Keep(_) => fs::read(dir.join(name))?,
+            Change::Full(bytes) => bytes.to_vec(),
+            Change::Delta(_, digest, len, ops) => {
+                let old = fs::read(dir.join(name))?;
+                rebuild(&old, *len, ops)
+                    .filter(|new| sha256(new) == *digest)
+                    .ok_or_else(|| {
+                        invalid(format!("{}: patch does not reproduce {}", patch, name))
+                    })?
+            }
+        };
+        let path = output.join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let temp = output.join(format!("{}{}", name, STAGED));
+        staged.push((temp.clone(), path));
+        fs::write(temp, contents)
+    });
+    if let Err(err) = result {
+        for (temp, _) in &staged {
+            let _ = fs::remove_file(temp);
+        }
+        return Err(err);
+    }
+    for (temp, path) in &staged {
+        fs::rename(temp, path)?;
+    }
+
+    let mut counts = [0usize; 4];
+    for (name, change) in &changes {
+        let kind = match change {
+            Change::Keep(_) => KEEP,
+            Change::Remove => REMOVE,
+            Change::Full(_) => FULL,
+            Change::Delta(..) => DELTA,
+        };
+        counts[kind as usize] += 1;
+        if in_place && kind == REMOVE {
+            match fs::remove_file(dir.join(name)) {
+                Err(err) if err.kind() != ErrorKind::NotFound => return Err(err),
+                _ => {}
+            }
+        }
+    }
+    println!(
+        "Applied {} to {}: {} changed, {} added, {} removed, {} unchanged",
+        patch,
+        output.display(),
+        counts[DELTA as usize],
+        counts[FULL as usize],
+        counts[REMOVE as usize],
+        counts[KEEP as usize]
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build(dir: &Path, rows: impl Iterator<Item = u32>, options: &[&str]) -> String {
+        let data = dir.join("data");
+        fs::create_dir_all(&data).unwrap();
+        let csv: String = rows
+            .map(|row| {
+                let start = 16777216 + row * 256;
+                format!(
+                    "\"{}\",\"{}\",\"AU\",\"-\",\"-\",\"-\",\"-27.{}\",\"153.{}\"\n",
+                    start,
+                    start + 255,
+                    row,
+                    row % 7
+                )
+            })
+            .collect();
+        fs::write(data.join("IP2LOCATION-LITE-DB5.CSV"), csv).unwrap();
+        let out = dir.join("bins").to_string_lossy().into_owned();
+        let mut raw = vec![data.to_string_lossy().into_owned(), "--out-dir".to_string()];
+        raw.extend(
+            [out.as_str(), "--quiet"]
+                .into_iter()
+                .chain(options.iter().copied())
+                .map(str::to_string),
+        );
+        crate::build(&Args::parse(&raw, crate::BUILD_SWITCHES)).unwrap();
+        out
+    }
+
+    fn copy(from: &str, to: &Path) {
+        for name in files(Path::new(from)).unwrap() {
+            let path = to.join(&name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::copy(Path::new(from).join(&name), path).unwrap();
+        }
+    }
+
+    fn assert_same(expected: &str, actual: &Path) {
+        let names = files(Path::new(expected)).unwrap();
+        assert_eq!(files(actual).unwrap(), names);
+        for name in names {
+            let expected = fs::read(Path::new(expected).join(&name)).unwrap();
+            assert!(
+                fs::read(actual.join(&name)).unwrap() == expected,
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn applied_patch_reproduces_the_new_build() {
+        let root = std::env::temp_dir().join(format!("ip2x-patch-{}", std::process::id()));
+        let old = build(&root.join("old"), 0..400, &["--country-index"]);
+        let new = build(
+            &root.join("new"),
+            (0..400).filter(|row| row % 50 != 7).chain(500..520),
+            &["--shard-bits", "2"],
+        );
+        let patch = root.join("update.ip2xp").to_string_lossy().into_owned();
+        create(&old, &new, &patch).unwrap();
+
+        let output = root.join("output");
+        apply(&patch, &old, &output.to_string_lossy()).unwrap();
+        assert_same(&new, &output);
+
+        let in_place = root.join("in-place");
+        copy(&old, &in_place);
+        let in_place_dir = in_place.to_string_lossy().into_owned();
+        apply(&patch, &in_place_dir, &in_place_dir).unwrap();
+        assert_same(&new, &in_place);
+
+        let err = apply(&patch, &new, &root.join("again").to_string_lossy()).err();
+        assert_eq!(err.map(|err| err.kind()), Some(ErrorKind::InvalidData));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,407 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Error, ErrorKind, Read, Result, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use serde_json::Value;
+
+use crate::cli::Args;
+use crate::csv_writer::quote;
+use crate::enrich::{self, COLUMNS};
+use crate::reader::{open_from_args, Ip2xReader};
+
+const PCAP_MICROS: u32 = 0xa1b2_c3d4;
+const PCAP_NANOS: u32 = 0xa1b2_3c4d;
+const PCAPNG_SECTION: u32 = 0x0a0d_0d0a;
+const PCAPNG_BYTE_ORDER: u32 = 0x1a2b_3c4d;
+const PCAPNG_INTERFACE: u32 = 1;
+const PCAPNG_SIMPLE_PACKET: u32 = 3;
+const PCAPNG_ENHANCED_PACKET: u32 = 6;
+const OPTION_TSRESOL: u16 = 9;
+
+const LINK_NULL: u16 = 0;
+const LINK_ETHERNET: u16 = 1;
+const LINK_RAW: u16 = 101;
+const LINK_LOOP: u16 = 108;
+const LINK_LINUX_SLL: u16 = 113;
+const LINK_IPV4: u16 = 228;
+const LINK_IPV6: u16 = 229;
+const LINK_LINUX_SLL2: u16 = 276;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const ETHERTYPE_VLAN: [u16; 2] = [0x8100, 0x88a8];
+const IPV6_EXTENSIONS: [u8; 3] = [0, 43, 60];
+const IPV6_FRAGMENT: u8 = 44;
+const IPV6_AUTH: u8 = 51;
+const FLOW_COLUMNS: [&str; 9] = [
+    "first_seen",
+    "last_seen",
+    "protocol",
+    "src",
+    "src_port",
+    "dst",
+    "dst_port",
+    "packets",
+    "bytes",
+];
+
+type Endpoint = (IpAddr, Option<u16>);
+
+struct Flow {
+    first_seen: f64,
+    last_seen: f64,
+    protocol: u8,
+    src: Endpoint,
+    dst: Endpoint,
+    packets: u64,
+    bytes: u64,
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+fn u16_at(bytes: &[u8], at: usize, big: bool) -> Option<u16> {
+    let raw = bytes.get(at..at + 2)?.try_into().ok()?;
+    Some(if big {
+        u16::from_be_bytes(raw)
+    } else {
+        u16::from_le_bytes(raw)
+    })
+}
+
+fn u32_at(bytes: &[u8], at: usize, big: bool) -> Option<u32> {
+    let raw = bytes.get(at..at + 4)?.try_into().ok()?;
+    Some(if big {
+        u32::from_be_bytes(raw)
+    } else {
+        u32::from_le_bytes(raw)
+    })
+}
+
+fn read_exact_or_eof(input: &mut impl Read, buffer: &mut [u8]) -> Result<bool> {
+    match input.read_exact(buffer) {
+        Ok(()) => Ok(true),
+        Err(err) if err.kind() == ErrorKind::UnexpectedEof => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+fn read_pcap(
+    input: &mut impl Read,
+    magic: [u8; 4],
+    visit: &mut impl FnMut(f64, u16, &[u8], u32),
+) -> Result<()> {
+    let (big, nanos) = match (u32::from_le_bytes(magic), u32::from_be_bytes(magic)) {
+        (PCAP_MICROS, _) => (false, false),
+        (PCAP_NANOS, _) => (false, true),
+        (_, PCAP_MICROS) => (true, false),
+        (_, PCAP_NANOS) => (true, true),
+        _ => return Err(invalid("Not a pcap or pcapng capture")),
+    };
+    let mut header = [0u8; 20];
+    input.read_exact(&mut header)?;
+    let link = u32_at(&header, 16, big).unwrap_or_default() as u16;
+    let divisor = if nanos { 1e9 } else { 1e6 };
+
+    let mut record = [0u8; 16];
+    let mut data = Vec::new();
+    while read_exact_or_eof(input, &mut record)? {
+        let field = |at| u32_at(&record, at, big).unwrap_or_default();
+        data.resize(field(8) as usize, 0);
+        input.read_exact(&mut data)?;
+        visit(
+            field(0) as f64 + field(4) as f64 / divisor,
+            link,
+            &data,
+            field(12),
+        );
+    }
+    Ok(())
+}
+
+fn tsresol(options: &[u8], big: bool) -> f64 {
+    let mut at = 0;
+    while let (Some(code), Some(len)) = (u16_at(options, at, big), u16_at(options, at + 2, big)) {
+        if code == OPTION_TSRESOL && len == 1 {
+            let value = options.get(at + 4).copied().unwrap_or(6);
+            return match value & 0x80 {
+                0 => 10f64.powi((value & 0x7f) as i32),
+                _ => 2f64.powi((value & 0x7f) as i32),
+            };
+        }
+        if code == 0 {
+            break;
+        }
+        at += 4 + (len as usize).div_ceil(4) * 4;
+    }
+    1e6
+}
+
+fn read_pcapng(input: &mut impl Read, visit: &mut impl FnMut(f64, u16, &[u8], u32)) -> Result<()> {
+    let mut big = false;
+    let mut interfaces: Vec<(u16, f64)> = Vec::new();
+    let mut kind = PCAPNG_SECTION.to_le_bytes();
+    let mut length = [0u8; 4];
+    let mut body = Vec::new();
+    loop {
+        input.read_exact(&mut length)?;
+        body.clear();
+        let kind_id = u32_at(&kind, 0, big).unwrap_or_default();
+        if kind_id == PCAPNG_SECTION {
+            let mut order = [0u8; 4];
+            input.read_exact(&mut order)?;
+            big = u32::from_be_bytes(order) == PCAPNG_BYTE_ORDER;
+            interfaces.clear();
+            body.extend_from_slice(&order);
+        }
+        let length = u32_at(&length, 0, big).unwrap_or_default() as usize;
+        if length < 12 + body.len() || !length.is_multiple_of(4) {
+            return Err(invalid("Malformed pcapng block"));
+        }
+        let start = body.len();
+        body.resize(length - 8, 0);
+        input.read_exact(&mut body[start..])?;
+        let block = &body[..length - 12];
+        match kind_id {
+            PCAPNG_INTERFACE => {
+                let link = u16_at(block, 0, big).unwrap_or_default();
+                interfaces.push((link, tsresol(block.get(8..).unwrap_or_default(), big)));
+            }
+            PCAPNG_ENHANCED_PACKET => {
+                let field = |at| u32_at(block, at, big).unwrap_or_default();
+                let Some(&(link, resolution)) = interfaces.get(field(0) as usize) else {
+                    return Err(invalid("pcapng packet for an undeclared interface"));
+                };
+                let ticks = (field(4) as u64) << 32 | field(8) as u64;
+                let captured = (field(12) as usize).min(block.len().saturating_sub(20));
+                visit(
+                    ticks as f64 / resolution,
+                    link,
+                    &block[20..20 + captured],
+                    field(16),
+                );
+            }
+            PCAPNG_SIMPLE_PACKET => {
+                let Some(&(link, _)) = interfaces.first() else {
+                    return Err(invalid("pcapng packet for an undeclared interface"));
+                };
+                let original = u32_at(block, 0, big).unwrap_or_default();
+                let captured = (original as usize).min(block.len().saturating_sub(4));
+                visit(0.0, link, &block[4..4 + captured], original);
+            }
+            _ => {}
+        }
+        if !read_exact_or_eof(input, &mut kind)? {
+            return Ok(());
+        }
+    }
+}
+
+fn ip_packet(link: u16, frame: &[u8]) -> Option<&[u8]> {
+    let (ethertype, payload) = match link {
+        LINK_ETHERNET => {
+            let mut at = 12;
+            let mut ethertype = u16_at(frame, at, true)?;
+            while ETHERTYPE_VLAN.contains(&ethertype) {
+                at += 4;
+                ethertype = u16_at(frame, at, true)?;
+            }
+            (Some(ethertype), frame.get(at + 2..)?)
+        }
+        LINK_LINUX_SLL => (u16_at(frame, 14, true), frame.get(16..)?),
+        LINK_LINUX_SLL2 => (u16_at(frame, 0, true), frame.get(20..)?),
+        LINK_NULL | LINK_LOOP => (None, frame.get(4..)?),
+        LINK_RAW | LINK_IPV4 | LINK_IPV6 => (None, frame),
+        _ => return None,
+    };
+    match ethertype {
+        None | Some(ETHERTYPE_IPV4 | ETHERTYPE_IPV6) => Some(payload),
+        Some(_) => None,
+    }
+}
+
+fn parse_ip(packet: &[u8]) -> Option<(u8, Endpoint, Endpoint)> {
+    let (protocol, src, dst, transport) = match packet.first()? >> 4 {
+        4 => {
+            let header = ((packet[0] & 0x0f) as usize) * 4;
+            let src: [u8; 4] = packet.get(12..16)?.try_into().ok()?;
+            let dst: [u8; 4] = packet.get(16..20)?.try_into().ok()?;
+            let fragment = u16_at(packet, 6, true)? & 0x1fff;
+            let transport = packet.get(header..).filter(|_| fragment == 0);
+            (
+                packet[9],
+                IpAddr::V4(Ipv4Addr::from(src)),
+                IpAddr::V4(Ipv4Addr::from(dst)),
+                transport,
+            )
+        }
+        6 => {
+            let src: [u8; 16] = packet.get(8..24)?.try_into().ok()?;
+            let dst: [u8; 16] = packet.get(24..40)?.try_into().ok()?;
+            let mut next = packet[6];
+            let mut at = 40;
+            let mut first_fragment = true;
+            while IPV6_EXTENSIONS.contains(&next) || [IPV6_FRAGMENT, IPV6_AUTH].contains(&next) {
+                let header = packet.get(at..at + 2)?;
+                if next == IPV6_FRAGMENT {
+                    first_fragment = u16_at(packet, at + 2, true)? & 0xfff8 == 0;
+                    at += 8;
+                } else if next == IPV6_AUTH {
+                    at += (header[1] as usize + 2) * 4;
+                } else {
+                    at += (header[1] as usize + 1) * 8;
+                }
+                next = header[0];
+            }
+            (
+                next,
+                IpAddr::V6(Ipv6Addr::from(src)),
+                IpAddr::V6(Ipv6Addr::from(dst)),
+                packet.get(at..).filter(|_| first_fragment),
+            )
+        }
+        _ => return None,
+    };
+    let ports = transport
+        .filter(|_| matches!(protocol, 6 | 17 | 132))
+        .and_then(|t| Some((u16_at(t, 0, true)?, u16_at(t, 2, true)?)));
+    Some((
+        protocol,
+        (src, ports.map(|p| p.0)),
+        (dst, ports.map(|p| p.1)),
+    ))
+}
+
+fn protocol_name(protocol: u8) -> String {
+    match protocol {
+        1 => "icmp".to_string(),
+        6 => "tcp".to_string(),
+        17 => "udp".to_string(),
+        58 => "icmpv6".to_string(),
+        132 => "sctp".to_string(),
+        other => other.to_string(),
+    }
+}
+
+fn flows(path: &str) -> Result<Vec<Flow>> {
+    let mut input = BufReader::new(File::open(path)?);
+    let mut flows: Vec<Flow> = Vec::new();
+    let mut index: HashMap<(u8, Endpoint, Endpoint), usize> = HashMap::new();
+    let mut visit = |time: f64, link: u16, frame: &[u8], length: u32| {
+        let Some((protocol, src, dst)) = ip_packet(link, frame).and_then(parse_ip) else {
+            return;
+        };
+        let key = (protocol, src.min(dst), src.max(dst));
+        let i = *index.entry(key).or_insert_with(|| {
+            flows.push(Flow {
+                first_seen: time,
+                last_seen: time,
+                protocol,
+                src,
+                dst,
+                packets: 0,
+                bytes: 0,
+            });
+            flows.len() - 1
+        });
+        let flow = &mut flows[i];
+        flow.first_seen = flow.first_seen.min(time);
+        flow.last_seen = flow.last_seen.max(time);
+        flow.packets += 1;
+        flow.bytes += length as u64;
+    };
+
+    let mut magic = [0u8; 4];
+    input.read_exact(&mut magic)?;
+    match u32::from_le_bytes(magic) {
+        PCAPNG_SECTION => read_pcapng(&mut input, &mut visit)?,
+        _ => read_pcap(&mut input, magic, &mut visit)?,
+    }
+    Ok(flows)
+}
+
+fn flow_values(flow: &Flow) -> [Value; 9] {
+    let port = |port: Option<u16>| port.map_or(Value::Null, Value::from);
+    [
+        format!("{:.6}", flow.first_seen)
+            .parse::<f64>()
+            .map_or(Value::Null, Value::from),
+        format!("{:.6}", flow.last_seen)
+            .parse::<f64>()
+            .map_or(Value::Null, Value::from),
+        protocol_name(flow.protocol).into(),
+        flow.src.0.to_string().into(),
+        port(flow.src.1),
+        flow.dst.0.to_string().into(),
+        port(flow.dst.1),
+        flow.packets.into(),
+        flow.bytes.into(),
+    ]
+}
+
+fn annotate(args: &Args, path: &str) -> Result<()> {
+    let json = match args.value(&["--format"]).unwrap_or("csv") {
+        "csv" => false,
+        "json" => true,
+        other => {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Unknown output format: {} (expected csv or json)", other),
+            ))
+        }
+    };
+    let reader = open_from_args(args)?;
+    let flows = flows(path)?;
+
+    let mut names: Vec<String> = FLOW_COLUMNS.iter().map(|name| name.to_string()).collect();
+    for side in ["src", "dst"] {
+        names.extend(COLUMNS.iter().map(|name| format!("{}_{}", side, name)));
+    }
+    let mut cache: HashMap<IpAddr, [Value; 6]> = HashMap::new();
+    let mut lookup = |reader: &Ip2xReader, ip: IpAddr| {
+        cache
+            .entry(ip)
+            .or_insert_with(|| enrich::columns(reader, Some(ip)))
+            .clone()
+    };
+
+    let mut out = BufWriter::new(io::stdout().lock());
+    if !json {
+        writeln!(out, "{}", names.join(","))?;
+    }
+    for flow in &flows {
+        let mut values = flow_values(flow).to_vec();
+        values.extend(lookup(&reader, flow.src.0));
+        values.extend(lookup(&reader, flow.dst.0));
+        if json {
+            let fields: Vec<String> = names
+                .iter()
+                .zip(&values)
+                .map(|(name, value)| format!("\"{}\":{}", name, value))
+                .collect();
+            writeln!(out, "{{{}}}", fields.join(","))?;
+        } else {
+            let fields: Vec<String> = values
+                .iter()
+                .map(|value| match value {
+                    Value::String(s) => quote(s),
+                    other => enrich::text(other).unwrap_or_default(),
+                })
+                .collect();
+            writeln!(out, "{}", fields.join(","))?;
+        }
+    }
+    out.flush()
+}
+
+pub fn run(args: &Args) -> Result<()> {
+    match args.positional() {
+        [command, path] if command == "annotate" => annotate(args, path),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Usage: ip2x pcap annotate <capture.pcap|capture.pcapng> [--format csv|json] [--bins <dir>]",
+        )),
+    }
+}
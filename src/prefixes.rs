@@ -0,0 +1,139 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Error, ErrorKind, Result, Write};
+
+use ip2x_core::varint::read_varint;
+
+use crate::encoding::write_varint;
+use crate::format::Dataset;
+use crate::interval::coalesce;
+use crate::merge::{self, Shape};
+
+pub const ASN_INDEX_FILE: &str = "asn_index.bin";
+pub const COUNTRY_INDEX_FILE: &str = "country_index.bin";
+pub const ISP_INDEX_FILE: &str = "isp_index.bin";
+const MAGIC: &[u8; 4] = b"IP2P";
+
+pub struct PrefixIndex {
+    bytes: Vec<u8>,
+    keys: Vec<(String, usize)>,
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+pub fn entries(dataset: Dataset) -> Vec<(String, u128, u128)> {
+    match dataset {
+        Dataset::Asn(strings, data) => data
+            .iter()
+            .filter_map(|r| Some((strings.get(r.3)?.clone(), r.0, r.1)))
+            .collect(),
+        Dataset::Isp(strings, data) => data
+            .iter()
+            .filter_map(|r| Some((strings.get(r.2.checked_sub(1)?)?.clone(), r.0, r.1)))
+            .collect(),
+        Dataset::Country(ranges) => {
+            match merge::reshape(Dataset::Country(ranges), Shape::Flatten) {
+                Dataset::Country(ranges) => ranges
+                    .iter()
+                    .filter(|r| &r.2 != b"--")
+                    .map(|r| (String::from_utf8_lossy(&r.2).into_owned(), r.0, r.1))
+                    .collect(),
+                _ => Vec::new(),
+            }
+        }
+        _ => Vec::new(),
+    }
+}
+
+impl PrefixIndex {
+    pub fn write(path: &str, entries: impl Iterator<Item = (String, u128, u128)>) -> Result<usize> {
+        let mut keys: BTreeMap<String, Vec<(u128, u128)>> = BTreeMap::new();
+        for (key, from, to) in entries {
+            keys.entry(key).or_default().push((from, to));
+        }
+        let mut body = Vec::new();
+        let mut directory = Vec::new();
+        for (key, ranges) in keys.iter_mut() {
+            let ranges = coalesce(std::mem::take(ranges));
+            directory.push((key, body.len()));
+            write_varint(&mut body, ranges.len() as u128)?;
+            let mut next = 0u128;
+            for (from, to) in ranges {
+                write_varint(&mut body, from - next)?;
+                write_varint(&mut body, to - from)?;
+                next = to.saturating_add(1);
+            }
+        }
+
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(MAGIC)?;
+        out.write_all(&(directory.len() as u32).to_le_bytes())?;
+        for (key, offset) in &directory {
+            write_varint(&mut out, key.len() as u128)?;
+            out.write_all(key.as_bytes())?;
+            out.write_all(&(*offset as u64).to_le_bytes())?;
+        }
+        out.write_all(&body)?;
+        out.flush()?;
+        Ok(directory.len())
+    }
+
+    pub fn read(path: &str) -> Result<PrefixIndex> {
+        let bytes = fs::read(path)?;
+        let mut input = bytes
+            .strip_prefix(MAGIC)
+            .ok_or_else(|| invalid("Not a prefix index"))?;
+        let take = |input: &mut &[u8], len: usize| -> Result<Vec<u8>> {
+            if input.len() < len {
+                return Err(invalid("Truncated prefix index"));
+            }
+            let (head, rest) = input.split_at(len);
+            *input = rest;
+            Ok(head.to_vec())
+        };
+        let count = u32::from_le_bytes(take(&mut input, 4)?.try_into().unwrap());
+        let mut keys = Vec::with_capacity(count.min(1 << 20) as usize);
+        for _ in 0..count {
+            let len = read_varint(&mut input)? as usize;
+            let key = String::from_utf8_lossy(&take(&mut input, len)?).into_owned();
+            let offset = u64::from_le_bytes(take(&mut input, 8)?.try_into().unwrap()) as usize;
+            keys.push((key, offset));
+        }
+        let body = bytes.len() - input.len();
+        let keys = keys
+            .into_iter()
+            .map(|(key, offset)| (key, body + offset))
+            .collect();
+        Ok(PrefixIndex { bytes, keys })
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.keys.iter().map(|(key, _)| key.as_str())
+    }
+
+    pub fn get(&self, key: &str) -> Result<Vec<(u128, u128)>> {
+        let Ok(i) = self.keys.binary_search_by(|(k, _)| k.as_str().cmp(key)) else {
+            return Ok(Vec::new());
+        };
+        let mut input = self
+            .bytes
+            .get(self.keys[i].1..)
+            .ok_or_else(|| invalid("Bad prefix index offset"))?;
+        let count = read_varint(&mut input)? as usize;
+        let mut ranges = Vec::with_capacity(count.min(1 << 20));
+        let mut next = 0u128;
+        for _ in 0..count {
+            let from = next
+                .checked_add(read_varint(&mut input)?)
+                .ok_or_else(|| invalid("Prefix index range overflow"))?;
+            let to = from
+                .checked_add(read_varint(&mut input)?)
+                .ok_or_else(|| invalid("Prefix index range overflow"))?;
+            ranges.push((from, to));
+            next = to.saturating_add(1);
+        }
+        Ok(ranges)
+    }
+}
@@ -0,0 +1,82 @@
+use crate::cli::Args;
+use crate::format::{Dataset, Kind, UNKNOWN_LAST_SEEN};
+use crate::interval::coalesce;
+
+const FULL_SWITCHES: [&str; 10] = [
+    "--geo-accuracy",
+    "--reverse-geocode",
+    "--asn-index",
+    "--country-index",
+    "--isp-index",
+    "--network-type",
+    "--connection-type",
+    "--domain",
+    "--isp-groups",
+    "--bloom",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    Minimal,
+    Standard,
+    Full,
+}
+
+impl Profile {
+    pub fn parse(name: &str) -> Option<Profile> {
+        match name {
+            "minimal" => Some(Profile::Minimal),
+            "standard" => Some(Profile::Standard),
+            "full" => Some(Profile::Full),
+            _ => None,
+        }
+    }
+
+    pub fn from_args(args: &Args) -> Profile {
+        args.value(&["--profile"])
+            .and_then(Profile::parse)
+            .unwrap_or(Profile::Standard)
+    }
+
+    pub fn includes(self, kind: Kind) -> bool {
+        self != Profile::Minimal || matches!(kind, Kind::Country | Kind::Proxy)
+    }
+
+    pub fn expand(self, args: &Args, has_db24: bool) -> Args {
+        if self != Profile::Full {
+            return args.clone();
+        }
+        let mut switches = Vec::new();
+        if has_db24 {
+            switches.push("--elevation");
+        }
+        if args.value(&["--format"]).unwrap_or("bin") == "bin" {
+            switches.extend(FULL_SWITCHES);
+            if cfg!(feature = "roaring") {
+                switches.push("--roaring");
+            }
+        }
+        args.with_switches(&switches)
+    }
+
+    pub fn reduce(self, dataset: Dataset) -> Dataset {
+        match (self, dataset) {
+            (Profile::Minimal, Dataset::Proxy(types)) => Dataset::Proxy(
+                types
+                    .into_iter()
+                    .map(|(name, ranges)| {
+                        let ranges = coalesce(ranges.iter().map(|r| (r.0, r.1)).collect());
+                        (
+                            name,
+                            ranges
+                                .into_iter()
+                                .map(|(from, to)| (from, to, 1, UNKNOWN_LAST_SEEN))
+                                .collect(),
+                        )
+                    })
+                    .collect(),
+            ),
+            (_, dataset) => dataset,
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use std::io::{stderr, BufReader, IsTerminal, Read, Result};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::manifest;
+use crate::storage::{self, Source};
+
+pub const QUIET: u8 = 0;
+pub const NORMAL: u8 = 1;
+pub const VERBOSE: u8 = 2;
+
+const BAR_WIDTH: usize = 30;
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+const TICKS_PER_CLOCK_CHECK: u64 = 256;
+
+static LEVEL: AtomicU8 = AtomicU8::new(NORMAL);
+static RECORDS_READ: AtomicU64 = AtomicU64::new(0);
+
+pub fn set_level(level: u8) {
+    LEVEL.store(level, Ordering::Relaxed);
+}
+
+pub fn level() -> u8 {
+    LEVEL.load(Ordering::Relaxed)
+}
+
+pub fn count_read(records: usize) {
+    RECORDS_READ.fetch_add(records as u64, Ordering::Relaxed);
+}
+
+#[cfg(feature = "tracing")]
+pub fn records_read() -> u64 {
+    RECORDS_READ.load(Ordering::Relaxed)
+}
+
+pub fn report(label: &str, count: usize, unit: &str, started: Instant) {
+    if level() >= NORMAL {
+        eprintln!(
+            "{}: {} {} in {:.1}s",
+            label,
+            count,
+            unit,
+            started.elapsed().as_secs_f64()
+        );
+    }
+}
+
+pub struct Progress {
+    label: String,
+    unit: &'static str,
+    total: u64,
+    position: u64,
+    count: u64,
+    ticks: u64,
+    started: Instant,
+    drawn: Instant,
+    live: bool,
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+}
+
+impl Progress {
+    pub fn new(label: &str, unit: &'static str, total: u64) -> Progress {
+        let now = Instant::now();
+        Progress {
+            label: label.to_string(),
+            unit,
+            total,
+            position: 0,
+            count: 0,
+            ticks: 0,
+            started: now,
+            drawn: now,
+            live: level() >= NORMAL && stderr().is_terminal(),
+            #[cfg(feature = "tracing")]
+            span: tracing::info_span!(
+                "read",
+                source = label,
+                unit,
+                count = tracing::field::Empty,
+                duration_ms = tracing::field::Empty,
+            ),
+        }
+    }
+
+    pub fn tick(&mut self, position: u64, count: u64) {
+        self.position += position;
+        self.count += count;
+        self.ticks += 1;
+        if self.live
+            && self.ticks.is_multiple_of(TICKS_PER_CLOCK_CHECK)
+            && self.drawn.elapsed() >= REDRAW_INTERVAL
+        {
+            self.drawn = Instant::now();
+            self.draw();
+        }
+    }
+
+    fn draw(&self) {
+        let filled = match self.total {
+            0 => 0,
+            total => (self.position.min(total) * BAR_WIDTH as u64 / total) as usize,
+        };
+        eprint!(
+            "\r\x1b[2K{} [{}{}] {}% {} {}",
+            self.label,
+            "#".repeat(filled),
+            "-".repeat(BAR_WIDTH - filled),
+            filled * 100 / BAR_WIDTH,
+            self.count,
+            self.unit
+        );
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        #[cfg(feature = "tracing")]
+        {
+            self.span.record("count", self.count);
+            let duration_ms = self.started.elapsed().as_millis() as u64;
+            self.span.record("duration_ms", duration_ms);
+        }
+        if self.live {
+            eprint!("\r\x1b[2K");
+        }
+        if level() >= VERBOSE {
+            report(&self.label, self.count as usize, self.unit, self.started);
+        }
+    }
+}
+
+pub struct Tracked<R> {
+    inner: R,
+    progress: Progress,
+}
+
+pub fn open(path: impl AsRef<Path>) -> Result<BufReader<Tracked<Source>>> {
+    let path = path.as_ref();
+    let (inner, size) = storage::open(path)?;
+    manifest::record_source(&path.to_string_lossy());
+    Ok(BufReader::new(Tracked {
+        inner,
+        progress: Progress::new(&path.display().to_string(), "lines", size),
+    }))
+}
+
+impl<R: Read> Read for Tracked<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let read = self.inner.read(buf)?;
+        let lines = buf[..read].iter().filter(|&&byte| byte == b'\n').count();
+        self.progress.tick(read as u64, lines as u64);
+        count_read(lines);
+        Ok(read)
+    }
+}
@@ -0,0 +1,162 @@
+use std::fs::File;
+use std::io::{BufWriter, Result, Write};
+
+use crate::encoding::write_varint;
+use crate::format::{subdivision_code, AlternateCountry, Dataset, IspRange, Kind};
+
+const VARINT: u8 = 0;
+const LENGTH_DELIMITED: u8 = 2;
+const FIXED32: u8 = 5;
+
+#[derive(Default)]
+struct Message(Vec<u8>);
+
+impl Message {
+    fn tag(&mut self, field: u32, wire_type: u8) -> Result<()> {
+        write_varint(&mut self.0, ((field << 3) | wire_type as u32) as u128)
+    }
+
+    fn uint(&mut self, field: u32, value: u64) -> Result<()> {
+        if value == 0 {
+            return Ok(());
+        }
+        self.tag(field, VARINT)?;
+        write_varint(&mut self.0, value as u128)
+    }
+
+    fn sint(&mut self, field: u32, value: i64) -> Result<()> {
+        self.uint(field, ((value << 1) ^ (value >> 63)) as u64)
+    }
+
+    fn float(&mut self, field: u32, value: f32) -> Result<()> {
+        if value == 0.0 {
+            return Ok(());
+        }
+        self.tag(field, FIXED32)?;
+        self.0.extend_from_slice(&value.to_le_bytes());
+        Ok(())
+    }
+
+    fn bytes(&mut self, field: u32, value: &[u8]) -> Result<()> {
+        self.tag(field, LENGTH_DELIMITED)?;
+        write_varint(&mut self.0, value.len() as u128)?;
+        self.0.extend_from_slice(value);
+        Ok(())
+    }
+
+    fn string(&mut self, field: u32, value: &str) -> Result<()> {
+        self.bytes(field, value.as_bytes())
+    }
+
+    fn message(&mut self, field: u32, value: &Message) -> Result<()> {
+        self.bytes(field, &value.0)
+    }
+
+    fn range(&mut self, from: u128, to: u128) -> Result<()> {
+        self.bytes(1, &from.to_be_bytes())?;
+        self.bytes(2, &to.to_be_bytes())
+    }
+}
+
+fn encode_strings(
+    database: &mut Message,
+    strings: &[String],
+    records: impl Iterator<Item = IspRange>,
+) -> Result<()> {
+    for s in strings {
+        database.string(1, s)?;
+    }
+    for (from, to, a, b, c) in records {
+        let mut record = Message::default();
+        record.range(from, to)?;
+        record.uint(3, a as u64)?;
+        record.uint(4, b as u64)?;
+        record.uint(5, c as u64)?;
+        database.message(2, &record)?;
+    }
+    Ok(())
+}
+
+fn encode(dataset: &Dataset) -> Result<Message> {
+    let mut database = Message::default();
+    match dataset {
+        Dataset::Geo(ranges) => {
+            for (from, to, lat, lon, accuracy) in ranges {
+                let mut record = Message::default();
+                record.range(*from, *to)?;
+                record.float(3, *lat)?;
+                record.float(4, *lon)?;
+                record.uint(5, accuracy.unwrap_or(0) as u64)?;
+                database.message(1, &record)?;
+            }
+        }
+        Dataset::Proxy(types) => {
+            for (name, ranges) in types {
+                let mut proxy_type = Message::default();
+                proxy_type.string(1, name)?;
+                for (from, to, confidence, _) in ranges {
+                    let mut range = Message::default();
+                    range.range(*from, *to)?;
+                    range.uint(3, *confidence as u64)?;
+                    proxy_type.message(2, &range)?;
+                }
+                database.message(1, &proxy_type)?;
+            }
+        }
+        Dataset::Asn(strings, data) => {
+            let records = data.iter().map(|r| (r.0, r.1, r.2, r.3, r.4));
+            encode_strings(&mut database, strings, records)?;
+        }
+        Dataset::Isp(strings, data) | Dataset::Mobile(strings, data) => {
+            database.string(1, "-")?;
+            encode_strings(&mut database, strings, data.iter().copied())?;
+        }
+        Dataset::Usage(ranges) => {
+            for (from, to, mask) in ranges {
+                let mut record = Message::default();
+                record.range(*from, *to)?;
+                record.uint(3, *mask as u64)?;
+                database.message(1, &record)?;
+            }
+        }
+        Dataset::Elevation(ranges) => {
+            for (from, to, meters) in ranges {
+                let mut record = Message::default();
+                record.range(*from, *to)?;
+                record.sint(3, *meters as i64)?;
+                database.message(1, &record)?;
+            }
+        }
+        Dataset::Country(ranges) => {
+            for range in ranges {
+                let (from, to, country, continent, eu, alternate, _) = range;
+                let mut record = Message::default();
+                record.range(*from, *to)?;
+                record.bytes(3, country)?;
+                if continent != b"--" {
+                    record.bytes(4, continent)?;
+                }
+                record.uint(5, *eu as u64)?;
+                if let Some((source, code)) = alternate {
+                    record.bytes(6, code)?;
+                    record.uint(7, (*source == AlternateCountry::Represented) as u64)?;
+                }
+                if let Some(subdivision) = subdivision_code(range) {
+                    record.bytes(8, subdivision.as_bytes())?;
+                }
+                database.message(1, &record)?;
+            }
+        }
+    }
+    Ok(database)
+}
+
+pub fn file_name(kind: Kind) -> String {
+    format!("{}.pb", kind.name())
+}
+
+pub fn write(path: &str, dataset: &Dataset) -> Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    out.write_all(&encode(dataset)?.0)?;
+    out.flush()
+}
@@ -0,0 +1,147 @@
+pub const PROXY_SETS_FILE: &str = "proxy_types.roaring";
+pub const ANY_TYPE: &str = "ANY";
+
+#[cfg(feature = "roaring")]
+mod sets {
+    use std::fs::{self, File};
+    use std::io::{BufWriter, Error, ErrorKind, Read, Result, Write};
+
+    use roaring::RoaringBitmap;
+
+    use super::ANY_TYPE;
+    use crate::format::ProxyRange;
+
+    const MAGIC: &[u8; 4] = b"IP2R";
+    const IPV4_BASE: u128 = 0xffff << 32;
+    const IPV4_END: u128 = IPV4_BASE | 0xffff_ffff;
+
+    pub struct ProxySets(Vec<(String, RoaringBitmap)>);
+
+    fn invalid(message: &str) -> Error {
+        Error::new(ErrorKind::InvalidData, message.to_string())
+    }
+
+    fn ipv4_bitmap(ranges: &[ProxyRange]) -> RoaringBitmap {
+        let mut bitmap = RoaringBitmap::new();
+        for &(from, to, ..) in ranges {
+            if to < IPV4_BASE || from > IPV4_END {
+                continue;
+            }
+            let from = from.max(IPV4_BASE) as u32;
+            let to = to.min(IPV4_END) as u32;
+            bitmap.insert_range(from..=to);
+        }
+        bitmap.optimize();
+        bitmap
+    }
+
+    impl ProxySets {
+        pub fn build(types: &[(String, Vec<ProxyRange>)]) -> Result<ProxySets> {
+            let sets: Vec<_> = types
+                .iter()
+                .map(|(name, ranges)| (name.clone(), ipv4_bitmap(ranges)))
+                .collect();
+            let mut any = sets.iter().map(|(_, bitmap)| bitmap).fold(
+                RoaringBitmap::new(),
+                |mut any, bitmap| {
+                    any |= bitmap;
+                    any
+                },
+            );
+            any.optimize();
+            Ok(ProxySets(
+                std::iter::once((ANY_TYPE.to_string(), any))
+                    .chain(sets)
+                    .collect(),
+            ))
+        }
+
+        pub fn write(&self, path: &str) -> Result<()> {
+            let mut out = BufWriter::new(File::create(path)?);
+            out.write_all(MAGIC)?;
+            out.write_all(&(self.0.len() as u32).to_le_bytes())?;
+            for (name, bitmap) in &self.0 {
+                out.write_all(&[name.len() as u8])?;
+                out.write_all(name.as_bytes())?;
+                out.write_all(&(bitmap.serialized_size() as u32).to_le_bytes())?;
+                bitmap.serialize_into(&mut out)?;
+            }
+            out.flush()
+        }
+
+        pub fn read(path: &str) -> Result<ProxySets> {
+            let bytes = fs::read(path)?;
+            let mut input = bytes
+                .strip_prefix(MAGIC)
+                .ok_or_else(|| invalid("Not a proxy set file"))?;
+            let mut take = |len: usize| -> Result<&[u8]> {
+                if input.len() < len {
+                    return Err(invalid("Truncated proxy set file"));
+                }
+                let (head, rest) = input.split_at(len);
+                input = rest;
+                Ok(head)
+            };
+            let count = u32::from_le_bytes(take(4)?.try_into().unwrap());
+            let mut sets = Vec::new();
+            for _ in 0..count {
+                let name_len = take(1)?[0] as usize;
+                let name = String::from_utf8_lossy(take(name_len)?).into_owned();
+                let len = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+                let mut data = take(len)?;
+                let bitmap = RoaringBitmap::deserialize_from(&mut data)?;
+                if data.read(&mut [0])? != 0 {
+                    return Err(invalid("Trailing bytes after proxy set"));
+                }
+                sets.push((name, bitmap));
+            }
+            Ok(ProxySets(sets))
+        }
+
+        pub fn contains(&self, ip: u128, proxy_type: &str) -> Option<bool> {
+            if ip >> 32 != 0xffff {
+                return None;
+            }
+            self.0
+                .iter()
+                .find(|(name, _)| name == proxy_type)
+                .map(|(_, bitmap)| bitmap.contains(ip as u32))
+        }
+    }
+}
+
+#[cfg(not(feature = "roaring"))]
+mod sets {
+    use std::io::{Error, ErrorKind, Result};
+
+    use crate::format::ProxyRange;
+
+    fn unsupported<T>() -> Result<T> {
+        Err(Error::new(
+            ErrorKind::Unsupported,
+            "Proxy sets require building with --features roaring",
+        ))
+    }
+
+    pub enum ProxySets {}
+
+    impl ProxySets {
+        pub fn build(_types: &[(String, Vec<ProxyRange>)]) -> Result<ProxySets> {
+            unsupported()
+        }
+
+        pub fn write(&self, _path: &str) -> Result<()> {
+            match *self {}
+        }
+
+        pub fn read(_path: &str) -> Result<ProxySets> {
+            unsupported()
+        }
+
+        pub fn contains(&self, _ip: u128, _proxy_type: &str) -> Option<bool> {
+            match *self {}
+        }
+    }
+}
+
+pub use sets::ProxySets;
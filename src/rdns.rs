@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Result, Write};
+use std::net::Ipv4Addr;
+use std::path::Path;
+
+use ip2x_core::varint::read_varint;
+use serde_json::Value;
+
+use crate::encoding::write_varint;
+use crate::format::{intern_with_offset, RdnsRange};
+use crate::ip::{ip_to_u128, ipv4_to_ipv6};
+use crate::storage;
+
+pub const RDNS_FILE: &str = "rdns.bin";
+const MAGIC: &[u8; 4] = b"IP2D";
+const V4_BLOCK_BITS: u32 = 8;
+const V6_BLOCK_BITS: u32 = 64;
+
+#[derive(Default)]
+pub struct RdnsTable {
+    pub strings: Vec<String>,
+    pub ranges: Vec<RdnsRange>,
+    string_map: HashMap<String, usize>,
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+#[cfg(feature = "gzip")]
+fn open(path: &str) -> Result<Box<dyn BufRead>> {
+    let (file, _) = storage::open(path)?;
+    Ok(match path.ends_with(".gz") {
+        true => Box::new(BufReader::new(flate2::read::MultiGzDecoder::new(file))),
+        false => Box::new(BufReader::new(file)),
+    })
+}
+
+#[cfg(not(feature = "gzip"))]
+fn open(path: &str) -> Result<Box<dyn BufRead>> {
+    if path.ends_with(".gz") {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            "reading .gz rDNS dumps requires building with --features gzip",
+        ));
+    }
+    Ok(Box::new(BufReader::new(storage::open(path)?.0)))
+}
+
+pub fn arpa_to_ip(name: &str) -> Option<u128> {
+    let name = name.trim_end_matches('.').to_ascii_lowercase();
+    if let Some(labels) = name.strip_suffix(".in-addr.arpa") {
+        let octets: Vec<u8> = labels
+            .split('.')
+            .rev()
+            .map(|label| label.parse().ok())
+            .collect::<Option<_>>()?;
+        let octets: [u8; 4] = octets.try_into().ok()?;
+        return Some(ipv4_to_ipv6(u32::from(Ipv4Addr::from(octets))));
+    }
+    let nibbles = name.strip_suffix(".ip6.arpa")?;
+    let digits: String = nibbles.split('.').rev().collect();
+    if digits.len() != 32 {
+        return None;
+    }
+    u128::from_str_radix(&digits, 16).ok()
+}
+
+fn host_ip(name: &str) -> Option<u128> {
+    match name.parse() {
+        Ok(ip) => Some(ip_to_u128(ip)),
+        Err(_) => arpa_to_ip(name),
+    }
+}
+
+pub fn pattern(host: &str) -> Option<String> {
+    let host = host.trim().trim_end_matches('.').to_ascii_lowercase();
+    if host.is_empty() || host.contains(char::is_whitespace) {
+        return None;
+    }
+    let mut out = String::with_capacity(host.len());
+    let mut in_digits = false;
+    for c in host.chars() {
+        match c.is_ascii_digit() {
+            true if in_digits => continue,
+            true => out.push('*'),
+            false => out.push(c),
+        }
+        in_digits = c.is_ascii_digit();
+    }
+    Some(out)
+}
+
+pub fn suffix(pattern: &str) -> &str {
+    let labels: Vec<&str> = pattern.split('.').collect();
+    let skip = match labels.iter().rposition(|label| label.contains('*')) {
+        Some(i) if i + 1 < labels.len() => i + 1,
+        Some(_) => 0,
+        None if labels.len() > 2 => 1,
+        None => 0,
+    };
+    let offset: usize = labels[..skip].iter().map(|label| label.len() + 1).sum();
+    &pattern[offset..]
+}
+
+fn block(ip: u128) -> (u128, u128) {
+    let bits = match ip >> 32 == 0xffff {
+        true => V4_BLOCK_BITS,
+        false => V6_BLOCK_BITS,
+    };
+    let mask = (1u128 << bits) - 1;
+    (ip & !mask, ip | mask)
+}
+
+impl RdnsTable {
+    pub fn load(paths: &[String]) -> Result<RdnsTable> {
+        let mut table = RdnsTable::default();
+        let mut counts: HashMap<(u128, usize), u32> = HashMap::new();
+        for path in paths {
+            let zone = Path::new(path.trim_end_matches(".gz"))
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("zone"));
+            let mut origin = String::new();
+            let mut owner = String::new();
+            for (number, line) in open(path)?.lines().enumerate() {
+                let line = line?;
+                let record = match zone {
+                    true => zone_record(&line, &mut origin, &mut owner),
+                    false => json_record(&line)
+                        .map_err(|err| invalid(format!("{}:{}: {}", path, number + 1, err)))?,
+                };
+                let Some((ip, pattern)) =
+                    record.and_then(|(name, host)| Some((host_ip(&name)?, pattern(&host)?)))
+                else {
+                    continue;
+                };
+                let pattern = table.intern(&pattern);
+                *counts.entry((block(ip).0, pattern)).or_default() += 1;
+            }
+        }
+
+        let mut blocks: Vec<((u128, usize), u32)> = counts.into_iter().collect();
+        blocks.sort_unstable_by_key(|&((from, _), _)| from);
+        for group in blocks.chunk_by(|a, b| a.0 .0 == b.0 .0) {
+            let (from, to) = block(group[0].0 .0);
+            let total = group.iter().map(|&(_, hosts)| hosts).sum::<u32>();
+            let &((_, pattern), hosts) = group
+                .iter()
+                .max_by(|a, b| {
+                    a.1.cmp(&b.1)
+                        .then_with(|| table.strings[b.0 .1 - 1].cmp(&table.strings[a.0 .1 - 1]))
+                })
+                .unwrap();
+            match table.ranges.last_mut() {
+                Some(last) if last.1.checked_add(1) == Some(from) && last.2 == pattern => {
+                    last.1 = to;
+                    last.3 += hosts;
+                    last.4 += total;
+                }
+                _ => table.ranges.push((from, to, pattern, hosts, total)),
+            }
+        }
+        Ok(table)
+    }
+
+    fn intern(&mut self, s: &str) -> usize {
+        intern_with_offset(s, &mut self.strings, &mut self.string_map)
+    }
+
+    pub fn write(&self, path: &str) -> Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(MAGIC)?;
+        write_varint(&mut out, self.strings.len() as u128)?;
+        for s in &self.strings {
+            write_varint(&mut out, s.len() as u128)?;
+            out.write_all(s.as_bytes())?;
+        }
+        write_varint(&mut out, self.ranges.len() as u128)?;
+        let mut next = 0u128;
+        for &(from, to, pattern, hosts, total) in &self.ranges {
+            write_varint(&mut out, from - next)?;
+            write_varint(&mut out, to - from)?;
+            write_varint(&mut out, pattern as u128)?;
+            write_varint(&mut out, hosts as u128)?;
+            write_varint(&mut out, total as u128)?;
+            next = to.saturating_add(1);
+        }
+        out.flush()
+    }
+
+    pub fn read(path: &str) -> Result<RdnsTable> {
+        let bytes = fs::read(path)?;
+        let mut input = bytes
+            .strip_prefix(MAGIC)
+            .ok_or_else(|| invalid(format!("{}: not an rDNS file", path)))?;
+        let overflow = || invalid(format!("{}: rDNS range overflow", path));
+
+        let count = read_varint(&mut input)? as usize;
+        let mut strings = Vec::with_capacity(count.min(1 << 20));
+        for _ in 0..count {
+            let len = read_varint(&mut input)? as usize;
+            if input.len() < len {
+                return Err(invalid(format!("{}: truncated rDNS file", path)));
+            }
+            let (head, rest) = input.split_at(len);
+            strings.push(String::from_utf8_lossy(head).into_owned());
+            input = rest;
+        }
+        let count = read_varint(&mut input)? as usize;
+        let mut ranges = Vec::with_capacity(count.min(1 << 20));
+        let mut next = 0u128;
+        for _ in 0..count {
+            let from = next
+                .checked_add(read_varint(&mut input)?)
+                .ok_or_else(overflow)?;
+            let to = from
+                .checked_add(read_varint(&mut input)?)
+                .ok_or_else(overflow)?;
+            let pattern = read_varint(&mut input)? as usize;
+            let hosts = read_varint(&mut input)? as u32;
+            let total = read_varint(&mut input)? as u32;
+            ranges.push((from, to, pattern, hosts, total));
+            next = to.saturating_add(1);
+        }
+        Ok(RdnsTable {
+            strings,
+            ranges,
+            string_map: HashMap::new(),
+        })
+    }
+}
+
+fn json_record(line: &str) -> std::result::Result<Option<(String, String)>, serde_json::Error> {
+    let line = line.trim();
+    if line.is_empty() {
+        return Ok(None);
+    }
+    let value: Value = serde_json::from_str(line)?;
+    let field = |key: &str| value.get(key).and_then(Value::as_str);
+    if field("type").is_some_and(|kind| !kind.eq_ignore_ascii_case("ptr")) {
+        return Ok(None);
+    }
+    Ok(field("name")
+        .zip(field("value"))
+        .map(|(name, host)| (name.to_string(), host.to_string())))
+}
+
+fn zone_record(line: &str, origin: &mut String, owner: &mut String) -> Option<(String, String)> {
+    let line = line.split(';').next().unwrap_or("");
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if let ["$ORIGIN", name, ..] = fields[..] {
+        *origin = name.to_string();
+        return None;
+    }
+    if fields.is_empty() || fields[0].starts_with('$') {
+        return None;
+    }
+    if !line.starts_with(char::is_whitespace) {
+        *owner = match fields[0] {
+            "@" => origin.clone(),
+            name if name.ends_with('.') || origin.is_empty() => name.to_string(),
+            name => format!("{}.{}", name, origin),
+        };
+    }
+    let ptr = fields.iter().position(|f| f.eq_ignore_ascii_case("PTR"))?;
+    let host = match *fields.get(ptr + 1)? {
+        host if host.ends_with('.') || origin.is_empty() => host.to_string(),
+        host => format!("{}.{}", host, origin),
+    };
+    Some((owner.clone(), host))
+}
@@ -0,0 +1,1134 @@
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::Arc;
+
+pub use ip2x_core::lookup::{LookupMode, RangeIndex, Ranged};
+use ip2x_core::usage;
+
+use crate::bloom::{ProxyFilter, BLOOM_FILE};
+use crate::cells::Grid;
+use crate::cli::Args;
+use crate::connection_type::{self, CONNECTION_TYPE_FILE};
+pub use crate::domain::registrable_domain;
+use crate::domain::{self, DOMAIN_FILE};
+use crate::format::{
+    read_layout, read_version, subdivision_code, AlternateCountry, AsnRange, CountryRange, Dataset,
+    DomainRange, ElevationRange, GeoRange, IspRange, Kind, MobileRange, NetworkTypeRange,
+    ProxyRange, RdnsRange, ThreatRange, UsageRange, FORMAT_VERSION, INDEXED, LEGACY_FORMAT_VERSION,
+    SHARED_STRINGS, STRINGS_FILE, TRIE, UNKNOWN_LAST_SEEN,
+};
+use crate::geocode::{haversine_km, CityIndex, Place, Radius, CITIES_FILE};
+use crate::index::{BlockCache, IndexedBin, DEFAULT_CACHE_BLOCKS};
+use crate::interval::coalesce;
+use crate::ip::ip_to_u128;
+pub use crate::ip::{IpRange, RangeRecord};
+use crate::isp_group::{self, ISP_GROUP_FILE};
+use crate::network_type::{self, NETWORK_TYPE_FILE};
+use crate::prefixes::{self, PrefixIndex, ASN_INDEX_FILE, COUNTRY_INDEX_FILE, ISP_INDEX_FILE};
+use crate::proxy_sets::{ProxySets, ANY_TYPE, PROXY_SETS_FILE};
+use crate::rdns::{self, RdnsTable, RDNS_FILE};
+use crate::signing::Verifier;
+use crate::threat::{ThreatLists, THREAT_FILE};
+use crate::trie::{self, Trie};
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GeoRecord {
+    pub latitude: f32,
+    pub longitude: f32,
+    pub accuracy_radius: Option<u16>,
+    pub cell: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CountryRecord {
+    pub country_code: String,
+    pub continent_code: String,
+    pub is_in_european_union: bool,
+    pub registered_country_code: Option<String>,
+    pub represented_country_code: Option<String>,
+    pub subdivision_code: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AsnRecord {
+    pub cidr: String,
+    pub asn: String,
+    pub as_name: String,
+    pub organization: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IspRecord {
+    pub isp: String,
+    pub domain: String,
+    pub provider: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IspGroup {
+    pub id: u32,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MobileRecord {
+    pub mcc: String,
+    pub mnc: String,
+    pub mobile_brand: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UsageRecord {
+    pub usage_type: String,
+    pub mask: u16,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProxyRecord {
+    pub proxy_type: String,
+    pub confidence: u8,
+    pub last_seen: Option<u16>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ThreatRecord {
+    pub list: String,
+    pub category: String,
+    pub reference: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RdnsRecord {
+    pub pattern: String,
+    pub suffix: String,
+    pub hosts: u32,
+    pub total: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Record {
+    pub ip: IpAddr,
+    pub geo: Option<GeoRecord>,
+    pub country: Option<CountryRecord>,
+    pub asn: Option<AsnRecord>,
+    pub proxy: Option<ProxyRecord>,
+    pub isp: Option<IspRecord>,
+    pub mobile: Option<MobileRecord>,
+    pub usage_type: Option<UsageRecord>,
+    pub elevation: Option<i16>,
+}
+
+fn best_in<'a, T: Ranged + Clone + 'a>(
+    blocks: impl Iterator<Item = &'a [T]>,
+    ip: u128,
+    mode: LookupMode,
+) -> Option<T> {
+    let mut best: Option<&T> = None;
+    for item in blocks.flat_map(|block| block.iter().rev()) {
+        let (from, to) = item.range();
+        if from > ip || to < ip {
+            continue;
+        }
+        let preferred = best.is_none_or(|b| {
+            let (b_from, b_to) = b.range();
+            mode.prefers(to - from, b_to - b_from)
+        });
+        if preferred {
+            best = Some(item);
+        }
+    }
+    best.cloned()
+}
+
+pub enum Table<T> {
+    Loaded(RangeIndex<T>),
+    Indexed(IndexedBin, fn(Dataset) -> Vec<T>, BlockCache<T>),
+    Trie(Trie, Vec<T>),
+}
+
+impl<T: Ranged + Clone> Table<T> {
+    pub fn find(&self, ip: u128, mode: LookupMode) -> Option<T> {
+        let (bin, records, cache) = match self {
+            Table::Loaded(index) => return index.find(ip, mode).cloned(),
+            Table::Trie(trie, items) => return items.get(trie.find(ip)?).cloned(),
+            Table::Indexed(bin, records, cache) => (bin, records, cache),
+        };
+        let blocks = bin
+            .candidates(ip)
+            .map(|point| cache.get_or_load(point.offset, || bin.block(point).map(records)))
+            .collect::<Result<Vec<_>>>()
+            .ok()?;
+        best_in(blocks.iter().map(|block| &block[..]), ip, mode)
+    }
+
+    pub fn find_many(&self, ips: &[u128], mode: LookupMode) -> Vec<Option<T>> {
+        let (bin, records, cache) = match self {
+            Table::Loaded(index) => {
+                return index
+                    .find_sorted(ips, mode)
+                    .into_iter()
+                    .map(|item| item.cloned())
+                    .collect()
+            }
+            Table::Trie(..) => return ips.iter().map(|&ip| self.find(ip, mode)).collect(),
+            Table::Indexed(bin, records, cache) => (bin, records, cache),
+        };
+        let mut open: Vec<(u64, Arc<Vec<T>>)> = Vec::new();
+        ips.iter()
+            .map(|&ip| {
+                let mut blocks = Vec::new();
+                for point in bin.candidates(ip) {
+                    let block = match open.iter().find(|(offset, _)| *offset == point.offset) {
+                        Some((_, block)) => block.clone(),
+                        None => cache
+                            .get_or_load(point.offset, || bin.block(point).map(records))
+                            .ok()?,
+                    };
+                    blocks.push((point.offset, block));
+                }
+                open = blocks;
+                best_in(open.iter().map(|(_, block)| &block[..]), ip, mode)
+            })
+            .collect()
+    }
+
+    pub fn is_indexed(&self) -> bool {
+        matches!(self, Table::Indexed(..))
+    }
+
+    pub fn cache_stats(&self) -> (u64, u64) {
+        match self {
+            Table::Indexed(_, _, cache) => cache.stats(),
+            _ => (0, 0),
+        }
+    }
+
+    pub fn all(&self) -> Result<Cow<'_, [T]>> {
+        match self {
+            Table::Loaded(index) => Ok(Cow::Borrowed(index.items())),
+            Table::Trie(_, items) => Ok(Cow::Borrowed(items)),
+            Table::Indexed(bin, records, _) => Ok(Cow::Owned(records(bin.read_all()?))),
+        }
+    }
+}
+
+impl<T> Default for Table<T> {
+    fn default() -> Self {
+        Table::Loaded(RangeIndex::default())
+    }
+}
+
+const _: fn() = || {
+    fn shared<T: Send + Sync>() {}
+    shared::<Ip2xReader>();
+};
+
+#[derive(Default)]
+pub struct Ip2xReader {
+    geo: Table<GeoRange>,
+    geo_cells: Option<Grid>,
+    cities: Option<CityIndex>,
+    proxy_types: Vec<(String, RangeIndex<ProxyRange>)>,
+    proxy_sets: Option<ProxySets>,
+    proxy_filter: Option<ProxyFilter>,
+    asn_strings: Vec<String>,
+    asn: Table<AsnRange>,
+    asn_index: Option<PrefixIndex>,
+    isp_strings: Vec<String>,
+    isp: Table<IspRange>,
+    isp_index: Option<PrefixIndex>,
+    mobile_strings: Vec<String>,
+    mobile: Table<MobileRange>,
+    usage: Table<UsageRange>,
+    elevation: Table<ElevationRange>,
+    country: Table<CountryRange>,
+    country_index: Option<PrefixIndex>,
+    threat_strings: Vec<String>,
+    threats: Vec<(String, RangeIndex<ThreatRange>)>,
+    rdns_strings: Vec<String>,
+    rdns: Option<RangeIndex<RdnsRange>>,
+    network_types: Vec<String>,
+    network_type: Option<RangeIndex<NetworkTypeRange>>,
+    connection_types: Vec<String>,
+    connection_type: Option<RangeIndex<NetworkTypeRange>>,
+    domains: Vec<String>,
+    domain: Option<RangeIndex<DomainRange>>,
+    isp_groups: Vec<String>,
+    isp_group: Option<RangeIndex<DomainRange>>,
+    mode: LookupMode,
+}
+
+fn newer_format(kind: Kind, version: u8) -> Error {
+    Error::new(
+        ErrorKind::Unsupported,
+        format!(
+            "{} uses bin format version {}, but this ip2x reads versions {} to {}; upgrade ip2x to open it",
+            kind.file_name(),
+            version,
+            LEGACY_FORMAT_VERSION,
+            FORMAT_VERSION
+        ),
+    )
+}
+
+impl Ip2xReader {
+    pub fn open(dir: &str) -> Result<Self> {
+        Self::open_with(dir, None, None)
+    }
+
+    pub fn open_verified(dir: &str, public_key: &str) -> Result<Self> {
+        Self::open_with(dir, Some(&Verifier::open(public_key)?), None)
+    }
+
+    pub fn open_mapped(dir: &str, cache_blocks: usize) -> Result<Self> {
+        Self::open_with(dir, None, Some(cache_blocks))
+    }
+
+    fn open_with(dir: &str, verifier: Option<&Verifier>, mapped: Option<usize>) -> Result<Self> {
+        let mut reader = Self::default();
+        let verify = |path: &str| match verifier {
+            Some(verifier) => verifier.verify(path),
+            None => Ok(()),
+        };
+
+        for kind in Kind::ALL {
+            let path = Path::new(dir).join(kind.file_name());
+            if !path.exists() {
+                continue;
+            }
+            let path = path.to_string_lossy();
+            verify(&path)?;
+            let layout = match read_version(&path)? {
+                LEGACY_FORMAT_VERSION..=FORMAT_VERSION => read_layout(&path)?,
+                version => return Err(newer_format(kind, version)),
+            };
+            if layout.has(SHARED_STRINGS) {
+                verify(&Path::new(dir).join(STRINGS_FILE).to_string_lossy())?;
+            }
+            if kind == Kind::Geo {
+                reader.geo_cells = layout.geo_cells;
+            }
+            if layout.has(INDEXED) && kind != Kind::Proxy {
+                let (bin, strings) = match mapped {
+                    Some(_) => IndexedBin::map(&path, kind)?,
+                    None => IndexedBin::open(&path, kind)?,
+                };
+                reader.attach(bin, strings, mapped.unwrap_or(DEFAULT_CACHE_BLOCKS));
+            } else if layout.has(TRIE) && kind != Kind::Proxy {
+                let bytes = fs::read(&*path)?;
+                let (trie, _) = trie::read_trailer(&bytes)?;
+                reader.load_trie(Dataset::read_from(&mut &bytes[..], kind, &path)?, trie);
+            } else {
+                reader.load(Dataset::read(&path, kind)?);
+            }
+        }
+
+        let sets = Path::new(dir).join(PROXY_SETS_FILE);
+        if sets.exists() {
+            verify(&sets.to_string_lossy())?;
+            reader.proxy_sets = match ProxySets::read(&sets.to_string_lossy()) {
+                Ok(sets) => Some(sets),
+                Err(err) if err.kind() == ErrorKind::Unsupported => None,
+                Err(err) => return Err(err),
+            };
+        }
+
+        let filter = Path::new(dir).join(BLOOM_FILE);
+        if filter.exists() {
+            verify(&filter.to_string_lossy())?;
+            reader.proxy_filter = Some(ProxyFilter::read(&filter.to_string_lossy())?);
+        }
+
+        let threats = Path::new(dir).join(THREAT_FILE);
+        if threats.exists() {
+            verify(&threats.to_string_lossy())?;
+            let threats = ThreatLists::read(&threats.to_string_lossy())?;
+            reader.threat_strings = threats.strings;
+            reader.threats = threats
+                .lists
+                .into_iter()
+                .map(|(name, ranges)| (name, RangeIndex::new(ranges)))
+                .collect();
+        }
+
+        let rdns = Path::new(dir).join(RDNS_FILE);
+        if rdns.exists() {
+            verify(&rdns.to_string_lossy())?;
+            let rdns = RdnsTable::read(&rdns.to_string_lossy())?;
+            reader.rdns_strings = rdns.strings;
+            reader.rdns = Some(RangeIndex::new(rdns.ranges));
+        }
+
+        let network_types = Path::new(dir).join(NETWORK_TYPE_FILE);
+        if network_types.exists() {
+            verify(&network_types.to_string_lossy())?;
+            let (names, ranges) = network_type::read(&network_types.to_string_lossy())?;
+            reader.network_types = names;
+            reader.network_type = Some(RangeIndex::new(ranges));
+        }
+
+        let connection_types = Path::new(dir).join(CONNECTION_TYPE_FILE);
+        if connection_types.exists() {
+            verify(&connection_types.to_string_lossy())?;
+            let (names, ranges) = connection_type::read(&connection_types.to_string_lossy())?;
+            reader.connection_types = names;
+            reader.connection_type = Some(RangeIndex::new(ranges));
+        }
+
+        let domains = Path::new(dir).join(DOMAIN_FILE);
+        if domains.exists() {
+            verify(&domains.to_string_lossy())?;
+            let (names, ranges) = domain::read(&domains.to_string_lossy())?;
+            reader.domains = names;
+            reader.domain = Some(RangeIndex::new(ranges));
+        }
+
+        let isp_groups = Path::new(dir).join(ISP_GROUP_FILE);
+        if isp_groups.exists() {
+            verify(&isp_groups.to_string_lossy())?;
+            let (names, ranges) = isp_group::read(&isp_groups.to_string_lossy())?;
+            reader.isp_groups = names;
+            reader.isp_group = Some(RangeIndex::new(ranges));
+        }
+
+        for (file, index) in [
+            (ASN_INDEX_FILE, &mut reader.asn_index),
+            (COUNTRY_INDEX_FILE, &mut reader.country_index),
+            (ISP_INDEX_FILE, &mut reader.isp_index),
+        ] {
+            let path = Path::new(dir).join(file);
+            if path.exists() {
+                verify(&path.to_string_lossy())?;
+                *index = Some(PrefixIndex::read(&path.to_string_lossy())?);
+            }
+        }
+
+        let cities = Path::new(dir).join(CITIES_FILE);
+        if cities.exists() {
+            verify(&cities.to_string_lossy())?;
+            reader.cities = Some(CityIndex::read(&cities.to_string_lossy())?);
+        }
+
+        Ok(reader)
+    }
+
+    pub fn load(&mut self, dataset: Dataset) {
+        match dataset {
+            Dataset::Geo(ranges) => self.geo = Table::Loaded(RangeIndex::new(ranges)),
+            Dataset::Proxy(types) => {
+                self.proxy_types = types
+                    .into_iter()
+                    .map(|(name, ranges)| (name, RangeIndex::new(ranges)))
+                    .collect()
+            }
+            Dataset::Asn(strings, data) => {
+                self.asn_strings = strings;
+                self.asn = Table::Loaded(RangeIndex::new(data));
+            }
+            Dataset::Isp(strings, data) => {
+                self.isp_strings = strings;
+                self.isp = Table::Loaded(RangeIndex::new(data));
+            }
+            Dataset::Mobile(strings, data) => {
+                self.mobile_strings = strings;
+                self.mobile = Table::Loaded(RangeIndex::new(data));
+            }
+            Dataset::Usage(ranges) => self.usage = Table::Loaded(RangeIndex::new(ranges)),
+            Dataset::Elevation(ranges) => self.elevation = Table::Loaded(RangeIndex::new(ranges)),
+            Dataset::Country(ranges) => self.country = Table::Loaded(RangeIndex::new(ranges)),
+        }
+    }
+
+    fn load_trie(&mut self, dataset: Dataset, trie: Trie) {
+        match dataset {
+            Dataset::Geo(ranges) => self.geo = Table::Trie(trie, ranges),
+            Dataset::Proxy(_) => {}
+            Dataset::Asn(strings, data) => {
+                self.asn_strings = strings;
+                self.asn = Table::Trie(trie, data);
+            }
+            Dataset::Isp(strings, data) => {
+                self.isp_strings = strings;
+                self.isp = Table::Trie(trie, data);
+            }
+            Dataset::Mobile(strings, data) => {
+                self.mobile_strings = strings;
+                self.mobile = Table::Trie(trie, data);
+            }
+            Dataset::Usage(ranges) => self.usage = Table::Trie(trie, ranges),
+            Dataset::Elevation(ranges) => self.elevation = Table::Trie(trie, ranges),
+            Dataset::Country(ranges) => self.country = Table::Trie(trie, ranges),
+        }
+    }
+
+    fn attach(&mut self, bin: IndexedBin, strings: Vec<String>, cache_blocks: usize) {
+        match bin.kind() {
+            Kind::Geo => {
+                self.geo = Table::Indexed(
+                    bin,
+                    |block| match block {
+                        Dataset::Geo(ranges) => ranges,
+                        _ => Vec::new(),
+                    },
+                    BlockCache::new(cache_blocks),
+                )
+            }
+            Kind::Asn => {
+                self.asn_strings = strings;
+                self.asn = Table::Indexed(
+                    bin,
+                    |block| match block {
+                        Dataset::Asn(_, data) => data,
+                        _ => Vec::new(),
+                    },
+                    BlockCache::new(cache_blocks),
+                );
+            }
+            Kind::Isp => {
+                self.isp_strings = strings;
+                self.isp = Table::Indexed(
+                    bin,
+                    |block| match block {
+                        Dataset::Isp(_, data) => data,
+                        _ => Vec::new(),
+                    },
+                    BlockCache::new(cache_blocks),
+                );
+            }
+            Kind::Mobile => {
+                self.mobile_strings = strings;
+                self.mobile = Table::Indexed(
+                    bin,
+                    |block| match block {
+                        Dataset::Mobile(_, data) => data,
+                        _ => Vec::new(),
+                    },
+                    BlockCache::new(cache_blocks),
+                );
+            }
+            Kind::Usage => {
+                self.usage = Table::Indexed(
+                    bin,
+                    |block| match block {
+                        Dataset::Usage(ranges) => ranges,
+                        _ => Vec::new(),
+                    },
+                    BlockCache::new(cache_blocks),
+                )
+            }
+            Kind::Elevation => {
+                self.elevation = Table::Indexed(
+                    bin,
+                    |block| match block {
+                        Dataset::Elevation(ranges) => ranges,
+                        _ => Vec::new(),
+                    },
+                    BlockCache::new(cache_blocks),
+                )
+            }
+            Kind::Country => {
+                self.country = Table::Indexed(
+                    bin,
+                    |block| match block {
+                        Dataset::Country(ranges) => ranges,
+                        _ => Vec::new(),
+                    },
+                    BlockCache::new(cache_blocks),
+                )
+            }
+            Kind::Proxy => {}
+        }
+    }
+
+    pub fn set_mode(&mut self, mode: LookupMode) {
+        self.mode = mode;
+    }
+
+    pub fn geo_grid(&self) -> Option<Grid> {
+        self.geo_cells
+    }
+
+    fn geo_record(
+        &self,
+        (_, _, latitude, longitude, accuracy_radius): GeoRange,
+    ) -> Option<GeoRecord> {
+        if latitude.is_nan() {
+            return None;
+        }
+        Some(GeoRecord {
+            latitude,
+            longitude,
+            accuracy_radius,
+            cell: self
+                .geo_cells
+                .and_then(|grid| grid.cell(latitude, longitude).ok()),
+        })
+    }
+
+    pub fn lookup_geo(&self, ip: IpAddr) -> Option<GeoRecord> {
+        self.geo_record(self.geo.find(ip_to_u128(ip), self.mode)?)
+    }
+
+    fn country_record(range: CountryRange) -> CountryRecord {
+        let (_, _, country, continent, eu, alternate, _) = &range;
+        let code = |bytes: &[u8; 2]| match bytes {
+            b"--" => "-".to_string(),
+            _ => String::from_utf8_lossy(bytes).into_owned(),
+        };
+        CountryRecord {
+            country_code: code(country),
+            continent_code: code(continent),
+            is_in_european_union: *eu,
+            registered_country_code: alternate
+                .filter(|(source, _)| *source == AlternateCountry::Registered)
+                .map(|(_, alternate)| code(&alternate)),
+            represented_country_code: alternate
+                .filter(|(source, _)| *source == AlternateCountry::Represented)
+                .map(|(_, alternate)| code(&alternate)),
+            subdivision_code: subdivision_code(&range),
+        }
+    }
+
+    pub fn lookup_country(&self, ip: IpAddr) -> Option<CountryRecord> {
+        Some(Self::country_record(
+            self.country.find(ip_to_u128(ip), self.mode)?,
+        ))
+    }
+
+    pub fn distance_km(&self, a: IpAddr, b: IpAddr) -> Option<f64> {
+        let (a, b) = (self.lookup_geo(a)?, self.lookup_geo(b)?);
+        Some(haversine_km(
+            a.latitude,
+            a.longitude,
+            b.latitude,
+            b.longitude,
+        ))
+    }
+
+    pub fn ranges_within(
+        &self,
+        latitude: f32,
+        longitude: f32,
+        radius_km: f64,
+    ) -> Result<Vec<GeoRange>> {
+        let radius = Radius::new(latitude, longitude, radius_km);
+        let mut last: Option<((u32, u32), bool)> = None;
+        let ranges = self.geo.all()?;
+        let within = ranges.iter().filter(|r| {
+            let key = (r.2.to_bits(), r.3.to_bits());
+            match last {
+                Some((seen, inside)) if seen == key => inside,
+                _ => {
+                    let inside = radius.contains(r.2, r.3);
+                    last = Some((key, inside));
+                    inside
+                }
+            }
+        });
+        Ok(within.cloned().collect())
+    }
+
+    pub fn reverse_lookup(&self, latitude: f32, longitude: f32) -> Option<Place> {
+        self.cities.as_ref()?.nearest(latitude, longitude)
+    }
+
+    fn proxy_record<'a>(
+        hits: impl Iterator<Item = (&'a String, &'a ProxyRange)>,
+    ) -> Option<ProxyRecord> {
+        hits.min_by_key(|(_, r)| (r.1 - r.0, Reverse(r.2)))
+            .map(|(name, r)| ProxyRecord {
+                proxy_type: name.clone(),
+                confidence: r.2,
+                last_seen: Some(r.3).filter(|&days| days != UNKNOWN_LAST_SEEN),
+            })
+    }
+
+    pub fn lookup_threats(&self, ip: IpAddr) -> Vec<ThreatRecord> {
+        let target = ip_to_u128(ip);
+        let string = |idx: usize| idx.checked_sub(1).and_then(|i| self.threat_strings.get(i));
+        self.threats
+            .iter()
+            .filter_map(|(list, index)| {
+                let &(_, _, category, reference) = index.find(target, self.mode)?;
+                Some(ThreatRecord {
+                    list: list.clone(),
+                    category: string(category).cloned().unwrap_or_default(),
+                    reference: string(reference).cloned(),
+                })
+            })
+            .collect()
+    }
+
+    pub fn lookup_rdns(&self, ip: IpAddr) -> Option<RdnsRecord> {
+        let &(_, _, pattern, hosts, total) = self.rdns.as_ref()?.find(ip_to_u128(ip), self.mode)?;
+        let pattern = self.rdns_strings.get(pattern.checked_sub(1)?)?;
+        Some(RdnsRecord {
+            pattern: pattern.clone(),
+            suffix: rdns::suffix(pattern).to_string(),
+            hosts,
+            total,
+        })
+    }
+
+    pub fn lookup_network_type(&self, ip: IpAddr) -> Option<&str> {
+        let &(_, _, network_type) = self
+            .network_type
+            .as_ref()?
+            .find(ip_to_u128(ip), self.mode)?;
+        self.network_types
+            .get(network_type as usize)
+            .map(String::as_str)
+    }
+
+    pub fn lookup_connection_type(&self, ip: IpAddr) -> Option<&str> {
+        let &(_, _, connection_type) = self
+            .connection_type
+            .as_ref()?
+            .find(ip_to_u128(ip), self.mode)?;
+        self.connection_types
+            .get(connection_type as usize)
+            .map(String::as_str)
+    }
+
+    pub fn lookup_domain(&self, ip: IpAddr) -> Option<String> {
+        let Some(index) = &self.domain else {
+            return registrable_domain(&self.lookup_isp(ip)?.domain);
+        };
+        let &(_, _, domain) = index.find(ip_to_u128(ip), self.mode)?;
+        self.domains.get(domain as usize).cloned()
+    }
+
+    pub fn lookup_isp_group(&self, ip: IpAddr) -> Option<IspGroup> {
+        let &(_, _, id) = self.isp_group.as_ref()?.find(ip_to_u128(ip), self.mode)?;
+        Some(IspGroup {
+            id,
+            name: self.isp_groups.get(id as usize)?.clone(),
+        })
+    }
+
+    pub fn maybe_proxy(&self, ip: IpAddr) -> bool {
+        self.proxy_filter
+            .as_ref()
+            .is_none_or(|filter| filter.contains(ip_to_u128(ip)))
+    }
+
+    pub fn lookup_proxy(&self, ip: IpAddr) -> Option<ProxyRecord> {
+        if !self.maybe_proxy(ip) {
+            return None;
+        }
+        let target = ip_to_u128(ip);
+        Self::proxy_record(
+            self.proxy_types
+                .iter()
+                .filter_map(|(name, index)| index.find(target, self.mode).map(|r| (name, r))),
+        )
+    }
+
+    pub fn is_proxy(&self, ip: IpAddr, proxy_type: Option<&str>) -> bool {
+        if !self.maybe_proxy(ip) {
+            return false;
+        }
+        let target = ip_to_u128(ip);
+        let proxy_type = proxy_type.unwrap_or(ANY_TYPE);
+        if let Some(hit) = self
+            .proxy_sets
+            .as_ref()
+            .and_then(|sets| sets.contains(target, proxy_type))
+        {
+            return hit;
+        }
+        self.proxy_types
+            .iter()
+            .filter(|(name, _)| proxy_type == ANY_TYPE || name == proxy_type)
+            .any(|(_, index)| index.find(target, self.mode).is_some())
+    }
+
+    fn asn_record(&self, (_, _, cidr, asn, name, org): AsnRange) -> AsnRecord {
+        let string = |idx: usize| self.asn_strings.get(idx).cloned().unwrap_or_default();
+        AsnRecord {
+            cidr: string(cidr),
+            asn: string(asn),
+            as_name: string(name),
+            organization: org.and_then(|idx| self.asn_strings.get(idx).cloned()),
+        }
+    }
+
+    pub fn lookup_asn(&self, ip: IpAddr) -> Option<AsnRecord> {
+        Some(self.asn_record(self.asn.find(ip_to_u128(ip), self.mode)?))
+    }
+
+    pub fn asn_prefixes(&self, asn: &str) -> Result<Vec<IpRange>> {
+        if let Some(index) = &self.asn_index {
+            return Ok(index.get(asn)?.into_iter().map(IpRange::from).collect());
+        }
+        let ranges = self.asn.all()?;
+        Ok(coalesce(
+            ranges
+                .iter()
+                .filter(|r| self.asn_strings.get(r.3).is_some_and(|s| s == asn))
+                .map(|r| (r.0, r.1))
+                .collect(),
+        )
+        .into_iter()
+        .map(IpRange::from)
+        .collect())
+    }
+
+    pub fn country_prefixes(&self, country: &str) -> Result<Vec<IpRange>> {
+        if let Some(index) = &self.country_index {
+            return Ok(index.get(country)?.into_iter().map(IpRange::from).collect());
+        }
+        let ranges = self.country.all()?.into_owned();
+        Ok(coalesce(
+            prefixes::entries(Dataset::Country(ranges))
+                .into_iter()
+                .filter(|(code, _, _)| code == country)
+                .map(|(_, from, to)| (from, to))
+                .collect(),
+        )
+        .into_iter()
+        .map(IpRange::from)
+        .collect())
+    }
+
+    fn isp_record(&self, (_, _, isp, domain, provider): IspRange) -> IspRecord {
+        let string = |idx: usize| match idx.checked_sub(1).and_then(|i| self.isp_strings.get(i)) {
+            Some(s) => s.clone(),
+            None => "-".to_string(),
+        };
+        IspRecord {
+            isp: string(isp),
+            domain: string(domain),
+            provider: string(provider),
+        }
+    }
+
+    pub fn search_isp(&self, needle: &str) -> Result<Vec<RangeRecord<String>>> {
+        let needle = needle.to_lowercase();
+        let mut found = Vec::new();
+        if let Some(index) = &self.isp_index {
+            for name in index
+                .keys()
+                .filter(|name| name.to_lowercase().contains(&needle))
+            {
+                found.extend(
+                    index
+                        .get(name)?
+                        .into_iter()
+                        .map(|(from, to)| RangeRecord::from((from, to, name.to_string()))),
+                );
+            }
+        } else {
+            let matches: Vec<bool> = self
+                .isp_strings
+                .iter()
+                .map(|name| name.to_lowercase().contains(&needle))
+                .collect();
+            let mut names: BTreeMap<&str, Vec<(u128, u128)>> = BTreeMap::new();
+            for r in self.isp.all()?.iter() {
+                if r.2
+                    .checked_sub(1)
+                    .is_some_and(|i| matches.get(i) == Some(&true))
+                {
+                    names
+                        .entry(&self.isp_strings[r.2 - 1])
+                        .or_default()
+                        .push((r.0, r.1));
+                }
+            }
+            for (name, ranges) in names {
+                found.extend(
+                    coalesce(ranges)
+                        .into_iter()
+                        .map(|(from, to)| RangeRecord::from((from, to, name.to_string()))),
+                );
+            }
+        }
+        found.sort_unstable_by(|a, b| (a.range, &a.value).cmp(&(b.range, &b.value)));
+        Ok(found)
+    }
+
+    pub fn lookup_isp(&self, ip: IpAddr) -> Option<IspRecord> {
+        Some(self.isp_record(self.isp.find(ip_to_u128(ip), self.mode)?))
+    }
+
+    fn mobile_record(&self, (_, _, mcc, mnc, brand): MobileRange) -> MobileRecord {
+        let string = |idx: usize| match idx.checked_sub(1).and_then(|i| self.mobile_strings.get(i))
+        {
+            Some(s) => s.clone(),
+            None => "-".to_string(),
+        };
+        MobileRecord {
+            mcc: string(mcc),
+            mnc: string(mnc),
+            mobile_brand: string(brand),
+        }
+    }
+
+    pub fn lookup_mobile(&self, ip: IpAddr) -> Option<MobileRecord> {
+        Some(self.mobile_record(self.mobile.find(ip_to_u128(ip), self.mode)?))
+    }
+
+    fn usage_record((_, _, mask): UsageRange) -> UsageRecord {
+        UsageRecord {
+            usage_type: usage::names(mask),
+            mask,
+        }
+    }
+
+    pub fn lookup_usage_type(&self, ip: IpAddr) -> Option<UsageRecord> {
+        self.usage
+            .find(ip_to_u128(ip), self.mode)
+            .map(Self::usage_record)
+    }
+
+    pub fn lookup_elevation(&self, ip: IpAddr) -> Option<i16> {
+        self.elevation.find(ip_to_u128(ip), self.mode).map(|r| r.2)
+    }
+
+    pub fn decodes_on_demand(&self) -> bool {
+        [
+            self.geo.is_indexed(),
+            self.country.is_indexed(),
+            self.asn.is_indexed(),
+            self.isp.is_indexed(),
+            self.mobile.is_indexed(),
+            self.usage.is_indexed(),
+            self.elevation.is_indexed(),
+        ]
+        .contains(&true)
+    }
+
+    pub fn cache_stats(&self) -> (u64, u64) {
+        [
+            self.geo.cache_stats(),
+            self.country.cache_stats(),
+            self.asn.cache_stats(),
+            self.isp.cache_stats(),
+            self.mobile.cache_stats(),
+            self.usage.cache_stats(),
+            self.elevation.cache_stats(),
+        ]
+        .iter()
+        .fold((0, 0), |(hits, misses), (h, m)| (hits + h, misses + m))
+    }
+
+    pub fn lookup_many(&self, ips: &[IpAddr]) -> Vec<Record> {
+        let mut order: Vec<usize> = (0..ips.len()).collect();
+        order.sort_unstable_by_key(|&i| ip_to_u128(ips[i]));
+        let sorted: Vec<u128> = order.iter().map(|&i| ip_to_u128(ips[i])).collect();
+
+        let geo = self.geo.find_many(&sorted, self.mode);
+        let country = self.country.find_many(&sorted, self.mode);
+        let asn = self.asn.find_many(&sorted, self.mode);
+        let isp = self.isp.find_many(&sorted, self.mode);
+        let mobile = self.mobile.find_many(&sorted, self.mode);
+        let usage = self.usage.find_many(&sorted, self.mode);
+        let elevation = self.elevation.find_many(&sorted, self.mode);
+        let proxy: Vec<_> = self
+            .proxy_types
+            .iter()
+            .map(|(name, index)| (name, index.find_sorted(&sorted, self.mode)))
+            .collect();
+
+        let mut records: Vec<Option<Record>> = vec![None; ips.len()];
+        for (rank, &i) in order.iter().enumerate() {
+            records[i] = Some(Record {
+                ip: ips[i],
+                geo: geo[rank].and_then(|range| self.geo_record(range)),
+                country: country[rank].clone().map(Self::country_record),
+                asn: asn[rank].map(|range| self.asn_record(range)),
+                proxy: Self::proxy_record(
+                    proxy
+                        .iter()
+                        .filter_map(|(name, hits)| hits[rank].map(|r| (*name, r))),
+                ),
+                isp: isp[rank].map(|range| self.isp_record(range)),
+                mobile: mobile[rank].map(|range| self.mobile_record(range)),
+                usage_type: usage[rank].map(Self::usage_record),
+                elevation: elevation[rank].map(|r| r.2),
+            });
+        }
+        records.into_iter().flatten().collect()
+    }
+}
+
+pub fn open_from_args(args: &Args) -> Result<Ip2xReader> {
+    let dir = args.value(&["--bins"]).unwrap_or(".");
+    let cache_blocks = match args.value(&["--block-cache"]) {
+        Some(blocks) => blocks
+            .parse()
+            .map_err(|_| Error::new(ErrorKind::InvalidInput, "--block-cache must be a number"))?,
+        None => DEFAULT_CACHE_BLOCKS,
+    };
+    let mode = match args.value(&["--mode"]) {
+        Some(name) => LookupMode::parse(name).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Unknown lookup mode: {} (expected most-specific or first-match)",
+                    name
+                ),
+            )
+        })?,
+        None => LookupMode::default(),
+    };
+    let mut reader = match (args.value(&["--public-key"]), args.flag("--mmap")) {
+        (Some(public_key), false) => Ip2xReader::open_verified(dir, public_key)?,
+        (None, false) => Ip2xReader::open(dir)?,
+        (public_key, true) => {
+            let verifier = public_key.map(Verifier::open).transpose()?;
+            Ip2xReader::open_with(dir, verifier.as_ref(), Some(cache_blocks))?
+        }
+    };
+    reader.set_mode(mode);
+    Ok(reader)
+}
+
+pub fn run(args: &Args) -> Result<()> {
+    if args.positional().is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Usage: ip2x lookup <ip>... [--bins <dir>] [--public-key <pem>] [--mmap [--block-cache <n>]] [--mode most-specific|first-match]",
+        ));
+    }
+
+    let reader = open_from_args(args)?;
+    let ips = args
+        .positional()
+        .iter()
+        .map(|ip| {
+            ip.parse()
+                .map_err(|_| Error::new(ErrorKind::InvalidInput, format!("Bad IP address: {}", ip)))
+        })
+        .collect::<Result<Vec<IpAddr>>>()?;
+    for record in reader.lookup_many(&ips) {
+        println!("{}", record.ip);
+        if let Some(geo) = record.geo {
+            match geo.accuracy_radius {
+                Some(km) => println!(
+                    "  location: {}, {} (within {} km)",
+                    geo.latitude, geo.longitude, km
+                ),
+                None => println!("  location: {}, {}", geo.latitude, geo.longitude),
+            }
+        }
+        if let Some(country) = record.country {
+            println!(
+                "  country: {} ({}){}{}{}{}",
+                country.country_code,
+                country.continent_code,
+                country
+                    .subdivision_code
+                    .as_ref()
+                    .map(|code| format!(", {}", code))
+                    .unwrap_or_default(),
+                if country.is_in_european_union {
+                    ", EU"
+                } else {
+                    ""
+                },
+                country
+                    .registered_country_code
+                    .map(|code| format!(", registered in {}", code))
+                    .unwrap_or_default(),
+                country
+                    .represented_country_code
+                    .map(|code| format!(", represents {}", code))
+                    .unwrap_or_default()
+            );
+        }
+        if let Some(asn) = record.asn {
+            println!(
+                "  asn: {} {} ({}){}",
+                asn.asn,
+                asn.as_name,
+                asn.cidr,
+                asn.organization
+                    .map(|org| format!(", organization {}", org))
+                    .unwrap_or_default()
+            );
+        }
+        if let Some(proxy) = record.proxy {
+            match proxy.last_seen {
+                Some(days) => println!(
+                    "  proxy_type: {} (confidence {}, last seen {})",
+                    proxy.proxy_type,
+                    proxy.confidence,
+                    match days {
+                        0 => "today".to_string(),
+                        1 => "1 day ago".to_string(),
+                        days => format!("{} days ago", days),
+                    }
+                ),
+                None => println!(
+                    "  proxy_type: {} (confidence {})",
+                    proxy.proxy_type, proxy.confidence
+                ),
+            }
+        }
+        if let Some(isp) = record.isp {
+            println!("  isp: {} ({}, {})", isp.isp, isp.domain, isp.provider);
+        }
+        if let Some(group) = reader.lookup_isp_group(record.ip) {
+            println!("  isp group: {} {}", group.id, group.name);
+        }
+        if let Some(mobile) = record.mobile {
+            println!(
+                "  mobile: {} (mcc {}, mnc {})",
+                mobile.mobile_brand, mobile.mcc, mobile.mnc
+            );
+        }
+        if let Some(usage) = record.usage_type {
+            println!("  usage_type: {}", usage.usage_type);
+        }
+        if let Some(meters) = record.elevation {
+            println!("  elevation: {} m", meters);
+        }
+        if let Some(network_type) = reader.lookup_network_type(record.ip) {
+            println!("  network type: {}", network_type);
+        }
+        if let Some(connection_type) = reader.lookup_connection_type(record.ip) {
+            println!("  connection type: {}", connection_type);
+        }
+        if let Some(domain) = reader
+            .domain
+            .as_ref()
+            .and_then(|_| reader.lookup_domain(record.ip))
+        {
+            println!("  domain: {}", domain);
+        }
+        if let Some(rdns) = reader.lookup_rdns(record.ip) {
+            println!(
+                "  rdns: {} ({}, {}/{} hosts)",
+                rdns.suffix, rdns.pattern, rdns.hosts, rdns.total
+            );
+        }
+        for threat in reader.lookup_threats(record.ip) {
+            match threat.reference {
+                Some(reference) => println!(
+                    "  threat: {} ({}, {})",
+                    threat.list, threat.category, reference
+                ),
+                None => println!("  threat: {} ({})", threat.list, threat.category),
+            }
+        }
+    }
+    Ok(())
+}
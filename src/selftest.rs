@@ -0,0 +1,380 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Error, ErrorKind, Result};
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::cli::Args;
+use crate::csv_reader::parse_line;
+use crate::format::{coordinate_scale, read_layout, Dataset, Kind};
+use crate::ip::{ip_to_u128, ipv4_to_ipv6, range_to_cidrs, u128_to_ip};
+use crate::logging;
+use crate::maxmind::MaxMindReader;
+use crate::merge::{CsvPriority, GeoPriority};
+use crate::reader::{AsnRecord, Ip2xReader, IspRecord};
+use crate::{
+    csv_priority, default_data_dir, geo_priority, load_asn_organizations, parse_f32, parse_u128,
+    ASN_V4_CSV, ASN_V6_CSV, GEO_V4_CSV, GEO_V6_CSV, MAXMIND_ANONYMOUS, MAXMIND_ASN, MAXMIND_CITY,
+    PROXY_V4_CSV, PROXY_V6_CSV,
+};
+
+const MAX_REPORTED: usize = 10;
+const COORDINATE_SLACK: f32 = 2e-5;
+
+pub struct Rng(pub u64);
+
+impl Rng {
+    pub fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, bound: u128) -> u128 {
+        let wide = ((self.next() as u128) << 64) | self.next() as u128;
+        if bound == 0 {
+            wide
+        } else {
+            wide % bound
+        }
+    }
+}
+
+type Candidates<T> = Vec<(u128, u128, T)>;
+
+#[derive(Default)]
+struct Expected {
+    ip2location: Candidates<(f32, f32)>,
+    maxmind: Candidates<(f32, f32)>,
+    asn: Candidates<AsnRecord>,
+    proxy: Candidates<String>,
+    anonymous: Vec<&'static str>,
+    isp: Candidates<IspRecord>,
+}
+
+#[derive(Clone, Copy)]
+struct Options {
+    geo_priority: GeoPriority,
+    csv_priority: CsvPriority,
+    cidr_aligned: bool,
+}
+
+impl Options {
+    fn from_args(args: &Args) -> Options {
+        Options {
+            geo_priority: geo_priority(args),
+            csv_priority: csv_priority(args),
+            cidr_aligned: args.flag("--cidr-aligned"),
+        }
+    }
+
+    fn size(self, ip: u128, from: u128, to: u128) -> u128 {
+        if !self.cidr_aligned {
+            return to - from;
+        }
+        range_to_cidrs(from, to)
+            .into_iter()
+            .map(|(network, prefix)| (network, u128::MAX.checked_shr(prefix as u32).unwrap_or(0)))
+            .find(|&(network, hosts)| (network..=network + hosts).contains(&ip))
+            .map_or(to - from, |(_, hosts)| hosts)
+    }
+
+    fn smallest<T>(self, ip: u128, candidates: &[(u128, u128, T)]) -> Vec<&T> {
+        let size = |c: &(u128, u128, T)| self.size(ip, c.0, c.1);
+        let smallest = candidates.iter().map(size).min();
+        candidates
+            .iter()
+            .filter(|c| Some(size(c)) == smallest)
+            .map(|c| &c.2)
+            .collect()
+    }
+
+    fn geo(self, ip: u128, expected: &Expected) -> Vec<(f32, f32)> {
+        let (first, second) = (&expected.ip2location, &expected.maxmind);
+        let layer = match self.geo_priority {
+            GeoPriority::Ip2LocationFirst if !first.is_empty() => first.clone(),
+            GeoPriority::MaxMindFirst if !second.is_empty() => second.clone(),
+            GeoPriority::Ip2LocationFirst => second.clone(),
+            GeoPriority::MaxMindFirst => first.clone(),
+            GeoPriority::MostSpecific => {
+                let taken = |m: &&(u128, u128, _)| first.iter().any(|g| (g.0, g.1) == (m.0, m.1));
+                first
+                    .iter()
+                    .chain(second.iter().filter(|m| !taken(m)))
+                    .cloned()
+                    .collect()
+            }
+        };
+        self.smallest(ip, &layer).into_iter().copied().collect()
+    }
+}
+
+pub fn run(args: &Args) -> Result<()> {
+    let samples: usize = args
+        .value(&["--samples"])
+        .unwrap_or("10000")
+        .parse()
+        .map_err(|_| Error::new(ErrorKind::InvalidInput, "--samples must be a number"))?;
+    let data_dir = args
+        .value(&["--data"])
+        .map(str::to_string)
+        .unwrap_or_else(default_data_dir);
+    let bins_dir = args.value(&["--bins"]).unwrap_or(".");
+    let seed = match args.value(&["--seed"]) {
+        Some(seed) => seed
+            .parse()
+            .map_err(|_| Error::new(ErrorKind::InvalidInput, "--seed must be a number"))?,
+        None => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(1),
+    };
+
+    let reader = Ip2xReader::open(bins_dir)?;
+    let geo_path = Path::new(bins_dir).join(Kind::Geo.file_name());
+    let tolerance = match read_layout(&geo_path.to_string_lossy()) {
+        Ok(layout) => 0.5 / coordinate_scale(layout) as f32 + COORDINATE_SLACK,
+        Err(_) => 0.0,
+    };
+    let guide = match Dataset::read(&geo_path.to_string_lossy(), Kind::Geo) {
+        Ok(Dataset::Geo(ranges)) => ranges.into_iter().map(|r| (r.0, r.1)).collect(),
+        _ => Vec::new(),
+    };
+
+    let mut rng = Rng(seed | 1);
+    let mut ips: Vec<u128> = (0..samples).map(|_| sample(&mut rng, &guide)).collect();
+    ips.sort_unstable();
+
+    let options = Options::from_args(args);
+    let expected = expected_from_sources(&data_dir, &ips, options);
+
+    println!("Self-test: {} samples, seed {}", samples, seed);
+    let mut mismatches = Vec::new();
+    for (ip, expected) in ips.iter().zip(&expected) {
+        compare(
+            &reader,
+            u128_to_ip(*ip),
+            expected,
+            options,
+            tolerance,
+            &mut mismatches,
+        );
+    }
+
+    for artifact in ["geo", "asn", "proxy", "isp"] {
+        let count = mismatches.iter().filter(|(a, _)| *a == artifact).count();
+        println!("  {}: {} mismatches", artifact, count);
+    }
+    for (artifact, message) in mismatches.iter().take(MAX_REPORTED) {
+        println!("  [{}] {}", artifact, message);
+    }
+
+    if !mismatches.is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("{} mismatches between bins and sources", mismatches.len()),
+        ));
+    }
+    Ok(())
+}
+
+fn sample(rng: &mut Rng, guide: &[(u128, u128)]) -> u128 {
+    if guide.is_empty() || rng.next().is_multiple_of(2) {
+        return ipv4_to_ipv6(rng.next() as u32);
+    }
+    let (from, to) = guide[rng.below(guide.len() as u128) as usize];
+    from + rng.below((to - from).wrapping_add(1))
+}
+
+fn expected_from_sources(data_dir: &str, ips: &[u128], options: Options) -> Vec<Expected> {
+    let mut expected: Vec<Expected> = (0..ips.len()).map(|_| Expected::default()).collect();
+    let path = |file: &str| format!("{}/{}", data_dir, file);
+    let csvs = |v4, v6| options.csv_priority.order(v4, v6);
+
+    for (file, is_v4) in csvs(GEO_V4_CSV, GEO_V6_CSV) {
+        scan_csv(&path(file), is_v4, ips, |i, from, to, parts| {
+            if parts.len() < 8 {
+                return;
+            }
+            let (lat, lon) = (parse_f32(&parts[6]), parse_f32(&parts[7]));
+            let geo = &mut expected[i].ip2location;
+            if (lat != 0.0 || lon != 0.0) && !geo.iter().any(|g| (g.0, g.1) == (from, to)) {
+                geo.push((from, to, (lat, lon)));
+            }
+        });
+    }
+
+    if let Ok(maxmind) = MaxMindReader::open(&path(MAXMIND_CITY)) {
+        for (i, ip) in ips.iter().enumerate() {
+            let Some((from, to, record)) = maxmind.lookup_network(&u128_to_ip(*ip).to_string())
+            else {
+                continue;
+            };
+            let Some((lat, lon)) = MaxMindReader::location(&record) else {
+                continue;
+            };
+            if lat != 0.0 || lon != 0.0 {
+                expected[i].maxmind.push((from, to, (lat, lon)));
+            }
+        }
+    }
+
+    let organizations = load_asn_organizations(&path(MAXMIND_ASN));
+    for (file, is_v4) in csvs(ASN_V4_CSV, ASN_V6_CSV) {
+        scan_csv(&path(file), is_v4, ips, |i, from, to, parts| {
+            if parts.len() < 5 || parts[3] == "-" {
+                return;
+            }
+            let record = AsnRecord {
+                cidr: parts[2].clone(),
+                asn: parts[3].clone(),
+                as_name: parts[4].clone(),
+                organization: parts[3]
+                    .parse()
+                    .ok()
+                    .and_then(|asn: u64| organizations.get(&asn).cloned()),
+            };
+            keep_first(&mut expected[i].asn, from, to, record);
+        });
+    }
+
+    for (file, is_v4) in csvs(PROXY_V4_CSV, PROXY_V6_CSV) {
+        scan_csv(&path(file), is_v4, ips, |i, from, to, parts| {
+            if parts.len() < 3 {
+                return;
+            }
+            keep_first(&mut expected[i].proxy, from, to, parts[2].clone());
+            if parts.len() < 9 {
+                return;
+            }
+            let record = IspRecord {
+                isp: parts[7].clone(),
+                domain: parts[8].clone(),
+                provider: parts.get(13).cloned().unwrap_or_else(|| "-".to_string()),
+            };
+            keep_first(&mut expected[i].isp, from, to, record);
+        });
+    }
+
+    if let Ok(maxmind) = MaxMindReader::open(&path(MAXMIND_ANONYMOUS)) {
+        for (i, ip) in ips.iter().enumerate() {
+            if let Some(record) = maxmind.lookup(&u128_to_ip(*ip).to_string()) {
+                expected[i].anonymous = MaxMindReader::anonymous_types(&record);
+            }
+        }
+    }
+
+    expected
+}
+
+fn scan_csv(
+    path: &str,
+    is_v4: bool,
+    ips: &[u128],
+    mut visit: impl FnMut(usize, u128, u128, &[String]),
+) {
+    let Ok(file) = File::open(path) else {
+        logging::warn(&format!("skipped: {} not found", path));
+        return;
+    };
+
+    for line in BufReader::new(file).lines().map_while(|line| line.ok()) {
+        let parts = parse_line(&line);
+        if parts.len() < 2 {
+            continue;
+        }
+
+        let (mut from, mut to) = (parse_u128(&parts[0]), parse_u128(&parts[1]));
+        if is_v4 {
+            from = ipv4_to_ipv6(from as u32);
+            to = ipv4_to_ipv6(to as u32);
+        }
+
+        let first = ips.partition_point(|ip| *ip < from);
+        let last = ips.partition_point(|ip| *ip <= to);
+        for i in first..last {
+            visit(i, from, to, &parts);
+        }
+    }
+}
+
+fn keep_first<T>(candidates: &mut Candidates<T>, from: u128, to: u128, value: T) {
+    if !candidates.iter().any(|c| (c.0, c.1) == (from, to)) {
+        candidates.push((from, to, value));
+    }
+}
+
+fn compare(
+    reader: &Ip2xReader,
+    ip: IpAddr,
+    expected: &Expected,
+    options: Options,
+    tolerance: f32,
+    mismatches: &mut Vec<(&'static str, String)>,
+) {
+    let target = ip_to_u128(ip);
+    let accepted = options.geo(target, expected);
+    let record = reader.lookup_geo(ip);
+    let actual = record.as_ref().map(|g| (g.latitude, g.longitude));
+    let geo_ok = match (&record, reader.geo_grid()) {
+        (Some(record), Some(grid)) => accepted
+            .iter()
+            .any(|g| grid.cell(g.0, g.1).ok() == record.cell),
+        (Some(record), None) => accepted.iter().any(|g| {
+            (g.0 - record.latitude).abs() <= tolerance
+                && (g.1 - record.longitude).abs() <= tolerance
+        }),
+        (None, _) => accepted.is_empty(),
+    };
+    if !geo_ok {
+        mismatches.push((
+            "geo",
+            format!("{}: expected {:?}, got {:?}", ip, accepted.first(), actual),
+        ));
+    }
+
+    check(
+        "asn",
+        ip,
+        options.smallest(target, &expected.asn),
+        reader.lookup_asn(ip),
+        mismatches,
+    );
+
+    let actual_proxy = reader.lookup_proxy(ip).map(|p| p.proxy_type);
+    let anonymous = actual_proxy
+        .as_deref()
+        .is_some_and(|t| expected.anonymous.contains(&t));
+    if !anonymous {
+        let accepted = options.smallest(target, &expected.proxy);
+        check("proxy", ip, accepted, actual_proxy, mismatches);
+    }
+
+    check(
+        "isp",
+        ip,
+        options.smallest(target, &expected.isp),
+        reader.lookup_isp(ip),
+        mismatches,
+    );
+}
+
+fn check<T: PartialEq + std::fmt::Debug>(
+    artifact: &'static str,
+    ip: IpAddr,
+    accepted: Vec<&T>,
+    actual: Option<T>,
+    mismatches: &mut Vec<(&'static str, String)>,
+) {
+    let ok = match &actual {
+        Some(actual) => accepted.contains(&actual),
+        None => accepted.is_empty(),
+    };
+    if !ok {
+        mismatches.push((
+            artifact,
+            format!("{}: expected {:?}, got {:?}", ip, accepted.first(), actual),
+        ));
+    }
+}
@@ -0,0 +1,423 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Error, ErrorKind, Result, Seek, SeekFrom};
+use std::net::IpAddr;
+#[cfg(feature = "watch")]
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::extract::{Path, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use bytes::BytesMut;
+use futures::stream;
+use serde_json::json;
+use tokio::io::{AsyncReadExt, Take};
+use tokio::net::TcpListener;
+
+use crate::cli::Args;
+use crate::logging;
+use crate::manifest::{self, MANIFEST_FILE};
+use crate::metrics;
+use crate::reader::{self, Ip2xReader};
+use crate::update::Updater;
+
+const DEFAULT_BIND: &str = "127.0.0.1:8080";
+const MAX_BATCH: usize = 1000;
+const DEFAULT_UPDATE_INTERVAL: u64 = 300;
+const CHUNK_SIZE: usize = 64 * 1024;
+
+type Shared = Arc<Live>;
+
+pub struct Live {
+    args: Args,
+    reader: RwLock<Arc<Ip2xReader>>,
+    checksums: RwLock<Arc<HashMap<String, String>>>,
+}
+
+impl Live {
+    pub fn open(args: &Args) -> Result<Live> {
+        let reader = reader::open_from_args(args)?;
+        let checksums = manifest::checksums(bins_dir(args))?;
+        metrics::load_dataset(bins_dir(args));
+        Ok(Live {
+            args: args.clone(),
+            reader: RwLock::new(Arc::new(reader)),
+            checksums: RwLock::new(Arc::new(checksums)),
+        })
+    }
+
+    pub fn get(&self) -> Arc<Ip2xReader> {
+        self.reader
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .clone()
+    }
+
+    pub fn reload(&self) -> Result<()> {
+        let dir = bins_dir(&self.args);
+        let loaded = manifest::verify(dir).and_then(|_| {
+            Ok((
+                reader::open_from_args(&self.args)?,
+                manifest::checksums(dir)?,
+            ))
+        });
+        let (reader, checksums) = loaded.map_err(|err| {
+            Error::new(
+                err.kind(),
+                format!(
+                    "Reloading {} failed, keeping the current bins: {}",
+                    dir, err
+                ),
+            )
+        })?;
+        *self.reader.write().unwrap_or_else(|err| err.into_inner()) = Arc::new(reader);
+        *self
+            .checksums
+            .write()
+            .unwrap_or_else(|err| err.into_inner()) = Arc::new(checksums);
+        metrics::load_dataset(dir);
+        logging::info(&format!("reloaded bins from {}", dir));
+        Ok(())
+    }
+
+    fn checksums(&self) -> Arc<HashMap<String, String>> {
+        self.checksums
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .clone()
+    }
+}
+
+fn bins_dir(args: &Args) -> &str {
+    args.value(&["--bins"]).unwrap_or(".")
+}
+
+fn failure(status: StatusCode, message: String) -> Response {
+    (status, Json(json!({ "error": message }))).into_response()
+}
+
+fn parse_ips(ips: &[String]) -> std::result::Result<Vec<IpAddr>, String> {
+    ips.iter()
+        .map(|ip| ip.parse().map_err(|_| format!("Bad IP address: {}", ip)))
+        .collect()
+}
+
+async fn lookup(State(live): State<Shared>, Path(ip): Path<String>) -> Response {
+    let timer = metrics::timer("http");
+    match parse_ips(&[ip]) {
+        Ok(ips) => {
+            let records = live.get().lookup_many(&ips);
+            timer.observe(ips.len());
+            Json(&records[0]).into_response()
+        }
+        Err(message) => failure(StatusCode::BAD_REQUEST, message),
+    }
+}
+
+async fn lookup_batch(State(live): State<Shared>, Json(ips): Json<Vec<String>>) -> Response {
+    if ips.len() > MAX_BATCH {
+        return failure(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("At most {} IPs per batch", MAX_BATCH),
+        );
+    }
+    let timer = metrics::timer("http_batch");
+    match parse_ips(&ips) {
+        Ok(ips) => {
+            let records = live.get().lookup_many(&ips);
+            timer.observe(ips.len());
+            Json(records).into_response()
+        }
+        Err(message) => failure(StatusCode::BAD_REQUEST, message),
+    }
+}
+
+fn byte_range(header: Option<&str>, len: u64) -> Option<Option<(u64, u64)>> {
+    let Some((first, last)) = header
+        .and_then(|header| header.strip_prefix("bytes="))
+        .filter(|ranges| !ranges.contains(','))
+        .and_then(|range| range.trim().split_once('-'))
+    else {
+        return Some(None);
+    };
+    let range = match (first.parse::<u64>(), last.parse::<u64>()) {
+        (Ok(first), Ok(last)) if first <= last => (first, last.min(len.saturating_sub(1))),
+        (Ok(first), Err(_)) if last.is_empty() => (first, len.saturating_sub(1)),
+        (Err(_), Ok(suffix)) if first.is_empty() && suffix > 0 => {
+            (len.saturating_sub(suffix), len.saturating_sub(1))
+        }
+        _ => return Some(None),
+    };
+    (range.0 < len).then_some(Some(range))
+}
+
+fn file_body(mut file: File, start: u64, len: u64) -> Result<Body> {
+    file.seek(SeekFrom::Start(start))?;
+    let file = tokio::fs::File::from_std(file).take(len);
+    let chunks = stream::try_unfold(file, |mut file: Take<tokio::fs::File>| async move {
+        let mut chunk = BytesMut::with_capacity(CHUNK_SIZE);
+        match file.read_buf(&mut chunk).await? {
+            0 if file.limit() > 0 => Err(Error::from(ErrorKind::UnexpectedEof)),
+            0 => Ok(None),
+            _ => Ok(Some((chunk.freeze(), file))),
+        }
+    });
+    Ok(Body::from_stream(chunks))
+}
+
+fn serve_file(
+    path: std::path::PathBuf,
+    checksum: Option<String>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let file = File::open(&path)?;
+    let len = file.metadata()?.len();
+    let checksum = match checksum {
+        Some(checksum) => checksum,
+        None => manifest::sha256(&path)?,
+    };
+    let etag = format!("\"{}\"", checksum);
+    let text = |name: header::HeaderName| headers.get(name).and_then(|value| value.to_str().ok());
+    let cached = text(header::IF_NONE_MATCH).is_some_and(|tags| {
+        tags.split(',')
+            .map(|tag| tag.trim().trim_start_matches("W/"))
+            .any(|tag| tag == "*" || tag == etag)
+    });
+    if cached {
+        return Ok((StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response());
+    }
+    let range = match text(header::IF_RANGE) {
+        Some(tag) if tag != etag => Some(None),
+        _ => byte_range(text(header::RANGE), len),
+    };
+    let content_type = match path.extension().is_some_and(|ext| ext == "json") {
+        true => "application/json",
+        false => "application/octet-stream",
+    };
+    let common = [
+        (header::ETAG, etag),
+        (header::ACCEPT_RANGES, "bytes".to_string()),
+        (header::CONTENT_TYPE, content_type.to_string()),
+    ];
+    Ok(match range {
+        None => (
+            StatusCode::RANGE_NOT_SATISFIABLE,
+            [(header::CONTENT_RANGE, format!("bytes */{}", len))],
+        )
+            .into_response(),
+        Some(None) => (
+            common,
+            [(header::CONTENT_LENGTH, len.to_string())],
+            file_body(file, 0, len)?,
+        )
+            .into_response(),
+        Some(Some((first, last))) => {
+            let content_range = format!("bytes {}-{}/{}", first, last, len);
+            (
+                StatusCode::PARTIAL_CONTENT,
+                common,
+                [
+                    (header::CONTENT_RANGE, content_range),
+                    (header::CONTENT_LENGTH, (last - first + 1).to_string()),
+                ],
+                file_body(file, first, last - first + 1)?,
+            )
+                .into_response()
+        }
+    })
+}
+
+async fn dataset(
+    State(live): State<Shared>,
+    Path(artifact): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let checksums = live.checksums();
+    let listed = |name: &str| name == MANIFEST_FILE || checksums.contains_key(name);
+    let listed = listed(&artifact) || artifact.strip_suffix(".sig").is_some_and(listed);
+    if !listed {
+        return failure(
+            StatusCode::NOT_FOUND,
+            format!("Unknown artifact: {}", artifact),
+        );
+    }
+    let path = std::path::Path::new(bins_dir(&live.args)).join(&artifact);
+    let checksum = checksums.get(&artifact).cloned();
+    match tokio::task::spawn_blocking(move || serve_file(path, checksum, headers)).await {
+        Ok(Ok(response)) => response,
+        Ok(Err(err)) if err.kind() == ErrorKind::NotFound => failure(
+            StatusCode::NOT_FOUND,
+            format!("Artifact not found: {}", artifact),
+        ),
+        Ok(Err(err)) => failure(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
+        Err(err) => failure(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
+    }
+}
+
+#[cfg(feature = "metrics")]
+async fn scrape(State(live): State<Shared>) -> String {
+    metrics::render(&live.get())
+}
+
+pub fn router(live: Shared) -> Router {
+    let router = Router::new()
+        .route("/v1/lookup/{ip}", get(lookup))
+        .route("/v1/lookup", post(lookup_batch))
+        .route("/v1/dataset/{*artifact}", get(dataset));
+    #[cfg(feature = "metrics")]
+    let router = router.route("/metrics", get(scrape));
+    router.with_state(live)
+}
+
+fn reload(live: &Live) {
+    if let Err(err) = live.reload() {
+        logging::error(&err);
+    }
+}
+
+#[cfg(unix)]
+fn reload_on_hangup(live: Shared) -> Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = signal(SignalKind::hangup())?;
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            let live = live.clone();
+            let _ = tokio::task::spawn_blocking(move || reload(&live)).await;
+        }
+    });
+    Ok(())
+}
+
+#[cfg(feature = "watch")]
+fn reload_on_change(live: Shared) {
+    let is_artifact = |path: &PathBuf| {
+        path.file_name().is_some_and(|name| name == MANIFEST_FILE)
+            || path
+                .extension()
+                .is_some_and(|ext| ext == "bin" || ext == "sig")
+    };
+    std::thread::spawn(move || {
+        let dir = bins_dir(&live.args).to_string();
+        crate::watch::run(&dir, |changed| match changed.iter().any(is_artifact) {
+            true => live.reload(),
+            false => Ok(()),
+        })
+    });
+}
+
+pub fn run(args: &Args) -> Result<()> {
+    let bind = args.value(&["--bind"]).unwrap_or(DEFAULT_BIND);
+    let grpc_bind = args.value(&["--grpc-bind"]);
+    if grpc_bind.is_some() && !cfg!(feature = "grpc") {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            "--grpc-bind requires building with --features grpc",
+        ));
+    }
+    if args.flag("--watch") && !cfg!(feature = "watch") {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            "--watch requires building with --features watch",
+        ));
+    }
+    let interval = match args.value(&["--update-interval"]) {
+        Some(secs) => secs.parse().ok().filter(|&secs| secs > 0).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                "--update-interval must be a positive number of seconds",
+            )
+        })?,
+        None => DEFAULT_UPDATE_INTERVAL,
+    };
+    let live = Arc::new(Live::open(args)?);
+    if let Some(url) = args.value(&["--auto-update"]) {
+        let dir = bins_dir(args);
+        let updater = match args.value(&["--public-key"]) {
+            Some(public_key) => Updater::new_verified(url, dir, public_key)?,
+            None => Updater::new(url, dir)?,
+        };
+        let updater = match args.value(&["--ca-bundle"]) {
+            Some(path) => updater.ca_bundle(path)?,
+            None => updater,
+        };
+        let live = live.clone();
+        updater.spawn(Duration::from_secs(interval), move || live.reload());
+    }
+    #[cfg(feature = "watch")]
+    if args.flag("--watch") {
+        reload_on_change(live.clone());
+    }
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+    runtime.block_on(async {
+        let listener = TcpListener::bind(bind)
+            .await
+            .map_err(|err| Error::new(err.kind(), format!("Cannot bind {}: {}", bind, err)))?;
+        logging::info(&format!("serving lookups on {}", listener.local_addr()?));
+        #[cfg(unix)]
+        reload_on_hangup(live.clone())?;
+        #[cfg(feature = "grpc")]
+        if let Some(addr) = grpc_bind {
+            let grpc = crate::grpc::bind(live.clone(), addr).await?;
+            let http = async { axum::serve(listener, router(live)).await };
+            return tokio::try_join!(http, grpc).map(|_| ());
+        }
+        axum::serve(listener, router(live)).await
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body(response: Response) -> Vec<u8> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let bytes = runtime.block_on(axum::body::to_bytes(response.into_body(), usize::MAX));
+        bytes.unwrap().to_vec()
+    }
+
+    #[test]
+    fn streams_whole_files_and_ranges() {
+        let path = std::env::temp_dir().join(format!("ip2x-serve-{}.bin", std::process::id()));
+        let data: Vec<u8> = (0..3 * CHUNK_SIZE as u32).map(|i| i as u8).collect();
+        std::fs::write(&path, &data).unwrap();
+        let request = |range: Option<&str>| {
+            let mut headers = HeaderMap::new();
+            if let Some(range) = range {
+                headers.insert(header::RANGE, range.parse().unwrap());
+            }
+            serve_file(path.clone(), Some("sum".to_string()), headers).unwrap()
+        };
+
+        let whole = request(None);
+        assert_eq!(whole.status(), StatusCode::OK);
+        assert_eq!(
+            whole.headers()[header::CONTENT_LENGTH],
+            data.len().to_string()
+        );
+        assert_eq!(body(whole), data);
+
+        let first = CHUNK_SIZE as u64 - 3;
+        let last = 2 * CHUNK_SIZE as u64 + 5;
+        let partial = request(Some(&format!("bytes={}-{}", first, last)));
+        assert_eq!(partial.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            partial.headers()[header::CONTENT_RANGE],
+            format!("bytes {}-{}/{}", first, last, data.len())
+        );
+        assert_eq!(body(partial), data[first as usize..=last as usize]);
+
+        let tail = request(Some("bytes=-4"));
+        assert_eq!(body(tail), data[data.len() - 4..]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
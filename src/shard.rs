@@ -0,0 +1,159 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Result, Write};
+use std::path::Path;
+
+use serde_json::json;
+
+use crate::extract::{remap_asn, remap_offset};
+use crate::format::Dataset;
+use crate::interval::subtract;
+use crate::ip::{format_cidr, ipv4_to_ipv6};
+
+pub const SHARD_DIR: &str = "shards";
+pub const INDEX_FILE: &str = "index.json";
+
+type Span = (u128, u128, usize);
+
+fn shards(bits: u32) -> (Vec<(String, String)>, Vec<Span>) {
+    let width = bits.div_ceil(4) as usize;
+    let v4 = (ipv4_to_ipv6(0), ipv4_to_ipv6(u32::MAX));
+    let mut names = Vec::new();
+    let mut spans = Vec::new();
+    for i in 0..1u128 << bits {
+        let from = v4.0 | i << (32 - bits);
+        spans.push((from, from | (u32::MAX >> bits) as u128, names.len()));
+        names.push((
+            format!("v4-{:0width$x}.bin", i),
+            format_cidr(from, 96 + bits as u8),
+        ));
+    }
+    for i in 0..1u128 << bits {
+        let from = i << (128 - bits);
+        for (from, to) in subtract(from, from | u128::MAX >> bits, &[v4]) {
+            spans.push((from, to, names.len()));
+        }
+        names.push((
+            format!("v6-{:0width$x}.bin", i),
+            format_cidr(from, bits as u8),
+        ));
+    }
+    spans.sort_unstable();
+    (names, spans)
+}
+
+fn split<T: Clone>(
+    ranges: &[T],
+    spans: &[Span],
+    count: usize,
+    range: impl Fn(&T) -> (u128, u128),
+) -> Vec<Vec<T>> {
+    let mut buckets = vec![Vec::new(); count];
+    let mut stamp = vec![usize::MAX; count];
+    for (i, r) in ranges.iter().enumerate() {
+        let (from, to) = range(r);
+        let first = spans.partition_point(|s| s.1 < from);
+        for &(_, _, shard) in spans[first..].iter().take_while(|s| s.0 <= to) {
+            if stamp[shard] != i {
+                stamp[shard] = i;
+                buckets[shard].push(r.clone());
+            }
+        }
+    }
+    buckets
+}
+
+fn split_dataset(dataset: &Dataset, spans: &[Span], count: usize) -> Vec<Dataset> {
+    match dataset {
+        Dataset::Geo(ranges) => split(ranges, spans, count, |r| (r.0, r.1))
+            .into_iter()
+            .map(Dataset::Geo)
+            .collect(),
+        Dataset::Proxy(types) => {
+            let mut shards = vec![Vec::new(); count];
+            for (name, ranges) in types {
+                for (shard, ranges) in split(ranges, spans, count, |r| (r.0, r.1))
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(_, ranges)| !ranges.is_empty())
+                {
+                    shards[shard].push((name.clone(), ranges));
+                }
+            }
+            shards.into_iter().map(Dataset::Proxy).collect()
+        }
+        Dataset::Asn(strings, data) => split(data, spans, count, |r| (r.0, r.1))
+            .into_iter()
+            .map(|data| {
+                let (strings, data) = remap_asn(strings, data);
+                Dataset::Asn(strings, data)
+            })
+            .collect(),
+        Dataset::Isp(strings, data) => split(data, spans, count, |r| (r.0, r.1))
+            .into_iter()
+            .map(|data| {
+                let (strings, data) = remap_offset(strings, data);
+                Dataset::Isp(strings, data)
+            })
+            .collect(),
+        Dataset::Mobile(strings, data) => split(data, spans, count, |r| (r.0, r.1))
+            .into_iter()
+            .map(|data| {
+                let (strings, data) = remap_offset(strings, data);
+                Dataset::Mobile(strings, data)
+            })
+            .collect(),
+        Dataset::Usage(ranges) => split(ranges, spans, count, |r| (r.0, r.1))
+            .into_iter()
+            .map(Dataset::Usage)
+            .collect(),
+        Dataset::Elevation(ranges) => split(ranges, spans, count, |r| (r.0, r.1))
+            .into_iter()
+            .map(Dataset::Elevation)
+            .collect(),
+        Dataset::Country(ranges) => split(ranges, spans, count, |r| (r.0, r.1))
+            .into_iter()
+            .map(Dataset::Country)
+            .collect(),
+    }
+}
+
+pub fn write(
+    out: &Path,
+    dataset: &Dataset,
+    bits: u32,
+    write: impl Fn(&Dataset, &str) -> Result<()>,
+) -> Result<Vec<(String, Option<usize>)>> {
+    let kind = dataset.kind();
+    let dir = Path::new(SHARD_DIR).join(kind.name());
+    fs::create_dir_all(out.join(&dir))?;
+    let (names, spans) = shards(bits);
+
+    let mut artifacts = Vec::new();
+    let mut entries = Vec::new();
+    for ((file, prefix), shard) in names
+        .iter()
+        .zip(split_dataset(dataset, &spans, names.len()))
+    {
+        if shard.len() == 0 {
+            continue;
+        }
+        let path = dir.join(file).to_string_lossy().into_owned();
+        write(&shard, &out.join(&path).to_string_lossy())?;
+        entries.push(json!({ "prefix": prefix, "path": file, "records": shard.len() }));
+        artifacts.push((path, Some(shard.len())));
+    }
+
+    let path = dir.join(INDEX_FILE).to_string_lossy().into_owned();
+    let count = entries.len();
+    let index = json!({
+        "artifact": kind.file_name(),
+        "bits": bits,
+        "shards": entries,
+    });
+    let mut out = BufWriter::new(File::create(out.join(&path))?);
+    serde_json::to_writer_pretty(&mut out, &index)?;
+    out.write_all(b"\n")?;
+    out.flush()?;
+    artifacts.push((path, Some(count)));
+    Ok(artifacts)
+}
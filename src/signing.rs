@@ -0,0 +1,110 @@
+#[cfg(feature = "sign")]
+mod keys {
+    use std::fs;
+    use std::io::{Error, ErrorKind, Result};
+
+    use ed25519_dalek::pkcs8::{DecodePrivateKey, DecodePublicKey};
+    use ed25519_dalek::{Signature, Signer as _, SigningKey, VerifyingKey};
+
+    fn signature_path(path: &str) -> String {
+        format!("{}.sig", path)
+    }
+
+    fn read_key(path: &str) -> Result<String> {
+        fs::read_to_string(path).map_err(|err| Error::new(err.kind(), format!("{}: {}", path, err)))
+    }
+
+    fn invalid(path: &str, err: impl std::fmt::Display) -> Error {
+        Error::new(ErrorKind::InvalidData, format!("{}: {}", path, err))
+    }
+
+    pub struct Signer(SigningKey);
+
+    impl Signer {
+        pub fn open(key_path: &str) -> Result<Signer> {
+            let pem = read_key(key_path)?;
+            SigningKey::from_pkcs8_pem(&pem)
+                .map(Signer)
+                .map_err(|err| invalid(key_path, err))
+        }
+
+        pub fn sign(&self, path: &str) -> Result<()> {
+            let signature = self.0.sign(&fs::read(path)?);
+            fs::write(signature_path(path), signature.to_bytes())
+        }
+    }
+
+    pub struct Verifier(VerifyingKey);
+
+    impl Verifier {
+        pub fn open(key_path: &str) -> Result<Verifier> {
+            let pem = read_key(key_path)?;
+            VerifyingKey::from_public_key_pem(&pem)
+                .map(Verifier)
+                .map_err(|err| invalid(key_path, err))
+        }
+
+        pub fn verify(&self, path: &str) -> Result<()> {
+            let sig_path = signature_path(path);
+            let bytes = fs::read(&sig_path).map_err(|err| {
+                Error::new(
+                    err.kind(),
+                    format!("Missing signature {}: {}", sig_path, err),
+                )
+            })?;
+            let signature = Signature::from_slice(&bytes).map_err(|err| invalid(&sig_path, err))?;
+            self.0
+                .verify_strict(&fs::read(path)?, &signature)
+                .map_err(|_| invalid(path, "signature does not match"))
+        }
+
+        pub fn verify_bytes(&self, name: &str, data: &[u8], signature: &[u8]) -> Result<()> {
+            let signature = Signature::from_slice(signature).map_err(|err| invalid(name, err))?;
+            self.0
+                .verify_strict(data, &signature)
+                .map_err(|_| invalid(name, "signature does not match"))
+        }
+    }
+}
+
+#[cfg(not(feature = "sign"))]
+mod keys {
+    use std::io::{Error, ErrorKind, Result};
+
+    fn unsupported<T>() -> Result<T> {
+        Err(Error::new(
+            ErrorKind::Unsupported,
+            "Signing requires building with --features sign",
+        ))
+    }
+
+    pub enum Signer {}
+
+    impl Signer {
+        pub fn open(_key_path: &str) -> Result<Signer> {
+            unsupported()
+        }
+
+        pub fn sign(&self, _path: &str) -> Result<()> {
+            match *self {}
+        }
+    }
+
+    pub enum Verifier {}
+
+    impl Verifier {
+        pub fn open(_key_path: &str) -> Result<Verifier> {
+            unsupported()
+        }
+
+        pub fn verify(&self, _path: &str) -> Result<()> {
+            match *self {}
+        }
+
+        pub fn verify_bytes(&self, _name: &str, _data: &[u8], _signature: &[u8]) -> Result<()> {
+            match *self {}
+        }
+    }
+}
+
+pub use keys::{Signer, Verifier};
@@ -0,0 +1,226 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Result, Write};
+use std::mem::size_of;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::format::{GeoRange, Order};
+
+const MAX_RUNS: usize = 64;
+
+static RUN_ID: AtomicUsize = AtomicUsize::new(0);
+
+type SortKey = (u128, u128, u64);
+
+pub fn parse_size(value: &str) -> Option<usize> {
+    let value = value.trim();
+    let (digits, scale) = match value.char_indices().last()? {
+        (i, 'K' | 'k') => (&value[..i], 1 << 10),
+        (i, 'M' | 'm') => (&value[..i], 1 << 20),
+        (i, 'G' | 'g') => (&value[..i], 1 << 30),
+        _ => (value, 1),
+    };
+    digits.parse::<usize>().ok()?.checked_mul(scale)
+}
+
+pub trait Record: Sized {
+    fn range(&self) -> (u128, u128);
+    fn write_to(&self, out: &mut impl Write) -> Result<()>;
+    fn read_from(input: &mut impl Read) -> Result<Self>;
+}
+
+fn read_array<const N: usize>(input: &mut impl Read) -> Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    input.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+impl Record for GeoRange {
+    fn range(&self) -> (u128, u128) {
+        (self.0, self.1)
+    }
+
+    fn write_to(&self, out: &mut impl Write) -> Result<()> {
+        out.write_all(&self.0.to_le_bytes())?;
+        out.write_all(&self.1.to_le_bytes())?;
+        out.write_all(&self.2.to_le_bytes())?;
+        out.write_all(&self.3.to_le_bytes())?;
+        match self.4 {
+            Some(radius) => {
+                out.write_all(&[1])?;
+                out.write_all(&radius.to_le_bytes())
+            }
+            None => out.write_all(&[0]),
+        }
+    }
+
+    fn read_from(input: &mut impl Read) -> Result<Self> {
+        let from = u128::from_le_bytes(read_array(input)?);
+        let to = u128::from_le_bytes(read_array(input)?);
+        let lat = f32::from_le_bytes(read_array(input)?);
+        let lon = f32::from_le_bytes(read_array(input)?);
+        let accuracy = match read_array::<1>(input)? {
+            [0] => None,
+            _ => Some(u16::from_le_bytes(read_array(input)?)),
+        };
+        Ok((from, to, lat, lon, accuracy))
+    }
+}
+
+fn sort_key<T: Record>(order: Order, seq: u64, item: &T) -> SortKey {
+    let (from, to) = item.range();
+    (from, order.size_key(to - from), seq)
+}
+
+fn write_run<T: Record>(items: impl Iterator<Item = Result<(u64, T)>>) -> Result<(PathBuf, usize)> {
+    let path = std::env::temp_dir().join(format!(
+        "ip2x-{}-{}.run",
+        std::process::id(),
+        RUN_ID.fetch_add(1, Ordering::Relaxed)
+    ));
+    let mut out = BufWriter::new(File::create(&path)?);
+    let mut count = 0;
+    for item in items {
+        let (seq, item) = item?;
+        out.write_all(&seq.to_le_bytes())?;
+        item.write_to(&mut out)?;
+        count += 1;
+    }
+    out.flush()?;
+    Ok((path, count))
+}
+
+pub struct Spill<T> {
+    order: Order,
+    capacity: usize,
+    buffer: Vec<(u64, T)>,
+    runs: Vec<(PathBuf, usize)>,
+    pushed: u64,
+}
+
+impl<T: Record + Clone> Spill<T> {
+    pub fn new(budget: usize, order: Order) -> Spill<T> {
+        Spill {
+            order,
+            capacity: (budget / size_of::<(u64, T)>()).max(1),
+            buffer: Vec::new(),
+            runs: Vec::new(),
+            pushed: 0,
+        }
+    }
+
+    pub fn pushed(&self) -> u64 {
+        self.pushed
+    }
+
+    pub fn push(&mut self, item: T) -> Result<()> {
+        self.buffer.push((self.pushed, item));
+        self.pushed += 1;
+        if self.buffer.len() >= self.capacity {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    fn sort(&mut self) {
+        let order = self.order;
+        self.buffer
+            .sort_unstable_by_key(|(seq, item)| sort_key(order, *seq, item));
+    }
+
+    fn spill(&mut self) -> Result<()> {
+        self.sort();
+        let run = write_run(self.buffer.iter().map(|item| Ok(item.clone())))?;
+        self.runs.push(run);
+        self.buffer.clear();
+        if self.runs.len() >= MAX_RUNS {
+            let merged = write_run(self.sorted()?)?;
+            for (path, _) in self.runs.drain(..) {
+                fs::remove_file(path)?;
+            }
+            self.runs.push(merged);
+        }
+        Ok(())
+    }
+
+    pub fn finish(&mut self) {
+        self.sort();
+    }
+
+    pub fn sorted(&self) -> Result<Merge<'_, T>> {
+        let mut merge = Merge {
+            order: self.order,
+            runs: Vec::new(),
+            memory: self.buffer.iter(),
+            heads: Vec::new(),
+            heap: BinaryHeap::new(),
+        };
+        for (path, count) in &self.runs {
+            merge.runs.push((BufReader::new(File::open(path)?), *count));
+        }
+        for source in 0..=merge.runs.len() {
+            merge.heads.push(None);
+            merge.advance(source)?;
+        }
+        Ok(merge)
+    }
+}
+
+impl<T> Drop for Spill<T> {
+    fn drop(&mut self) {
+        for (path, _) in &self.runs {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+impl<T: Record + Clone> Extend<T> for Spill<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, items: I) {
+        for item in items {
+            self.push(item).expect("Failed to spill ranges to disk");
+        }
+    }
+}
+
+pub struct Merge<'a, T> {
+    order: Order,
+    runs: Vec<(BufReader<File>, usize)>,
+    memory: std::slice::Iter<'a, (u64, T)>,
+    heads: Vec<Option<(u64, T)>>,
+    heap: BinaryHeap<Reverse<(SortKey, usize)>>,
+}
+
+impl<T: Record + Clone> Merge<'_, T> {
+    fn advance(&mut self, source: usize) -> Result<()> {
+        let next = match self.runs.get_mut(source) {
+            Some((_, 0)) => None,
+            Some((input, remaining)) => {
+                *remaining -= 1;
+                let seq = u64::from_le_bytes(read_array(input)?);
+                Some((seq, T::read_from(input)?))
+            }
+            None => self.memory.next().cloned(),
+        };
+        if let Some((seq, item)) = &next {
+            self.heap
+                .push(Reverse((sort_key(self.order, *seq, item), source)));
+        }
+        self.heads[source] = next;
+        Ok(())
+    }
+}
+
+impl<T: Record + Clone> Iterator for Merge<'_, T> {
+    type Item = Result<(u64, T)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse((_, source)) = self.heap.pop()?;
+        let head = self.heads[source].take();
+        Some(
+            self.advance(source)
+                .map(|()| head.expect("heap entry without head")),
+        )
+    }
+}
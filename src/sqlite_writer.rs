@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use std::io::{Error, Result};
+
+use ip2x_core::usage;
+use rusqlite::{params, Connection, Transaction};
+
+use crate::format::{subdivision_code, Dataset};
+
+const SCHEMA: &str = "
+    CREATE TABLE strings (id INTEGER PRIMARY KEY, value TEXT NOT NULL UNIQUE);
+    CREATE TABLE geo (ip_from BLOB NOT NULL, ip_to BLOB NOT NULL, latitude REAL, longitude REAL, accuracy_radius INTEGER);
+    CREATE TABLE proxy_types (ip_from BLOB NOT NULL, ip_to BLOB NOT NULL, proxy_type_id INTEGER REFERENCES strings(id), confidence INTEGER NOT NULL);
+    CREATE TABLE asn (ip_from BLOB NOT NULL, ip_to BLOB NOT NULL, cidr_id INTEGER REFERENCES strings(id), asn_id INTEGER REFERENCES strings(id), as_name_id INTEGER REFERENCES strings(id));
+    CREATE TABLE isp (ip_from BLOB NOT NULL, ip_to BLOB NOT NULL, isp_id INTEGER REFERENCES strings(id), domain_id INTEGER REFERENCES strings(id), provider_id INTEGER REFERENCES strings(id));
+    CREATE TABLE mobile (ip_from BLOB NOT NULL, ip_to BLOB NOT NULL, mcc_id INTEGER REFERENCES strings(id), mnc_id INTEGER REFERENCES strings(id), mobile_brand_id INTEGER REFERENCES strings(id));
+    CREATE TABLE usage_type (ip_from BLOB NOT NULL, ip_to BLOB NOT NULL, usage_type TEXT, usage_mask INTEGER NOT NULL);
+    CREATE TABLE elevation (ip_from BLOB NOT NULL, ip_to BLOB NOT NULL, elevation INTEGER NOT NULL);
+    CREATE TABLE country (ip_from BLOB NOT NULL, ip_to BLOB NOT NULL, country_code TEXT NOT NULL, continent_code TEXT, is_in_european_union INTEGER NOT NULL, alternate_country_code TEXT, alternate_country_type TEXT, subdivision_code TEXT);
+";
+
+const INDEXES: &str = "
+    CREATE INDEX geo_range ON geo (ip_from, ip_to);
+    CREATE INDEX proxy_types_range ON proxy_types (ip_from, ip_to);
+    CREATE INDEX asn_range ON asn (ip_from, ip_to);
+    CREATE INDEX isp_range ON isp (ip_from, ip_to);
+    CREATE INDEX mobile_range ON mobile (ip_from, ip_to);
+    CREATE INDEX usage_type_range ON usage_type (ip_from, ip_to);
+    CREATE INDEX elevation_range ON elevation (ip_from, ip_to);
+    CREATE INDEX country_range ON country (ip_from, ip_to);
+";
+
+pub fn write(path: &str, datasets: impl Iterator<Item = Dataset>) -> Result<()> {
+    if std::path::Path::new(path).exists() {
+        std::fs::remove_file(path)?;
+    }
+    write_database(path, datasets).map_err(Error::other)
+}
+
+fn write_database(path: &str, datasets: impl Iterator<Item = Dataset>) -> rusqlite::Result<()> {
+    let mut conn = Connection::open(path)?;
+    conn.execute_batch("PRAGMA journal_mode = OFF; PRAGMA synchronous = OFF;")?;
+    conn.execute_batch(SCHEMA)?;
+
+    let mut strings = StringTable::default();
+    for dataset in datasets {
+        let tx = conn.transaction()?;
+        insert_dataset(&tx, &mut strings, &dataset)?;
+        tx.commit()?;
+    }
+
+    conn.execute_batch(INDEXES)
+}
+
+#[derive(Default)]
+struct StringTable {
+    ids: HashMap<String, i64>,
+}
+
+impl StringTable {
+    fn id(&mut self, tx: &Transaction, value: &str) -> rusqlite::Result<Option<i64>> {
+        if value.is_empty() || value == "-" {
+            return Ok(None);
+        }
+        if let Some(&id) = self.ids.get(value) {
+            return Ok(Some(id));
+        }
+        let id = self.ids.len() as i64 + 1;
+        tx.prepare_cached("INSERT INTO strings (id, value) VALUES (?1, ?2)")?
+            .execute(params![id, value])?;
+        self.ids.insert(value.to_string(), id);
+        Ok(Some(id))
+    }
+}
+
+fn ip_blob(value: u128) -> [u8; 16] {
+    value.to_be_bytes()
+}
+
+fn insert_dataset(
+    tx: &Transaction,
+    strings: &mut StringTable,
+    dataset: &Dataset,
+) -> rusqlite::Result<()> {
+    match dataset {
+        Dataset::Geo(ranges) => {
+            let mut stmt = tx.prepare("INSERT INTO geo VALUES (?1, ?2, ?3, ?4, ?5)")?;
+            for (from, to, lat, lon, accuracy) in ranges {
+                stmt.execute(params![
+                    ip_blob(*from),
+                    ip_blob(*to),
+                    *lat as f64,
+                    *lon as f64,
+                    accuracy
+                ])?;
+            }
+        }
+        Dataset::Proxy(types) => {
+            let mut stmt = tx.prepare("INSERT INTO proxy_types VALUES (?1, ?2, ?3, ?4)")?;
+            for (name, ranges) in types {
+                let id = strings.id(tx, name)?;
+                for (from, to, confidence, _) in ranges {
+                    stmt.execute(params![ip_blob(*from), ip_blob(*to), id, confidence])?;
+                }
+            }
+        }
+        Dataset::Asn(table, data) => {
+            let mut stmt = tx.prepare("INSERT INTO asn VALUES (?1, ?2, ?3, ?4, ?5)")?;
+            for (from, to, cidr, asn, name, _) in data {
+                let mut resolve = |idx: usize| {
+                    let value = table.get(idx).map(String::as_str).unwrap_or("-");
+                    strings.id(tx, value)
+                };
+                let ids = (resolve(*cidr)?, resolve(*asn)?, resolve(*name)?);
+                stmt.execute(params![ip_blob(*from), ip_blob(*to), ids.0, ids.1, ids.2])?;
+            }
+        }
+        Dataset::Isp(table, data) | Dataset::Mobile(table, data) => {
+            let name = match dataset {
+                Dataset::Mobile(..) => "mobile",
+                _ => "isp",
+            };
+            let mut stmt =
+                tx.prepare(&format!("INSERT INTO {} VALUES (?1, ?2, ?3, ?4, ?5)", name))?;
+            for (from, to, a, b, c) in data {
+                let mut resolve = |idx: usize| {
+                    let value = idx
+                        .checked_sub(1)
+                        .and_then(|i| table.get(i))
+                        .map(String::as_str)
+                        .unwrap_or("-");
+                    strings.id(tx, value)
+                };
+                let ids = (resolve(*a)?, resolve(*b)?, resolve(*c)?);
+                stmt.execute(params![ip_blob(*from), ip_blob(*to), ids.0, ids.1, ids.2])?;
+            }
+        }
+        Dataset::Usage(ranges) => {
+            let mut stmt = tx.prepare("INSERT INTO usage_type VALUES (?1, ?2, ?3, ?4)")?;
+            for (from, to, mask) in ranges {
+                stmt.execute(params![
+                    ip_blob(*from),
+                    ip_blob(*to),
+                    usage::names(*mask),
+                    mask
+                ])?;
+            }
+        }
+        Dataset::Elevation(ranges) => {
+            let mut stmt = tx.prepare("INSERT INTO elevation VALUES (?1, ?2, ?3)")?;
+            for (from, to, meters) in ranges {
+                stmt.execute(params![ip_blob(*from), ip_blob(*to), meters])?;
+            }
+        }
+        Dataset::Country(ranges) => {
+            let mut stmt =
+                tx.prepare("INSERT INTO country VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)")?;
+            for range in ranges {
+                let (from, to, country, continent, eu, alternate, _) = range;
+                let continent = (continent != b"--").then(|| String::from_utf8_lossy(continent));
+                stmt.execute(params![
+                    ip_blob(*from),
+                    ip_blob(*to),
+                    String::from_utf8_lossy(country),
+                    continent,
+                    eu,
+                    alternate.map(|(_, code)| String::from_utf8_lossy(&code).into_owned()),
+                    alternate.map(|(source, _)| source.name()),
+                    subdivision_code(range)
+                ])?;
+            }
+        }
+    }
+    Ok(())
+}
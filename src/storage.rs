@@ -0,0 +1,268 @@
+use std::fs::{self, File};
+use std::io::{Read, Result};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+pub type Source = Box<dyn Read + Send>;
+
+pub fn is_remote(path: &str) -> bool {
+    path.starts_with("s3://") || path.starts_with("gs://")
+}
+
+fn remote(path: &Path) -> Option<&str> {
+    path.to_str().filter(|path| is_remote(path))
+}
+
+pub fn exists(path: impl AsRef<Path>) -> bool {
+    let path = path.as_ref();
+    match remote(path) {
+        Some(uri) => store::exists(uri),
+        None => path.exists(),
+    }
+}
+
+pub fn open(path: impl AsRef<Path>) -> Result<(Source, u64)> {
+    let path = path.as_ref();
+    match remote(path) {
+        Some(uri) => store::open(uri),
+        None => {
+            let file = File::open(path)?;
+            let size = file.metadata()?.len();
+            Ok((Box::new(file), size))
+        }
+    }
+}
+
+pub fn read(path: impl AsRef<Path>) -> Result<Vec<u8>> {
+    let path = path.as_ref();
+    match remote(path) {
+        Some(_) => {
+            let (mut source, size) = open(path)?;
+            let mut bytes = Vec::with_capacity(size as usize);
+            source.read_to_end(&mut bytes)?;
+            Ok(bytes)
+        }
+        None => fs::read(path),
+    }
+}
+
+pub fn read_to_string(path: impl AsRef<Path>) -> Result<String> {
+    String::from_utf8(read(path)?).map_err(std::io::Error::other)
+}
+
+pub fn list(dir: &Path) -> Result<Vec<PathBuf>> {
+    match remote(dir) {
+        Some(uri) => store::list(uri),
+        None => fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect(),
+    }
+}
+
+pub fn describe(path: &str) -> Option<(u64, Option<String>, SystemTime)> {
+    store::describe(path)
+}
+
+pub fn upload(uri: &str, files: &[String]) -> Result<()> {
+    for file in files {
+        store::upload(file, &format!("{}/{}", uri.trim_end_matches('/'), file))?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "object-store")]
+mod store {
+    use std::collections::HashMap;
+    use std::fs::File;
+    use std::io::{Error, ErrorKind, Read, Result};
+    use std::path::PathBuf;
+    use std::sync::{Arc, LazyLock, Mutex};
+    use std::time::SystemTime;
+
+    use bytes::Bytes;
+    use futures::stream::{BoxStream, StreamExt};
+    use object_store::aws::AmazonS3Builder;
+    use object_store::buffered::BufWriter;
+    use object_store::gcp::GoogleCloudStorageBuilder;
+    use object_store::path::Path as Key;
+    use object_store::ObjectStore;
+    use sha2::{Digest, Sha256};
+    use tokio::io::AsyncWriteExt;
+    use tokio::runtime::Runtime;
+
+    use super::Source;
+
+    type Described = (u64, Option<String>, SystemTime);
+
+    static RUNTIME: LazyLock<Runtime> = LazyLock::new(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .expect("tokio runtime")
+    });
+    static STORES: LazyLock<Mutex<HashMap<String, Arc<dyn ObjectStore>>>> =
+        LazyLock::new(Mutex::default);
+    static OBJECTS: LazyLock<Mutex<HashMap<String, Described>>> = LazyLock::new(Mutex::default);
+
+    fn error(uri: &str, err: object_store::Error) -> Error {
+        match err {
+            object_store::Error::NotFound { .. } => {
+                Error::new(ErrorKind::NotFound, format!("{}: not found", uri))
+            }
+            err => Error::other(format!("{}: {}", uri, err)),
+        }
+    }
+
+    fn locate(uri: &str) -> Result<(Arc<dyn ObjectStore>, Key)> {
+        let (scheme, rest) = uri.split_once("://").unwrap_or(("", uri));
+        let (bucket, key) = rest.split_once('/').unwrap_or((rest, ""));
+        let key: Vec<&str> = key.split('/').filter(|part| !part.is_empty()).collect();
+        let mut stores = STORES.lock().unwrap();
+        let store = match stores.get(&format!("{}://{}", scheme, bucket)) {
+            Some(store) => store.clone(),
+            None => {
+                let store: Arc<dyn ObjectStore> = match scheme {
+                    "s3" => Arc::new(
+                        AmazonS3Builder::from_env()
+                            .with_bucket_name(bucket)
+                            .build()
+                            .map_err(|err| error(uri, err))?,
+                    ),
+                    _ => Arc::new(
+                        GoogleCloudStorageBuilder::from_env()
+                            .with_bucket_name(bucket)
+                            .build()
+                            .map_err(|err| error(uri, err))?,
+                    ),
+                };
+                stores.insert(format!("{}://{}", scheme, bucket), store.clone());
+                store
+            }
+        };
+        Ok((store, Key::from(key.join("/"))))
+    }
+
+    pub fn exists(uri: &str) -> bool {
+        locate(uri).is_ok_and(|(store, key)| RUNTIME.block_on(store.head(&key)).is_ok())
+    }
+
+    struct Object {
+        uri: String,
+        stream: BoxStream<'static, object_store::Result<Bytes>>,
+        chunk: Bytes,
+        hasher: Sha256,
+    }
+
+    impl Read for Object {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            while self.chunk.is_empty() {
+                match RUNTIME.block_on(self.stream.next()) {
+                    Some(chunk) => self.chunk = chunk.map_err(|err| error(&self.uri, err))?,
+                    None => {
+                        let digest = self.hasher.clone().finalize();
+                        let digest = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+                        if let Some(object) = OBJECTS.lock().unwrap().get_mut(&self.uri) {
+                            object.1 = Some(digest);
+                        }
+                        return Ok(0);
+                    }
+                }
+            }
+            let len = buf.len().min(self.chunk.len());
+            let chunk = self.chunk.split_to(len);
+            buf[..len].copy_from_slice(&chunk);
+            self.hasher.update(&chunk);
+            Ok(len)
+        }
+    }
+
+    pub fn open(uri: &str) -> Result<(Source, u64)> {
+        let (store, key) = locate(uri)?;
+        let result = RUNTIME
+            .block_on(store.get(&key))
+            .map_err(|err| error(uri, err))?;
+        let size = result.meta.size;
+        OBJECTS.lock().unwrap().insert(
+            uri.to_string(),
+            (size, None, SystemTime::from(result.meta.last_modified)),
+        );
+        let object = Object {
+            uri: uri.to_string(),
+            stream: result.into_stream(),
+            chunk: Bytes::new(),
+            hasher: Sha256::new(),
+        };
+        Ok((Box::new(object), size))
+    }
+
+    pub fn list(uri: &str) -> Result<Vec<PathBuf>> {
+        let (store, key) = locate(uri)?;
+        let listed = RUNTIME
+            .block_on(store.list_with_delimiter(Some(&key)))
+            .map_err(|err| error(uri, err))?;
+        let dir = uri.trim_end_matches('/');
+        Ok(listed
+            .objects
+            .iter()
+            .filter_map(|object| object.location.filename())
+            .map(|name| PathBuf::from(format!("{}/{}", dir, name)))
+            .collect())
+    }
+
+    pub fn describe(uri: &str) -> Option<Described> {
+        OBJECTS.lock().unwrap().get(uri).cloned()
+    }
+
+    pub fn upload(path: &str, uri: &str) -> Result<()> {
+        let (store, key) = locate(uri)?;
+        let mut file = File::open(path)?;
+        RUNTIME.block_on(async {
+            let mut writer = BufWriter::new(store, key);
+            let mut buffer = vec![0u8; 1 << 20];
+            loop {
+                let read = file.read(&mut buffer)?;
+                if read == 0 {
+                    break;
+                }
+                writer.write_all(&buffer[..read]).await?;
+            }
+            writer.shutdown().await
+        })
+    }
+}
+
+#[cfg(not(feature = "object-store"))]
+mod store {
+    use std::io::{Error, ErrorKind, Result};
+    use std::path::PathBuf;
+    use std::time::SystemTime;
+
+    use super::Source;
+
+    fn unsupported<T>() -> Result<T> {
+        Err(Error::new(
+            ErrorKind::Unsupported,
+            "s3:// and gs:// paths require building with --features object-store",
+        ))
+    }
+
+    pub fn exists(_uri: &str) -> bool {
+        false
+    }
+
+    pub fn open(_uri: &str) -> Result<(Source, u64)> {
+        unsupported()
+    }
+
+    pub fn list(_uri: &str) -> Result<Vec<PathBuf>> {
+        unsupported()
+    }
+
+    pub fn describe(_uri: &str) -> Option<(u64, Option<String>, SystemTime)> {
+        None
+    }
+
+    pub fn upload(_path: &str, _uri: &str) -> Result<()> {
+        unsupported()
+    }
+}
@@ -0,0 +1,255 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Result, Write};
+use std::path::Path;
+
+use ip2x_core::varint::read_varint;
+
+use crate::csv_reader::CsvReader;
+use crate::encoding::write_varint;
+use crate::format::{intern_with_offset, sort_ranges, ThreatRange};
+use crate::ip::parse_cidr;
+use crate::storage;
+
+pub const THREAT_FILE: &str = "threat.bin";
+const MAGIC: &[u8; 4] = b"IP2T";
+const DEFAULT_CATEGORY: &str = "blocklist";
+const ABUSEIPDB_CATEGORY: &str = "abuse";
+const SPAMHAUS_CATEGORY: &str = "spamhaus-drop";
+const ABUSEIPDB_CATEGORIES: [&str; 23] = [
+    "DNS Compromise",
+    "DNS Poisoning",
+    "Fraud Orders",
+    "DDoS Attack",
+    "FTP Brute-Force",
+    "Ping of Death",
+    "Phishing",
+    "Fraud VoIP",
+    "Open Proxy",
+    "Web Spam",
+    "Email Spam",
+    "Blog Spam",
+    "VPN IP",
+    "Port Scan",
+    "Hacking",
+    "SQL Injection",
+    "Spoofing",
+    "Brute-Force",
+    "Bad Web Bot",
+    "Exploited Host",
+    "Web App Attack",
+    "SSH",
+    "IoT Targeted",
+];
+
+#[derive(Default)]
+pub struct ThreatLists {
+    pub strings: Vec<String>,
+    pub lists: Vec<(String, Vec<ThreatRange>)>,
+    string_map: HashMap<String, usize>,
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+impl ThreatLists {
+    pub fn load(paths: &[String]) -> Result<ThreatLists> {
+        let mut threats = ThreatLists::default();
+        for path in paths {
+            let name = Path::new(path)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_else(|| path.clone());
+            let is_csv = Path::new(path)
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+            let mut ranges = match is_csv {
+                true => threats.read_abuseipdb(path)?,
+                false => threats.read_blocklist(path)?,
+            };
+            sort_ranges(&mut ranges, |r| (r.0, r.1));
+            threats.lists.push((name, ranges));
+        }
+        Ok(threats)
+    }
+
+    fn intern(&mut self, s: &str) -> usize {
+        intern_with_offset(s, &mut self.strings, &mut self.string_map)
+    }
+
+    fn read_blocklist(&mut self, path: &str) -> Result<Vec<ThreatRange>> {
+        let mut category = self.intern(DEFAULT_CATEGORY);
+        let mut ranges = Vec::new();
+        for (number, line) in storage::read_to_string(path)?.lines().enumerate() {
+            let line = line.trim();
+            if let Some(comment) = line.strip_prefix('#') {
+                if let Some((key, value)) = comment.split_once(':') {
+                    if key.trim().eq_ignore_ascii_case("category") && !value.trim().is_empty() {
+                        category = self.intern(value.trim());
+                    }
+                }
+                continue;
+            }
+            if let Some(comment) = line.strip_prefix(';') {
+                if comment.to_ascii_lowercase().contains("spamhaus") {
+                    category = self.intern(SPAMHAUS_CATEGORY);
+                }
+                continue;
+            }
+            let line = line.split('#').next().unwrap_or("");
+            let (entry, reference) = line.split_once(';').unwrap_or((line, ""));
+            let entry = entry.trim();
+            if entry.is_empty() {
+                continue;
+            }
+            let (from, to) = parse_cidr(entry).ok_or_else(|| {
+                invalid(format!("{}:{}: invalid CIDR '{}'", path, number + 1, entry))
+            })?;
+            let reference = match reference.trim() {
+                "" => 0,
+                reference => self.intern(reference),
+            };
+            ranges.push((from, to, category, reference));
+        }
+        Ok(ranges)
+    }
+
+    fn read_abuseipdb(&mut self, path: &str) -> Result<Vec<ThreatRange>> {
+        let mut reader = CsvReader::new(BufReader::new(storage::open(path)?.0));
+        let header = match reader.next_record() {
+            Some(header) => header?,
+            None => return Ok(Vec::new()),
+        };
+        let column = |names: &[&str]| {
+            (0..header.len()).find(|&i| {
+                names
+                    .iter()
+                    .any(|name| header[i].eq_ignore_ascii_case(name))
+            })
+        };
+        let ip_column = column(&["ipAddress", "IP"])
+            .ok_or_else(|| invalid(format!("{}: no ipAddress or IP column", path)))?;
+        let categories_column = column(&["Categories"]);
+
+        let mut reported: HashMap<(u128, u128), BTreeSet<String>> = HashMap::new();
+        let mut order = Vec::new();
+        let mut number = 1;
+        while let Some(fields) = reader.next_record() {
+            let fields = fields?;
+            number += 1;
+            let Some(ip) = fields
+                .get(ip_column)
+                .map(str::trim)
+                .filter(|ip| !ip.is_empty())
+            else {
+                continue;
+            };
+            let range = parse_cidr(ip)
+                .ok_or_else(|| invalid(format!("{}:{}: invalid IP '{}'", path, number, ip)))?;
+            let names = reported.entry(range).or_insert_with(|| {
+                order.push(range);
+                BTreeSet::new()
+            });
+            let ids = categories_column.and_then(|i| fields.get(i)).unwrap_or("");
+            for id in ids.split(',').map(str::trim).filter(|id| !id.is_empty()) {
+                let name = id
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|id| ABUSEIPDB_CATEGORIES.get(id.checked_sub(1)?))
+                    .map_or_else(|| id.to_string(), |name| name.to_string());
+                names.insert(name);
+            }
+        }
+
+        let mut ranges = Vec::with_capacity(order.len());
+        for (from, to) in order {
+            let names = &reported[&(from, to)];
+            let category = match names.is_empty() {
+                true => ABUSEIPDB_CATEGORY.to_string(),
+                false => names.iter().cloned().collect::<Vec<_>>().join(", "),
+            };
+            ranges.push((from, to, self.intern(&category), 0));
+        }
+        Ok(ranges)
+    }
+
+    pub fn range_count(&self) -> usize {
+        self.lists.iter().map(|(_, ranges)| ranges.len()).sum()
+    }
+
+    pub fn write(&self, path: &str) -> Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(MAGIC)?;
+        write_varint(&mut out, self.strings.len() as u128)?;
+        for s in &self.strings {
+            write_varint(&mut out, s.len() as u128)?;
+            out.write_all(s.as_bytes())?;
+        }
+        write_varint(&mut out, self.lists.len() as u128)?;
+        for (name, ranges) in &self.lists {
+            write_varint(&mut out, name.len() as u128)?;
+            out.write_all(name.as_bytes())?;
+            write_varint(&mut out, ranges.len() as u128)?;
+            let mut previous = 0u128;
+            for &(from, to, category, reference) in ranges {
+                write_varint(&mut out, from - previous)?;
+                write_varint(&mut out, to - from)?;
+                write_varint(&mut out, category as u128)?;
+                write_varint(&mut out, reference as u128)?;
+                previous = from;
+            }
+        }
+        out.flush()
+    }
+
+    pub fn read(path: &str) -> Result<ThreatLists> {
+        let bytes = fs::read(path)?;
+        let mut input = bytes
+            .strip_prefix(MAGIC)
+            .ok_or_else(|| invalid(format!("{}: not a threat file", path)))?;
+        let truncated = || invalid(format!("{}: truncated threat file", path));
+        let overflow = || invalid(format!("{}: threat range overflow", path));
+        let text = |input: &mut &[u8]| -> Result<String> {
+            let len = read_varint(input)? as usize;
+            if input.len() < len {
+                return Err(truncated());
+            }
+            let (head, rest) = input.split_at(len);
+            *input = rest;
+            Ok(String::from_utf8_lossy(head).into_owned())
+        };
+
+        let count = read_varint(&mut input)? as usize;
+        let mut strings = Vec::with_capacity(count.min(1 << 20));
+        for _ in 0..count {
+            strings.push(text(&mut input)?);
+        }
+        let count = read_varint(&mut input)? as usize;
+        let mut lists = Vec::with_capacity(count.min(1 << 10));
+        for _ in 0..count {
+            let name = text(&mut input)?;
+            let count = read_varint(&mut input)? as usize;
+            let mut ranges = Vec::with_capacity(count.min(1 << 20));
+            let mut previous = 0u128;
+            for _ in 0..count {
+                let from = previous
+                    .checked_add(read_varint(&mut input)?)
+                    .ok_or_else(overflow)?;
+                let to = from
+                    .checked_add(read_varint(&mut input)?)
+                    .ok_or_else(overflow)?;
+                let category = read_varint(&mut input)? as usize;
+                let reference = read_varint(&mut input)? as usize;
+                ranges.push((from, to, category, reference));
+                previous = from;
+            }
+            lists.push((name, ranges));
+        }
+        Ok(ThreatLists {
+            strings,
+            lists,
+            string_map: HashMap::new(),
+        })
+    }
+}
@@ -0,0 +1,98 @@
+#[cfg(feature = "tls")]
+mod backend {
+    use std::fs::File;
+    use std::io::{BufReader, Error, ErrorKind, Result};
+    use std::net::TcpStream;
+    use std::sync::Arc;
+
+    use rustls::pki_types::ServerName;
+    use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+
+    fn invalid(name: &str, err: impl std::fmt::Display) -> Error {
+        Error::new(ErrorKind::InvalidData, format!("{}: {}", name, err))
+    }
+
+    fn roots(ca_bundle: Option<&str>) -> Result<RootCertStore> {
+        let mut roots = RootCertStore::empty();
+        match ca_bundle {
+            Some(path) => {
+                let file = File::open(path)
+                    .map_err(|err| Error::new(err.kind(), format!("{}: {}", path, err)))?;
+                for cert in rustls_pemfile::certs(&mut BufReader::new(file)) {
+                    roots
+                        .add(cert.map_err(|err| invalid(path, err))?)
+                        .map_err(|err| invalid(path, err))?;
+                }
+                if roots.is_empty() {
+                    return Err(invalid(path, "no certificates in CA bundle"));
+                }
+            }
+            None => {
+                roots.add_parsable_certificates(rustls_native_certs::load_native_certs().certs);
+                if roots.is_empty() {
+                    return Err(Error::new(
+                        ErrorKind::NotFound,
+                        "No system CA certificates found, pass --ca-bundle",
+                    ));
+                }
+            }
+        }
+        Ok(roots)
+    }
+
+    pub struct Tls(Arc<ClientConfig>);
+
+    impl Tls {
+        pub fn open(ca_bundle: Option<&str>) -> Result<Tls> {
+            let provider = Arc::new(rustls::crypto::ring::default_provider());
+            let config = ClientConfig::builder_with_provider(provider)
+                .with_safe_default_protocol_versions()
+                .map_err(|err| invalid("TLS", err))?
+                .with_root_certificates(roots(ca_bundle)?)
+                .with_no_client_auth();
+            Ok(Tls(Arc::new(config)))
+        }
+
+        pub fn connect(
+            &self,
+            host: &str,
+            mut stream: TcpStream,
+        ) -> Result<StreamOwned<ClientConnection, TcpStream>> {
+            let name = ServerName::try_from(host.to_string()).map_err(|err| invalid(host, err))?;
+            let mut connection =
+                ClientConnection::new(self.0.clone(), name).map_err(|err| invalid(host, err))?;
+            while connection.is_handshaking() {
+                connection.complete_io(&mut stream).map_err(|err| {
+                    Error::new(
+                        err.kind(),
+                        format!("TLS handshake with {} failed: {}", host, err),
+                    )
+                })?;
+            }
+            Ok(StreamOwned::new(connection, stream))
+        }
+    }
+}
+
+#[cfg(not(feature = "tls"))]
+mod backend {
+    use std::io::{Error, ErrorKind, Result};
+    use std::net::TcpStream;
+
+    pub enum Tls {}
+
+    impl Tls {
+        pub fn open(_ca_bundle: Option<&str>) -> Result<Tls> {
+            Err(Error::new(
+                ErrorKind::Unsupported,
+                "https:// URLs and --ca-bundle require building with --features tls",
+            ))
+        }
+
+        pub fn connect(&self, _host: &str, _stream: TcpStream) -> Result<TcpStream> {
+            match *self {}
+        }
+    }
+}
+
+pub use backend::Tls;
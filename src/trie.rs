@@ -0,0 +1,174 @@
+use std::fs::{self, OpenOptions};
+use std::io::{BufWriter, Error, ErrorKind, Result, Write};
+
+use crate::format::{Dataset, Kind};
+use crate::ip::range_to_cidrs;
+
+pub const TRAILER_MAGIC: &[u8; 4] = b"IP2T";
+const ROOT_BITS: u32 = 16;
+const ROOT_SIZE: usize = 1 << ROOT_BITS;
+const IPV4_DEPTH: u32 = 96 + ROOT_BITS;
+const TAIL_LEN: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Child {
+    Empty,
+    Node(u32),
+    Leaf(u32),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Trie {
+    v4: Vec<u32>,
+    v6: Vec<u32>,
+    nodes: Vec<[u32; 2]>,
+}
+
+fn bit(ip: u128, depth: u32) -> usize {
+    ((ip >> (127 - depth)) & 1) as usize
+}
+
+fn insert(nodes: &mut Vec<[Child; 2]>, root: &mut Child, network: u128, prefix: u8, leaf: u32) {
+    let Child::Node(mut node) = *root else {
+        return;
+    };
+    if prefix == 0 {
+        *root = Child::Leaf(leaf);
+        return;
+    }
+    for depth in 0..prefix as u32 - 1 {
+        let side = bit(network, depth);
+        node = match nodes[node as usize][side] {
+            Child::Node(next) => next,
+            child => {
+                let next = nodes.len() as u32;
+                nodes.push([child; 2]);
+                nodes[node as usize][side] = Child::Node(next);
+                next
+            }
+        };
+    }
+    nodes[node as usize][bit(network, prefix as u32 - 1)] = Child::Leaf(leaf);
+}
+
+fn walk(nodes: &[[Child; 2]], root: Child, network: u128, bits: u32) -> Child {
+    let mut child = root;
+    for depth in 0..bits {
+        match child {
+            Child::Node(node) => child = nodes[node as usize][bit(network, depth)],
+            _ => break,
+        }
+    }
+    child
+}
+
+pub fn build(ranges: &[(u128, u128)]) -> Trie {
+    let mut nodes = vec![[Child::Empty; 2]];
+    let mut root = Child::Node(0);
+    for (leaf, &(from, to)) in ranges.iter().enumerate() {
+        for (network, prefix) in range_to_cidrs(from, to) {
+            insert(&mut nodes, &mut root, network, prefix, leaf as u32);
+        }
+    }
+
+    let count = nodes.len() as u32;
+    let encode = |child: Child| match child {
+        Child::Node(node) => node,
+        Child::Empty => count,
+        Child::Leaf(leaf) => count + 1 + leaf,
+    };
+    Trie {
+        v4: (0..ROOT_SIZE as u128)
+            .map(|top| encode(walk(&nodes, root, (0xffff << 32) | top << 16, IPV4_DEPTH)))
+            .collect(),
+        v6: (0..ROOT_SIZE as u128)
+            .map(|top| encode(walk(&nodes, root, top << (128 - ROOT_BITS), ROOT_BITS)))
+            .collect(),
+        nodes: nodes
+            .iter()
+            .map(|[left, right]| [encode(*left), encode(*right)])
+            .collect(),
+    }
+}
+
+impl Trie {
+    pub fn find(&self, ip: u128) -> Option<usize> {
+        let count = self.nodes.len() as u32;
+        let (mut value, mut depth) = match ip >> 32 {
+            0xffff => (self.v4[(ip >> 16) as u16 as usize], IPV4_DEPTH),
+            _ => (self.v6[(ip >> (128 - ROOT_BITS)) as usize], ROOT_BITS),
+        };
+        while value < count && depth < 128 {
+            value = self.nodes[value as usize][bit(ip, depth)];
+            depth += 1;
+        }
+        match value < count {
+            true => None,
+            false => value.checked_sub(count + 1).map(|leaf| leaf as usize),
+        }
+    }
+}
+
+pub fn append(path: &str, kind: Kind) -> Result<()> {
+    let bytes = fs::read(path)?;
+    let dataset = Dataset::read_from(&mut &bytes[..], kind, path)?;
+    let trie = build(&dataset.bounds());
+    let mut out = BufWriter::new(OpenOptions::new().append(true).open(path)?);
+    for value in trie
+        .v4
+        .iter()
+        .chain(&trie.v6)
+        .chain(trie.nodes.iter().flatten())
+    {
+        out.write_all(&value.to_le_bytes())?;
+    }
+    out.write_all(&(trie.nodes.len() as u32).to_le_bytes())?;
+    out.write_all(&(bytes.len() as u64).to_le_bytes())?;
+    out.write_all(TRAILER_MAGIC)?;
+    out.flush()
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+pub fn read_trailer(bytes: &[u8]) -> Result<(Trie, usize)> {
+    let tail = bytes
+        .len()
+        .checked_sub(TAIL_LEN)
+        .map(|start| &bytes[start..])
+        .filter(|tail| tail.ends_with(TRAILER_MAGIC))
+        .ok_or_else(|| invalid("Missing trie trailer"))?;
+    let count = u32::from_le_bytes(tail[..4].try_into().unwrap()) as usize;
+    let records_end = u64::from_le_bytes(tail[4..12].try_into().unwrap()) as usize;
+    let values: Vec<u32> = count
+        .checked_mul(2)
+        .and_then(|values| values.checked_add(2 * ROOT_SIZE)?.checked_mul(4))
+        .and_then(|len| bytes.get(records_end..records_end.checked_add(len)?))
+        .filter(|table| records_end + table.len() + TAIL_LEN == bytes.len())
+        .ok_or_else(|| invalid("Bad trie trailer"))?
+        .chunks_exact(4)
+        .map(|value| u32::from_le_bytes(value.try_into().unwrap()))
+        .collect();
+
+    let (roots, nodes) = values.split_at(2 * ROOT_SIZE);
+    let trie = Trie {
+        v4: roots[..ROOT_SIZE].to_vec(),
+        v6: roots[ROOT_SIZE..].to_vec(),
+        nodes: nodes
+            .chunks_exact(2)
+            .map(|pair| [pair[0], pair[1]])
+            .collect(),
+    };
+    Ok((trie, records_end))
+}
+
+pub fn check(path: &str, kind: Kind) -> Result<usize> {
+    let bytes = fs::read(path)?;
+    let (trie, records_end) = read_trailer(&bytes)?;
+    let dataset = Dataset::read_from(&mut &bytes[..records_end], kind, path)?;
+    if build(&dataset.bounds()) != trie {
+        return Err(invalid("Trie does not match the records"));
+    }
+    Ok(bytes.len() - records_end)
+}
@@ -0,0 +1,483 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Error, ErrorKind, Result, Write};
+use std::net::IpAddr;
+use std::path::{Component, Path, PathBuf};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+use serde_json::Value;
+
+use crate::http;
+use crate::logging;
+use crate::manifest::{sha256, MANIFEST_FILE};
+use crate::signing::Verifier;
+use crate::sigv4::Credentials;
+use crate::tls::Tls;
+
+const PARTIAL: &str = ".part";
+const DEFAULT_S3_REGION: &str = "us-east-1";
+const ATTEMPTS: u32 = 5;
+const BACKOFF: Duration = Duration::from_secs(1);
+
+pub struct Updater {
+    base: String,
+    dir: String,
+    verifier: Option<Verifier>,
+    tls: Option<Tls>,
+    credentials: Option<Credentials>,
+    etag: Option<String>,
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+fn s3_url(location: &str, region: Option<&str>, endpoint: Option<&str>) -> String {
+    let (bucket, prefix) = location.split_once('/').unwrap_or((location, ""));
+    match endpoint {
+        Some(endpoint) => format!("{}/{}/{}", endpoint.trim_end_matches('/'), bucket, prefix),
+        None => format!(
+            "https://{}.s3.{}.amazonaws.com/{}",
+            bucket,
+            region.unwrap_or(DEFAULT_S3_REGION),
+            prefix
+        ),
+    }
+}
+
+fn env(names: &[&str]) -> Option<String> {
+    names
+        .iter()
+        .find_map(|name| std::env::var(name).ok().filter(|value| !value.is_empty()))
+}
+
+fn s3_region() -> Option<String> {
+    env(&["AWS_REGION", "AWS_DEFAULT_REGION"])
+}
+
+fn base_url(source: &str) -> String {
+    let base = match source.strip_prefix("s3://") {
+        Some(location) => s3_url(
+            location,
+            s3_region().as_deref(),
+            env(&["AWS_ENDPOINT_URL_S3", "AWS_ENDPOINT_URL"]).as_deref(),
+        ),
+        None => source.to_string(),
+    };
+    base.trim_end_matches('/').to_string()
+}
+
+fn loopback(url: &str) -> bool {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let host = rest.split('/').next().unwrap_or("");
+    let host = host.rsplit_once('@').map_or(host, |(_, host)| host);
+    let name = match host.rsplit_once(':') {
+        Some((name, port)) if port.parse::<u16>().is_ok() => name,
+        _ => host,
+    };
+    let name = name.trim_start_matches('[').trim_end_matches(']');
+    name.eq_ignore_ascii_case("localhost")
+        || name.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+fn partial(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(PARTIAL);
+    PathBuf::from(name)
+}
+
+fn signed<'a>(
+    url: &str,
+    headers: &[(&'a str, &'a str)],
+    credentials: Option<&Credentials>,
+) -> Result<Vec<(String, String)>> {
+    let mut headers: Vec<(String, String)> = headers
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    if let Some(credentials) = credentials {
+        let (_, host, target) = http::split_url(url)?;
+        headers.extend(credentials.sign(host, target, SystemTime::now()));
+    }
+    Ok(headers)
+}
+
+fn get<W: Write>(
+    url: &str,
+    headers: &[(&str, &str)],
+    tls: Option<&Tls>,
+    credentials: Option<&Credentials>,
+    body: impl FnOnce(&http::Response) -> Result<W>,
+) -> Result<http::Response> {
+    let headers = signed(url, headers, credentials)?;
+    let headers: Vec<(&str, &str)> = headers
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .collect();
+    http::get(url, &headers, tls, body)
+}
+
+fn download(
+    url: &str,
+    partial: &Path,
+    tls: Option<&Tls>,
+    credentials: Option<&Credentials>,
+) -> Result<()> {
+    if let Some(parent) = partial.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let offset = fs::metadata(partial).map_or(0, |metadata| metadata.len());
+    let range = format!("bytes={}-", offset);
+    let headers = match offset {
+        0 => Vec::new(),
+        _ => vec![("Range", range.as_str())],
+    };
+    get(
+        url,
+        &headers,
+        tls,
+        credentials,
+        |response| -> Result<Box<dyn Write>> {
+            match response.status {
+                200 => Ok(Box::new(File::create(partial)?)),
+                206 => Ok(Box::new(OpenOptions::new().append(true).open(partial)?)),
+                416 if offset > 0 => Ok(Box::new(io::sink())),
+                status => Err(Error::other(format!("{}: HTTP {}", url, status))),
+            }
+        },
+    )?;
+    Ok(())
+}
+
+fn download_verified(
+    url: &str,
+    partial: &Path,
+    expected: &str,
+    tls: Option<&Tls>,
+    credentials: Option<&Credentials>,
+    backoff: Duration,
+) -> Result<()> {
+    let mut attempt = 1;
+    loop {
+        let result = download(url, partial, tls, credentials).and_then(|()| {
+            match sha256(partial)? == expected {
+                true => Ok(()),
+                false => {
+                    fs::remove_file(partial)?;
+                    Err(invalid(format!("Checksum mismatch: {}", url)))
+                }
+            }
+        });
+        match result {
+            Err(err) if attempt < ATTEMPTS => {
+                let delay = backoff * 2u32.pow(attempt - 1);
+                logging::warn(&format!(
+                    "{} (attempt {} of {}), retrying in {:?}",
+                    err, attempt, ATTEMPTS, delay
+                ));
+                thread::sleep(delay);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+impl Updater {
+    fn open(source: &str, dir: &str, verifier: Option<Verifier>) -> Result<Updater> {
+        let base = base_url(source);
+        let tls = match base.starts_with("https://") {
+            true => Some(Tls::open(None)?),
+            false => None,
+        };
+        let credentials = match source.starts_with("s3://") {
+            true => Credentials::from_env(&s3_region().unwrap_or(DEFAULT_S3_REGION.to_string())),
+            false => None,
+        };
+        Ok(Updater {
+            base,
+            dir: dir.to_string(),
+            verifier,
+            tls,
+            credentials,
+            etag: None,
+        })
+    }
+
+    pub fn new(source: &str, dir: &str) -> Result<Updater> {
+        let updater = Updater::open(source, dir, None)?;
+        if !updater.base.starts_with("https://") && !loopback(&updater.base) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Refusing unsigned updates from {} over plain HTTP: use https:// or pass --public-key",
+                    updater.base
+                ),
+            ));
+        }
+        Ok(updater)
+    }
+
+    pub fn new_verified(source: &str, dir: &str, public_key: &str) -> Result<Updater> {
+        Updater::open(source, dir, Some(Verifier::open(public_key)?))
+    }
+
+    pub fn ca_bundle(mut self, path: &str) -> Result<Updater> {
+        self.tls = Some(Tls::open(Some(path))?);
+        Ok(self)
+    }
+
+    fn url(&self, name: &str) -> String {
+        format!("{}/{}", self.base, name)
+    }
+
+    fn fetch(&self, name: &str) -> Result<Vec<u8>> {
+        let url = self.url(name);
+        let mut body = Vec::new();
+        get(
+            &url,
+            &[],
+            self.tls.as_ref(),
+            self.credentials.as_ref(),
+            |response| match response.status {
+                200 => Ok(&mut body),
+                status => Err(Error::other(format!("{}: HTTP {}", url, status))),
+            },
+        )?;
+        Ok(body)
+    }
+
+    pub fn poll(&mut self) -> Result<bool> {
+        let url = self.url(MANIFEST_FILE);
+        let mut body = Vec::new();
+        let headers: Vec<(&str, &str)> = self
+            .etag
+            .iter()
+            .map(|etag| ("If-None-Match", etag.as_str()))
+            .collect();
+        let response = get(
+            &url,
+            &headers,
+            self.tls.as_ref(),
+            self.credentials.as_ref(),
+            |response| match response.status {
+                200 => Ok(&mut body),
+                status => Err(Error::other(format!("{}: HTTP {}", url, status))),
+            },
+        )?;
+        if response.status == 304 {
+            return Ok(false);
+        }
+        let signature_name = format!("{}.sig", MANIFEST_FILE);
+        let signature = match &self.verifier {
+            Some(verifier) => {
+                let signature = self.fetch(&signature_name)?;
+                verifier.verify_bytes(&url, &body, &signature)?;
+                Some(signature)
+            }
+            None => None,
+        };
+        let manifest: Value =
+            serde_json::from_slice(&body).map_err(|err| invalid(format!("{}: {}", url, err)))?;
+
+        let dir = Path::new(&self.dir);
+        let mut staged = Vec::new();
+        for artifact in manifest["artifacts"].as_array().into_iter().flatten() {
+            let (Some(path), Some(expected)) =
+                (artifact["path"].as_str(), artifact["sha256"].as_str())
+            else {
+                continue;
+            };
+            if Path::new(path)
+                .components()
+                .any(|part| !matches!(part, Component::Normal(_)))
+            {
+                return Err(invalid(format!("{}: unsafe artifact path '{}'", url, path)));
+            }
+            let target = dir.join(path);
+            if sha256(&target).is_ok_and(|actual| actual == expected) {
+                continue;
+            }
+            let downloaded = partial(&target);
+            download_verified(
+                &self.url(path),
+                &downloaded,
+                expected,
+                self.tls.as_ref(),
+                self.credentials.as_ref(),
+                BACKOFF,
+            )?;
+            if let Some(verifier) = &self.verifier {
+                let name = format!("{}.sig", path);
+                let signature = self.fetch(&name)?;
+                verifier.verify_bytes(&self.url(path), &fs::read(&downloaded)?, &signature)?;
+                let target = dir.join(&name);
+                fs::write(partial(&target), signature)?;
+                staged.push((partial(&target), target));
+            }
+            staged.push((downloaded, target));
+        }
+        if let Some(signature) = signature {
+            let target = dir.join(&signature_name);
+            fs::write(partial(&target), signature)?;
+            staged.push((partial(&target), target));
+        }
+        let target = dir.join(MANIFEST_FILE);
+        fs::write(partial(&target), &body)?;
+        staged.push((partial(&target), target));
+        for (partial, target) in &staged {
+            fs::rename(partial, target)?;
+        }
+        self.etag = response.header("ETag").map(str::to_string);
+        Ok(true)
+    }
+
+    pub fn spawn(
+        mut self,
+        interval: Duration,
+        mut on_update: impl FnMut() -> Result<()> + Send + 'static,
+    ) -> JoinHandle<()> {
+        thread::spawn(move || loop {
+            match self.poll() {
+                Ok(true) => {
+                    if let Err(err) = on_update() {
+                        logging::error(&err);
+                    }
+                }
+                Ok(false) => {}
+                Err(err) => logging::error(&Error::new(
+                    err.kind(),
+                    format!(
+                        "Updating from {} failed, keeping the current bins: {}",
+                        self.base, err
+                    ),
+                )),
+            }
+            thread::sleep(interval);
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unsigned_plain_http_is_rejected_for_remote_hosts() {
+        for source in ["http://builder:8080/v1/dataset", "http://10.0.0.1/bins"] {
+            let err = Updater::new(source, "bins").err().expect(source);
+            assert_eq!(err.kind(), ErrorKind::InvalidInput, "{}", source);
+        }
+    }
+
+    #[test]
+    fn unsigned_updates_are_allowed_over_loopback() {
+        for source in [
+            "http://localhost:8080/v1/dataset",
+            "http://127.0.0.1/bins",
+            "http://[::1]:8080/bins",
+        ] {
+            assert!(Updater::new(source, "bins").is_ok(), "{}", source);
+        }
+    }
+
+    #[cfg(not(feature = "tls"))]
+    #[test]
+    fn https_without_tls_feature_fails_loudly() {
+        let err = Updater::new("https://builder/v1/dataset", "bins").err();
+        assert_eq!(err.map(|err| err.kind()), Some(ErrorKind::Unsupported));
+        let updater = Updater::new("http://localhost:8080/v1/dataset", "bins").unwrap();
+        let err = updater.ca_bundle("ca.pem").err();
+        assert_eq!(err.map(|err| err.kind()), Some(ErrorKind::Unsupported));
+    }
+
+    #[cfg(feature = "tls")]
+    #[test]
+    fn missing_ca_bundle_is_an_error() {
+        let updater = Updater::new("http://localhost:8080/v1/dataset", "bins").unwrap();
+        let err = updater.ca_bundle("missing-ca.pem").err();
+        assert_eq!(err.map(|err| err.kind()), Some(ErrorKind::NotFound));
+    }
+
+    #[test]
+    fn s3_maps_to_regional_https_endpoint() {
+        assert_eq!(
+            s3_url("bins/edge/v1", Some("eu-central-1"), None),
+            "https://bins.s3.eu-central-1.amazonaws.com/edge/v1"
+        );
+        assert_eq!(
+            s3_url("bins", None, None),
+            "https://bins.s3.us-east-1.amazonaws.com/"
+        );
+        assert_eq!(
+            s3_url(
+                "bins/edge",
+                Some("eu-central-1"),
+                Some("https://minio.internal:9000/")
+            ),
+            "https://minio.internal:9000/bins/edge"
+        );
+    }
+
+    #[test]
+    fn s3_requests_are_signed_when_credentials_are_set() {
+        let credentials = Credentials::new("AKIDEXAMPLE", "secret", None, "eu-central-1");
+        let url = "https://bins.s3.eu-central-1.amazonaws.com/edge/geo.bin";
+        let headers = signed(url, &[("Range", "bytes=10-")], Some(&credentials)).unwrap();
+        let names: Vec<&str> = headers.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "Range",
+                "x-amz-content-sha256",
+                "x-amz-date",
+                "Authorization"
+            ]
+        );
+        assert!(headers[3]
+            .1
+            .starts_with("AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/"));
+        assert!(headers[3].1.contains("/eu-central-1/s3/aws4_request"));
+        assert_eq!(signed(url, &[], None).unwrap(), []);
+    }
+
+    #[test]
+    fn checksum_mismatch_is_retried_from_scratch() {
+        use std::io::Read;
+        use std::net::TcpListener;
+
+        let dir = std::env::temp_dir().join(format!("ip2x-update-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let good = dir.join("good");
+        fs::write(&good, b"fresh bins").unwrap();
+        let expected = sha256(&good).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/geo.bin", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            for body in ["stale", "torn bins", "fresh bins"] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = Vec::new();
+                let mut byte = [0u8; 1];
+                while !request.ends_with(b"\r\n\r\n") {
+                    stream.read_exact(&mut byte).unwrap();
+                    request.push(byte[0]);
+                }
+                assert!(!String::from_utf8_lossy(&request).contains("Range:"));
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+        });
+
+        let downloaded = dir.join("geo.bin.part");
+        download_verified(&url, &downloaded, &expected, None, None, Duration::ZERO).unwrap();
+        server.join().unwrap();
+        assert_eq!(fs::read(&downloaded).unwrap(), b"fresh bins");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,460 @@
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+use ip2x_core::bin::{self, Header, IPV4_BASE};
+use ip2x_core::usage;
+use ip2x_core::varint::read_varint;
+
+use crate::cli::Args;
+use crate::connection_type::{self, CONNECTION_TYPE_FILE};
+use crate::domain::{self, DOMAIN_FILE};
+use crate::format::{
+    read_bin, read_block, read_layout, read_preamble, read_strings_file, Dataset, Kind, Order,
+    SyncState, INDEXED, IPV4_ONLY, STRINGS_FILE, TRIE,
+};
+use crate::geocode::{CityIndex, CITIES_FILE};
+use crate::index;
+use crate::isp_group::{self, ISP_GROUP_FILE};
+use crate::network_type::{self, NETWORK_TYPE_FILE};
+use crate::prefixes::{PrefixIndex, ASN_INDEX_FILE, COUNTRY_INDEX_FILE, ISP_INDEX_FILE};
+use crate::rdns::{RdnsTable, RDNS_FILE};
+use crate::threat::{ThreatLists, THREAT_FILE};
+use crate::trie;
+
+const MAX_REPORTED: usize = 10;
+
+pub fn run(args: &Args) -> Result<()> {
+    if args.positional().is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Usage: ip2x validate <file.bin>... [--kind geo|proxy|asn|isp|mobile|usage_type|elevation|country]",
+        ));
+    }
+
+    let mut failed = 0;
+    for path in args.positional() {
+        let kind = args.value(&["--kind"]);
+        let issues = match kind.is_none().then(|| check_auxiliary(path)).flatten() {
+            Some(issues) => issues,
+            None => match Kind::detect(kind, path) {
+                Ok(kind) => validate_file(path, kind),
+                Err(err) => vec![err.to_string()],
+            },
+        };
+
+        if issues.is_empty() {
+            println!("{}: OK", path);
+            continue;
+        }
+
+        failed += 1;
+        println!("{}: {} issue(s)", path, issues.len());
+        for issue in issues.iter().take(MAX_REPORTED) {
+            println!("  - {}", issue);
+        }
+        if issues.len() > MAX_REPORTED {
+            println!("  ... and {} more", issues.len() - MAX_REPORTED);
+        }
+    }
+
+    if failed > 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("{} file(s) failed validation", failed),
+        ));
+    }
+    Ok(())
+}
+
+fn check_auxiliary(path: &str) -> Option<Vec<String>> {
+    let name = Path::new(path).file_name()?.to_str()?;
+    let decoded = match name {
+        STRINGS_FILE => read_strings_file(path).map(drop),
+        CITIES_FILE => CityIndex::read(path).map(drop),
+        ASN_INDEX_FILE | COUNTRY_INDEX_FILE | ISP_INDEX_FILE => PrefixIndex::read(path).map(drop),
+        THREAT_FILE => ThreatLists::read(path).map(drop),
+        RDNS_FILE => RdnsTable::read(path).map(drop),
+        NETWORK_TYPE_FILE => network_type::read(path).map(drop),
+        CONNECTION_TYPE_FILE => connection_type::read(path).map(drop),
+        DOMAIN_FILE => domain::read(path).map(drop),
+        ISP_GROUP_FILE => isp_group::read(path).map(drop),
+        _ => return None,
+    };
+    Some(
+        decoded
+            .err()
+            .map(|err| format!("decode failed: {}", err))
+            .into_iter()
+            .collect(),
+    )
+}
+
+pub fn validate_file(path: &str, kind: Kind) -> Vec<String> {
+    let bytes = match read_bin(path) {
+        Ok(bytes) => bytes,
+        Err(err) => return vec![format!("cannot open: {}", err)],
+    };
+
+    let issues = check_encoding(&bytes, kind, path);
+    if !issues.is_empty() {
+        return issues;
+    }
+
+    let mut input = &bytes[..];
+    let dataset = match Dataset::read_from(&mut input, kind, path) {
+        Ok(dataset) => dataset,
+        Err(err) => return vec![format!("decode failed: {}", err)],
+    };
+
+    let mut issues = validate(&dataset);
+    if let Ok(layout) = read_layout(path) {
+        let order = layout.header().order();
+        match &dataset {
+            Dataset::Proxy(types) => {
+                for (name, ranges) in types {
+                    let context = format!("type {}: ", name);
+                    check_order(
+                        ranges.iter().map(|r| (r.0, r.1)),
+                        order,
+                        &context,
+                        &mut issues,
+                    );
+                }
+            }
+            _ => check_order(dataset.bounds().into_iter(), order, "", &mut issues),
+        }
+    }
+    let expected = match read_layout(path) {
+        Ok(layout) if layout.has(INDEXED) => match index::check(path, kind) {
+            Ok(len) => len,
+            Err(err) => {
+                issues.push(format!("block index: {}", err));
+                input.len()
+            }
+        },
+        Ok(layout) if layout.has(TRIE) => match trie::check(path, kind) {
+            Ok(len) => len,
+            Err(err) => {
+                issues.push(format!("trie: {}", err));
+                input.len()
+            }
+        },
+        _ => 0,
+    };
+    if input.len() != expected {
+        issues.push(format!(
+            "{} trailing bytes after last record",
+            input.len() - expected.min(input.len())
+        ));
+    }
+    issues
+}
+
+pub fn validate(dataset: &Dataset) -> Vec<String> {
+    let mut issues = Vec::new();
+
+    match dataset {
+        Dataset::Geo(ranges) => {
+            for (i, (_, _, lat, lon, _)) in ranges.iter().enumerate() {
+                let unlocated = lat.is_nan() && lon.is_nan();
+                if !unlocated && (!(-90.0..=90.0).contains(lat) || !(-180.0..=180.0).contains(lon))
+                {
+                    issues.push(format!(
+                        "record {}: coordinates {}, {} out of bounds",
+                        i, lat, lon
+                    ));
+                }
+            }
+        }
+        Dataset::Proxy(types) => {
+            for (name, ranges) in types {
+                let context = format!("type {}: ", name);
+                for (i, (_, _, confidence, _)) in ranges.iter().enumerate() {
+                    if *confidence == 0 {
+                        issues.push(format!("{}record {}: zero confidence", context, i));
+                    }
+                }
+            }
+        }
+        Dataset::Asn(strings, data) => {
+            for (i, (_, _, cidr, asn, name, _)) in data.iter().enumerate() {
+                for (field, idx) in [("cidr", cidr), ("asn", asn), ("name", name)] {
+                    if *idx >= strings.len() {
+                        issues.push(format!(
+                            "record {}: {} index {} exceeds string table size {}",
+                            i,
+                            field,
+                            idx,
+                            strings.len()
+                        ));
+                    }
+                }
+            }
+        }
+        Dataset::Isp(strings, data) | Dataset::Mobile(strings, data) => {
+            let fields = match dataset {
+                Dataset::Mobile(..) => ["mcc", "mnc", "mobile_brand"],
+                _ => ["isp", "domain", "provider"],
+            };
+            for (i, (_, _, a, b, c)) in data.iter().enumerate() {
+                for (field, idx) in fields.into_iter().zip([a, b, c]) {
+                    if *idx > strings.len() {
+                        issues.push(format!(
+                            "record {}: {} index {} exceeds string table size {}",
+                            i,
+                            field,
+                            idx,
+                            strings.len() + 1
+                        ));
+                    }
+                }
+            }
+        }
+        Dataset::Usage(ranges) => {
+            for (i, (_, _, mask)) in ranges.iter().enumerate() {
+                if *mask == 0 || mask & !usage::KNOWN_MASK != 0 {
+                    issues.push(format!("record {}: invalid usage mask {:#x}", i, mask));
+                }
+            }
+        }
+        Dataset::Elevation(_) => {}
+        Dataset::Country(ranges) => {
+            for (i, (_, _, country, _, _, alternate, subdivision)) in ranges.iter().enumerate() {
+                let alternate = alternate.iter().map(|(_, code)| code);
+                for code in std::iter::once(country).chain(alternate) {
+                    if !code.iter().all(u8::is_ascii_uppercase) {
+                        issues.push(format!(
+                            "record {}: invalid country code {:?}",
+                            i,
+                            String::from_utf8_lossy(code)
+                        ));
+                    }
+                }
+                if let Some(subdivision) = subdivision {
+                    if !(1..=3).contains(&subdivision.len())
+                        || !subdivision
+                            .bytes()
+                            .all(|b| b.is_ascii_uppercase() || b.is_ascii_digit())
+                    {
+                        issues.push(format!(
+                            "record {}: invalid subdivision code {:?}",
+                            i, subdivision
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    issues
+}
+
+fn check_encoding(bytes: &[u8], kind: Kind, path: &str) -> Vec<String> {
+    let mut issues = Vec::new();
+    if let Err(err) = scan_ranges(&mut &bytes[..], kind, path, &mut issues) {
+        issues.push(format!("decode failed: {}", err));
+    }
+    issues
+}
+
+fn scan_ranges(input: &mut &[u8], kind: Kind, path: &str, issues: &mut Vec<String>) -> Result<()> {
+    let (layout, strings) = read_preamble(input, kind, path)?;
+    let header = layout.header();
+    if kind != Kind::Proxy {
+        return scan_sections(input, header, "", issues, |input, state| {
+            read_block(input, kind, layout, strings.len(), state, 1).map(|_| ())
+        });
+    }
+
+    for _ in 0..bin::read_u16(input)? {
+        let len = bin::read_u8(input)? as usize;
+        let name = input.get(..len).ok_or(ErrorKind::UnexpectedEof)?;
+        let context = format!("type {}: ", String::from_utf8_lossy(name));
+        *input = &input[len..];
+        scan_sections(input, header, &context, issues, |input, state| {
+            state.prev_from = bin::read_proxy_record(input, header, state.prev_from)?.0;
+            Ok(())
+        })?;
+        if !issues.is_empty() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn scan_sections(
+    input: &mut &[u8],
+    header: Header,
+    context: &str,
+    issues: &mut Vec<String>,
+    mut skip: impl FnMut(&mut &[u8], &mut SyncState) -> Result<()>,
+) -> Result<()> {
+    for &base in header.section_bases() {
+        let mut state = SyncState {
+            prev_from: base,
+            ..SyncState::default()
+        };
+        let mut prev: Option<(u128, u128)> = None;
+        for i in 0..bin::read_u32(input)? {
+            let (from, to) = match raw_range(&mut &input[..], header, state.prev_from) {
+                Ok(range) => range,
+                Err(issue) => {
+                    issues.push(format!("{}record {}: {}", context, i, issue));
+                    return Ok(());
+                }
+            };
+            if let Some((prev_from, prev_to)) = prev.filter(|&(prev_from, _)| prev_from == from) {
+                let order = header.order();
+                if order.size_key(to - from) <= order.size_key(prev_to - prev_from) {
+                    issues.push(format!(
+                        "{}record {}: zero start delta makes {}-{} overlap previous range {}-{}",
+                        context, i, from, to, prev_from, prev_to
+                    ));
+                    return Ok(());
+                }
+            }
+            skip(input, &mut state)?;
+            prev = Some((from, to));
+        }
+    }
+    Ok(())
+}
+
+fn raw_range(
+    input: &mut &[u8],
+    header: Header,
+    prev_from: u128,
+) -> std::result::Result<(u128, u128), String> {
+    if header.has(IPV4_ONLY) {
+        let from = bin::read_u32(input).map_err(|err| format!("start: {}", err))?;
+        let to = bin::read_u32(input).map_err(|err| format!("end: {}", err))?;
+        if to < from {
+            return Err(format!("end {} before start {}", to, from));
+        }
+        return Ok((IPV4_BASE + from as u128, IPV4_BASE + to as u128));
+    }
+    let gap = read_varint(input).map_err(|err| format!("start delta: {}", err))?;
+    let size = read_varint(input).map_err(|err| format!("size varint: {}", err))?;
+    let from = prev_from
+        .checked_add(gap)
+        .ok_or_else(|| format!("start delta {} wraps past {}", gap, u128::MAX))?;
+    let to = from.checked_add(size).ok_or_else(|| {
+        format!(
+            "size {} from start {} overflows past {}",
+            size,
+            from,
+            u128::MAX
+        )
+    })?;
+    Ok((from, to))
+}
+
+fn check_order(
+    ranges: impl Iterator<Item = (u128, u128)>,
+    order: Order,
+    context: &str,
+    issues: &mut Vec<String>,
+) {
+    let mut prev: Option<(u128, u128)> = None;
+    for (i, (from, to)) in ranges.enumerate() {
+        if let Some((prev_from, prev_to)) = prev {
+            let size = order.size_key(to.wrapping_sub(from));
+            if from == prev_from && size < order.size_key(prev_to.wrapping_sub(prev_from)) {
+                issues.push(format!(
+                    "{}record {}: breaks the {} order of ranges starting at {}",
+                    context,
+                    i,
+                    order.name(),
+                    from
+                ));
+            }
+        }
+        prev = Some((from, to));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::{write_signed_varint, write_varint};
+
+    fn elevation(records: &[(u128, &[u8])]) -> Vec<u8> {
+        let mut out = (records.len() as u32).to_le_bytes().to_vec();
+        for (gap, size) in records {
+            write_varint(&mut out, *gap).unwrap();
+            out.extend_from_slice(size);
+            write_signed_varint(&mut out, 100).unwrap();
+        }
+        out
+    }
+
+    fn varint(value: u128) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_varint(&mut out, value).unwrap();
+        out
+    }
+
+    fn check(bytes: &[u8]) -> Vec<String> {
+        check_encoding(bytes, Kind::Elevation, "elevation.bin")
+    }
+
+    #[test]
+    fn accepts_unlocated_geo_ranges() {
+        let path = std::env::temp_dir().join(format!("ip2x-unlocated-{}.bin", std::process::id()));
+        let path = path.to_string_lossy();
+        let ranges = vec![
+            (IPV4_BASE, IPV4_BASE + 255, 52.52, 13.405, None),
+            (IPV4_BASE + 256, IPV4_BASE + 511, f32::NAN, f32::NAN, None),
+        ];
+        Dataset::Geo(ranges).write(&path).unwrap();
+        let issues = validate_file(&path, Kind::Geo);
+        std::fs::remove_file(&*path).unwrap();
+        assert!(issues.is_empty(), "{:?}", issues);
+    }
+
+    #[test]
+    fn flags_half_missing_coordinates() {
+        let issues = validate(&Dataset::Geo(vec![(0, 255, f32::NAN, 13.405, None)]));
+        assert!(issues[0].contains("out of bounds"), "{:?}", issues);
+    }
+
+    #[test]
+    fn accepts_nested_ranges() {
+        let bytes = elevation(&[(10, &varint(0)), (0, &varint(255)), (300, &varint(5))]);
+        assert!(check(&bytes).is_empty());
+    }
+
+    #[test]
+    fn flags_size_varint_overflow() {
+        let mut size = vec![0xFF; 18];
+        size.push(0x7F);
+        let issues = check(&elevation(&[(10, &size)]));
+        assert!(issues[0].contains("size varint"), "{:?}", issues);
+    }
+
+    #[test]
+    fn flags_wrapping_start_delta() {
+        let bytes = elevation(&[(u128::MAX - 5, &varint(0)), (10, &varint(0))]);
+        let issues = check(&bytes);
+        assert!(issues[0].contains("record 1: start delta"), "{:?}", issues);
+    }
+
+    #[test]
+    fn flags_size_past_address_space() {
+        let issues = check(&elevation(&[(u128::MAX - 5, &varint(10))]));
+        assert!(issues[0].contains("overflows past"), "{:?}", issues);
+    }
+
+    #[test]
+    fn flags_zero_gap_overlap() {
+        for second in [255, 100] {
+            let bytes = elevation(&[(10, &varint(255)), (0, &varint(second))]);
+            let issues = check(&bytes);
+            assert!(
+                issues[0].contains("record 1: zero start delta"),
+                "{:?}",
+                issues
+            );
+        }
+    }
+}
@@ -0,0 +1,135 @@
+pub use crate::encoding::{
+    read_signed_varint, read_varint, unzigzag, write_signed_varint, write_varint, zigzag, MAX_LEN,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::ErrorKind;
+
+    fn encode(value: u128) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_varint(&mut out, value).unwrap();
+        out
+    }
+
+    fn encode_signed(value: i64) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_signed_varint(&mut out, value).unwrap();
+        out
+    }
+
+    fn boundaries() -> Vec<u128> {
+        let mut values = vec![0, 1, u128::MAX];
+        for bits in 1..128 {
+            let power = 1u128 << bits;
+            values.extend([power - 1, power, power + 1]);
+        }
+        values
+    }
+
+    #[test]
+    fn known_encodings() {
+        assert_eq!(encode(0), [0x00]);
+        assert_eq!(encode(1), [0x01]);
+        assert_eq!(encode(127), [0x7F]);
+        assert_eq!(encode(128), [0x80, 0x01]);
+        assert_eq!(encode(300), [0xAC, 0x02]);
+        assert_eq!(encode(16383), [0xFF, 0x7F]);
+        assert_eq!(encode(16384), [0x80, 0x80, 0x01]);
+    }
+
+    #[test]
+    fn zigzag_order() {
+        for (value, encoded) in [(0, 0), (-1, 1), (1, 2), (-2, 3), (2, 4)] {
+            assert_eq!(zigzag(value), encoded);
+            assert_eq!(unzigzag(encoded), value);
+        }
+        assert_eq!(zigzag(i64::MAX), u64::MAX - 1);
+        assert_eq!(zigzag(i64::MIN), u64::MAX);
+    }
+
+    #[test]
+    fn round_trips_every_u16() {
+        for value in 0..=u16::MAX as u128 {
+            let bytes = encode(value);
+            assert_eq!(read_varint(&mut bytes.as_slice()).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn round_trips_every_i16() {
+        for value in i16::MIN as i64..=i16::MAX as i64 {
+            let bytes = encode_signed(value);
+            assert_eq!(read_signed_varint(&mut bytes.as_slice()).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn round_trips_power_of_two_boundaries() {
+        for value in boundaries() {
+            let bytes = encode(value);
+            let bits = 128 - value.leading_zeros() as usize;
+            assert_eq!(bytes.len(), bits.div_ceil(7).max(1), "{}", value);
+            assert_eq!(read_varint(&mut bytes.as_slice()).unwrap(), value);
+        }
+        assert_eq!(encode(u128::MAX).len(), MAX_LEN);
+    }
+
+    #[test]
+    fn round_trips_signed_boundaries() {
+        let mut values = vec![0, i64::MIN, i64::MAX, i64::MIN + 1, i64::MAX - 1];
+        for bits in 0..63 {
+            let power = 1i64 << bits;
+            values.extend([power - 1, power, -power, -power - 1]);
+        }
+        for value in values {
+            let bytes = encode_signed(value);
+            assert_eq!(read_signed_varint(&mut bytes.as_slice()).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn reads_consecutive_values() {
+        let values = boundaries();
+        let mut bytes = Vec::new();
+        for &value in &values {
+            write_varint(&mut bytes, value).unwrap();
+        }
+        let mut input = bytes.as_slice();
+        for &value in &values {
+            assert_eq!(read_varint(&mut input).unwrap(), value);
+        }
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    fn rejects_values_past_u128() {
+        let mut bytes = encode(u128::MAX);
+        bytes[MAX_LEN - 1] = 0x04;
+        let err = read_varint(&mut bytes.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+        let too_long = [0x80; MAX_LEN + 1];
+        let err = read_varint(&mut too_long.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_signed_values_past_u64() {
+        let bytes = encode(u64::MAX as u128 + 1);
+        let err = read_signed_varint(&mut bytes.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn truncated_input_is_eof() {
+        for value in [128, 1 << 20, u128::MAX] {
+            let bytes = encode(value);
+            let err = read_varint(&mut &bytes[..bytes.len() - 1]).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+        }
+        let err = read_varint(&mut [].as_slice()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    }
+}
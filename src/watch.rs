@@ -0,0 +1,45 @@
+use std::collections::BTreeSet;
+use std::io::{Error, Result};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::time::Duration;
+
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+
+use crate::logging;
+
+const DEBOUNCE: Duration = Duration::from_secs(2);
+
+fn collect(event: notify::Result<Event>, changed: &mut BTreeSet<PathBuf>) {
+    match event {
+        Ok(event) if !matches!(event.kind, EventKind::Access(_)) => changed.extend(event.paths),
+        Ok(_) => {}
+        Err(err) => logging::warn(&format!("watch: {}", err)),
+    }
+}
+
+pub fn run(dir: &str, mut rebuild: impl FnMut(&[PathBuf]) -> Result<()>) -> Result<()> {
+    let (sender, events) = channel();
+    let mut watcher = notify::recommended_watcher(sender).map_err(Error::other)?;
+    watcher
+        .watch(Path::new(dir), RecursiveMode::NonRecursive)
+        .map_err(Error::other)?;
+    logging::info(&format!("watching {} for changes", dir));
+
+    while let Ok(event) = events.recv() {
+        let mut changed = BTreeSet::new();
+        collect(event, &mut changed);
+        loop {
+            match events.recv_timeout(DEBOUNCE) {
+                Ok(event) => collect(event, &mut changed),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+        }
+        let changed: Vec<PathBuf> = changed.into_iter().collect();
+        if let Err(err) = rebuild(&changed) {
+            logging::error(&err);
+        }
+    }
+    Ok(())
+}
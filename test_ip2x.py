@@ -0,0 +1,94 @@
+import os
+import shutil
+import subprocess
+import tempfile
+import unittest
+from pathlib import Path
+
+import ip2x
+
+ROOT = Path(__file__).resolve().parent
+ROWS = [
+    (16777216, 16777471, "AU", -27.46794, 153.02809),
+    (16777472, 16778239, "CN", 26.06139, 119.30611),
+    (16778240, 16779263, "AU", -37.814, 144.96332),
+    (16779264, 16781311, "CN", 0.0, 0.0),
+]
+LAYOUTS = [
+    [],
+    ["--index", "--index-interval", "2"],
+    ["--layout", "trie"],
+    ["--order", "largest-first"],
+    ["--split-families"],
+    ["--ipv4-only"],
+    ["--geo-precision", "5"],
+]
+
+
+def ip2x_command() -> list:
+    binary = os.environ.get("IP2X_BIN")
+    if binary:
+        return [binary]
+    return ["cargo", "run", "--quiet", "--manifest-path", str(ROOT / "Cargo.toml"), "--"]
+
+
+def dotted(value: int) -> str:
+    return ".".join(str(value >> shift & 0xFF) for shift in (24, 16, 8, 0))
+
+
+class GeoRoundTrip(unittest.TestCase):
+    @classmethod
+    def setUpClass(cls):
+        cls.data = tempfile.mkdtemp()
+        with open(Path(cls.data) / "IP2LOCATION-LITE-DB5.CSV", "w") as f:
+            for start, end, country, lat, lon in ROWS:
+                f.write(f'"{start}","{end}","{country}","-","-","-","{lat:.6f}","{lon:.6f}"\n')
+
+    @classmethod
+    def tearDownClass(cls):
+        shutil.rmtree(cls.data)
+
+    def build(self, out: str, options: list):
+        result = subprocess.run(
+            ip2x_command() + ["build", self.data, "--keep-unlocated", "-q"] + options,
+            cwd=out,
+            capture_output=True,
+            text=True,
+        )
+        self.assertEqual(result.returncode, 0, result.stderr)
+
+    def test_reads_freshly_built_geo_bin(self):
+        for options in LAYOUTS:
+            with self.subTest(options=options), tempfile.TemporaryDirectory() as out:
+                self.build(out, options)
+                cwd = os.getcwd()
+                os.chdir(out)
+                try:
+                    db = ip2x.DatabaseLoader()
+                    db._load_geo()
+                finally:
+                    os.chdir(cwd)
+
+                self.assertEqual(len(db.geo_ranges), len(ROWS))
+                for start, end, _, lat, lon in ROWS:
+                    for ip in (dotted(start), dotted(end)):
+                        result = db.lookup_geo(ip)
+                        if lat == lon == 0.0:
+                            self.assertEqual(result, {}, ip)
+                            continue
+                        self.assertAlmostEqual(result["latitude"], lat, places=3, msg=ip)
+                        self.assertAlmostEqual(result["longitude"], lon, places=3, msg=ip)
+
+    def test_rejects_unknown_flags(self):
+        with tempfile.TemporaryDirectory() as out:
+            self.build(out, [])
+            path = Path(out) / "geo.bin"
+            raw = bytearray(path.read_bytes())
+            raw[5:9] = (int.from_bytes(raw[5:9], "little") | 1 << 20).to_bytes(4, "little")
+            path.write_bytes(raw)
+            with open(path, "rb") as f, self.assertRaises(ValueError):
+                ip2x.read_header(f)
+
+
+if __name__ == "__main__":
+    unittest.main()
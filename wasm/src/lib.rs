@@ -0,0 +1,158 @@
+use std::net::IpAddr;
+
+use ip2x_core::bin::{AlternateCountry, CountryRange};
+use ip2x_core::lookup::LookupMode;
+use ip2x_core::{reader, usage};
+use js_sys::{Object, Reflect};
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct Reader(reader::Reader);
+
+fn error(err: ip2x_core::Error) -> JsError {
+    JsError::new(&err.to_string())
+}
+
+fn set(object: &Object, key: &str, value: impl Into<JsValue>) {
+    Reflect::set(object, &key.into(), &value.into()).unwrap();
+}
+
+fn object(fields: &[(&str, JsValue)]) -> JsValue {
+    let object = Object::new();
+    for (key, value) in fields {
+        set(&object, key, value.clone());
+    }
+    object.into()
+}
+
+fn strings(keys: [&str; 3], values: [&str; 3]) -> JsValue {
+    let fields = keys
+        .iter()
+        .zip(values)
+        .map(|(&key, value)| (key, value.into()));
+    object(&fields.collect::<Vec<_>>())
+}
+
+fn code(bytes: &[u8; 2]) -> String {
+    match bytes {
+        b"--" => "-".to_string(),
+        _ => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+fn country((_, _, country, continent, eu, alternate, subdivision): &CountryRange) -> JsValue {
+    let alternate_code = |wanted: AlternateCountry| match alternate {
+        Some((source, alternate)) if *source == wanted => code(alternate).into(),
+        _ => JsValue::NULL,
+    };
+    let subdivision = subdivision.as_ref().map_or(JsValue::NULL, |subdivision| {
+        format!("{}-{}", String::from_utf8_lossy(country), subdivision).into()
+    });
+    object(&[
+        ("country_code", code(country).into()),
+        ("continent_code", code(continent).into()),
+        ("is_in_european_union", (*eu).into()),
+        (
+            "registered_country_code",
+            alternate_code(AlternateCountry::Registered),
+        ),
+        (
+            "represented_country_code",
+            alternate_code(AlternateCountry::Represented),
+        ),
+        ("subdivision_code", subdivision),
+    ])
+}
+
+#[wasm_bindgen]
+impl Reader {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Reader {
+        Reader::default()
+    }
+
+    #[wasm_bindgen(js_name = loadStrings)]
+    pub fn load_strings(&mut self, bytes: &[u8]) -> Result<(), JsError> {
+        self.0.load_strings(bytes).map_err(error)
+    }
+
+    pub fn load(&mut self, name: &str, bytes: &[u8]) -> Result<(), JsError> {
+        self.0
+            .load(name, bytes)
+            .map_err(|err| JsError::new(&format!("Cannot load {}: {}", name, err)))
+    }
+
+    #[wasm_bindgen(js_name = setMode)]
+    pub fn set_mode(&mut self, mode: &str) -> Result<(), JsError> {
+        self.0.mode = LookupMode::parse(mode)
+            .ok_or_else(|| JsError::new(&format!("Unknown lookup mode: {}", mode)))?;
+        Ok(())
+    }
+
+    pub fn lookup(&self, ip: &str) -> Result<JsValue, JsError> {
+        let parsed: IpAddr = ip
+            .parse()
+            .map_err(|_| JsError::new(&format!("Invalid IP address: {}", ip)))?;
+        let reader = &self.0;
+        let record = Object::new();
+        set(&record, "ip", ip);
+
+        if let Some(&(_, _, latitude, longitude, accuracy)) = reader.geo(parsed) {
+            let accuracy = accuracy.map_or(JsValue::NULL, JsValue::from);
+            set(
+                &record,
+                "geo",
+                object(&[
+                    ("latitude", latitude.into()),
+                    ("longitude", longitude.into()),
+                    ("accuracy_radius", accuracy),
+                ]),
+            );
+        }
+        if let Some(range) = reader.country(parsed) {
+            set(&record, "country", country(range));
+        }
+        if let Some(values) = reader.asn(parsed) {
+            set(&record, "asn", strings(["cidr", "asn", "as_name"], values));
+        }
+        if let Some((name, confidence)) = reader.proxy(parsed) {
+            set(
+                &record,
+                "proxy",
+                object(&[
+                    ("proxy_type", name.into()),
+                    ("confidence", confidence.into()),
+                ]),
+            );
+        }
+        if let Some(values) = reader.isp(parsed) {
+            set(
+                &record,
+                "isp",
+                strings(["isp", "domain", "provider"], values),
+            );
+        }
+        if let Some(values) = reader.mobile(parsed) {
+            set(
+                &record,
+                "mobile",
+                strings(["mcc", "mnc", "mobile_brand"], values),
+            );
+        }
+        if let Some(mask) = reader.usage(parsed) {
+            set(
+                &record,
+                "usage_type",
+                object(&[
+                    ("usage_type", usage::names(mask).into()),
+                    ("mask", mask.into()),
+                ]),
+            );
+        }
+        if let Some(meters) = reader.elevation(parsed) {
+            set(&record, "elevation", meters);
+        }
+        Ok(record.into())
+    }
+}